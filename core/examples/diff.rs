@@ -0,0 +1,52 @@
+//! Compares two 2D patterns cell-by-cell.
+//!
+//! Usage: `cargo run --example diff -- [--cells] OLD_FILE NEW_FILE`
+//!
+//! Both files may be in any format supported by `ndcell_core::io`. Prints a
+//! summary of the differences (and, with `--cells`, every differing cell) and
+//! exits with status 1 if the patterns differ.
+
+use std::process::exit;
+
+use ndcell_core::io::import_ndtree_from_bytes;
+use ndcell_core::prelude::*;
+
+fn main() {
+    let mut list_cells = false;
+    let mut paths = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--cells" => list_cells = true,
+            _ => paths.push(arg),
+        }
+    }
+    if paths.len() != 2 {
+        eprintln!("usage: diff [--cells] OLD_FILE NEW_FILE");
+        exit(2);
+    }
+
+    let old = load(&paths[0]);
+    let new = load(&paths[1]);
+    let diff = old.diff(&new);
+
+    println!("{}", diff);
+    if list_cells {
+        for (pos, old_state, new_state) in diff.cells() {
+            println!("{}: {} -> {}", pos, old_state, new_state);
+        }
+    }
+    if !diff.is_empty() {
+        exit(1);
+    }
+}
+
+fn load(path: &str) -> NdTree2D {
+    let contents = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("error reading {}: {}", path, e);
+        exit(2);
+    });
+    import_ndtree_from_bytes(&contents).unwrap_or_else(|e| {
+        eprintln!("error parsing {}: {}", path, e);
+        exit(2);
+    })
+}
@@ -0,0 +1,86 @@
+//! Simulates a 2D pattern and prints statistics about its node pool.
+//!
+//! Usage: `cargo run --example pool_stats -- [--rule RULE] [--step STEP] FILE GENS`
+//!
+//! The file may be in any format supported by `ndcell_core::io`, and the rule
+//! must be a Life-like rule such as `B3/S23` (the default). Simulates `GENS`
+//! generations, `STEP` generations at a time (all at once by default), and then
+//! prints statistics about node counts, memory usage, and the HashLife result
+//! cache.
+
+use std::convert::TryFrom;
+use std::process::exit;
+use std::time::Instant;
+
+use ndcell_core::io::import_ndtree_from_bytes;
+use ndcell_core::prelude::*;
+use ndcell_core::sim::rule::{MooreTotalistic2D, LIFE};
+
+fn main() {
+    let mut rule = LIFE;
+    let mut step = None;
+    let mut positional = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" => {
+                let s = args.next().unwrap_or_else(|| usage());
+                rule = MooreTotalistic2D::try_from(s.as_str()).unwrap_or_else(|()| {
+                    eprintln!("error parsing rule {}", s);
+                    exit(2);
+                });
+            }
+            "--step" => step = Some(parse_gens(&args.next().unwrap_or_else(|| usage()))),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        usage();
+    }
+    let mut ndtree = load(&positional[0]);
+    let gens = parse_gens(&positional[1]);
+    let step = step.unwrap_or_else(|| gens.clone());
+    if step.is_zero() {
+        eprintln!("step size must be positive");
+        exit(2);
+    }
+
+    let start = Instant::now();
+    let mut remaining = gens;
+    while remaining.is_positive() {
+        let this_step = std::cmp::min(&step, &remaining).clone();
+        hashlife::step(&mut ndtree, &rule, &this_step);
+        remaining -= this_step;
+    }
+    let elapsed = start.elapsed();
+
+    println!("Population: {}", ndtree.root_ref().population());
+    println!("Simulation time: {:.2?}", elapsed);
+    print!("{}", ndtree.pool().access().stats());
+}
+
+fn usage() -> ! {
+    eprintln!("usage: pool_stats [--rule RULE] [--step STEP] FILE GENS");
+    exit(2);
+}
+
+fn parse_gens(s: &str) -> BigInt {
+    match s.parse::<BigInt>() {
+        Ok(gens) if !gens.is_negative() => gens,
+        _ => {
+            eprintln!("invalid number of generations: {}", s);
+            exit(2);
+        }
+    }
+}
+
+fn load(path: &str) -> NdTree2D {
+    let contents = std::fs::read(path).unwrap_or_else(|e| {
+        eprintln!("error reading {}: {}", path, e);
+        exit(2);
+    });
+    import_ndtree_from_bytes(&contents).unwrap_or_else(|e| {
+        eprintln!("error parsing {}: {}", path, e);
+        exit(2);
+    })
+}
@@ -0,0 +1,116 @@
+//! Searches random 2D soups and keeps a census of the resulting objects.
+//!
+//! Usage: `cargo run --release --example soup_search -- [--rule RULE] [--size SIZE] [--seed-base SEED] CENSUS SOUPS`
+//!
+//! The rule must be a Life-like rule such as `B3/S23` (the default). Searches
+//! `SOUPS` more soups of size `SIZE` (16 by default), saving the census to the
+//! file `CENSUS` every 100 soups. If the file already exists, the search
+//! resumes from it, so the search can be stopped and restarted at any time.
+//!
+//! Soups are numbered starting at `SEED` (0 by default). When searching on
+//! several machines, give each one a different seed base, such as a different
+//! multiple of 4294967296, so that they do not search the same soups.
+
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::process::exit;
+
+use ndcell_core::sim::rule::{MooreTotalistic2D, LIFE};
+use ndcell_core::sim::{SoupSearch, SoupSearchParams};
+
+/// Number of soups to search between saving the census.
+const SAVE_INTERVAL: usize = 100;
+
+fn main() {
+    let mut rule = LIFE;
+    let mut params = SoupSearchParams::new();
+    let mut positional = vec![];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rule" => {
+                let s = args.next().unwrap_or_else(|| usage());
+                rule = MooreTotalistic2D::try_from(s.as_str()).unwrap_or_else(|()| {
+                    eprintln!("error parsing rule {}", s);
+                    exit(2);
+                });
+            }
+            "--size" => {
+                let s = args.next().unwrap_or_else(|| usage());
+                let size = s.parse().unwrap_or_else(|_| usage());
+                params = params.with_soup_size(size);
+            }
+            "--seed-base" => {
+                let s = args.next().unwrap_or_else(|| usage());
+                let seed_base = s.parse().unwrap_or_else(|_| usage());
+                params = params.with_seed_base(seed_base);
+            }
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        usage();
+    }
+    let path = &positional[0];
+    let mut remaining: usize = positional[1].parse().unwrap_or_else(|_| usage());
+
+    let mut search = SoupSearch::new(&rule, params);
+    if let Ok(file) = File::open(path) {
+        search
+            .read_census(BufReader::new(file))
+            .unwrap_or_else(|e| {
+                eprintln!("error reading {}: {}", path, e);
+                exit(2);
+            });
+        println!("Resuming from soup {}", search.next_seed());
+    }
+
+    while remaining > 0 {
+        let count = std::cmp::min(remaining, SAVE_INTERVAL);
+        if let Err(e) = search.run(count) {
+            eprintln!("error in soup {}: {}", search.next_seed(), e);
+            save(&search, path);
+            exit(2);
+        }
+        remaining -= count;
+        save(&search, path);
+        println!(
+            "{} soups, {} objects",
+            search.census().soups(),
+            search.census().total(),
+        );
+    }
+    print!("{}", search.census());
+}
+
+fn usage() -> ! {
+    eprintln!("usage: soup_search [--rule RULE] [--size SIZE] [--seed-base SEED] CENSUS SOUPS");
+    exit(2);
+}
+
+/// Saves the census to a temporary file and then moves it into place, so that
+/// stopping the search never leaves a partially written census.
+fn save(search: &SoupSearch<'_, ndcell_core::dim::Dim2D>, path: &str) {
+    let temp_path = format!("{}.tmp", path);
+    if let Err(e) = write_census_file(search, &temp_path) {
+        eprintln!("error writing {}: {}", temp_path, e);
+        exit(2);
+    }
+    if let Err(e) = std::fs::rename(&temp_path, path) {
+        eprintln!("error writing {}: {}", path, e);
+        exit(2);
+    }
+}
+
+/// Writes the census to a file and waits until it is on disk.
+fn write_census_file(
+    search: &SoupSearch<'_, ndcell_core::dim::Dim2D>,
+    path: &str,
+) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    search.write_census(&mut writer)?;
+    // Dropping a `BufWriter` ignores errors, so flush it explicitly.
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.sync_all()
+}
@@ -4,11 +4,15 @@ use std::convert::TryFrom;
 use std::sync::Arc;
 
 use crate::dim::*;
-use crate::ndtree::{NdTree, NodeRefTrait};
+use crate::io::PatternMetadata;
+use crate::ndtree::{NdTree, NodePoolStats, NodeRefTrait};
 use crate::num::{BigInt, BigUint, Zero};
 use crate::sim::rule::{DummyRule, NdRule};
 use crate::sim::{hashlife, AsSimulate, Simulate};
 
+#[cfg(feature = "serde")]
+pub use crate::serde_impls::AutomatonSeed;
+
 /// 1D cellular automaton.
 pub type Automaton1D = NdAutomaton<Dim1D>;
 /// 2D cellular automaton.
@@ -116,14 +120,15 @@ pub enum AutomatonMut<'a> {
 }
 
 /// Cellular automaton simulation, including a grid of cells (stored using an
-/// `NdTree`), a rule, and a generation count.
+/// `NdTree`), a rule, a generation count, and metadata such as the name of
+/// the pattern.
 #[allow(missing_docs)]
 #[derive(Debug, Clone)]
 pub struct NdAutomaton<D: Dim> {
     pub ndtree: NdTree<D>,
     pub rule: Arc<dyn NdRule<D>>,
     pub generations: BigInt,
-    pub comments: String,
+    pub metadata: PatternMetadata,
 }
 impl<D: Dim> Default for NdAutomaton<D> {
     fn default() -> Self {
@@ -131,7 +136,7 @@ impl<D: Dim> Default for NdAutomaton<D> {
             ndtree: NdTree::default(),
             rule: Arc::new(DummyRule),
             generations: BigInt::zero(),
-            comments: String::new(),
+            metadata: PatternMetadata::default(),
         }
     }
 }
@@ -152,10 +157,19 @@ impl<D: Dim> Simulate for NdAutomaton<D> {
         hashlife::step(&mut self.ndtree, &*self.rule, gens);
         self.generations += gens;
     }
+    fn step_with_memory_limit(&mut self, gens: &BigInt, max_memory: usize) -> usize {
+        let gc_count =
+            hashlife::step_with_memory_limit(&mut self.ndtree, &*self.rule, gens, max_memory);
+        self.generations += gens;
+        gc_count
+    }
 
     fn memory_usage(&self) -> usize {
         self.ndtree.pool().access().memory_usage()
     }
+    fn node_pool_stats(&self) -> NodePoolStats {
+        self.ndtree.pool().access().stats()
+    }
     fn yield_to_gc(&self) {
         let _ = self.ndtree.pool().yield_to_gc();
     }
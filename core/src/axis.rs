@@ -6,6 +6,7 @@ pub use Axis::*;
 
 /// Enumeration of the six dimensions supported by NDCell.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Axis {
     /// X axis (generally horizontal/"width").
     X = 0,
@@ -0,0 +1,646 @@
+//! Compact binary format native to NDCell
+//!
+//! Like Macrocell, this format stores the deduplicated DAG of ND-tree nodes,
+//! so it can represent enormous repetitive patterns efficiently. All integers
+//! are stored as [LEB128](https://en.wikipedia.org/wiki/LEB128) varints.
+//!
+//! A file consists of the magic bytes `NDCB`, a version byte, a flags byte,
+//! and then the body, which is compressed using zlib if bit 0 of the flags
+//! byte is set. The body contains, in order:
+//!
+//! - number of dimensions
+//! - rule name (length + 1, or 0 if absent, followed by UTF-8 bytes)
+//! - generation count (signed)
+//! - comments (length followed by UTF-8 bytes)
+//! - number of metadata entries, followed by key/value string pairs
+//! - center of the root node (one signed integer per axis)
+//! - layer of the root node
+//! - number of nodes, followed by each node
+//!
+//! Each node begins with its layer. Nodes at layer 1 contain their `2^NDIM`
+//! cell states; larger nodes contain the indices of their `2^NDIM` children,
+//! using the same order as `NodeRef::subdivide()`. Nodes are indexed starting
+//! from 1 in the order they appear, and must be defined before they are used.
+//! Index 0 refers to an empty node. The last node is the root.
+//!
+//! Compressing the body requires the `gzip` feature (enabled by default).
+//! Without it, patterns are always written uncompressed, and reading a
+//! compressed pattern returns `BinaryError::UnsupportedCompression`.
+//!
+//! Since the format is not text, `to_string()` and `from_str()` use the
+//! base64 encoding of the binary data, which is convenient for copying and
+//! pasting. `read_from()` accepts either form.
+
+#[cfg(feature = "gzip")]
+use flate2::read::ZlibDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::ZlibEncoder;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+use super::utils::read_prefix;
+use super::{CaFormatTrait, TwoState};
+use crate::axis::AXES;
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndtree::{
+    FlatNdTree, FlatNdTreeNode, Layer, NdTree, NodeRef, NodeRefTrait, Region, SharedNodePool,
+};
+use crate::ndvec::{BigVec, BigVec6D};
+use crate::num::{BigInt, One, Zero};
+
+/// Magic bytes at the beginning of every file.
+pub(super) const MAGIC: &[u8] = b"NDCB";
+/// Start of the base64 encoding of `MAGIC` followed by any version byte less
+/// than 16.
+pub(super) const BASE64_MAGIC: &[u8] = b"TkRDQ";
+/// Current version of the format.
+const VERSION: u8 = 1;
+/// Flag indicating that the body is compressed.
+const FLAG_COMPRESSED: u8 = 1 << 0;
+/// Maximum layer of a node, to avoid absurd allocations from invalid input.
+const MAX_LAYER: usize = 1 << 16;
+
+/// Result type returned by fallible binary format routines.
+pub type BinaryResult<T> = Result<T, BinaryError>;
+
+/// Error encountered during binary import/export.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum BinaryError {
+    MissingHeader,
+    UnsupportedVersion(u8),
+    UnexpectedEnd,
+    InvalidVarint,
+    InvalidUtf8,
+    InvalidBase64,
+    BadDimensionality { expected: usize, got: usize },
+    InvalidNodeIndex(usize),
+    InvalidContent,
+    UnsupportedCompression,
+    IoError(String),
+}
+impl From<io::Error> for BinaryError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::UnexpectedEof => Self::UnexpectedEnd,
+            _ => Self::IoError(e.to_string()),
+        }
+    }
+}
+impl fmt::Display for BinaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "Missing NDCell binary header"),
+            Self::UnsupportedVersion(v) => write!(f, "Unsupported NDCell binary version: {}", v),
+            Self::UnexpectedEnd => write!(f, "Unexpected end of data"),
+            Self::InvalidVarint => write!(f, "Invalid integer"),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 in string"),
+            Self::InvalidBase64 => write!(f, "Invalid base64"),
+            Self::BadDimensionality { expected, got } => {
+                write!(f, "Expected {}D pattern; got {}D pattern", expected, got)
+            }
+            Self::InvalidNodeIndex(i) => {
+                write!(f, "Node #{} is referenced before it is defined", i)
+            }
+            Self::InvalidContent => write!(f, "Invalid NDCell binary content"),
+            Self::UnsupportedCompression => write!(
+                f,
+                "Compressed NDCell binary requires the `gzip` feature of ndcell_core",
+            ),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+/// Pattern in NDCell's compact binary format.
+#[derive(Debug, Clone)]
+pub struct Binary {
+    /// Number of dimensions.
+    ndim: usize,
+    /// Automaton rule.
+    rule: Option<String>,
+    /// Number of generations simulated.
+    gen: BigInt,
+    /// Pattern comments.
+    comments: String,
+    /// Additional key/value metadata.
+    metadata: Vec<(String, String)>,
+    /// Center of the root node (only the first `ndim` axes are used).
+    center: BigVec6D,
+    /// Layer of the root node.
+    root_layer: Layer,
+    /// List of nodes, where index 0 is the empty node and the last one is the
+    /// root.
+    nodes: Vec<BinaryNode>,
+    /// Whether to compress the body when serializing.
+    compressed: bool,
+}
+
+/// Node in a `Binary` pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BinaryNode {
+    /// Empty node at any layer.
+    Empty,
+    /// Layer-1 node containing cell states.
+    Leaf(Box<[u8]>),
+    /// Node containing indices of its children.
+    NonLeaf {
+        layer: Layer,
+        children: Box<[usize]>,
+    },
+}
+impl BinaryNode {
+    fn layer(&self) -> Option<Layer> {
+        match self {
+            Self::Empty => None,
+            Self::Leaf(_) => Some(Layer(1)),
+            Self::NonLeaf { layer, .. } => Some(*layer),
+        }
+    }
+}
+
+impl Binary {
+    /// Returns the additional key/value metadata.
+    pub fn metadata(&self) -> &[(String, String)] {
+        &self.metadata
+    }
+    /// Sets the additional key/value metadata.
+    #[must_use = "This method returns a new value instead of mutating its input"]
+    pub fn with_metadata(mut self, metadata: Vec<(String, String)>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+    /// Returns whether the body is compressed when serializing.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+    /// Sets whether to compress the body when serializing. Compression is
+    /// enabled by default, but requires the `gzip` feature; without it, the
+    /// body is never compressed.
+    #[must_use = "This method returns a new value instead of mutating its input"]
+    pub fn with_compression(mut self, compressed: bool) -> Self {
+        self.compressed = compressed && cfg!(feature = "gzip");
+        self
+    }
+
+    /// Serializes the pattern to raw bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer = vec![];
+        self.write_to(&mut buffer, TwoState::default())
+            .expect("Writing to a Vec should not fail");
+        buffer
+    }
+    /// Deserializes a pattern from raw bytes.
+    pub fn from_bytes(bytes: &[u8]) -> BinaryResult<Self> {
+        Self::read_from(bytes)
+    }
+
+    fn write_body(&self, w: &mut impl Write) -> io::Result<()> {
+        write_varint(w, self.ndim)?;
+        match &self.rule {
+            Some(rule) => {
+                write_varint(w, rule.len() + 1)?;
+                w.write_all(rule.as_bytes())?;
+            }
+            None => write_varint(w, 0)?,
+        }
+        write_bigint(w, &self.gen)?;
+        write_string(w, &self.comments)?;
+        write_varint(w, self.metadata.len())?;
+        for (k, v) in &self.metadata {
+            write_string(w, k)?;
+            write_string(w, v)?;
+        }
+        for &ax in &AXES[..self.ndim] {
+            write_bigint(w, &self.center[ax])?;
+        }
+        write_varint(w, self.root_layer.to_usize())?;
+        write_varint(w, self.nodes.len() - 1)?;
+        for node in &self.nodes[1..] {
+            match node {
+                BinaryNode::Empty => unreachable!("Only node #0 may be empty"),
+                BinaryNode::Leaf(cells) => {
+                    write_varint(w, 1)?;
+                    for &cell in cells.iter() {
+                        write_varint(w, cell as usize)?;
+                    }
+                }
+                BinaryNode::NonLeaf { layer, children } => {
+                    write_varint(w, layer.to_usize())?;
+                    for &child in children.iter() {
+                        write_varint(w, child)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    #[cfg(feature = "gzip")]
+    fn read_compressed_body(r: impl Read) -> BinaryResult<Self> {
+        Self::read_body(&mut io::BufReader::new(ZlibDecoder::new(r)))
+    }
+    #[cfg(not(feature = "gzip"))]
+    fn read_compressed_body(_r: impl Read) -> BinaryResult<Self> {
+        Err(BinaryError::UnsupportedCompression)
+    }
+    fn read_body(r: &mut impl Read) -> BinaryResult<Self> {
+        let ndim = read_varint(r)?;
+        if ndim < 1 || ndim > AXES.len() {
+            return Err(BinaryError::InvalidContent);
+        }
+        let rule = match read_varint(r)? {
+            0 => None,
+            len => Some(read_utf8(r, len - 1)?),
+        };
+        let gen = read_bigint(r)?;
+        let comments = read_string(r)?;
+        let metadata = (0..read_varint(r)?)
+            .map(|_| Ok((read_string(r)?, read_string(r)?)))
+            .collect::<BinaryResult<_>>()?;
+        let mut center = BigVec6D::origin();
+        for &ax in &AXES[..ndim] {
+            center[ax] = read_bigint(r)?;
+        }
+        let root_layer = read_layer(r)?;
+
+        let branching_factor = 1 << ndim;
+        let node_count = read_varint(r)?;
+        let mut nodes = vec![BinaryNode::Empty];
+        for _ in 0..node_count {
+            let node = match read_layer(r)? {
+                Layer(0) => return Err(BinaryError::InvalidContent),
+                Layer(1) => BinaryNode::Leaf(
+                    (0..branching_factor)
+                        .map(|_| {
+                            let cell = read_varint(r)?;
+                            if cell > u8::MAX as usize {
+                                return Err(BinaryError::InvalidContent);
+                            }
+                            Ok(cell as u8)
+                        })
+                        .collect::<BinaryResult<_>>()?,
+                ),
+                layer => BinaryNode::NonLeaf {
+                    layer,
+                    children: (0..branching_factor)
+                        .map(|_| {
+                            let child = read_varint(r)?;
+                            match nodes.get(child).map(BinaryNode::layer) {
+                                None => Err(BinaryError::InvalidNodeIndex(child)),
+                                Some(None) => Ok(child),
+                                Some(Some(l)) if l == layer.child_layer() => Ok(child),
+                                Some(Some(_)) => Err(BinaryError::InvalidContent),
+                            }
+                        })
+                        .collect::<BinaryResult<_>>()?,
+                },
+            };
+            nodes.push(node);
+        }
+
+        match nodes.last().and_then(BinaryNode::layer) {
+            Some(layer) if layer != root_layer => return Err(BinaryError::InvalidContent),
+            _ if root_layer == Layer(0) => return Err(BinaryError::InvalidContent),
+            _ => (),
+        }
+
+        Ok(Self {
+            ndim,
+            rule,
+            gen,
+            comments,
+            metadata,
+            center,
+            root_layer,
+            nodes,
+            compressed: false,
+        })
+    }
+}
+
+impl fmt::Display for Binary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", base64_encode(&self.to_bytes()))
+    }
+}
+impl FromStr for Binary {
+    type Err = BinaryError;
+
+    fn from_str(s: &str) -> BinaryResult<Self> {
+        Self::read_from(s.as_bytes())
+    }
+}
+
+impl CaFormatTrait for Binary {
+    fn write_to(&self, mut writer: impl io::Write, _two_states: TwoState) -> BinaryResult<()> {
+        writer.write_all(MAGIC)?;
+        #[cfg(feature = "gzip")]
+        if self.compressed {
+            writer.write_all(&[VERSION, FLAG_COMPRESSED])?;
+            let mut encoder = ZlibEncoder::new(writer, flate2::Compression::default());
+            self.write_body(&mut encoder)?;
+            encoder.finish()?;
+            return Ok(());
+        }
+        writer.write_all(&[VERSION, 0])?;
+        self.write_body(&mut writer)?;
+        Ok(())
+    }
+    fn read_from(reader: impl io::BufRead) -> BinaryResult<Self> {
+        let mut reader = read_prefix(reader, MAGIC.len(), |prefix| prefix.len() >= MAGIC.len())?;
+        if !reader.get_ref().0.get_ref().starts_with(MAGIC) {
+            // Assume base64.
+            let mut text = String::new();
+            reader
+                .read_to_string(&mut text)
+                .map_err(|_| BinaryError::InvalidBase64)?;
+            text.retain(|ch| !ch.is_ascii_whitespace());
+            let bytes = base64_decode(&text).ok_or(BinaryError::InvalidBase64)?;
+            if !bytes.starts_with(MAGIC) {
+                return Err(BinaryError::MissingHeader);
+            }
+            return Self::read_from(&bytes[..]);
+        }
+
+        let mut header = [0_u8; 6];
+        reader.read_exact(&mut header)?;
+        let [_, _, _, _, version, flags] = header;
+        if version != VERSION {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+        let compressed = flags & FLAG_COMPRESSED != 0;
+        let ret = if compressed {
+            Self::read_compressed_body(reader)?
+        } else {
+            Self::read_body(&mut reader)?
+        };
+        Ok(ret.with_compression(compressed))
+    }
+
+    fn rule(&self) -> Option<&str> {
+        self.rule.as_deref()
+    }
+    fn with_rule(mut self, rule: Option<impl ToString>) -> Self {
+        self.rule = rule.map(|r| r.to_string());
+        self
+    }
+
+    fn generation(&self) -> BigInt {
+        self.gen.clone()
+    }
+    fn with_generation(mut self, generation: BigInt) -> Self {
+        self.gen = generation;
+        self
+    }
+
+    fn comments(&self) -> &str {
+        &self.comments
+    }
+    fn comments_mut(&mut self) -> &mut String {
+        &mut self.comments
+    }
+
+    fn region<D: Dim>(&self) -> Region<D> {
+        if self.nodes.len() <= 1 {
+            return Region::Empty;
+        }
+        let half_len = self.root_layer.child_layer().big_len();
+        let center: BigVec<D> = BigVec::from_fn(|ax| self.center[ax].clone());
+        let min = center.clone() - &half_len;
+        let max = center + &(half_len - BigInt::one());
+        Region::Rect(BigRect::span(min, max))
+    }
+
+    fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> BinaryResult<NdTree<D>> {
+        if self.ndim != D::NDIM {
+            return Err(BinaryError::BadDimensionality {
+                expected: D::NDIM,
+                got: self.ndim,
+            });
+        }
+
+        let node_pool_access = node_pool.access();
+        let mut nodes: Vec<NodeRef<'_, D>> = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let new_node = match node {
+                // Put a dummy node at index 0 -- it is handled specially
+                // anyway.
+                BinaryNode::Empty => node_pool_access.get_empty_base(),
+                BinaryNode::Leaf(cells) => node_pool_access.get_from_cells(cells.clone()),
+                BinaryNode::NonLeaf { layer, children } => {
+                    node_pool_access.join_nodes(children.iter().map(|&i| match i {
+                        0 => node_pool_access.get_empty(layer.child_layer()),
+                        i => nodes[i],
+                    }))
+                }
+            };
+            nodes.push(new_node);
+        }
+
+        let root = match self.nodes.len() {
+            1 => node_pool_access.get_empty(self.root_layer),
+            _ => *nodes.last().unwrap(),
+        };
+        let center = BigVec::from_fn(|ax| self.center[ax].clone());
+        Ok(NdTree::from_node_centered_on(root, center))
+    }
+
+    fn from_ndtree<D: Dim>(ndtree: &NdTree<D>, rect: Option<BigRect<D>>) -> BinaryResult<Self> {
+        let ndtree = match rect {
+            Some(r) => ndtree.get_region(Region::Rect(r)),
+            None => ndtree.clone(),
+        };
+        let mut center = BigVec6D::origin();
+        let ndtree_center = ndtree.center_pos();
+        for &ax in D::axes() {
+            center[ax] = ndtree_center[ax].clone();
+        }
+
+        let root = ndtree.root_ref();
+        let flat = FlatNdTree::from_node(&root, Layer(1), |node| {
+            node.as_leaf()
+                .expect("Layer-1 node is not a leaf node")
+                .cells()
+                .to_vec()
+                .into_boxed_slice()
+        });
+
+        // Nodes in a `FlatNdTree` always come after their children, so each
+        // node's layer can be computed from its children.
+        let mut nodes = vec![BinaryNode::Empty];
+        for flat_node in &flat.nodes()[1..] {
+            nodes.push(match flat_node {
+                FlatNdTreeNode::Leaf(cells, _) => BinaryNode::Leaf(cells.clone()),
+                FlatNdTreeNode::NonLeaf(children, _) => BinaryNode::NonLeaf {
+                    layer: children
+                        .iter()
+                        .filter_map(|&i| nodes[i].layer())
+                        .next()
+                        .expect("Non-empty node has only empty children")
+                        .parent_layer(),
+                    children: children.clone(),
+                },
+            });
+        }
+        debug_assert!(flat.root_idx() == 0 || flat.root_idx() == nodes.len() - 1);
+
+        Ok(Self {
+            ndim: D::NDIM,
+            rule: None,
+            gen: BigInt::zero(),
+            comments: String::new(),
+            metadata: vec![],
+            center,
+            root_layer: root.layer(),
+            nodes,
+            compressed: cfg!(feature = "gzip"),
+        })
+    }
+
+    fn from_automaton_to_string(automaton: &crate::automaton::Automaton) -> BinaryResult<String> {
+        Ok(Self::from_automaton(automaton)?.to_string())
+    }
+    fn from_ndautomaton_to_string<D: Dim>(
+        automaton: &crate::automaton::NdAutomaton<D>,
+        rect: Option<BigRect<D>>,
+    ) -> BinaryResult<String> {
+        Ok(Self::from_ndautomaton(automaton, rect)?.to_string())
+    }
+    fn from_ndtree_to_string<D: Dim>(
+        ndtree: &NdTree<D>,
+        rect: Option<BigRect<D>>,
+        _two_states: TwoState,
+    ) -> BinaryResult<String> {
+        Ok(Self::from_ndtree(ndtree, rect)?.to_string())
+    }
+}
+
+/// Writes an unsigned LEB128 varint.
+pub(crate) fn write_varint(w: &mut impl Write, mut n: usize) -> io::Result<()> {
+    loop {
+        let byte = (n & 0x7F) as u8;
+        n >>= 7;
+        if n == 0 {
+            return w.write_all(&[byte]);
+        }
+        w.write_all(&[byte | 0x80])?;
+    }
+}
+/// Reads an unsigned LEB128 varint.
+pub(crate) fn read_varint(r: &mut impl Read) -> BinaryResult<usize> {
+    let mut ret: usize = 0;
+    let mut shift = 0;
+    loop {
+        let mut byte = [0_u8];
+        r.read_exact(&mut byte)?;
+        let low_bits = (byte[0] & 0x7F) as usize;
+        if shift >= usize::MAX.count_ones() || (low_bits << shift) >> shift != low_bits {
+            return Err(BinaryError::InvalidVarint);
+        }
+        ret |= low_bits << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(ret);
+        }
+        shift += 7;
+    }
+}
+/// Reads a layer, stored as an unsigned varint.
+fn read_layer(r: &mut impl Read) -> BinaryResult<Layer> {
+    match read_varint(r)? {
+        l if l <= MAX_LAYER => Ok(Layer(l as u32)),
+        _ => Err(BinaryError::InvalidContent),
+    }
+}
+
+/// Writes a signed integer as its length in bytes followed by its
+/// little-endian two's complement representation.
+fn write_bigint(w: &mut impl Write, n: &BigInt) -> io::Result<()> {
+    let bytes = if n.is_zero() {
+        vec![]
+    } else {
+        n.to_signed_bytes_le()
+    };
+    write_varint(w, bytes.len())?;
+    w.write_all(&bytes)
+}
+/// Reads a signed integer written by `write_bigint()`.
+fn read_bigint(r: &mut impl Read) -> BinaryResult<BigInt> {
+    let len = read_varint(r)?;
+    let bytes = read_bytes(r, len)?;
+    Ok(BigInt::from_signed_bytes_le(&bytes))
+}
+
+/// Writes a string as its length in bytes followed by its UTF-8
+/// representation.
+fn write_string(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_varint(w, s.len())?;
+    w.write_all(s.as_bytes())
+}
+/// Reads a string written by `write_string()`.
+fn read_string(r: &mut impl Read) -> BinaryResult<String> {
+    let len = read_varint(r)?;
+    read_utf8(r, len)
+}
+/// Reads a UTF-8 string with a known length in bytes.
+fn read_utf8(r: &mut impl Read, len: usize) -> BinaryResult<String> {
+    String::from_utf8(read_bytes(r, len)?).map_err(|_| BinaryError::InvalidUtf8)
+}
+/// Reads a known number of bytes, without trusting `len` enough to allocate
+/// it all upfront.
+fn read_bytes(r: &mut impl Read, len: usize) -> BinaryResult<Vec<u8>> {
+    let mut ret = vec![];
+    r.take(len as u64).read_to_end(&mut ret)?;
+    if ret.len() == len {
+        Ok(ret)
+    } else {
+        Err(BinaryError::UnexpectedEnd)
+    }
+}
+
+/// Characters used by the standard base64 alphabet, in order.
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes using standard base64 with padding.
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0_u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+/// Decodes standard base64, with or without padding. Returns `None` if the
+/// input contains any other characters or has an impossible length.
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s
+        .strip_suffix("==")
+        .or_else(|| s.strip_suffix('='))
+        .unwrap_or(s);
+    if s.len() % 4 == 1 {
+        return None;
+    }
+    let mut ret = Vec::with_capacity(s.len() / 4 * 3 + 2);
+    for chunk in s.as_bytes().chunks(4) {
+        let mut n = 0_u32;
+        for (i, &ch) in chunk.iter().enumerate() {
+            let digit = BASE64_CHARS.iter().position(|&c| c == ch)? as u32;
+            n |= digit << (18 - 6 * i);
+        }
+        ret.extend_from_slice(&n.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,189 @@
+use super::*;
+use crate::prelude::*;
+
+fn glider() -> NdTree2D {
+    let mut ndtree = NdTree::new();
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        ndtree.set_cell(&NdVec::big(pos), 1_u8);
+    }
+    ndtree
+}
+
+/// Asserts that two trees (possibly using different node pools) contain the
+/// same cells.
+fn assert_same_cells<D: Dim>(expected: &NdTree<D>, actual: &NdTree<D>) {
+    let cells = |ndtree: &NdTree<D>| {
+        ndtree
+            .nonzero_cells()
+            .collect::<std::collections::HashSet<_>>()
+    };
+    assert_eq!(cells(expected), cells(actual));
+}
+
+#[test]
+fn test_binary_2d() {
+    let ndtree = glider();
+    for &compressed in &[true, false] {
+        let binary = Binary::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_rule(Some("B3/S23"))
+            .with_generation(BigInt::from(-12345_i64))
+            .with_comments("#N Glider\n#C The smallest spaceship\n")
+            .with_metadata(vec![("author".to_owned(), "Richard Guy".to_owned())])
+            .with_compression(compressed);
+        let bytes = binary.to_bytes();
+        assert!(bytes.starts_with(MAGIC));
+
+        // The magic number may be split across reads.
+        let small_reader = io::BufReader::with_capacity(1, &bytes[..]);
+        assert_eq!(bytes, Binary::read_from(small_reader).unwrap().to_bytes());
+
+        let binary = Binary::from_bytes(&bytes).unwrap();
+        assert_eq!(compressed && cfg!(feature = "gzip"), binary.is_compressed());
+        assert_eq!(Some("B3/S23"), binary.rule());
+        assert_eq!(BigInt::from(-12345_i64), binary.generation());
+        assert_eq!("#N Glider\n#C The smallest spaceship\n", binary.comments());
+        assert_eq!(
+            &[("author".to_owned(), "Richard Guy".to_owned())],
+            binary.metadata(),
+        );
+        let new_ndtree: NdTree2D = binary.to_ndtree(ndtree.pool().new_ref()).unwrap();
+        assert_eq!(ndtree, new_ndtree);
+        assert!(binary
+            .region::<Dim2D>()
+            .bounding_rect()
+            .unwrap()
+            .contains(&ndtree.bounding_rect().unwrap()));
+    }
+
+    // Export a smaller rectangle.
+    let rect = NdRect::span(NdVec::big([0, -1]), NdVec::big([1, 0]));
+    let s = Binary::from_ndtree_to_string(&ndtree, Some(rect), TwoState::default()).unwrap();
+    let new_ndtree: NdTree2D = Binary::from_string_to_ndtree(&s).unwrap();
+    assert_eq!(3, new_ndtree.root_ref().population().to_usize().unwrap());
+    assert_eq!(0, new_ndtree.get_cell(&NdVec::big([0, 1])));
+    assert_eq!(1, new_ndtree.get_cell(&NdVec::big([1, -1])));
+}
+
+#[test]
+fn test_binary_empty() {
+    let ndtree = NdTree3D::new();
+    let s = Binary::from_ndtree_to_string(&ndtree, None, TwoState::default()).unwrap();
+    let binary: Binary = s.parse().unwrap();
+    assert!(binary.region::<Dim3D>().bounding_rect().is_none());
+    let new_ndtree: NdTree3D = binary.to_ndtree(SharedNodePool::new()).unwrap();
+    assert!(new_ndtree.root_ref().is_empty());
+}
+
+#[test]
+fn test_binary_3d() {
+    let mut ndtree = NdTree3D::new();
+    for (i, &pos) in [[0, 0, 0], [-5, 3, 9], [100, -100, 7], [1, 2, 3]]
+        .iter()
+        .enumerate()
+    {
+        ndtree.set_cell(&NdVec::big(pos), i as u8 + 1);
+    }
+    let s = Binary::from_ndtree_to_string(&ndtree, None, TwoState::default()).unwrap();
+    let new_ndtree: NdTree3D = Binary::from_string_to_ndtree(&s).unwrap();
+    assert_same_cells(&ndtree, &new_ndtree);
+
+    // Dimensionality must match.
+    assert_eq!(
+        Err(BinaryError::BadDimensionality {
+            expected: 2,
+            got: 3
+        }),
+        Binary::from_string_to_ndtree::<Dim2D>(&s),
+    );
+}
+
+#[test]
+fn test_binary_dedup() {
+    // A large periodic pattern contains only a handful of distinct nodes.
+    let mut ndtree = NdTree2D::new();
+    for x in (0..256).step_by(4) {
+        for y in (0..256).step_by(4) {
+            ndtree.set_cell(&NdVec::big([x, y]), 1_u8);
+        }
+    }
+    let binary = Binary::from_ndtree(&ndtree, None)
+        .unwrap()
+        .with_compression(false);
+    assert!(binary.to_bytes().len() < 100);
+
+    let new_ndtree: NdTree2D = Binary::from_bytes(&binary.to_bytes())
+        .unwrap()
+        .to_ndtree(ndtree.pool().new_ref())
+        .unwrap();
+    assert_eq!(ndtree, new_ndtree);
+}
+
+#[test]
+fn test_binary_autodetect() {
+    let ndtree = glider();
+    let binary = Binary::from_ndtree(&ndtree, None).unwrap();
+    let bytes = binary.to_bytes();
+    let s = binary.to_string();
+
+    assert_eq!(CaFormat::Binary, CaFormat::guess_from_prefix(&bytes));
+    assert_eq!(CaFormat::Binary, CaFormat::guess_from_prefix(s.as_bytes()));
+    assert_same_cells(
+        &ndtree,
+        &crate::io::import_ndtree_from_string::<Dim2D>(&s).unwrap(),
+    );
+}
+
+#[test]
+fn test_base64() {
+    for &(bytes, encoded) in &[
+        (&b""[..], ""),
+        (b"f", "Zg=="),
+        (b"fo", "Zm8="),
+        (b"foo", "Zm9v"),
+        (b"foob", "Zm9vYg=="),
+        (b"\xFB\xFF\xBF", "+/+/"),
+    ] {
+        assert_eq!(encoded, base64_encode(bytes));
+        assert_eq!(Some(bytes.to_vec()), base64_decode(encoded));
+        assert_eq!(
+            Some(bytes.to_vec()),
+            base64_decode(encoded.trim_end_matches('=')),
+        );
+    }
+    assert_eq!(None, base64_decode("Zm9vY"));
+    assert_eq!(None, base64_decode("Zm9v!"));
+    assert_eq!(None, base64_decode("Zg=g"));
+}
+
+#[test]
+fn test_binary_errors() {
+    assert_eq!(
+        BinaryError::MissingHeader,
+        Binary::from_str("QUJDRA==").unwrap_err(),
+    );
+    assert_eq!(
+        BinaryError::InvalidBase64,
+        Binary::from_str("not base64!").unwrap_err(),
+    );
+    assert_eq!(
+        BinaryError::UnsupportedVersion(99),
+        Binary::from_bytes(b"NDCB\x63\x00").unwrap_err(),
+    );
+
+    let bytes = Binary::from_ndtree(&glider(), None)
+        .unwrap()
+        .with_compression(false)
+        .to_bytes();
+    assert_eq!(
+        BinaryError::UnexpectedEnd,
+        Binary::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+    );
+
+    // Layer-2 root node referencing node #5, which does not exist.
+    let bytes = b"NDCB\x01\x00\x02\x00\x00\x00\x00\x00\x00\x02\x01\x02\x05\x00\x00\x00";
+    assert_eq!(
+        BinaryError::InvalidNodeIndex(5),
+        Binary::from_bytes(bytes).unwrap_err(),
+    );
+}
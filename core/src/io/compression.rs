@@ -0,0 +1,467 @@
+//! Transparent compression and decompression of serialized patterns.
+//!
+//! Compressed input is detected from its first few bytes, so any format can be
+//! read from a gzip (`.gz`) or Zstandard (`.zst`) file without the caller
+//! needing to know in advance whether it is compressed.
+//!
+//! Each compression method requires a cargo feature: `gzip` (enabled by
+//! default) or `zstd` (disabled by default, since it builds a C library).
+//! Compressed input is still detected when its feature is disabled, but
+//! reading or writing it returns an error.
+//!
+//! Zip archives (`.zip`) are not supported because they may contain many
+//! files; the pattern must be extracted first. They are detected so that the
+//! error says so instead of failing to guess the format.
+
+#[cfg(feature = "gzip")]
+use flate2::bufread::MultiGzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+use std::path::Path;
+
+use super::utils::{read_prefix, PrefixedReader};
+
+/// Magic bytes at the beginning of a gzip stream.
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+/// Magic bytes at the beginning of a Zstandard frame.
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+/// Magic bytes at the beginning of a zip archive.
+const ZIP_MAGIC: &[u8] = &[0x50, 0x4B, 0x03, 0x04];
+/// Number of bytes needed by `Compression::detect()` and `check_not_zip()`.
+const MAGIC_LEN: usize = 4;
+
+/// Compression applied to a serialized pattern.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Compression {
+    /// No compression.
+    None,
+    /// gzip compression (`.gz`), which requires the `gzip` feature.
+    Gzip,
+    /// Zstandard compression (`.zst`), which requires the `zstd` feature.
+    Zstd,
+}
+impl Default for Compression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "Zstandard"),
+        }
+    }
+}
+impl Compression {
+    /// List of all compression methods, including those whose features are
+    /// disabled.
+    pub const ALL: &'static [Self] = &[Self::None, Self::Gzip, Self::Zstd];
+
+    /// Returns whether the cargo feature required by the compression method
+    /// is enabled.
+    pub fn is_supported(self) -> bool {
+        match self {
+            Self::None => true,
+            Self::Gzip => cfg!(feature = "gzip"),
+            Self::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+    /// Returns the error for a compression method whose feature is disabled.
+    fn unsupported_error(self) -> io::Error {
+        let feature = match self {
+            Self::None => "",
+            Self::Gzip => "gzip",
+            Self::Zstd => "zstd",
+        };
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} compression requires the `{}` feature of ndcell_core",
+                self, feature,
+            ),
+        )
+    }
+
+    /// Detects the compression of a stream from its first few bytes.
+    ///
+    /// Four bytes are enough to distinguish all compression methods.
+    pub fn detect(prefix: &[u8]) -> Self {
+        if prefix.starts_with(GZIP_MAGIC) {
+            Self::Gzip
+        } else if prefix.starts_with(ZSTD_MAGIC) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+    /// Returns the compression indicated by the extension of a file path,
+    /// such as `glider.rle.gz`.
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        match path.as_ref().extension().and_then(|ext| ext.to_str()) {
+            Some("gz") => Self::Gzip,
+            Some("zst") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+    /// Returns the file extension conventionally used for the compression
+    /// method, not including the leading `.`.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gz"),
+            Self::Zstd => Some("zst"),
+        }
+    }
+
+    /// Wraps a writer so that everything written to it is compressed.
+    /// `CompressedWriter::finish()` must be called to finish the stream.
+    ///
+    /// Returns an error if the feature required by the compression method is
+    /// disabled.
+    pub fn compress_writer<W: Write>(self, writer: W) -> io::Result<CompressedWriter<W>> {
+        Ok(match self {
+            Self::None => CompressedWriter::None(writer),
+            #[cfg(feature = "gzip")]
+            Self::Gzip => {
+                CompressedWriter::Gzip(GzEncoder::new(writer, flate2::Compression::default()))
+            }
+            #[cfg(feature = "zstd")]
+            Self::Zstd => {
+                CompressedWriter::Zstd(zstd::Encoder::new(writer, zstd::DEFAULT_COMPRESSION_LEVEL)?)
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(self.unsupported_error()),
+        })
+    }
+    /// Compresses a byte slice.
+    pub fn compress(self, bytes: &[u8]) -> io::Result<Vec<u8>> {
+        let mut writer = self.compress_writer(vec![])?;
+        writer.write_all(bytes)?;
+        writer.finish()
+    }
+}
+
+/// Returns an error if `prefix` is the beginning of a zip archive, which is
+/// not supported.
+pub(super) fn check_not_zip(prefix: &[u8]) -> io::Result<()> {
+    if prefix.starts_with(ZIP_MAGIC) {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Zip archives are not supported; extract the pattern first",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reader that decompresses its input if necessary, returned by
+/// `decompress_reader()`.
+///
+/// Each variant contains the bytes read to detect the compression, chained
+/// with the rest of the input.
+#[allow(missing_docs)]
+pub enum DecompressedReader<R: BufRead> {
+    None(PrefixedReader<R>),
+    #[cfg(feature = "gzip")]
+    Gzip(io::BufReader<MultiGzDecoder<PrefixedReader<R>>>),
+    #[cfg(feature = "zstd")]
+    Zstd(io::BufReader<zstd::Decoder<'static, PrefixedReader<R>>>),
+}
+impl<R: BufRead> fmt::Debug for DecompressedReader<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DecompressedReader({})", self.compression())
+    }
+}
+impl<R: BufRead> DecompressedReader<R> {
+    /// Returns the compression that was detected.
+    pub fn compression(&self) -> Compression {
+        match self {
+            Self::None(_) => Compression::None,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(_) => Compression::Gzip,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => Compression::Zstd,
+        }
+    }
+}
+impl<R: BufRead> Read for DecompressedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::None(r) => r.read(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.read(buf),
+        }
+    }
+}
+impl<R: BufRead> BufRead for DecompressedReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        match self {
+            Self::None(r) => r.fill_buf(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.fill_buf(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.fill_buf(),
+        }
+    }
+    fn consume(&mut self, amt: usize) {
+        match self {
+            Self::None(r) => r.consume(amt),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(r) => r.consume(amt),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(r) => r.consume(amt),
+        }
+    }
+}
+
+/// Wraps a reader so that its contents are decompressed, detecting the
+/// compression from the first few bytes using `Compression::detect()`.
+/// Uncompressed input is passed through unchanged.
+///
+/// Returns an error if the input is a zip archive or if the feature required
+/// by its compression method is disabled.
+pub fn decompress_reader<R: BufRead>(reader: R) -> io::Result<DecompressedReader<R>> {
+    let reader = read_prefix(reader, MAGIC_LEN, |prefix| prefix.len() >= MAGIC_LEN)?;
+    let prefix = reader.get_ref().0.get_ref();
+    check_not_zip(prefix)?;
+    let compression = Compression::detect(prefix);
+    Ok(match compression {
+        Compression::None => DecompressedReader::None(reader),
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => {
+            DecompressedReader::Gzip(io::BufReader::new(MultiGzDecoder::new(reader)))
+        }
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => {
+            DecompressedReader::Zstd(io::BufReader::new(zstd::Decoder::with_buffer(reader)?))
+        }
+        #[allow(unreachable_patterns)]
+        _ => return Err(compression.unsupported_error()),
+    })
+}
+
+/// Writer that compresses its output, returned by
+/// `Compression::compress_writer()`.
+#[allow(missing_docs)]
+pub enum CompressedWriter<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+impl<W: Write> fmt::Debug for CompressedWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CompressedWriter({})", self.compression())
+    }
+}
+impl<W: Write> CompressedWriter<W> {
+    /// Returns the compression used by the writer.
+    pub fn compression(&self) -> Compression {
+        match self {
+            Self::None(_) => Compression::None,
+            #[cfg(feature = "gzip")]
+            Self::Gzip(_) => Compression::Gzip,
+            #[cfg(feature = "zstd")]
+            Self::Zstd(_) => Compression::Zstd,
+        }
+    }
+    /// Finishes the compressed stream and returns the underlying writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Self::None(mut w) => {
+                w.flush()?;
+                Ok(w)
+            }
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.finish(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.finish(),
+        }
+    }
+}
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::None(w) => w.write(buf),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.write(buf),
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::None(w) => w.flush(),
+            #[cfg(feature = "gzip")]
+            Self::Gzip(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            Self::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the compression methods whose features are enabled.
+    fn supported() -> Vec<Compression> {
+        Compression::ALL
+            .iter()
+            .copied()
+            .filter(|c| c.is_supported())
+            .collect()
+    }
+
+    #[test]
+    fn test_compression() {
+        let data = "x = 3, y = 3, rule = B3/S23\nbo$2bo$3o!\n".repeat(100);
+        for compression in supported() {
+            let compressed = compression.compress(data.as_bytes()).unwrap();
+            assert_eq!(compression, Compression::detect(&compressed));
+            if compression != Compression::None {
+                assert!(compressed.len() < data.len() / 10);
+            }
+
+            let mut reader = decompress_reader(&compressed[..]).unwrap();
+            assert_eq!(compression, reader.compression());
+            let mut decompressed = String::new();
+            reader.read_to_string(&mut decompressed).unwrap();
+            assert_eq!(data, decompressed);
+
+            // The magic bytes may be split across reads.
+            let small_reader = io::BufReader::with_capacity(1, &compressed[..]);
+            let mut reader = decompress_reader(small_reader).unwrap();
+            assert_eq!(compression, reader.compression());
+            let mut decompressed = String::new();
+            reader.read_to_string(&mut decompressed).unwrap();
+            assert_eq!(data, decompressed);
+        }
+
+        // Compression methods whose features are disabled are still detected.
+        for &(compression, magic) in &[
+            (Compression::Gzip, GZIP_MAGIC),
+            (Compression::Zstd, ZSTD_MAGIC),
+        ] {
+            if !compression.is_supported() {
+                assert!(compression.compress(data.as_bytes()).is_err());
+                assert!(decompress_reader(magic).is_err());
+            }
+        }
+
+        // Zip archives are rejected.
+        let zip = b"PK\x03\x04\x14\x00";
+        let e = decompress_reader(&zip[..]).unwrap_err();
+        assert!(e.to_string().contains("Zip archives are not supported"));
+        let e = crate::io::import_ndtree_from_bytes::<crate::dim::Dim2D>(zip).unwrap_err();
+        assert!(e.to_string().contains("Zip archives are not supported"));
+
+        assert_eq!(Compression::Gzip, Compression::from_path("glider.rle.gz"));
+        assert_eq!(Compression::Zstd, Compression::from_path("glider.mc.zst"));
+        assert_eq!(Compression::None, Compression::from_path("glider.rle"));
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_import_compressed() {
+        use crate::io::{
+            export_ndtree_to_string, import_ndtree_from_bytes, import_ndtree_from_reader,
+        };
+        use crate::prelude::*;
+
+        let mut ndtree = NdTree2D::new();
+        for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+            ndtree.set_cell(&NdVec::big(pos), 1_u8);
+        }
+        let cells = |ndtree: &NdTree2D| ndtree.nonzero_cells().collect::<Vec<_>>();
+
+        for &format in &[CaFormat::Rle, CaFormat::Macrocell] {
+            let s = export_ndtree_to_string(&ndtree, format, TwoState::TwoStates, None).unwrap();
+            for compression in supported() {
+                let bytes = compression.compress(s.as_bytes()).unwrap();
+                // Format detection works on the decompressed stream.
+                let imported: NdTree2D = import_ndtree_from_reader(&bytes[..]).unwrap();
+                assert_eq!(cells(&ndtree), cells(&imported));
+                let imported: NdTree2D = import_ndtree_from_bytes(&bytes).unwrap();
+                assert_eq!(cells(&ndtree), cells(&imported));
+            }
+        }
+
+        // Raw binary patterns are not valid UTF-8.
+        let bytes = Binary::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_compression(false)
+            .to_bytes();
+        let bytes = Compression::Gzip.compress(&bytes).unwrap();
+        let imported: NdTree2D = import_ndtree_from_bytes(&bytes).unwrap();
+        assert_eq!(cells(&ndtree), cells(&imported));
+
+        // Truncated input is an error.
+        let bytes = Compression::Gzip.compress(b"x = 1, y = 1\no!").unwrap();
+        assert!(import_ndtree_from_bytes::<Dim2D>(&bytes[..bytes.len() - 4]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "gzip")]
+    fn test_export_compressed() {
+        use crate::io::{
+            export_ndautomaton_to_writer, export_ndtree_to_string, export_ndtree_to_writer,
+            import_ndtree_from_bytes,
+        };
+        use crate::prelude::*;
+
+        let mut ndtree = NdTree2D::new();
+        for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+            ndtree.set_cell(&NdVec::big(pos), 1_u8);
+        }
+        let cells = |ndtree: &NdTree2D| ndtree.nonzero_cells().collect::<Vec<_>>();
+
+        for &format in &[CaFormat::Rle, CaFormat::Macrocell] {
+            let s = export_ndtree_to_string(&ndtree, format, TwoState::TwoStates, None).unwrap();
+            for compression in supported() {
+                let mut bytes = vec![];
+                export_ndtree_to_writer(
+                    &ndtree,
+                    format,
+                    TwoState::TwoStates,
+                    None,
+                    compression,
+                    &mut bytes,
+                )
+                .unwrap();
+                assert_eq!(compression, Compression::detect(&bytes));
+                let mut decompressed = String::new();
+                decompress_reader(&bytes[..])
+                    .unwrap()
+                    .read_to_string(&mut decompressed)
+                    .unwrap();
+                assert_eq!(s, decompressed);
+                let imported: NdTree2D = import_ndtree_from_bytes(&bytes).unwrap();
+                assert_eq!(cells(&ndtree), cells(&imported));
+            }
+        }
+
+        let mut automaton = NdAutomaton::default();
+        automaton.ndtree = ndtree.clone();
+        automaton.set_rule(crate::sim::rule::LIFE);
+        let mut bytes = vec![];
+        export_ndautomaton_to_writer(
+            &automaton,
+            CaFormat::Rle,
+            None,
+            Compression::Gzip,
+            &mut bytes,
+        )
+        .unwrap();
+        assert_eq!(Compression::Gzip, Compression::detect(&bytes));
+        let imported: NdTree2D = import_ndtree_from_bytes(&bytes).unwrap();
+        assert_eq!(cells(&ndtree), cells(&imported));
+    }
+}
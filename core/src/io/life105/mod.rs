@@ -0,0 +1,430 @@
+//! ["Life 1.05" format](https://www.conwaylife.com/wiki/Life_1.05)
+//!
+//! The first line is the header `#Life 1.05`. After that, `#D` lines are
+//! comments, `#N` indicates Conway's Game of Life, and `#R` gives a rule in
+//! `survival/birth` notation. Cells are given in blocks of rows, where `.` is a
+//! dead cell and `*` is a live cell, and each block begins with a `#P` line
+//! giving the position of its top left corner, with Y increasing downwards.
+//! This format only supports 2D patterns with two states.
+//!
+//! Comments are stored using the same conventions as RLE: `#D` lines become
+//! `#C` lines.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use super::utils::ndtree_rows_2d;
+use super::{CaFormatTrait, TextSpan, TwoState};
+use crate::axis::{X, Y};
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndtree::{NdTree, Region, SharedNodePool};
+use crate::ndvec::BigVec;
+use crate::num::{BigInt, Zero};
+
+/// Header at the beginning of every Life 1.05 file.
+const LIFE_105_HEADER: &str = "#Life 1.05";
+/// Maximum number of cells in each row of a block.
+const MAX_LINE_LEN: usize = 80;
+/// Rule indicated by `#N`.
+const CONWAY_RULE: &str = "B3/S23";
+
+/// Result type returned by fallible Life 1.05 routines.
+pub type Life105Result<T> = Result<T, Life105Error>;
+
+lazy_static::lazy_static! {
+    /// Regex matching a rule in `survival/birth` notation.
+    static ref SB_RULE_REGEX: regex::Regex = regex::Regex::new(r"^(\d*)/(\d*)$").unwrap();
+    /// Regex matching a rule in `Bbirth/Ssurvival` notation.
+    static ref BS_RULE_REGEX: regex::Regex =
+        regex::Regex::new(r"^[Bb](\d*)/?[Ss](\d*)$").unwrap();
+}
+
+/// Error encountered during Life 1.05 import/export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Life105Error {
+    /// The type of error.
+    pub kind: Life105ErrorKind,
+    /// The location of the error in the Life 1.05 file (if any).
+    pub span: Option<TextSpan>,
+}
+impl Life105Error {
+    /// Attaches a span to this error, if it does not already have one.
+    pub fn with_span(mut self, span: TextSpan) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+impl From<Life105ErrorKind> for Life105Error {
+    fn from(kind: Life105ErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+}
+impl From<io::Error> for Life105Error {
+    fn from(e: io::Error) -> Self {
+        Life105ErrorKind::IoError(e.to_string()).into()
+    }
+}
+impl fmt::Display for Life105Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+/// Type of error encountered during Life 1.05 import/export.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Life105ErrorKind {
+    MissingHeader,
+    UnknownSymbol(char),
+    BadPosition,
+    StateOutOfRange(u8),
+    Non2D,
+    TooBig,
+    UnsupportedRule(String),
+    IoError(String),
+}
+impl fmt::Display for Life105ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "Missing Life 1.05 header"),
+            Self::UnknownSymbol(ch) => write!(f, "Unknown symbol: '{}'", ch),
+            Self::BadPosition => write!(f, "Expected two integer coordinates after '#P'"),
+            Self::StateOutOfRange(state) => write!(
+                f,
+                "State out of range: #{}; Life 1.05 only supports two states",
+                state,
+            ),
+            Self::Non2D => write!(f, "Life 1.05 only supports 2D patterns"),
+            Self::TooBig => write!(f, "Pattern is too big; try another format like Macrocell"),
+            Self::UnsupportedRule(rule) => write!(
+                f,
+                "Unsupported rule: {:?}; Life 1.05 only supports rules in B/S notation",
+                rule,
+            ),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+/// Life 1.05 contents.
+#[derive(Debug, Clone)]
+pub struct Life105 {
+    /// Automaton rule.
+    rule: Option<String>,
+    /// Pattern comments.
+    comments: String,
+    /// Blocks of cells.
+    blocks: Vec<Life105Block>,
+}
+
+/// Block of cells in a Life 1.05 file.
+#[derive(Debug, Clone)]
+struct Life105Block {
+    /// Position of the top left corner of the block, using NDCell coordinates
+    /// (meaning Y increases upward).
+    x: BigInt,
+    y: BigInt,
+    /// Rows of cells from top to bottom, each omitting trailing dead cells.
+    rows: Vec<Vec<bool>>,
+}
+impl Life105Block {
+    /// Returns the width of the block.
+    fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+
+/// Displays Life 1.05 contents, omitting the rule if it is not in B/S
+/// notation. Use `write_to()` to get an error for such a rule instead.
+impl fmt::Display for Life105 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", LIFE_105_HEADER)?;
+        for line in self.comments.trim_end().lines() {
+            let comment = line
+                .strip_prefix("#C")
+                .or_else(|| line.strip_prefix("#c"))
+                .or_else(|| line.strip_prefix("#D"))
+                .unwrap_or(line);
+            writeln!(f, "#D {}", comment.trim())?;
+        }
+        if let Some(rule) = &self.rule {
+            if let Some(captures) = BS_RULE_REGEX.captures(rule) {
+                if rule == CONWAY_RULE {
+                    writeln!(f, "#N")?;
+                } else {
+                    writeln!(f, "#R {}/{}", &captures[2], &captures[1])?;
+                }
+            }
+        }
+        for block in &self.blocks {
+            // Y increases downwards in Life 1.05 but upwards in NDCell.
+            writeln!(f, "#P {} {}", block.x, -&block.y)?;
+            for row in &block.rows {
+                if row.is_empty() {
+                    // Blank lines are ignored, so use a dead cell instead.
+                    write!(f, ".")?;
+                }
+                for &cell in row {
+                    write!(f, "{}", if cell { '*' } else { '.' })?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+impl FromStr for Life105 {
+    type Err = Life105Error;
+
+    fn from_str(s: &str) -> Life105Result<Self> {
+        Self::read_from(s.as_bytes())
+    }
+}
+
+impl CaFormatTrait for Life105 {
+    fn write_to(&self, mut writer: impl io::Write, _two_states: TwoState) -> Life105Result<()> {
+        if let Some(rule) = &self.rule {
+            if !BS_RULE_REGEX.is_match(rule) {
+                return Err(Life105ErrorKind::UnsupportedRule(rule.clone()).into());
+            }
+        }
+        write!(writer, "{}", self)?;
+        Ok(())
+    }
+    fn read_from(mut reader: impl io::BufRead) -> Life105Result<Self> {
+        let mut has_header = false;
+        let mut rule = None;
+        let mut comments = String::new();
+        let mut blocks: Vec<Life105Block> = vec![];
+
+        let mut line_buffer = String::new();
+        let mut line_number = 0;
+        while reader.read_line(&mut line_buffer)? != 0 {
+            line_number += 1;
+            let raw_line = line_buffer.trim_end_matches(&['\n', '\r'][..]);
+            let line = raw_line.trim();
+            let line_span = || TextSpan::whole_line(line_number, raw_line);
+
+            if line.is_empty() {
+                // Skip blank lines.
+            } else if !has_header {
+                // The first non-blank line must be the header.
+                if !line.starts_with(LIFE_105_HEADER) {
+                    return Err(
+                        Life105Error::from(Life105ErrorKind::MissingHeader).with_span(line_span())
+                    );
+                }
+                has_header = true;
+            } else if let Some(comment) = line.strip_prefix("#D") {
+                comments.push_str(&format!("#C {}", comment.trim()));
+                comments.push('\n');
+            } else if line.starts_with("#N") {
+                rule = Some(CONWAY_RULE.to_owned());
+            } else if let Some(rule_str) = line.strip_prefix("#R") {
+                let rule_str = rule_str.trim();
+                rule = Some(match SB_RULE_REGEX.captures(rule_str) {
+                    Some(captures) => format!("B{}/S{}", &captures[2], &captures[1]),
+                    None => rule_str.to_owned(),
+                });
+            } else if let Some(pos_str) = line.strip_prefix("#P") {
+                let mut coords = pos_str.split_whitespace().map(|s| s.parse::<BigInt>());
+                match (coords.next(), coords.next(), coords.next()) {
+                    (Some(Ok(x)), Some(Ok(y)), None) => blocks.push(Life105Block {
+                        x,
+                        y: -y,
+                        rows: vec![],
+                    }),
+                    _ => {
+                        return Err(Life105Error::from(Life105ErrorKind::BadPosition)
+                            .with_span(line_span()));
+                    }
+                }
+            } else if line.starts_with('#') {
+                comments.push_str(line);
+                comments.push('\n');
+            } else {
+                if blocks.is_empty() {
+                    // Cells before the first `#P` line start at the origin.
+                    blocks.push(Life105Block {
+                        x: BigInt::zero(),
+                        y: BigInt::zero(),
+                        rows: vec![],
+                    });
+                }
+                let line_start = raw_line.len() - raw_line.trim_start().len();
+                let mut row = Vec::with_capacity(line.len());
+                for (i, ch) in line.char_indices() {
+                    match ch {
+                        '.' => row.push(false),
+                        '*' => row.push(true),
+                        _ => {
+                            let start = line_start + i;
+                            let span =
+                                TextSpan::new(line_number, raw_line, start..start + ch.len_utf8());
+                            return Err(Life105Error::from(Life105ErrorKind::UnknownSymbol(ch))
+                                .with_span(span));
+                        }
+                    }
+                }
+                // Omit trailing dead cells.
+                while row.last() == Some(&false) {
+                    row.pop();
+                }
+                blocks.last_mut().unwrap().rows.push(row);
+            }
+            line_buffer.clear();
+        }
+
+        if !has_header {
+            return Err(Life105ErrorKind::MissingHeader.into());
+        }
+
+        Ok(Self {
+            rule,
+            comments,
+            blocks,
+        })
+    }
+
+    fn rule(&self) -> Option<&str> {
+        self.rule.as_deref()
+    }
+    fn with_rule(mut self, rule: Option<impl ToString>) -> Self {
+        self.rule = rule.map(|r| r.to_string());
+        self
+    }
+
+    fn generation(&self) -> BigInt {
+        BigInt::zero()
+    }
+    fn with_generation(self, _generation: BigInt) -> Self {
+        self
+    }
+
+    fn comments(&self) -> &str {
+        &self.comments
+    }
+    fn comments_mut(&mut self) -> &mut String {
+        &mut self.comments
+    }
+
+    fn region<D: Dim>(&self) -> Region<D> {
+        self.blocks
+            .iter()
+            .filter(|block| block.width() > 0)
+            .map(|block| {
+                let corner = |x: BigInt, y: BigInt| {
+                    BigVec::from_fn(|ax| match ax {
+                        X => x.clone(),
+                        Y => y.clone(),
+                        _ => BigInt::zero(),
+                    })
+                };
+                let top_left = corner(block.x.clone(), block.y.clone());
+                let bottom_right = corner(
+                    &block.x + (block.width() - 1),
+                    &block.y - (block.rows.len() - 1),
+                );
+                BigRect::span(top_left, bottom_right)
+            })
+            .fold(None, |acc: Option<BigRect<D>>, rect| match acc {
+                Some(acc) => Some(BigRect::span_rects(acc, rect)),
+                None => Some(rect),
+            })
+            .map_or(Region::Empty, Region::Rect)
+    }
+
+    fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> Life105Result<NdTree<D>> {
+        if D::NDIM != 2 {
+            return Err(Life105ErrorKind::Non2D.into());
+        }
+        let mut ret = NdTree::with_node_pool(node_pool);
+        for block in &self.blocks {
+            for (dy, row) in block.rows.iter().enumerate() {
+                for (dx, &cell) in row.iter().enumerate() {
+                    if cell {
+                        let pos = BigVec::from_fn(|ax| match ax {
+                            X => &block.x + dx,
+                            _ => &block.y - dy,
+                        });
+                        ret.set_cell(&pos, 1_u8);
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn from_ndtree<D: Dim>(ndtree: &NdTree<D>, rect: Option<BigRect<D>>) -> Life105Result<Self> {
+        if D::NDIM != 2 {
+            return Err(Life105ErrorKind::Non2D.into());
+        }
+        let rect = match rect.or_else(|| ndtree.bounding_rect()) {
+            Some(r) => r,
+            None => {
+                return Ok(Self {
+                    rule: None,
+                    comments: String::new(),
+                    blocks: vec![],
+                })
+            }
+        };
+
+        let rows = ndtree_rows_2d(ndtree, &rect).ok_or(Life105ErrorKind::TooBig)?;
+        for &state in rows.iter().flatten() {
+            if state > 1 {
+                return Err(Life105ErrorKind::StateOutOfRange(state).into());
+            }
+        }
+
+        // Split the pattern into vertical strips so that lines are not too
+        // long, omitting empty rows at the top and bottom of each strip.
+        let width = rows.iter().map(Vec::len).max().unwrap_or(0);
+        let mut blocks = vec![];
+        for strip_start in (0..width).step_by(MAX_LINE_LEN) {
+            let strip_end = std::cmp::min(strip_start + MAX_LINE_LEN, width);
+            let strip_rows: Vec<Vec<bool>> = rows
+                .iter()
+                .map(|row| {
+                    let mut strip_row: Vec<bool> = row
+                        .get(strip_start..std::cmp::min(strip_end, row.len()))
+                        .unwrap_or(&[])
+                        .iter()
+                        .map(|&state| state != 0)
+                        .collect();
+                    while strip_row.last() == Some(&false) {
+                        strip_row.pop();
+                    }
+                    strip_row
+                })
+                .collect();
+            let first = match strip_rows.iter().position(|row| !row.is_empty()) {
+                Some(i) => i,
+                None => continue,
+            };
+            let last = strip_rows.iter().rposition(|row| !row.is_empty()).unwrap();
+            blocks.push(Life105Block {
+                x: &rect.min()[X] + strip_start,
+                y: &rect.max()[Y] - first,
+                rows: strip_rows[first..=last].to_vec(),
+            });
+        }
+
+        Ok(Self {
+            rule: None,
+            comments: String::new(),
+            blocks,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,88 @@
+use super::*;
+use crate::prelude::*;
+
+#[test]
+fn test_life105_2d() {
+    const GLIDER: &str = "\
+#Life 1.05
+#D glider
+#N
+#P -1 -1
+.*
+..*
+***
+";
+    let ndtree: NdTree2D = Life105::from_string_to_ndtree(GLIDER).unwrap();
+    assert_eq!(5, ndtree.root_ref().population().to_usize().unwrap());
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+
+    let life105: Life105 = GLIDER.parse().unwrap();
+    assert_eq!("#C glider\n", life105.comments());
+    assert_eq!(Some("B3/S23"), life105.rule());
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([-1, -1]), NdVec::big([1, 1]))),
+        life105.region::<Dim2D>().bounding_rect(),
+    );
+    assert_eq!(GLIDER, life105.to_string());
+
+    let life105: Life105 = "#Life 1.05\n#R 23/36\n*\n".parse().unwrap();
+    assert_eq!(Some("B36/S23"), life105.rule());
+    let life105 = life105.with_rule(Some("B36/S23"));
+    assert_eq!("#Life 1.05\n#R 23/36\n#P 0 0\n*\n", life105.to_string());
+    let life105 = life105.with_rule(Some("Langtons-Ant"));
+    assert_eq!("#Life 1.05\n#P 0 0\n*\n", life105.to_string());
+    assert_eq!(
+        Life105ErrorKind::UnsupportedRule("Langtons-Ant".to_owned()),
+        life105
+            .write_to(vec![], TwoState::TwoStates)
+            .unwrap_err()
+            .kind,
+    );
+
+    // Multiple blocks
+    let ndtree: NdTree2D =
+        Life105::from_string_to_ndtree("#Life 1.05\n*\n#P 10 5\n.\n.*\n").unwrap();
+    assert_eq!(1, ndtree.get_cell(&NdVec::big([0, 0])));
+    assert_eq!(1, ndtree.get_cell(&NdVec::big([11, -6])));
+    assert_eq!(2, ndtree.root_ref().population().to_usize().unwrap());
+
+    // Wide patterns are split into blocks.
+    let mut ndtree = NdTree2D::default();
+    ndtree.set_cell(&NdVec::big([0, 0]), 1);
+    ndtree.set_cell(&NdVec::big([100, -2]), 1);
+    assert_eq!(
+        "#Life 1.05\n#P 0 0\n*\n#P 80 2\n....................*\n",
+        Life105::from_ndtree_to_string(&ndtree, None, TwoState::TwoStates).unwrap(),
+    );
+}
+
+#[test]
+fn test_life105_errors() {
+    let e = Life105::from_string_to_ndtree::<Dim2D>("*\n").unwrap_err();
+    assert_eq!(Life105ErrorKind::MissingHeader, e.kind);
+
+    let e = Life105::from_string_to_ndtree::<Dim2D>("#Life 1.05\n#P 1\n").unwrap_err();
+    assert_eq!(Life105ErrorKind::BadPosition, e.kind);
+    assert_eq!(Some(2), e.span.map(|span| span.line));
+
+    let e = Life105::from_string_to_ndtree::<Dim2D>("#Life 1.05\n.*\n*x*\n").unwrap_err();
+    assert_eq!(Life105ErrorKind::UnknownSymbol('x'), e.kind);
+    let span = e.span.unwrap();
+    assert_eq!((3, 2), (span.line, span.column));
+
+    let mut ndtree = NdTree2D::default();
+    ndtree.set_cell(&NdVec::big([0, 0]), 2);
+    assert_eq!(
+        Life105ErrorKind::StateOutOfRange(2),
+        Life105::from_ndtree(&ndtree, None).unwrap_err().kind,
+    );
+
+    assert_eq!(
+        Life105ErrorKind::Non2D,
+        Life105::from_string_to_ndtree::<Dim1D>("#Life 1.05\n")
+            .unwrap_err()
+            .kind,
+    );
+}
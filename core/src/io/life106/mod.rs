@@ -0,0 +1,257 @@
+//! ["Life 1.06" format](https://www.conwaylife.com/wiki/Life_1.06)
+//!
+//! The first line is the header `#Life 1.06`, and each line after that
+//! contains the X and Y coordinates of a single live cell, with Y increasing
+//! downwards. Other lines beginning with `#` are treated as comments. This
+//! format only supports 2D patterns with two states.
+
+use itertools::Itertools;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use super::{CaFormatTrait, TextSpan, TwoState};
+use crate::axis::{X, Y};
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndtree::{NdTree, Region, SharedNodePool};
+use crate::ndvec::BigVec;
+use crate::num::{BigInt, Zero};
+
+/// Header at the beginning of every Life 1.06 file.
+const LIFE_106_HEADER: &str = "#Life 1.06";
+
+/// Result type returned by fallible Life 1.06 routines.
+pub type Life106Result<T> = Result<T, Life106Error>;
+
+/// Error encountered during Life 1.06 import/export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Life106Error {
+    /// The type of error.
+    pub kind: Life106ErrorKind,
+    /// The location of the error in the Life 1.06 file (if any).
+    pub span: Option<TextSpan>,
+}
+impl Life106Error {
+    /// Attaches a span to this error, if it does not already have one.
+    pub fn with_span(mut self, span: TextSpan) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+impl From<Life106ErrorKind> for Life106Error {
+    fn from(kind: Life106ErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+}
+impl From<io::Error> for Life106Error {
+    fn from(e: io::Error) -> Self {
+        Life106ErrorKind::IoError(e.to_string()).into()
+    }
+}
+impl fmt::Display for Life106Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+/// Type of error encountered during Life 1.06 import/export.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Life106ErrorKind {
+    MissingHeader,
+    InvalidCell,
+    StateOutOfRange(u8),
+    Non2D,
+    IoError(String),
+}
+impl fmt::Display for Life106ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingHeader => write!(f, "Missing Life 1.06 header"),
+            Self::InvalidCell => write!(f, "Expected two integer coordinates"),
+            Self::StateOutOfRange(state) => write!(
+                f,
+                "State out of range: #{}; Life 1.06 only supports two states",
+                state,
+            ),
+            Self::Non2D => write!(f, "Life 1.06 only supports 2D patterns"),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+/// Life 1.06 contents.
+#[derive(Debug, Clone)]
+pub struct Life106 {
+    /// Pattern comments.
+    comments: String,
+    /// Positions of live cells, using NDCell coordinates (meaning Y increases
+    /// upward).
+    cells: Vec<(BigInt, BigInt)>,
+}
+impl fmt::Display for Life106 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", LIFE_106_HEADER)?;
+        for line in self.comments.trim_end().lines() {
+            if !line.starts_with('#') {
+                write!(f, "#C ")?;
+            }
+            writeln!(f, "{}", line)?;
+        }
+        for (x, y) in &self.cells {
+            writeln!(f, "{} {}", x, -y)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for Life106 {
+    type Err = Life106Error;
+
+    fn from_str(s: &str) -> Life106Result<Self> {
+        Self::read_from(s.as_bytes())
+    }
+}
+
+impl CaFormatTrait for Life106 {
+    fn write_to(&self, mut writer: impl io::Write, _two_states: TwoState) -> Life106Result<()> {
+        write!(writer, "{}", self)?;
+        Ok(())
+    }
+    fn read_from(mut reader: impl io::BufRead) -> Life106Result<Self> {
+        let mut has_header = false;
+        let mut comments = String::new();
+        let mut cells = vec![];
+
+        let mut line_buffer = String::new();
+        let mut line_number = 0;
+        while reader.read_line(&mut line_buffer)? != 0 {
+            line_number += 1;
+            let raw_line = line_buffer.trim_end_matches(&['\n', '\r'][..]);
+            let line = raw_line.trim();
+            let line_span = || TextSpan::whole_line(line_number, raw_line);
+
+            if line.is_empty() {
+                // Skip blank lines.
+            } else if !has_header {
+                // The first non-blank line must be the header.
+                if !line.starts_with(LIFE_106_HEADER) {
+                    return Err(
+                        Life106Error::from(Life106ErrorKind::MissingHeader).with_span(line_span())
+                    );
+                }
+                has_header = true;
+            } else if line.starts_with('#') {
+                comments.push_str(line);
+                comments.push('\n');
+            } else {
+                let (x, y): (BigInt, BigInt) = line
+                    .split_whitespace()
+                    .map(|s| s.parse().ok())
+                    .collect_tuple()
+                    .and_then(|(x, y)| Some((x?, y?)))
+                    .ok_or_else(|| {
+                        Life106Error::from(Life106ErrorKind::InvalidCell).with_span(line_span())
+                    })?;
+                // Y increases downwards in Life 1.06 but upwards in NDCell.
+                cells.push((x, -y));
+            }
+            line_buffer.clear();
+        }
+
+        if !has_header {
+            return Err(Life106ErrorKind::MissingHeader.into());
+        }
+
+        Ok(Self { comments, cells })
+    }
+
+    fn rule(&self) -> Option<&str> {
+        None
+    }
+    fn with_rule(self, _rule: Option<impl ToString>) -> Self {
+        self
+    }
+
+    fn generation(&self) -> BigInt {
+        BigInt::zero()
+    }
+    fn with_generation(self, _generation: BigInt) -> Self {
+        self
+    }
+
+    fn comments(&self) -> &str {
+        &self.comments
+    }
+    fn comments_mut(&mut self) -> &mut String {
+        &mut self.comments
+    }
+
+    fn region<D: Dim>(&self) -> Region<D> {
+        let x_range = self.cells.iter().map(|(x, _)| x).minmax().into_option();
+        let y_range = self.cells.iter().map(|(_, y)| y).minmax().into_option();
+        match x_range.zip(y_range) {
+            Some(((x_min, x_max), (y_min, y_max))) => {
+                let corner = |x: &BigInt, y: &BigInt| {
+                    BigVec::from_fn(|ax| match ax {
+                        X => x.clone(),
+                        Y => y.clone(),
+                        _ => BigInt::zero(),
+                    })
+                };
+                Region::Rect(BigRect::span(corner(x_min, y_min), corner(x_max, y_max)))
+            }
+            None => Region::Empty,
+        }
+    }
+
+    fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> Life106Result<NdTree<D>> {
+        if D::NDIM != 2 {
+            return Err(Life106ErrorKind::Non2D.into());
+        }
+        let mut ret = NdTree::with_node_pool(node_pool);
+        for (x, y) in &self.cells {
+            let pos = BigVec::from_fn(|ax| match ax {
+                X => x.clone(),
+                _ => y.clone(),
+            });
+            ret.set_cell(&pos, 1_u8);
+        }
+        Ok(ret)
+    }
+
+    fn from_ndtree<D: Dim>(ndtree: &NdTree<D>, rect: Option<BigRect<D>>) -> Life106Result<Self> {
+        if D::NDIM != 2 {
+            return Err(Life106ErrorKind::Non2D.into());
+        }
+        let slice = match &rect {
+            Some(r) => ndtree.slice_containing(r),
+            None => ndtree.as_slice(),
+        };
+        let nonzero_cells = match rect {
+            Some(r) => slice.nonzero_cells_in_rect(r),
+            None => slice.nonzero_cells(),
+        };
+        let mut cells = nonzero_cells
+            .map(|(pos, state)| match state {
+                1 => Ok((pos[X].clone(), pos[Y].clone())),
+                _ => Err(Life106ErrorKind::StateOutOfRange(state)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Sort from top to bottom, then left to right.
+        cells.sort_by(|(x1, y1), (x2, y2)| y2.cmp(y1).then(x1.cmp(x2)));
+        Ok(Self {
+            comments: String::new(),
+            cells,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
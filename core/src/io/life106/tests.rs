@@ -0,0 +1,58 @@
+use super::*;
+use crate::prelude::*;
+
+#[test]
+fn test_life106_2d() {
+    const GLIDER: &str = "\
+#Life 1.06
+#C glider
+0 -1
+1 0
+-1 1
+0 1
+1 1
+";
+    let ndtree: NdTree2D = Life106::from_string_to_ndtree(GLIDER).unwrap();
+    assert_eq!(5, ndtree.root_ref().population().to_usize().unwrap());
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+
+    let life106: Life106 = GLIDER.parse().unwrap();
+    assert_eq!("#C glider\n", life106.comments());
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([-1, -1]), NdVec::big([1, 1]))),
+        life106.region::<Dim2D>().bounding_rect(),
+    );
+
+    // Cells are exported from top to bottom and left to right.
+    assert_eq!(
+        GLIDER.replace("#C glider\n", ""),
+        Life106::from_ndtree_to_string(&ndtree, None, TwoState::TwoStates).unwrap(),
+    );
+    let rect = NdRect::span(NdVec::big([0, -1]), NdVec::big([1, 0]));
+    assert_eq!(
+        "#Life 1.06\n1 0\n0 1\n1 1\n",
+        Life106::from_ndtree_to_string(&ndtree, Some(rect), TwoState::TwoStates).unwrap(),
+    );
+}
+
+#[test]
+fn test_life106_errors() {
+    let e = Life106::from_string_to_ndtree::<Dim2D>("0 0\n").unwrap_err();
+    assert_eq!(Life106ErrorKind::MissingHeader, e.kind);
+
+    let e = Life106::from_string_to_ndtree::<Dim2D>("#Life 1.06\n0 0\n1 2 3\n").unwrap_err();
+    assert_eq!(Life106ErrorKind::InvalidCell, e.kind);
+    assert_eq!(Some(3), e.span.map(|span| span.line));
+
+    let e = Life106::from_string_to_ndtree::<Dim2D>("#Life 1.06\n0 x\n").unwrap_err();
+    assert_eq!(Life106ErrorKind::InvalidCell, e.kind);
+
+    assert_eq!(
+        Life106ErrorKind::Non2D,
+        Life106::from_string_to_ndtree::<Dim1D>("#Life 1.06\n")
+            .unwrap_err()
+            .kind,
+    );
+}
@@ -1,11 +1,13 @@
 use itertools::Itertools;
+use std::convert::TryInto;
 use std::fmt;
 use std::str::FromStr;
 
 use super::*;
+use crate::axis::Axis::{self, U, V, W, X, Y, Z};
 use crate::axis::AXES;
 use crate::ndtree::Layer;
-use crate::ndvec::BigVec6D;
+use crate::ndvec::{BigVec6D, UVec6D};
 use crate::num::{BigInt, Zero};
 
 /// Macrocell contents.
@@ -21,6 +23,10 @@ pub struct Macrocell {
     pub(super) offset: BigVec6D,
     /// ND-tree nodes.
     pub(super) nodes: Vec<MacrocellNode>,
+    /// Line number of each node in the text it was read from, used to locate
+    /// errors when converting to an ND-tree. This is empty if the Macrocell
+    /// was not read from text.
+    pub(super) node_lines: Vec<usize>,
     /// Pattern comments, not including offset, generation count, etc.
     pub(super) comments: String,
 }
@@ -47,8 +53,58 @@ impl Macrocell {
         self
     }
 }
-impl fmt::Display for Macrocell {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+
+/// Parses a `#O` line containing the offset of the pattern, returning `None`
+/// if the line is not a `#O` line or contains anything other than integers
+/// (such as the author of the pattern in an RLE-style `#O` comment).
+pub(super) fn parse_offset_line(line: &str) -> Option<BigVec6D> {
+    let values = line
+        .strip_prefix("#O")?
+        .split_ascii_whitespace()
+        .map(|s| s.parse().ok())
+        .collect::<Option<Vec<BigInt>>>()?;
+    if values.len() > AXES.len() {
+        return None;
+    }
+    let mut ret = BigVec6D::origin();
+    for (&ax, axis_offset) in AXES.iter().zip(values) {
+        ret[ax] = axis_offset;
+    }
+    Some(ret)
+}
+
+/// Prefix of a comment line that would otherwise be read as the offset of the
+/// pattern (such as an RLE-style `#O` author made only of numbers). A line
+/// with this prefix is read as a comment starting with `#O` and the rest of
+/// the line.
+pub(super) const ESCAPED_AUTHOR_PREFIX: &str = "#O\\";
+
+/// Escapes a comment line that begins with `#O` so that it is not read as an
+/// offset, returning `None` if the line does not need escaping. Lines that
+/// already begin with `ESCAPED_AUTHOR_PREFIX` are escaped again so that they
+/// round-trip as well.
+pub(super) fn escape_author_line(line: &str) -> Option<String> {
+    let rest = line.strip_prefix("#O")?;
+    if parse_offset_line(line).is_some() || rest.starts_with('\\') {
+        Some(format!("{}{}", ESCAPED_AUTHOR_PREFIX, rest))
+    } else {
+        None
+    }
+}
+
+impl Macrocell {
+    /// Returns the location of the node at `index` in the text that the
+    /// Macrocell was read from, if any. The original text is not stored, so
+    /// the span shows the node as it would be written instead.
+    pub(super) fn node_span(&self, index: usize) -> Option<TextSpan> {
+        let &line_number = self.node_lines.get(index)?;
+        match self.nodes.get(index)? {
+            MacrocellNode::Empty => None,
+            node => Some(TextSpan::whole_line(line_number, &node.to_string())),
+        }
+    }
+
+    fn _fmt(&self, f: &mut fmt::Formatter<'_>, nodes: &[MacrocellNode]) -> fmt::Result {
         // Header
         writeln!(f, "{}", crate::MACROCELL_HEADER)?;
         // Rule name (optional)
@@ -64,8 +120,7 @@ impl fmt::Display for Macrocell {
         if let Some(ndim) = AXES
             .iter()
             .rev()
-            .filter(|&&ax| !self.offset[ax].is_zero())
-            .next()
+            .find(|&&ax| !self.offset[ax].is_zero())
             .map(|&ax| ax as usize + 1)
         {
             write!(f, "#O")?;
@@ -83,91 +138,166 @@ impl fmt::Display for Macrocell {
                 || line.starts_with("#R")
                 // '#G' indicates the generation count (not a valid comment)
                 || line.starts_with("#G")
-                // '#O' indicates the offset (not a valid comment)
-                || line.starts_with("#O");
+                // Golly's timeline and view lines are ignored when importing
+                || is_ignored_line(line);
+            if let Some(escaped) = escape_author_line(line) {
+                // '#O' followed by integers indicates the offset, but it may
+                // also be an author made only of numbers.
+                writeln!(f, "{}", escaped)?;
+                continue;
+            }
             if needs_prefix {
                 write!(f, "# ")?;
             }
             writeln!(f, "{}", line)?;
         }
         // Nodes/contents
-        for node in &self.nodes {
+        for node in nodes {
             match node {
                 MacrocellNode::Empty => (),
-                MacrocellNode::Leaf8x8 { .. } => {
-                    writeln!(f, "Error: cannot generate 8x8 leaf node")?
-                }
-                MacrocellNode::NonLeaf { .. } => writeln!(f, "{}", node)?,
+                _ => writeln!(f, "{}", node)?,
             }
         }
         Ok(())
     }
-}
-impl FromStr for Macrocell {
-    type Err = MacrocellError;
-
-    fn from_str(s: &str) -> MacrocellResult<Self> {
-        let mut lines = s.trim().lines().map(|s| s.trim());
-        {
-            // Check header.
-            let header_line = lines.next().ok_or(MacrocellError::MissingHeader)?;
-            if !header_line.starts_with("[M2]") {
-                return Err(MacrocellError::MissingHeader)?;
-            }
-        }
 
-        let mut rule: Option<String> = None;
-        let mut gen = BigInt::zero();
-        let mut offset = BigVec6D::origin();
-        let mut nodes = vec![MacrocellNode::Empty];
-        let mut comments = String::new();
+    /// Returns the nodes converted to the two-state variant of the format, in
+    /// which nodes of length 8 are written using `.` and `*` and smaller nodes
+    /// are omitted. All nonzero cells are considered live.
+    fn two_state_nodes(&self) -> Vec<MacrocellNode> {
+        let ndim = match self.nodes.iter().find_map(|node| match node {
+            MacrocellNode::NonLeaf { children, .. } => Some(children.len().trailing_zeros()),
+            _ => None,
+        }) {
+            Some(ndim) => ndim as usize,
+            // There are no nodes that need converting.
+            None => return self.nodes.clone(),
+        };
 
-        for line in lines {
-            if line.is_empty() {
-                continue;
-            } else if line.starts_with("#R") {
-                rule = Some(line[2..].trim().to_owned());
-            } else if line.starts_with("#G") {
-                gen = line[2..].trim().parse().unwrap_or(gen);
-            } else if line.starts_with("#O") {
-                let values_iter = line.split_ascii_whitespace().filter_map(|s| s.parse().ok());
-                for (&ax, axis_offset) in AXES.iter().zip(values_iter) {
-                    offset[ax] = axis_offset;
+        let root_index = self.nodes.len() - 1;
+        let mut new_indices = vec![0; self.nodes.len()];
+        let mut ret = vec![MacrocellNode::Empty];
+        for (i, node) in self.nodes.iter().enumerate().skip(1) {
+            let new_node = match node.layer() {
+                Layer(3) => MacrocellNode::Leaf8 {
+                    live_cells: self.leaf8_live_cells(i, Layer(3), UVec6D::origin()),
+                },
+                // Wrap a small root node in a leaf node with the same center.
+                l if l < Layer(3) && i == root_index => {
+                    let offset = UVec6D::from_fn(|ax| match ax as usize {
+                        ax if ax < ndim => 4 - l.child_layer().len().unwrap_or(0),
+                        _ => 0,
+                    });
+                    MacrocellNode::Leaf8 {
+                        live_cells: self.leaf8_live_cells(i, l, offset),
+                    }
+                }
+                l if l < Layer(3) => continue,
+                layer => MacrocellNode::NonLeaf {
+                    layer,
+                    children: match node {
+                        MacrocellNode::NonLeaf { children, .. } => {
+                            children.iter().map(|&child| new_indices[child]).collect()
+                        }
+                        _ => unreachable!("Only non-leaf nodes may be larger than 8 cells"),
+                    },
+                },
+            };
+            new_indices[i] = ret.len();
+            ret.push(new_node);
+        }
+        ret
+    }
+    /// Returns the positions of live cells within the node at `index`, which
+    /// is at `layer`, offset by `offset` within a leaf node of length 8.
+    fn leaf8_live_cells(&self, index: usize, layer: Layer, offset: UVec6D) -> Vec<UVec6D> {
+        let mut ret = vec![];
+        self._leaf8_live_cells(index, layer, offset, &mut ret);
+        ret
+    }
+    fn _leaf8_live_cells(&self, index: usize, layer: Layer, offset: UVec6D, out: &mut Vec<UVec6D>) {
+        // Child `i` of a node is offset along each axis by the corresponding
+        // bit of `i`.
+        let child_offset = |i: usize, child_len: usize| {
+            UVec6D::from_fn(|ax| (i >> ax as usize & 1) * child_len) + offset
+        };
+        match &self.nodes[index] {
+            MacrocellNode::Empty => (),
+            MacrocellNode::Leaf8 { live_cells } => {
+                out.extend(live_cells.iter().map(|&pos| pos + offset));
+            }
+            MacrocellNode::NonLeaf { children, .. } if layer == Layer(1) => out.extend(
+                children
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &state)| state != 0)
+                    .map(|(i, _)| child_offset(i, 1)),
+            ),
+            MacrocellNode::NonLeaf { children, .. } => {
+                let child_layer = layer.child_layer();
+                let child_len = child_layer.len().unwrap();
+                for (i, &child) in children.iter().enumerate() {
+                    if child != 0 {
+                        self._leaf8_live_cells(child, child_layer, child_offset(i, child_len), out);
+                    }
                 }
-            } else if line.starts_with("#") {
-                comments.push_str(line);
-                comments.push('\n');
-            } else {
-                nodes.push(line.parse()?);
             }
         }
+    }
+}
+impl fmt::Display for Macrocell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self._fmt(f, &self.nodes)
+    }
+}
+impl fmt::Binary for Macrocell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self._fmt(f, &self.two_state_nodes())
+    }
+}
+impl FromStr for Macrocell {
+    type Err = MacrocellError;
 
-        Ok(Macrocell {
-            rule,
-            gen,
-            offset,
-            nodes,
-            comments,
-        })
+    fn from_str(s: &str) -> MacrocellResult<Self> {
+        Self::read_from(s.as_bytes())
     }
 }
 
+/// Returns whether a line should be ignored when importing. These lines are
+/// used by Golly for timelines and views, which NDCell does not support.
+pub(super) fn is_ignored_line(line: &str) -> bool {
+    line.starts_with("#FRAME") || line.starts_with("#VIEW")
+}
+
 #[derive(Debug, Clone)]
 pub enum MacrocellNode {
     Empty,
-    Leaf8x8 {
-        bits: [u8; 8],
+    /// Two-state leaf node of length 8, written using `.` and `*`.
+    Leaf8 {
+        /// Positions of live cells, with all axes except X reversed (so that
+        /// the origin is the top left corner).
+        live_cells: Vec<UVec6D>,
     },
     NonLeaf {
         layer: Layer,
         children: Box<[usize]>,
     },
 }
+impl MacrocellNode {
+    /// Returns the layer of the node, or `Layer(0)` if it is empty.
+    pub fn layer(&self) -> Layer {
+        match self {
+            Self::Empty => Layer(0),
+            Self::Leaf8 { .. } => Layer(3),
+            Self::NonLeaf { layer, .. } => *layer,
+        }
+    }
+}
 impl FromStr for MacrocellNode {
     type Err = MacrocellError;
 
     fn from_str(s: &str) -> MacrocellResult<Self> {
-        use MacrocellError::InvalidContent;
+        use MacrocellErrorKind::InvalidContent;
 
         let first_char = s.chars().next().ok_or(InvalidContent)?;
         match first_char {
@@ -187,50 +317,106 @@ impl FromStr for MacrocellNode {
                     .collect::<Result<Vec<usize>, _>>()
                     .map_err(|_| InvalidContent)?
                     .into_boxed_slice();
+                if layer == Layer(1) {
+                    if let Some(&state) = children.iter().find(|&&state| state > u8::MAX as usize) {
+                        return Err(MacrocellErrorKind::StateOutOfRange(
+                            state.try_into().unwrap_or(u32::MAX),
+                        )
+                        .into());
+                    }
+                }
                 Ok(Self::NonLeaf { layer, children })
             }
 
-            '.' | '*' | '$' => {
-                let mut bits = [0_u8; 8];
-
-                let mut y = 0;
-                let mut x = 0;
-
-                for ch in s.chars() {
-                    if x == 8 || y == 8 {
-                        // End of row or square; skip this cell.
-                        continue;
-                    }
+            '.' | '*' | '$' | '/' | '%' => {
+                let mut live_cells = vec![];
+                let mut pos = UVec6D::origin();
 
-                    if ch == '*' {
-                        // Set this cell.
-                        bits[y] |= 1 << x;
-                    }
-                    if ch == '.' || ch == '*' {
-                        // Advance to the next cell.
-                        x += 1;
-                    }
-                    if ch == '$' {
-                        // Advance to the next row.
-                        x = 0;
-                        y += 1;
+                let mut chars = s.chars();
+                while let Some(ch) = chars.next() {
+                    let axis = match ch {
+                        '.' | '*' => X,
+                        '$' => Y,
+                        '/' => Z,
+                        '%' => match chars.next() {
+                            Some('W') => W,
+                            Some('U') => U,
+                            Some('V') => V,
+                            _ => return Err(InvalidContent.into()),
+                        },
+                        _ => return Err(InvalidContent.into()),
+                    };
+                    if axis == X {
+                        // Skip cells beyond the end of the row, like Golly.
+                        if ch == '*' && *pos.max_component() < 8 {
+                            live_cells.push(pos);
+                        }
+                        pos[X] += 1;
+                    } else {
+                        // Advance along the axis and reset all lower axes.
+                        for &ax in &AXES[..axis as usize] {
+                            pos[ax] = 0;
+                        }
+                        pos[axis] += 1;
                     }
                 }
 
-                Ok(Self::Leaf8x8 { bits })
+                Ok(Self::Leaf8 { live_cells })
             }
 
-            _ => Err(InvalidContent),
+            _ => Err(InvalidContent.into()),
         }
     }
 }
 impl fmt::Display for MacrocellNode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::Empty | Self::Leaf8x8 { .. } => Err(fmt::Error),
+            Self::Empty => Err(fmt::Error),
+            Self::Leaf8 { live_cells } => {
+                // Sort cells in the order they are written, with X as the
+                // least significant axis.
+                let mut live_cells = live_cells.clone();
+                live_cells.sort_by(|a, b| {
+                    AXES.iter()
+                        .rev()
+                        .map(|&ax| a[ax].cmp(&b[ax]))
+                        .fold(std::cmp::Ordering::Equal, std::cmp::Ordering::then)
+                });
+                live_cells.dedup();
+
+                let mut cursor = UVec6D::origin();
+                for pos in live_cells {
+                    // Find the most significant axis along which to advance.
+                    if let Some(&axis) = AXES[1..].iter().rev().find(|&&ax| pos[ax] != cursor[ax]) {
+                        write_separators(f, axis, pos[axis] - cursor[axis])?;
+                        for &ax in AXES[1..axis as usize].iter().rev() {
+                            write_separators(f, ax, pos[ax])?;
+                        }
+                        cursor = pos;
+                        cursor[X] = 0;
+                    }
+                    write!(f, "{}*", ".".repeat(pos[X] - cursor[X]))?;
+                    cursor[X] = pos[X] + 1;
+                }
+                // Terminate the last row, like Golly.
+                write!(f, "$")
+            }
             Self::NonLeaf { layer, children } => {
                 write!(f, "{} {}", layer.to_u32(), children.iter().join(" "))
             }
         }
     }
 }
+
+/// Writes `count` separators that advance along `axis` in a leaf node.
+fn write_separators(f: &mut fmt::Formatter<'_>, axis: Axis, count: usize) -> fmt::Result {
+    let separator = match axis {
+        X => "",
+        Y => "$",
+        Z => "/",
+        W => "%W",
+        U => "%U",
+        V => "%V",
+    };
+    write!(f, "{}", separator.repeat(count))
+}
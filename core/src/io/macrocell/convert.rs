@@ -1,15 +1,49 @@
 use itertools::Itertools;
+use std::io;
+use std::sync::Arc;
 
 use super::*;
-use crate::axis::Axis;
+use crate::automaton::{Automaton, NdAutomaton};
+use crate::axis::{Axis, AXES};
 use crate::dim::Dim;
+use crate::io::{PatternMetadata, ResolveRule};
 use crate::ndrect::BigRect;
-use crate::ndtree::{Layer, NdTree, NodeRef, NodeRefTrait, Region, SharedNodePool};
-use crate::ndvec::{BigVec, BigVec6D};
-use crate::num::{BigInt, Zero};
+use crate::ndtree::{
+    Layer, NdTree, NodePool, NodeRef, NodeRefTrait, NonzeroCells, Region, SharedNodePool,
+};
+use crate::ndvec::{BigVec, BigVec6D, UVec6D};
+use crate::num::{BigInt, ToPrimitive, Zero};
+use crate::sim::rule::{NdRule, Rule};
 use crate::HashMap;
 
 impl CaFormatTrait for Macrocell {
+    fn to_string_2_state(&self) -> String {
+        format!("{:b}", self)
+    }
+
+    fn write_to(&self, mut writer: impl io::Write, two_states: TwoState) -> MacrocellResult<()> {
+        match two_states {
+            TwoState::TwoStates => write!(writer, "{:b}", self)?,
+            TwoState::MoreStates => write!(writer, "{}", self)?,
+        }
+        Ok(())
+    }
+    fn read_from(reader: impl io::BufRead) -> MacrocellResult<Self> {
+        let mut parser = MacrocellParser::new(reader);
+        let mut nodes = vec![MacrocellNode::Empty];
+        let mut node_lines = vec![0];
+        parser.read_nodes(|node, line_number| {
+            nodes.push(node);
+            node_lines.push(line_number);
+            Ok(())
+        })?;
+        Ok(Macrocell {
+            nodes,
+            node_lines,
+            ..parser.finish()?
+        })
+    }
+
     fn rule(&self) -> Option<&str> {
         self.rule.as_ref().map(|r| r.as_str())
     }
@@ -39,7 +73,7 @@ impl CaFormatTrait for Macrocell {
 
         let size = match self.nodes.last() {
             None | Some(MacrocellNode::Empty) => return Region::Empty,
-            Some(MacrocellNode::Leaf8x8 { .. }) => 8.into(),
+            Some(MacrocellNode::Leaf8 { .. }) => 8.into(),
             Some(MacrocellNode::NonLeaf { layer, .. }) => layer.big_len(),
         };
 
@@ -49,72 +83,20 @@ impl CaFormatTrait for Macrocell {
     fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> Result<NdTree<D>, Self::Err> {
         let node_pool_access = node_pool.access();
         let mut nodes: Vec<NodeRef<'_, D>> = vec![];
-
-        for macrocell_node in &self.nodes {
-            let new_node = match macrocell_node {
-                // Put a dummy node at index 0 -- we'll handle index 0 specially
-                // anyway.
-                MacrocellNode::Empty => node_pool_access.get_empty_base(),
-
-                MacrocellNode::Leaf8x8 { bits } => {
-                    if D::NDIM != 2 {
-                        return Err(MacrocellError::LeafNodeNon2D);
+        for (i, macrocell_node) in self.nodes.iter().enumerate() {
+            let node =
+                node_from_macrocell(&node_pool_access, &nodes, macrocell_node).map_err(|e| {
+                    match self.node_span(i) {
+                        Some(span) => e.with_span(span),
+                        None => e,
                     }
-                    node_pool_access.get_from_cells(
-                        (0..8)
-                            // Reverse because Macrocell stores cells with
-                            // *decreasing* Y values.
-                            .rev()
-                            .flat_map(|y| (0..8).map(move |x| (x, y)))
-                            .map(|(x, y)| (bits[y] >> x) & 1)
-                            .collect_vec(),
-                    )
-                }
-
-                MacrocellNode::NonLeaf { layer, children } => {
-                    if children.len() != D::BRANCHING_FACTOR {
-                        return Err(MacrocellError::InvalidContent);
-                    }
-                    let children_iter = macrocell_index_iter::<D>().map(|i| children[i]);
-                    match layer {
-                        Layer(0) => return Err(MacrocellError::InvalidContent),
-                        Layer(1) => {
-                            // `as u8` will take the least-significant byte,
-                            // which is fine here.
-                            node_pool_access
-                                .get_from_cells(children_iter.map(|i| i as u8).collect_vec())
-                        }
-                        Layer(_) => node_pool_access.join_nodes(
-                            children_iter
-                                .map(|child_index| match child_index {
-                                    0 => Ok(node_pool_access.get_empty(layer.child_layer())),
-                                    i => {
-                                        let child = nodes
-                                            .get(i)
-                                            .copied()
-                                            .ok_or(MacrocellError::InvalidContent)?;
-                                        if child.layer() == layer.child_layer() {
-                                            Ok(child)
-                                        } else {
-                                            Err(MacrocellError::InvalidContent)
-                                        }
-                                    }
-                                })
-                                .collect::<MacrocellResult<Vec<_>>>()?,
-                        ),
-                    }
-                }
-            };
-
-            nodes.push(new_node);
+                })?;
+            nodes.push(node);
         }
-
-        let offset = BigVec::from_fn(|ax| self.offset[ax].clone());
-        Ok(NdTree::from_node_centered_on(
-            nodes
-                .pop()
-                .unwrap_or_else(|| node_pool_access.get_empty_base()),
-            offset,
+        Ok(ndtree_from_macrocell_nodes(
+            &node_pool_access,
+            nodes,
+            &self.offset,
         ))
     }
 
@@ -122,73 +104,465 @@ impl CaFormatTrait for Macrocell {
         ndtree: &NdTree<D>,
         rect: Option<BigRect<D>>,
     ) -> Result<Self, Self::Err> {
-        let mut offset = BigVec6D::origin();
-        let center = ndtree.center_pos();
-        for &ax in D::axes() {
-            offset[ax] = center[ax].clone();
-        }
-
-        let mut nodes = vec![MacrocellNode::Empty];
+        let mut ret = macrocell_header_of_ndtree(ndtree);
+        ret.nodes = vec![MacrocellNode::Empty];
         let ndtree = match rect {
             Some(r) => ndtree.get_region(Region::Rect(r)),
             None => ndtree.clone(),
         };
-        add_macrocell_node(
-            &mut nodes,
-            &mut HashMap::default(),
-            ndtree.root_ref().as_ref(),
-        );
+        let root = ndtree.root_ref();
+        MacrocellNodeVisitor::new(TwoState::MoreStates, |node| {
+            ret.nodes.push(node);
+            Ok(())
+        })
+        .visit_root(root.as_ref())?;
+        Ok(ret)
+    }
 
-        Ok(Macrocell {
+    fn from_reader_to_automaton<R: ResolveRule>(
+        reader: impl io::BufRead,
+        resolve_rule: R,
+    ) -> MacrocellResult<Result<Automaton, R::Err>> {
+        // The rule is in the header lines, which come before any nodes.
+        let mut parser = MacrocellParser::new(reader);
+        parser.read_header()?;
+        let rule_name = parser.rule.clone();
+        Ok(match resolve_rule.resolve_rule(rule_name.as_deref()) {
+            Ok(Rule::Rule1D(rule)) => Ok(Automaton::Automaton1D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule2D(rule)) => Ok(Automaton::Automaton2D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule3D(rule)) => Ok(Automaton::Automaton3D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule4D(rule)) => Ok(Automaton::Automaton4D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule5D(rule)) => Ok(Automaton::Automaton5D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule6D(rule)) => Ok(Automaton::Automaton6D(parser.read_ndautomaton(rule)?)),
+            Err(rule_resolution_error) => Err(rule_resolution_error),
+        })
+    }
+    fn from_reader_to_ndautomaton<D: Dim>(
+        reader: impl io::BufRead,
+        rule: Arc<dyn NdRule<D>>,
+    ) -> MacrocellResult<NdAutomaton<D>> {
+        MacrocellParser::new(reader).read_ndautomaton(rule)
+    }
+    fn from_reader_to_ndtree<D: Dim>(reader: impl io::BufRead) -> MacrocellResult<NdTree<D>> {
+        let (_, ndtree) = MacrocellParser::new(reader).read_ndtree(SharedNodePool::new())?;
+        Ok(ndtree)
+    }
+
+    fn from_ndautomaton_to_writer<D: Dim>(
+        automaton: &NdAutomaton<D>,
+        rect: Option<BigRect<D>>,
+        writer: impl io::Write,
+    ) -> MacrocellResult<()> {
+        let header = macrocell_header_of_ndtree(&automaton.ndtree)
+            .with_rule(Some(&automaton.rule))
+            .with_generation(automaton.generations.clone())
+            .with_comments(automaton.metadata.to_comments());
+        let two_states = TwoState::from_rule(&*automaton.rule);
+        write_macrocell(&header, &automaton.ndtree, rect, two_states, writer)
+    }
+    fn from_ndtree_to_writer<D: Dim>(
+        ndtree: &NdTree<D>,
+        rect: Option<BigRect<D>>,
+        two_states: TwoState,
+        writer: impl io::Write,
+    ) -> MacrocellResult<()> {
+        let header = macrocell_header_of_ndtree(ndtree);
+        write_macrocell(&header, ndtree, rect, two_states, writer)
+    }
+}
+
+/// Parser that reads a Macrocell one line at a time, passing each node to a
+/// closure instead of storing them.
+struct MacrocellParser<R> {
+    reader: R,
+    /// Buffer for the current line, reused for every line.
+    line_buffer: String,
+    line_number: usize,
+    /// Whether `line_buffer` contains a node that has not been parsed yet.
+    has_pending_node: bool,
+
+    has_header: bool,
+    rule: Option<String>,
+    gen: BigInt,
+    offset: BigVec6D,
+    comments: String,
+
+    /// Number of nodes parsed so far, including the empty node at index 0.
+    node_count: usize,
+}
+impl<R: io::BufRead> MacrocellParser<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_buffer: String::new(),
+            line_number: 0,
+            has_pending_node: false,
+
+            has_header: false,
             rule: None,
             gen: BigInt::zero(),
-            offset,
-            nodes,
+            offset: BigVec6D::origin(),
             comments: String::new(),
+
+            node_count: 1,
+        }
+    }
+
+    /// Reads lines up to the first node, which is left to be parsed by
+    /// `read_nodes()`.
+    fn read_header(&mut self) -> MacrocellResult<()> {
+        while !self.has_pending_node && self.read_line()? {}
+        if !self.has_header {
+            return Err(MacrocellErrorKind::MissingHeader.into());
+        }
+        Ok(())
+    }
+    /// Reads the rest of the input, calling `on_node` for each node along with
+    /// its line number.
+    fn read_nodes(
+        &mut self,
+        mut on_node: impl FnMut(MacrocellNode, usize) -> MacrocellResult<()>,
+    ) -> MacrocellResult<()> {
+        loop {
+            if self.has_pending_node {
+                self.parse_node(&mut on_node)?;
+            }
+            if !self.read_line()? {
+                return Ok(());
+            }
+        }
+    }
+    /// Reads the rest of the input into an ND-tree, returning it along with the
+    /// Macrocell without any nodes.
+    fn read_ndtree<D: Dim>(
+        mut self,
+        node_pool: SharedNodePool<D>,
+    ) -> MacrocellResult<(Macrocell, NdTree<D>)> {
+        let node_pool_access = node_pool.access();
+        let mut nodes: Vec<NodeRef<'_, D>> = vec![node_pool_access.get_empty_base()];
+        self.read_nodes(|macrocell_node, _| {
+            nodes.push(node_from_macrocell(
+                &node_pool_access,
+                &nodes,
+                &macrocell_node,
+            )?);
+            Ok(())
+        })?;
+        let macrocell = self.finish()?;
+        let ndtree = ndtree_from_macrocell_nodes(&node_pool_access, nodes, &macrocell.offset);
+        Ok((macrocell, ndtree))
+    }
+    /// Reads the rest of the input into an automaton with the given rule.
+    fn read_ndautomaton<D: Dim>(self, rule: Arc<dyn NdRule<D>>) -> MacrocellResult<NdAutomaton<D>> {
+        let (macrocell, ndtree) = self.read_ndtree(SharedNodePool::new())?;
+        Ok(NdAutomaton {
+            ndtree,
+            rule,
+            generations: macrocell.gen,
+            metadata: PatternMetadata::from_comments(&macrocell.comments),
         })
     }
-}
+    /// Returns the Macrocell that has been read, without any nodes.
+    fn finish(self) -> MacrocellResult<Macrocell> {
+        if !self.has_header {
+            return Err(MacrocellErrorKind::MissingHeader.into());
+        }
+        Ok(Macrocell {
+            rule: self.rule,
+            gen: self.gen,
+            offset: self.offset,
+            nodes: vec![],
+            node_lines: vec![],
+            comments: self.comments,
+        })
+    }
+
+    /// Reads the next line, returning `false` if there are no more lines. If
+    /// the line contains a node, it is left in `line_buffer` to be parsed by
+    /// `parse_node()`.
+    fn read_line(&mut self) -> MacrocellResult<bool> {
+        self.line_buffer.clear();
+        if self.reader.read_line(&mut self.line_buffer)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        let line = self.line_buffer.trim();
+        let line_span = || self.line_span();
 
-fn add_macrocell_node<'pool, D: Dim>(
-    node_list: &mut Vec<MacrocellNode>,
-    node_indices: &mut HashMap<NodeRef<'pool, D>, usize>,
-    new_node: NodeRef<'pool, D>,
-) -> usize {
-    // If the node is empty, return sentinel 0.
-    if new_node.is_empty() {
-        return 0;
+        if line.is_empty() {
+            // Skip blank lines.
+        } else if !self.has_header {
+            // The first non-blank line must be the header.
+            if !line.starts_with("[M2]") {
+                return Err(
+                    MacrocellError::from(MacrocellErrorKind::MissingHeader).with_span(line_span())
+                );
+            }
+            self.has_header = true;
+        } else if line.starts_with("[M2]") {
+            return Err(
+                MacrocellError::from(MacrocellErrorKind::DuplicateHeader).with_span(line_span())
+            );
+        } else if is_ignored_line(line) {
+            // Skip Golly timeline and view lines.
+        } else if let Some(rule_name) = line.strip_prefix("#R") {
+            self.rule = Some(rule_name.trim().to_owned());
+        } else if let Some(gen_str) = line.strip_prefix("#G") {
+            self.gen = gen_str.trim().parse().unwrap_or_else(|_| self.gen.clone());
+        } else if let Some(author) = line.strip_prefix(ESCAPED_AUTHOR_PREFIX) {
+            self.comments.push_str("#O");
+            self.comments.push_str(author);
+            self.comments.push('\n');
+        } else if let Some(new_offset) = parse_offset_line(line) {
+            self.offset = new_offset;
+        } else if line.starts_with('#') {
+            self.comments.push_str(line);
+            self.comments.push('\n');
+        } else {
+            self.has_pending_node = true;
+        }
+        Ok(true)
     }
+    /// Parses the node in `line_buffer` and passes it to `on_node`.
+    fn parse_node(
+        &mut self,
+        on_node: &mut impl FnMut(MacrocellNode, usize) -> MacrocellResult<()>,
+    ) -> MacrocellResult<()> {
+        self.has_pending_node = false;
 
-    // If we've already processed the node, return its index.
-    if let Some(&index) = node_indices.get(&new_node) {
-        return index;
+        let node: MacrocellNode = self
+            .line_buffer
+            .trim()
+            .parse()
+            .map_err(|e: MacrocellError| e.with_span(self.line_span()))?;
+        if let MacrocellNode::NonLeaf { layer, children } = &node {
+            // Children of layer-1 nodes are cell states, not indices.
+            if *layer > Layer(1) {
+                if let Some(&i) = children.iter().find(|&&i| i >= self.node_count) {
+                    return Err(
+                        MacrocellError::from(MacrocellErrorKind::InvalidNodeIndex(i))
+                            .with_span(self.line_span()),
+                    );
+                }
+            }
+        }
+        on_node(node, self.line_number).map_err(|e| e.with_span(self.line_span()))?;
+        self.node_count += 1;
+        Ok(())
     }
+    /// Returns a span covering the current line.
+    fn line_span(&self) -> TextSpan {
+        let raw_line = self.line_buffer.trim_end_matches(&['\n', '\r'][..]);
+        TextSpan::whole_line(self.line_number, raw_line)
+    }
+}
+
+/// Converts a Macrocell node to an ND-tree node, given the nodes that have
+/// already been converted.
+fn node_from_macrocell<'pool, D: Dim>(
+    node_pool: &'pool NodePool<D>,
+    nodes: &[NodeRef<'pool, D>],
+    macrocell_node: &MacrocellNode,
+) -> MacrocellResult<NodeRef<'pool, D>> {
+    Ok(match macrocell_node {
+        // Put a dummy node at index 0 -- we'll handle index 0 specially
+        // anyway.
+        MacrocellNode::Empty => node_pool.get_empty_base(),
 
-    let children = match new_node.layer() {
-        Layer(0) => panic!("Cannot create Macrocell from 1x1 node"),
-        Layer(1) => {
-            let cells = new_node.as_leaf().unwrap().cells();
-            macrocell_index_iter::<D>()
-                .map(|i| cells[i] as usize)
-                .collect_vec()
+        MacrocellNode::Leaf8 { live_cells } => {
+            let mut node = node_pool.get_empty(Layer(3));
+            for pos in live_cells {
+                if AXES[D::NDIM..].iter().any(|&ax| pos[ax] != 0) {
+                    return Err(MacrocellErrorKind::LeafNodeTooManyDimensions.into());
+                }
+                // Reverse all axes except X, because Macrocell stores cells
+                // with *decreasing* Y values (and Z values, etc.).
+                let pos = BigVec::from_fn(|ax| match ax {
+                    Axis::X => BigInt::from(pos[ax]),
+                    _ => BigInt::from(7 - pos[ax]),
+                });
+                node = node.set_cell(&pos, 1);
+            }
+            node
         }
-        Layer(_) => {
-            let children = new_node.subdivide().unwrap();
-            macrocell_index_iter::<D>()
-                .map(|i| add_macrocell_node(node_list, node_indices, children[i]))
-                .collect_vec()
+
+        MacrocellNode::NonLeaf { layer, children } => {
+            if children.len() != D::BRANCHING_FACTOR {
+                return Err(MacrocellErrorKind::InvalidContent.into());
+            }
+            let children_iter = macrocell_index_iter::<D>().map(|i| children[i]);
+            match layer {
+                Layer(0) => return Err(MacrocellErrorKind::InvalidContent.into()),
+                Layer(1) => {
+                    // `as u8` will take the least-significant byte, which is
+                    // fine here.
+                    node_pool.get_from_cells(children_iter.map(|i| i as u8).collect_vec())
+                }
+                Layer(_) => node_pool.join_nodes(
+                    children_iter
+                        .map(|child_index| match child_index {
+                            0 => Ok(node_pool.get_empty(layer.child_layer())),
+                            i => {
+                                let child = nodes
+                                    .get(i)
+                                    .copied()
+                                    .ok_or(MacrocellErrorKind::InvalidContent)?;
+                                if child.layer() == layer.child_layer() {
+                                    Ok(child)
+                                } else {
+                                    Err(MacrocellErrorKind::InvalidContent.into())
+                                }
+                            }
+                        })
+                        .collect::<MacrocellResult<Vec<_>>>()?,
+                ),
+            }
         }
+    })
+}
+
+/// Returns an ND-tree whose root is the last of the converted nodes of a
+/// Macrocell, centered on `offset`.
+fn ndtree_from_macrocell_nodes<'pool, D: Dim>(
+    node_pool: &'pool NodePool<D>,
+    mut nodes: Vec<NodeRef<'pool, D>>,
+    offset: &BigVec6D,
+) -> NdTree<D> {
+    let offset = BigVec::from_fn(|ax| offset[ax].clone());
+    NdTree::from_node_centered_on(
+        nodes.pop().unwrap_or_else(|| node_pool.get_empty_base()),
+        offset,
+    )
+}
+
+/// Returns a Macrocell with the headers for an ND-tree, but no nodes.
+fn macrocell_header_of_ndtree<D: Dim>(ndtree: &NdTree<D>) -> Macrocell {
+    let mut offset = BigVec6D::origin();
+    let center = ndtree.center_pos();
+    for &ax in D::axes() {
+        offset[ax] = center[ax].clone();
+    }
+    Macrocell {
+        rule: None,
+        gen: BigInt::zero(),
+        offset,
+        nodes: vec![],
+        node_lines: vec![],
+        comments: String::new(),
+    }
+}
+
+/// Writes a Macrocell with the given headers containing part of an ND-tree.
+fn write_macrocell<D: Dim>(
+    header: &Macrocell,
+    ndtree: &NdTree<D>,
+    rect: Option<BigRect<D>>,
+    two_states: TwoState,
+    mut writer: impl io::Write,
+) -> MacrocellResult<()> {
+    // The header has no nodes, so this only writes the header lines.
+    write!(writer, "{}", header)?;
+    let ndtree = match rect {
+        Some(r) => ndtree.get_region(Region::Rect(r)),
+        None => ndtree.clone(),
     };
+    let root = ndtree.root_ref();
+    MacrocellNodeVisitor::new(two_states, |node| Ok(writeln!(writer, "{}", node)?))
+        .visit_root(root.as_ref())
+}
 
-    let new_index = node_list.len();
-    node_list.push(MacrocellNode::NonLeaf {
-        layer: new_node.layer(),
-        children: children.into_boxed_slice(),
-    });
-    node_indices.insert(new_node, new_index);
+/// Visitor that converts the nodes of an ND-tree to Macrocell nodes, in an
+/// order such that each node comes after its children.
+struct MacrocellNodeVisitor<'pool, D: Dim, F> {
+    node_indices: HashMap<NodeRef<'pool, D>, usize>,
+    node_count: usize,
+    two_states: TwoState,
+    on_node: F,
+}
+impl<'pool, D: Dim, F: FnMut(MacrocellNode) -> MacrocellResult<()>>
+    MacrocellNodeVisitor<'pool, D, F>
+{
+    fn new(two_states: TwoState, on_node: F) -> Self {
+        Self {
+            node_indices: HashMap::default(),
+            // Index 0 is the empty node.
+            node_count: 1,
+            two_states,
+            on_node,
+        }
+    }
 
-    // Return the index where the new node was inserted
-    new_index
+    /// Visits the root node and all of its descendants.
+    fn visit_root(mut self, root: NodeRef<'pool, D>) -> MacrocellResult<()> {
+        let layer = root.layer();
+        if self.two_states == TwoState::TwoStates && layer < Layer(3) && !root.is_empty() {
+            // Wrap a small root node in a leaf node with the same center.
+            let offset = 4 - layer.child_layer().len().unwrap_or(0);
+            let live_cells = leaf8_live_cells(root, offset);
+            (self.on_node)(MacrocellNode::Leaf8 { live_cells })
+        } else {
+            self.visit(root).map(|_| ())
+        }
+    }
+    /// Visits a node and all of its descendants, returning the index of the
+    /// node.
+    fn visit(&mut self, node: NodeRef<'pool, D>) -> MacrocellResult<usize> {
+        // If the node is empty, return sentinel 0.
+        if node.is_empty() {
+            return Ok(0);
+        }
+
+        // If we've already processed the node, return its index.
+        if let Some(&index) = self.node_indices.get(&node) {
+            return Ok(index);
+        }
+
+        let macrocell_node = match node.layer() {
+            Layer(0) => panic!("Cannot create Macrocell from 1x1 node"),
+            Layer(3) if self.two_states == TwoState::TwoStates => MacrocellNode::Leaf8 {
+                live_cells: leaf8_live_cells(node, 0),
+            },
+            Layer(1) => {
+                let cells = node.as_leaf().unwrap().cells();
+                MacrocellNode::NonLeaf {
+                    layer: Layer(1),
+                    children: macrocell_index_iter::<D>()
+                        .map(|i| cells[i] as usize)
+                        .collect(),
+                }
+            }
+            layer => {
+                let children = node.subdivide().unwrap();
+                MacrocellNode::NonLeaf {
+                    layer,
+                    children: macrocell_index_iter::<D>()
+                        .map(|i| self.visit(children[i]))
+                        .collect::<MacrocellResult<_>>()?,
+                }
+            }
+        };
+
+        let index = self.node_count;
+        (self.on_node)(macrocell_node)?;
+        self.node_count += 1;
+        self.node_indices.insert(node, index);
+        Ok(index)
+    }
+}
+
+/// Returns the positions of nonzero cells in a node of length 8 or less,
+/// offset by `offset` along each axis within a leaf node of length 8, with all
+/// axes except X reversed.
+fn leaf8_live_cells<D: Dim>(node: NodeRef<'_, D>, offset: usize) -> Vec<UVec6D> {
+    NonzeroCells::new(node, BigVec::origin(), None)
+        .map(|(pos, _)| {
+            UVec6D::from_fn(|ax| match ax {
+                _ if ax as usize >= D::NDIM => 0,
+                Axis::X => pos[ax].to_usize().unwrap() + offset,
+                _ => 7 - (pos[ax].to_usize().unwrap() + offset),
+            })
+        })
+        .collect()
 }
 
 fn macrocell_index_iter<D: Dim>() -> impl Iterator<Item = usize> {
@@ -6,33 +6,78 @@
 mod components;
 mod convert;
 
-use super::CaFormatTrait;
+use super::{CaFormatTrait, TextSpan, TwoState};
 pub use components::Macrocell;
 use components::*;
 
 /// Result type returned by fallible Macrocell routines.
 pub type MacrocellResult<T> = Result<T, MacrocellError>;
 
-/// Error encountered during Macrocell import.
+/// Error encountered during Macrocell import/export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MacrocellError {
+    /// The type of error.
+    pub kind: MacrocellErrorKind,
+    /// The location of the error in the Macrocell (if any).
+    pub span: Option<TextSpan>,
+}
+impl MacrocellError {
+    /// Attaches a span to this error, if it does not already have one.
+    pub fn with_span(mut self, span: TextSpan) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+impl From<MacrocellErrorKind> for MacrocellError {
+    fn from(kind: MacrocellErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+}
+impl From<std::io::Error> for MacrocellError {
+    fn from(e: std::io::Error) -> Self {
+        MacrocellErrorKind::IoError(e.to_string()).into()
+    }
+}
+impl std::fmt::Display for MacrocellError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+/// Type of error encountered during Macrocell import/export.
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub enum MacrocellError {
+pub enum MacrocellErrorKind {
     StateOutOfRange(u32),
     DuplicateHeader,
     MissingHeader,
     InvalidContent,
-    LeafNodeNon2D,
+    InvalidNodeIndex(usize),
+    LeafNodeTooManyDimensions,
+    IoError(String),
 }
-impl std::fmt::Display for MacrocellError {
+impl std::fmt::Display for MacrocellErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            MacrocellError::StateOutOfRange(state) => write!(f, "State out of range: #{}", state),
-            MacrocellError::DuplicateHeader => write!(f, "Duplicate Macrocell header"),
-            MacrocellError::MissingHeader => write!(f, "Missing Macrocell header"),
-            MacrocellError::InvalidContent => write!(f, "Invalid Macrocell content"),
-            MacrocellError::LeafNodeNon2D => {
-                write!(f, "Non-2D Macrocell contains 2D 8x8 leaf node")
+            MacrocellErrorKind::StateOutOfRange(state) => {
+                write!(f, "State out of range: #{}", state)
+            }
+            MacrocellErrorKind::DuplicateHeader => write!(f, "Duplicate Macrocell header"),
+            MacrocellErrorKind::MissingHeader => write!(f, "Missing Macrocell header"),
+            MacrocellErrorKind::InvalidContent => write!(f, "Invalid Macrocell content"),
+            MacrocellErrorKind::InvalidNodeIndex(i) => {
+                write!(f, "Node #{} is referenced before it is defined", i)
+            }
+            MacrocellErrorKind::LeafNodeTooManyDimensions => {
+                write!(f, "Leaf node has more dimensions than the pattern")
             }
+            MacrocellErrorKind::IoError(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
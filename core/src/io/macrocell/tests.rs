@@ -1,3 +1,4 @@
+use proptest::prelude::*;
 use std::sync::Arc;
 
 use crate::prelude::*;
@@ -163,4 +164,270 @@ obobobobobobo33bobobobobobobobobobobobobobobobo$33b2ob2o3b2ob2o3b2ob2o
             .with_rule(Some("B3/S23"))
             .to_string(),
     );
+    // The two-state variant matches Golly's output.
+    assert_eq!(
+        MACROCELL.replace("[M2] (golly 3.0)", crate::MACROCELL_HEADER),
+        Macrocell::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_rule(Some("B3/S23"))
+            .to_string_2_state(),
+    );
+}
+
+#[test]
+fn test_macrocell_streaming() {
+    let mut ndtree = NdTree3D::new();
+    for i in -100..=100 {
+        ndtree.set_cell(&NdVec::big([i, i * 3 % 17, -i]), (i + 101) as u8);
+    }
+
+    let mut buffer = vec![];
+    Macrocell::from_ndtree_to_writer(&ndtree, None, TwoState::MoreStates, &mut buffer).unwrap();
+    let mc_string = Macrocell::from_ndtree_to_string(&ndtree, None, TwoState::MoreStates).unwrap();
+    assert_eq!(mc_string.as_bytes(), &buffer[..]);
+
+    // The format is detected from the first line.
+    let reader = std::io::BufReader::with_capacity(16, &buffer[..]);
+    let reimported: NdTree3D = crate::io::import_ndtree_from_reader(reader).unwrap();
+    assert_eq!(
+        mc_string,
+        Macrocell::from_ndtree_to_string(&reimported, None, TwoState::MoreStates).unwrap(),
+    );
+
+    let e = Macrocell::from_reader_to_ndtree::<Dim3D>(&b"\n\n1 0 1 0\n"[..]).unwrap_err();
+    assert_eq!(MacrocellErrorKind::MissingHeader, e.kind);
+    assert_eq!(Some(3), e.span.map(|span| span.line));
+}
+
+#[test]
+fn test_macrocell_error_spans() {
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n#R Life\n1 0 1 1 0\n2 1 0 0 2\n")
+        .unwrap_err();
+    assert_eq!(MacrocellErrorKind::InvalidNodeIndex(2), e.kind);
+    let span = e.span.unwrap();
+    assert_eq!((4, 1, 9), (span.line, span.column, span.len));
+    assert_eq!(
+        "Node #2 is referenced before it is defined at line 4, column 1:\n2 1 0 0 2\n^^^^^^^^^",
+        Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n#R Life\n1 0 1 1 0\n2 1 0 0 2\n")
+            .unwrap_err()
+            .to_string(),
+    );
+
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n\n  1 0 ? 1 0\n").unwrap_err();
+    assert_eq!(MacrocellErrorKind::InvalidContent, e.kind);
+    assert_eq!(Some((3, 3)), e.span.map(|span| (span.line, span.column)));
+
+    // Conversion errors are located at the node that caused them, whether or
+    // not the Macrocell is converted while it is being read.
+    let s = "[M2]\n1 0 1 1 0\n\n3 1 0 0 1\n";
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>(s).unwrap_err();
+    assert_eq!(MacrocellErrorKind::InvalidContent, e.kind);
+    assert_eq!(Some(4), e.span.map(|span| span.line));
+    let macrocell: Macrocell = s.parse().unwrap();
+    let e = macrocell
+        .to_ndtree::<Dim2D>(SharedNodePool::new())
+        .unwrap_err();
+    assert_eq!(MacrocellErrorKind::InvalidContent, e.kind);
+    assert_eq!(Some(4), e.span.as_ref().map(|span| span.line));
+    assert_eq!("3 1 0 0 1", e.span.unwrap().snippet);
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n#R Life\n*/*$\n").unwrap_err();
+    assert_eq!(MacrocellErrorKind::LeafNodeTooManyDimensions, e.kind);
+    assert_eq!(Some(3), e.span.map(|span| span.line));
+
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n[M2]\n").unwrap_err();
+    assert_eq!(MacrocellErrorKind::DuplicateHeader, e.kind);
+}
+
+#[test]
+fn test_macrocell_metadata() {
+    const MACROCELL: &str = "\
+[M2] (golly 3.0)
+#R B3/S23
+#O -10 20
+#N Glider
+#O Richard K. Guy, 1969
+#C The smallest spaceship.
+#C [[ ZOOM 16 ]]
+1 0 1 1 1
+";
+    let rule: Arc<dyn NdRule<Dim2D>> = Arc::new(DummyRule);
+    let automaton = Macrocell::from_string_to_ndautomaton(MACROCELL, Arc::clone(&rule)).unwrap();
+    let metadata = &automaton.metadata;
+    assert_eq!(Some("Glider"), metadata.name.as_deref());
+    assert_eq!(Some("Richard K. Guy, 1969"), metadata.author.as_deref());
+    assert_eq!(vec!["The smallest spaceship."], metadata.comments);
+    assert_eq!(vec!["#C [[ ZOOM 16 ]]"], metadata.viewer_scripts);
+    // The author is not mistaken for an offset.
+    assert_eq!(
+        3,
+        automaton.ndtree.root_ref().population().to_usize().unwrap()
+    );
+    assert_eq!(1, automaton.ndtree.get_cell(&NdVec::big([-10, 20])));
+
+    let exported = Macrocell::from_ndautomaton(&automaton, None)
+        .unwrap()
+        .with_rule(Some("B3/S23"))
+        .to_string();
+    assert_eq!(
+        MACROCELL.replace("[M2] (golly 3.0)", crate::MACROCELL_HEADER),
+        exported,
+    );
+    let reimported = Macrocell::from_string_to_ndautomaton(&exported, rule).unwrap();
+    assert_eq!(automaton.metadata, reimported.metadata);
+
+    // An author made only of numbers is escaped so that it is not mistaken
+    // for an offset, and so is a comment that looks like the escaped form.
+    let mut ndtree = NdTree2D::new();
+    ndtree.set_cell(&NdVec::big([3, 4]), 1_u8);
+    for &(comments, escaped) in &[
+        ("#O 1970 2\n", "#O\\ 1970 2\n"),
+        ("#O\n", "#O\\\n"),
+        ("#O\\ 1970\n", "#O\\\\ 1970\n"),
+    ] {
+        let exported = Macrocell::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_comments(comments)
+            .to_string();
+        assert!(exported.contains(escaped));
+        let macrocell: Macrocell = exported.parse().unwrap();
+        assert_eq!(comments, macrocell.comments());
+        let new_ndtree: NdTree2D = macrocell.to_ndtree(SharedNodePool::new()).unwrap();
+        assert_eq!(1, new_ndtree.get_cell(&NdVec::big([3, 4])));
+    }
+}
+
+#[test]
+fn test_macrocell_golly() {
+    // Two-state Golly file with a timeline, which is ignored.
+    const TWO_STATE: &str = "\
+[M2] (golly 4.0)
+#R B3/S23
+#G 17
+#FRAMES 2 0 1
+#FRAME 0 2
+#FRAME 1 2
+$$$$...*$....*$..***$
+4 0 0 1 0
+";
+    let mc: Macrocell = TWO_STATE.parse().unwrap();
+    assert_eq!(Some("B3/S23"), mc.rule());
+    assert_eq!(BigInt::from(17), mc.generation());
+    assert_eq!("", mc.comments());
+    let ndtree: NdTree2D = mc.to_ndtree(SharedNodePool::new()).unwrap();
+    assert_eq!(5, ndtree.root_ref().population().to_usize().unwrap());
+    // The bottom left 8x8 quadrant spans from (-8, -8) to (-1, -1).
+    for &pos in &[[-5, -5], [-4, -6], [-6, -7], [-5, -7], [-4, -7]] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+    assert_eq!(
+        format!(
+            "{}\n#R B3/S23\n#G 17\n$$$$...*$....*$..***$\n4 0 0 1 0\n",
+            crate::MACROCELL_HEADER,
+        ),
+        Macrocell::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_rule(Some("B3/S23"))
+            .with_generation(BigInt::from(17))
+            .to_string_2_state(),
+    );
+
+    // Multistate Golly file.
+    const MULTISTATE: &str = "\
+[M2] (golly 4.0)
+#R Brian's Brain
+1 0 2 1 0
+1 1 0 0 2
+2 1 2 0 1
+";
+    let ndtree: NdTree2D = Macrocell::from_string_to_ndtree(MULTISTATE).unwrap();
+    assert_eq!(6, ndtree.root_ref().population().to_usize().unwrap());
+    assert_eq!(2, ndtree.get_cell(&NdVec::big([-1, 1])));
+    assert_eq!(2, ndtree.get_cell(&NdVec::big([1, 0])));
+    assert_eq!(1, ndtree.get_cell(&NdVec::big([0, -2])));
+    assert_eq!(
+        MULTISTATE.replace("[M2] (golly 4.0)", crate::MACROCELL_HEADER),
+        Macrocell::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_rule(Some("Brian's Brain"))
+            .to_string(),
+    );
+
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>("[M2]\n1 0 256 0 0\n").unwrap_err();
+    assert_eq!(MacrocellErrorKind::StateOutOfRange(256), e.kind);
+}
+
+#[test]
+fn test_macrocell_nd_leaf() {
+    // A 3D leaf node uses `/` to advance along the Z axis.
+    const MACROCELL_3D: &str = "[M2]\n*$.*/..*$\n";
+    let ndtree: NdTree3D = Macrocell::from_string_to_ndtree(MACROCELL_3D).unwrap();
+    assert_eq!(3, ndtree.root_ref().population().to_usize().unwrap());
+    for &pos in &[[-4, 3, 3], [-3, 2, 3], [-2, 3, 2]] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+    assert_eq!(
+        format!("{}\n*$.*/..*$\n", crate::MACROCELL_HEADER),
+        Macrocell::from_ndtree_to_string(&ndtree, None, TwoState::TwoStates).unwrap(),
+    );
+
+    // Leaf nodes may not have more dimensions than the pattern.
+    let e = Macrocell::from_string_to_ndtree::<Dim2D>(MACROCELL_3D).unwrap_err();
+    assert_eq!(MacrocellErrorKind::LeafNodeTooManyDimensions, e.kind);
+    let e = Macrocell::from_string_to_ndtree::<Dim3D>("[M2]\n*%X*\n").unwrap_err();
+    assert_eq!(MacrocellErrorKind::InvalidContent, e.kind);
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    /// Tests that exporting and then importing a pattern preserves it, in
+    /// every dimensionality and in both variants of the format.
+    #[test]
+    fn test_macrocell_round_trip(
+        cells in prop::collection::vec(
+            (prop::array::uniform6(-40_isize..40), 1..=3_u8),
+            0..40,
+        ),
+        center in prop::array::uniform6(-10_isize..10),
+    ) {
+        _test_macrocell_round_trip_nd::<Dim1D>(&cells, center);
+        _test_macrocell_round_trip_nd::<Dim2D>(&cells, center);
+        _test_macrocell_round_trip_nd::<Dim3D>(&cells, center);
+        _test_macrocell_round_trip_nd::<Dim4D>(&cells, center);
+        _test_macrocell_round_trip_nd::<Dim5D>(&cells, center);
+        _test_macrocell_round_trip_nd::<Dim6D>(&cells, center);
+    }
+}
+
+fn _test_macrocell_round_trip_nd<D: Dim>(cells: &[([isize; 6], u8)], center: [isize; 6]) {
+    let to_bigvec = |pos: [isize; 6]| BigVec::<D>::from_fn(|ax| BigInt::from(pos[ax as usize]));
+    let mut ndtree = NdTree::<D>::with_center(to_bigvec(center));
+    let mut two_state_ndtree = NdTree::<D>::with_center(to_bigvec(center));
+    for &(pos, state) in cells {
+        ndtree.set_cell(&to_bigvec(pos), state);
+        two_state_ndtree.set_cell(&to_bigvec(pos), 1);
+    }
+
+    for &(ndtree, two_states) in &[
+        (&ndtree, TwoState::MoreStates),
+        (&two_state_ndtree, TwoState::TwoStates),
+    ] {
+        let s = Macrocell::from_ndtree_to_string(ndtree, None, two_states).unwrap();
+        // Nodes are written as they are visited, but the output is the same as
+        // for a whole `Macrocell`.
+        let macrocell = Macrocell::from_ndtree(ndtree, None).unwrap();
+        match two_states {
+            TwoState::TwoStates => assert_eq!(macrocell.to_string_2_state(), s),
+            TwoState::MoreStates => assert_eq!(macrocell.to_string(), s),
+        }
+        let reimported: NdTree<D> = Macrocell::from_string_to_ndtree(&s).unwrap();
+        assert_eq!(
+            ndtree.root_ref().population(),
+            reimported.root_ref().population(),
+        );
+        for &(pos, _) in cells {
+            let pos = to_bigvec(pos);
+            assert_eq!(ndtree.get_cell(&pos), reimported.get_cell(&pos), "{}", s);
+        }
+    }
 }
@@ -0,0 +1,201 @@
+//! Structured pattern metadata.
+//!
+//! Every format stores comments using the conventions of RLE: each line begins
+//! with `#` followed by a letter indicating its meaning. `PatternMetadata`
+//! splits these lines into fields so that they can be inspected and edited
+//! without parsing the comment string by hand.
+//!
+//! | Line               | Field            |
+//! | ------------------ | ---------------- |
+//! | `#N`               | `name`           |
+//! | `#O`               | `author`         |
+//! | `#C` or `#c`       | `comments`       |
+//! | `#C [[` ... `]]`   | `viewer_scripts` |
+//! | anything else      | `other`          |
+//!
+//! The position and generation count (such as CXRLE `Pos=` and `Gen=`) are not
+//! part of the metadata; they are stored in the position of the `NdTree` and
+//! in `NdAutomaton::generations` instead.
+
+/// Start of a LifeViewer script block.
+const VIEWER_SCRIPT_START: &str = "[[";
+/// End of a LifeViewer script block.
+const VIEWER_SCRIPT_END: &str = "]]";
+
+/// Metadata about a pattern, such as its name and author.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct PatternMetadata {
+    /// Name of the pattern (`#N`).
+    pub name: Option<String>,
+    /// Author of the pattern, and often the date it was discovered (`#O`).
+    pub author: Option<String>,
+    /// Comment lines (`#C`), without the `#C` prefix.
+    pub comments: Vec<String>,
+    /// [LifeViewer](https://conwaylife.com/wiki/LifeViewer) script blocks
+    /// delimited by `[[` and `]]`, each of which may span several lines.
+    /// These are stored verbatim, including the `#C` prefix on each line.
+    pub viewer_scripts: Vec<String>,
+    /// Other `#` lines that are not recognized, stored verbatim.
+    pub other: Vec<String>,
+}
+impl PatternMetadata {
+    /// Parses metadata from a comment string, where each line begins with `#`.
+    ///
+    /// Lines that do not begin with `#` are treated as `#C` comments.
+    pub fn from_comments(s: &str) -> Self {
+        let mut ret = Self::default();
+        // LifeViewer script block that has not yet been closed.
+        let mut viewer_script: Option<String> = None;
+
+        for line in s.lines().map(str::trim_end) {
+            let comment = if line.starts_with('#') {
+                line.strip_prefix("#C").or_else(|| line.strip_prefix("#c"))
+            } else {
+                Some(line)
+            };
+
+            if let Some(script) = &mut viewer_script {
+                script.push('\n');
+                script.push_str(line);
+                if line.contains(VIEWER_SCRIPT_END) {
+                    ret.viewer_scripts.extend(viewer_script.take());
+                }
+            } else if let Some(text) = comment {
+                match text.find(VIEWER_SCRIPT_START) {
+                    Some(i) if !text[i..].contains(VIEWER_SCRIPT_END) => {
+                        viewer_script = Some(line.to_owned());
+                    }
+                    Some(_) => ret.viewer_scripts.push(line.to_owned()),
+                    None => ret
+                        .comments
+                        .push(text.strip_prefix(' ').unwrap_or(text).to_owned()),
+                }
+            } else if let (Some(name), None) = (line.strip_prefix("#N"), &ret.name) {
+                ret.name = Some(name.trim().to_owned());
+            } else if let (Some(author), None) = (line.strip_prefix("#O"), &ret.author) {
+                ret.author = Some(author.trim().to_owned());
+            } else {
+                ret.other.push(line.to_owned());
+            }
+        }
+
+        // Keep unterminated script blocks rather than dropping them.
+        ret.viewer_scripts.extend(viewer_script);
+        ret
+    }
+
+    /// Returns the metadata as a comment string, where each line begins with
+    /// `#` and ends with a newline.
+    ///
+    /// Lines are written in a fixed order: name, author, comments, viewer
+    /// scripts, and then other lines. The relative order of lines within each
+    /// field is preserved, but lines from different fields that were
+    /// interleaved in the original comment string are regrouped, so a round
+    /// trip through `from_comments()` may reorder them.
+    pub fn to_comments(&self) -> String {
+        let mut ret = String::new();
+        let mut push_line = |line: &str| {
+            ret.push_str(line);
+            ret.push('\n');
+        };
+
+        if let Some(name) = &self.name {
+            push_line(&format!("#N {}", name));
+        }
+        if let Some(author) = &self.author {
+            push_line(&format!("#O {}", author));
+        }
+        for comment in &self.comments {
+            match comment.as_str() {
+                "" => push_line("#C"),
+                _ => push_line(&format!("#C {}", comment)),
+            }
+        }
+        for script in &self.viewer_scripts {
+            push_line(script);
+        }
+        for line in &self.other {
+            push_line(line);
+        }
+        ret
+    }
+
+    /// Returns `true` if there is no metadata.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pattern_metadata() {
+        const COMMENTS: &str = "\
+#N Gosper glider gun
+#O Bill Gosper, 1970
+#C The first known gun and the first known finite pattern with unbounded
+#C growth.
+#C
+#C   indented
+#C [[ ZOOM 8 GPS 10 ]]
+#C [[ THEME 6
+#C    AUTOSTART ]]
+#P 0 0
+#N Second name
+";
+        let metadata = PatternMetadata::from_comments(COMMENTS);
+        assert_eq!(Some("Gosper glider gun"), metadata.name.as_deref());
+        assert_eq!(Some("Bill Gosper, 1970"), metadata.author.as_deref());
+        assert_eq!(
+            vec![
+                "The first known gun and the first known finite pattern with unbounded",
+                "growth.",
+                "",
+                "  indented",
+            ],
+            metadata.comments,
+        );
+        assert_eq!(
+            vec![
+                "#C [[ ZOOM 8 GPS 10 ]]",
+                "#C [[ THEME 6\n#C    AUTOSTART ]]",
+            ],
+            metadata.viewer_scripts,
+        );
+        assert_eq!(vec!["#P 0 0", "#N Second name"], metadata.other);
+        assert_eq!(COMMENTS, metadata.to_comments());
+        assert_eq!(
+            metadata,
+            PatternMetadata::from_comments(&metadata.to_comments()),
+        );
+
+        // Lines without `#` are comments.
+        let metadata = PatternMetadata::from_comments("hello\n#c world\n");
+        assert_eq!(vec!["hello", "world"], metadata.comments);
+        assert_eq!("#C hello\n#C world\n", metadata.to_comments());
+
+        // Unterminated script blocks are kept.
+        let metadata = PatternMetadata::from_comments("#C [[ ZOOM 4\n#C GPS 8\n");
+        assert_eq!(vec!["#C [[ ZOOM 4\n#C GPS 8"], metadata.viewer_scripts);
+
+        // Interleaved lines are regrouped by field.
+        let metadata = PatternMetadata::from_comments(
+            "#C first\n#P 0 0\n#C [[ ZOOM 4 ]]\n#C second\n#O someone\n#N name\n",
+        );
+        assert_eq!(
+            "#N name\n#O someone\n#C first\n#C second\n#C [[ ZOOM 4 ]]\n#P 0 0\n",
+            metadata.to_comments(),
+        );
+        assert_eq!(
+            metadata,
+            PatternMetadata::from_comments(&metadata.to_comments()),
+        );
+
+        assert!(PatternMetadata::from_comments("").is_empty());
+        assert_eq!("", PatternMetadata::default().to_comments());
+    }
+}
@@ -1,11 +1,19 @@
 //! Formats for exporting/importing cellular automata.
 
 use std::fmt;
+use std::io;
 use std::str::FromStr;
 use std::sync::Arc;
 
+pub mod binary;
+mod compression;
+pub mod life105;
+pub mod life106;
 pub mod macrocell;
+mod metadata;
+pub mod plaintext;
 pub mod rle;
+mod span;
 mod utils;
 
 use crate::automaton::{Automaton, NdAutomaton};
@@ -14,8 +22,19 @@ use crate::ndrect::BigRect;
 use crate::ndtree::{NdTree, Region, SharedNodePool};
 use crate::num::BigInt;
 use crate::sim::rule::{NdRule, Rule};
-pub use macrocell::{Macrocell, MacrocellError, MacrocellResult};
-pub use rle::{Rle, RleError, RleResult};
+pub use binary::{Binary, BinaryError, BinaryResult};
+pub use compression::{decompress_reader, CompressedWriter, Compression, DecompressedReader};
+pub use life105::{Life105, Life105Error, Life105ErrorKind, Life105Result};
+pub use life106::{Life106, Life106Error, Life106ErrorKind, Life106Result};
+pub use macrocell::{Macrocell, MacrocellError, MacrocellErrorKind, MacrocellResult};
+pub use metadata::PatternMetadata;
+pub use plaintext::{Plaintext, PlaintextError, PlaintextErrorKind, PlaintextResult};
+pub use rle::{Rle, RleError, RleErrorKind, RleResult};
+pub use span::TextSpan;
+
+/// Maximum number of bytes read from the beginning of the input to guess its
+/// format.
+const MAX_GUESS_PREFIX_LEN: usize = 1024;
 
 /// Format that an automaton can be exported to or imported from.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -24,12 +43,68 @@ pub enum CaFormat {
     Rle,
     /// N-dimensional generalization of Golly Macrocell format.
     Macrocell,
+    /// Golly/LifeWiki plaintext format (`.cells`), 2D only.
+    Plaintext,
+    /// Life 1.05 format, 2D only.
+    Life105,
+    /// Life 1.06 format, 2D only.
+    Life106,
+    /// NDCell's compact binary format.
+    Binary,
 }
 impl fmt::Display for CaFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             CaFormat::Rle => write!(f, "RLE"),
             CaFormat::Macrocell => write!(f, "Macrocell"),
+            CaFormat::Plaintext => write!(f, "Plaintext"),
+            CaFormat::Life105 => write!(f, "Life 1.05"),
+            CaFormat::Life106 => write!(f, "Life 1.06"),
+            CaFormat::Binary => write!(f, "NDCell binary"),
+        }
+    }
+}
+
+impl CaFormat {
+    /// All supported formats.
+    pub const ALL: &'static [Self] = &[
+        CaFormat::Rle,
+        CaFormat::Macrocell,
+        CaFormat::Plaintext,
+        CaFormat::Life105,
+        CaFormat::Life106,
+        CaFormat::Binary,
+    ];
+
+    /// Guesses the format of a pattern from the first few bytes of its
+    /// serialized form.
+    ///
+    /// RLE has no mandatory signature, so anything that does not look like
+    /// another format is assumed to be RLE.
+    pub fn guess_from_prefix(prefix: &[u8]) -> Self {
+        let start = prefix
+            .iter()
+            .position(|b| !b.is_ascii_whitespace())
+            .unwrap_or(prefix.len());
+        let prefix = &prefix[start..];
+        let first_line = prefix.split(|&b| b == b'\n').next().unwrap_or(&[]);
+        if prefix.starts_with(binary::MAGIC) || prefix.starts_with(binary::BASE64_MAGIC) {
+            CaFormat::Binary
+        } else if prefix.starts_with(b"[M2]") {
+            CaFormat::Macrocell
+        } else if prefix.starts_with(b"#Life 1.05") {
+            CaFormat::Life105
+        } else if prefix.starts_with(b"#Life 1.06") {
+            CaFormat::Life106
+        } else if prefix.starts_with(b"!")
+            || (!first_line.is_empty()
+                && first_line
+                    .iter()
+                    .all(|b| matches!(b, b'.' | b'O' | b'o' | b'*' | b'\r')))
+        {
+            CaFormat::Plaintext
+        } else {
+            CaFormat::Rle
         }
     }
 }
@@ -40,6 +115,11 @@ impl fmt::Display for CaFormat {
 pub enum CaFormatError {
     RleError(RleError),
     MacrocellError(MacrocellError),
+    PlaintextError(PlaintextError),
+    Life105Error(Life105Error),
+    Life106Error(Life106Error),
+    BinaryError(BinaryError),
+    IoError(String),
 }
 impl From<RleError> for CaFormatError {
     fn from(e: RleError) -> Self {
@@ -51,11 +131,41 @@ impl From<MacrocellError> for CaFormatError {
         Self::MacrocellError(e)
     }
 }
+impl From<PlaintextError> for CaFormatError {
+    fn from(e: PlaintextError) -> Self {
+        Self::PlaintextError(e)
+    }
+}
+impl From<Life105Error> for CaFormatError {
+    fn from(e: Life105Error) -> Self {
+        Self::Life105Error(e)
+    }
+}
+impl From<Life106Error> for CaFormatError {
+    fn from(e: Life106Error) -> Self {
+        Self::Life106Error(e)
+    }
+}
+impl From<BinaryError> for CaFormatError {
+    fn from(e: BinaryError) -> Self {
+        Self::BinaryError(e)
+    }
+}
+impl From<io::Error> for CaFormatError {
+    fn from(e: io::Error) -> Self {
+        Self::IoError(e.to_string())
+    }
+}
 impl fmt::Display for CaFormatError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::RleError(e) => write!(f, "RLE error: {}", e),
             Self::MacrocellError(e) => write!(f, "Macrocell error: {}", e),
+            Self::PlaintextError(e) => write!(f, "Plaintext error: {}", e),
+            Self::Life105Error(e) => write!(f, "Life 1.05 error: {}", e),
+            Self::Life106Error(e) => write!(f, "Life 1.06 error: {}", e),
+            Self::BinaryError(e) => write!(f, "NDCell binary error: {}", e),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
@@ -72,6 +182,18 @@ pub fn export_ndtree_to_string<D: Dim>(
         CaFormat::Macrocell => {
             Macrocell::from_ndtree_to_string(ndtree, rect, two_states).map_err(Into::into)
         }
+        CaFormat::Plaintext => {
+            Plaintext::from_ndtree_to_string(ndtree, rect, two_states).map_err(Into::into)
+        }
+        CaFormat::Life105 => {
+            Life105::from_ndtree_to_string(ndtree, rect, two_states).map_err(Into::into)
+        }
+        CaFormat::Life106 => {
+            Life106::from_ndtree_to_string(ndtree, rect, two_states).map_err(Into::into)
+        }
+        CaFormat::Binary => {
+            Binary::from_ndtree_to_string(ndtree, rect, two_states).map_err(Into::into)
+        }
     }
 }
 /// Exports an automaton to a string using a particular format.
@@ -85,6 +207,16 @@ pub fn export_ndautomaton_to_string<D: Dim>(
         CaFormat::Macrocell => {
             Macrocell::from_ndautomaton_to_string(automaton, rect).map_err(Into::into)
         }
+        CaFormat::Plaintext => {
+            Plaintext::from_ndautomaton_to_string(automaton, rect).map_err(Into::into)
+        }
+        CaFormat::Life105 => {
+            Life105::from_ndautomaton_to_string(automaton, rect).map_err(Into::into)
+        }
+        CaFormat::Life106 => {
+            Life106::from_ndautomaton_to_string(automaton, rect).map_err(Into::into)
+        }
+        CaFormat::Binary => Binary::from_ndautomaton_to_string(automaton, rect).map_err(Into::into),
     }
 }
 /// Exports an automaton of any dimensionality to a string using a particular
@@ -96,7 +228,86 @@ pub fn export_automaton_to_string(
     match format {
         CaFormat::Rle => Rle::from_automaton_to_string(automaton).map_err(Into::into),
         CaFormat::Macrocell => Macrocell::from_automaton_to_string(automaton).map_err(Into::into),
+        CaFormat::Plaintext => Plaintext::from_automaton_to_string(automaton).map_err(Into::into),
+        CaFormat::Life105 => Life105::from_automaton_to_string(automaton).map_err(Into::into),
+        CaFormat::Life106 => Life106::from_automaton_to_string(automaton).map_err(Into::into),
+        CaFormat::Binary => Binary::from_automaton_to_string(automaton).map_err(Into::into),
+    }
+}
+
+/// Exports an ND-tree to a writer using a particular format.
+///
+/// The output is compressed using `compression` and written in many small
+/// pieces, so `writer` should usually be buffered.
+pub fn export_ndtree_to_writer<D: Dim>(
+    ndtree: &NdTree<D>,
+    format: CaFormat,
+    two_states: TwoState,
+    rect: Option<BigRect<D>>,
+    compression: Compression,
+    writer: impl io::Write,
+) -> Result<(), CaFormatError> {
+    let mut writer = compression.compress_writer(writer)?;
+    match format {
+        CaFormat::Rle => Rle::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?,
+        CaFormat::Macrocell => {
+            Macrocell::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?
+        }
+        CaFormat::Plaintext => {
+            Plaintext::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?
+        }
+        CaFormat::Life105 => Life105::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?,
+        CaFormat::Life106 => Life106::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?,
+        CaFormat::Binary => Binary::from_ndtree_to_writer(ndtree, rect, two_states, &mut writer)?,
+    }
+    writer.finish()?;
+    Ok(())
+}
+/// Exports an automaton to a writer using a particular format.
+///
+/// The output is compressed using `compression` and written in many small
+/// pieces, so `writer` should usually be buffered.
+pub fn export_ndautomaton_to_writer<D: Dim>(
+    automaton: &NdAutomaton<D>,
+    format: CaFormat,
+    rect: Option<BigRect<D>>,
+    compression: Compression,
+    writer: impl io::Write,
+) -> Result<(), CaFormatError> {
+    let mut writer = compression.compress_writer(writer)?;
+    match format {
+        CaFormat::Rle => Rle::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+        CaFormat::Macrocell => Macrocell::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+        CaFormat::Plaintext => Plaintext::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+        CaFormat::Life105 => Life105::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+        CaFormat::Life106 => Life106::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+        CaFormat::Binary => Binary::from_ndautomaton_to_writer(automaton, rect, &mut writer)?,
+    }
+    writer.finish()?;
+    Ok(())
+}
+/// Exports an automaton of any dimensionality to a writer using a particular
+/// format.
+///
+/// The output is compressed using `compression` and written in many small
+/// pieces, so `writer` should usually be buffered.
+pub fn export_automaton_to_writer(
+    automaton: &Automaton,
+    format: CaFormat,
+    compression: Compression,
+    writer: impl io::Write,
+) -> Result<(), CaFormatError> {
+    let mut writer = compression.compress_writer(writer)?;
+    match format {
+        CaFormat::Rle => Rle::from_automaton_to_writer(automaton, &mut writer)?,
+        CaFormat::Macrocell => Macrocell::from_automaton_to_writer(automaton, &mut writer)?,
+        CaFormat::Plaintext => Plaintext::from_automaton_to_writer(automaton, &mut writer)?,
+        CaFormat::Life105 => Life105::from_automaton_to_writer(automaton, &mut writer)?,
+        CaFormat::Life106 => Life106::from_automaton_to_writer(automaton, &mut writer)?,
+        CaFormat::Binary => Binary::from_automaton_to_writer(automaton, &mut writer)?,
     }
+    writer.finish()?;
+    Ok(())
 }
 
 /// Helper macro for `try_all_formats!`.
@@ -110,29 +321,205 @@ macro_rules! _try_format {
 }
 /// Lazily evaluates a method with the given arguments for each (hard-coded) CA
 /// format, returning the value from the first one that succeeds (returns
-/// `Ok()`). If all formats fail (return `Err()`), returns a `Vec` of all the
-/// `CaFormatError`s.
+/// `Ok()`). The format guessed from the start of the string is tried first. If
+/// all formats fail (return `Err()`), returns a `CaFormatImportError`
+/// containing all the `CaFormatError`s.
 macro_rules! try_all_formats {
-    ($($token:tt)+) => {
+    ($s:expr; $($token:tt)+) => {
+        let guessed_format = CaFormat::guess_from_prefix($s.as_bytes());
+        let other_formats = CaFormat::ALL.iter().copied().filter(|&f| f != guessed_format);
         let mut errors: Vec<CaFormatError> = vec![];
-        _try_format!(errors; Rle::$($token)+);
-        _try_format!(errors; Macrocell::$($token)+);
-        return Err(errors);
+        for format in std::iter::once(guessed_format).chain(other_formats) {
+            match format {
+                CaFormat::Rle => {
+                    _try_format!(errors; Rle::$($token)+);
+                }
+                CaFormat::Macrocell => {
+                    _try_format!(errors; Macrocell::$($token)+);
+                }
+                CaFormat::Plaintext => {
+                    _try_format!(errors; Plaintext::$($token)+);
+                }
+                CaFormat::Life105 => {
+                    _try_format!(errors; Life105::$($token)+);
+                }
+                CaFormat::Life106 => {
+                    _try_format!(errors; Life106::$($token)+);
+                }
+                CaFormat::Binary => {
+                    _try_format!(errors; Binary::$($token)+);
+                }
+            }
+        }
+        return Err(CaFormatImportError {
+            guessed_format,
+            errors,
+        });
     };
 }
 
+/// Error produced when a pattern cannot be imported using any format.
+#[derive(Debug, Clone)]
+pub struct CaFormatImportError {
+    /// Format that the pattern appears to be in, based on the first few bytes.
+    pub guessed_format: CaFormat,
+    /// Error resulting from each format that was tried, starting with
+    /// `guessed_format`.
+    pub errors: Vec<CaFormatError>,
+}
+impl CaFormatImportError {
+    /// Constructs an error from a single failed attempt to import `bytes`.
+    fn single(bytes: &[u8], error: CaFormatError) -> Self {
+        Self {
+            guessed_format: CaFormat::guess_from_prefix(bytes),
+            errors: vec![error],
+        }
+    }
+    /// Returns the error resulting from `guessed_format`.
+    pub fn guessed_format_error(&self) -> Option<&CaFormatError> {
+        self.errors.first()
+    }
+}
+impl fmt::Display for CaFormatImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to import pattern (looks like {})",
+            self.guessed_format
+        )?;
+        if let Some(e) = self.guessed_format_error() {
+            write!(f, "\n{}", e)?;
+        }
+        Ok(())
+    }
+}
+
 /// Imports an ND-tree from a string using the first format that works,
 /// returning the error resulting from each attempt if none succeeded.
-pub fn import_ndtree_from_string<D: Dim>(s: &str) -> Result<NdTree<D>, Vec<CaFormatError>> {
-    try_all_formats!(from_string_to_ndtree(s));
+pub fn import_ndtree_from_string<D: Dim>(s: &str) -> Result<NdTree<D>, CaFormatImportError> {
+    try_all_formats!(s; from_string_to_ndtree(s));
 }
 /// Imports an automaton from a string using the first format that works,
 /// returning the error resulting from each attempt if none succeeded.
 pub fn import_automaton_from_string<R: ResolveRule + Clone>(
     s: &str,
     resolve_rule: R,
-) -> Result<Result<Automaton, R::Err>, Vec<CaFormatError>> {
-    try_all_formats!(from_string_to_automaton(s, resolve_rule.clone()));
+) -> Result<Result<Automaton, R::Err>, CaFormatImportError> {
+    try_all_formats!(s; from_string_to_automaton(s, resolve_rule.clone()));
+}
+
+/// Imports an ND-tree from a string of bytes, which may be compressed, using
+/// the first format that works.
+///
+/// If the (decompressed) bytes are valid UTF-8, this tries every format like
+/// `import_ndtree_from_string()`; otherwise it only tries the format guessed
+/// from the first bytes.
+pub fn import_ndtree_from_bytes<D: Dim>(bytes: &[u8]) -> Result<NdTree<D>, CaFormatImportError> {
+    let bytes = decompress_bytes(bytes)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(s) => import_ndtree_from_string(s),
+        Err(_) => import_ndtree_from_reader(&bytes[..])
+            .map_err(|e| CaFormatImportError::single(&bytes, e)),
+    }
+}
+/// Imports an automaton from a string of bytes, which may be compressed, using
+/// the first format that works.
+///
+/// If the (decompressed) bytes are valid UTF-8, this tries every format like
+/// `import_automaton_from_string()`; otherwise it only tries the format
+/// guessed from the first bytes.
+pub fn import_automaton_from_bytes<R: ResolveRule + Clone>(
+    bytes: &[u8],
+    resolve_rule: R,
+) -> Result<Result<Automaton, R::Err>, CaFormatImportError> {
+    let bytes = decompress_bytes(bytes)?;
+    match std::str::from_utf8(&bytes) {
+        Ok(s) => import_automaton_from_string(s, resolve_rule),
+        Err(_) => import_automaton_from_reader(&bytes[..], resolve_rule)
+            .map_err(|e| CaFormatImportError::single(&bytes, e)),
+    }
+}
+/// Decompresses a string of bytes, if it is compressed.
+fn decompress_bytes(bytes: &[u8]) -> Result<std::borrow::Cow<'_, [u8]>, CaFormatImportError> {
+    use io::Read;
+
+    compression::check_not_zip(bytes).map_err(|e| CaFormatImportError::single(bytes, e.into()))?;
+    match Compression::detect(bytes) {
+        Compression::None => Ok(bytes.into()),
+        _ => {
+            let mut ret = vec![];
+            decompress_reader(bytes)
+                .and_then(|mut r| r.read_to_end(&mut ret))
+                .map_err(|e| CaFormatImportError::single(bytes, e.into()))?;
+            Ok(ret.into())
+        }
+    }
+}
+
+/// Imports an ND-tree from a reader, guessing the format from the first bytes
+/// of input using `CaFormat::guess_from_prefix()`. Compressed input is
+/// detected and decompressed automatically.
+///
+/// Unlike `import_ndtree_from_string()`, this only tries one format, because
+/// the input cannot be read a second time.
+pub fn import_ndtree_from_reader<D: Dim>(
+    reader: impl io::BufRead,
+) -> Result<NdTree<D>, CaFormatError> {
+    let (format, reader) = guess_format_of_reader(decompress_reader(reader)?)?;
+    match format {
+        CaFormat::Rle => Rle::from_reader_to_ndtree(reader).map_err(Into::into),
+        CaFormat::Macrocell => Macrocell::from_reader_to_ndtree(reader).map_err(Into::into),
+        CaFormat::Plaintext => Plaintext::from_reader_to_ndtree(reader).map_err(Into::into),
+        CaFormat::Life105 => Life105::from_reader_to_ndtree(reader).map_err(Into::into),
+        CaFormat::Life106 => Life106::from_reader_to_ndtree(reader).map_err(Into::into),
+        CaFormat::Binary => Binary::from_reader_to_ndtree(reader).map_err(Into::into),
+    }
+}
+/// Imports an automaton from a reader, guessing the format from the first
+/// bytes of input using `CaFormat::guess_from_prefix()`. Compressed input is
+/// detected and decompressed automatically.
+///
+/// Unlike `import_automaton_from_string()`, this only tries one format,
+/// because the input cannot be read a second time.
+pub fn import_automaton_from_reader<R: ResolveRule>(
+    reader: impl io::BufRead,
+    resolve_rule: R,
+) -> Result<Result<Automaton, R::Err>, CaFormatError> {
+    let (format, reader) = guess_format_of_reader(decompress_reader(reader)?)?;
+    match format {
+        CaFormat::Rle => Rle::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into),
+        CaFormat::Macrocell => {
+            Macrocell::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into)
+        }
+        CaFormat::Plaintext => {
+            Plaintext::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into)
+        }
+        CaFormat::Life105 => {
+            Life105::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into)
+        }
+        CaFormat::Life106 => {
+            Life106::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into)
+        }
+        CaFormat::Binary => {
+            Binary::from_reader_to_automaton(reader, resolve_rule).map_err(Into::into)
+        }
+    }
+}
+
+/// Guesses the format of the input using `CaFormat::guess_from_prefix()`,
+/// returning it along with a reader that yields the whole input.
+fn guess_format_of_reader<R: io::BufRead>(
+    reader: R,
+) -> io::Result<(CaFormat, utils::PrefixedReader<R>)> {
+    // Read up to the end of the first non-blank line.
+    let reader = utils::read_prefix(reader, MAX_GUESS_PREFIX_LEN, |prefix| {
+        prefix
+            .iter()
+            .skip_while(|b| b.is_ascii_whitespace())
+            .any(|&b| b == b'\n')
+    })?;
+    let format = CaFormat::guess_from_prefix(reader.get_ref().0.get_ref());
+    Ok((format, reader))
 }
 
 /// Whether a rule has more than two states.
@@ -202,6 +589,17 @@ pub trait CaFormatTrait: FromStr + ToString {
         self.to_string()
     }
 
+    /// Serializes the pattern to a writer.
+    ///
+    /// The output is written in many small pieces, so `writer` should usually
+    /// be buffered.
+    fn write_to(&self, writer: impl io::Write, two_states: TwoState) -> Result<(), Self::Err>;
+    /// Deserializes a pattern from a reader, one line at a time.
+    ///
+    /// `from_str()` is equivalent to calling this method on the bytes of the
+    /// string.
+    fn read_from(reader: impl io::BufRead) -> Result<Self, Self::Err>;
+
     /// Returns the name of the rule.
     fn rule(&self) -> Option<&str>;
     /// Sets the rule name.
@@ -273,7 +671,7 @@ pub trait CaFormatTrait: FromStr + ToString {
             ndtree: self.to_ndtree(node_pool)?,
             rule,
             generations: self.generation(),
-            comments: self.comments().to_owned(),
+            metadata: PatternMetadata::from_comments(self.comments()),
         })
     }
     /// Converts the serializable pattern into an ND-tree.
@@ -299,7 +697,7 @@ pub trait CaFormatTrait: FromStr + ToString {
         Ok(Self::from_ndtree(&automaton.ndtree, rect)?
             .with_rule(Some(&automaton.rule))
             .with_generation(automaton.generations.clone())
-            .with_comments(automaton.comments.clone()))
+            .with_comments(automaton.metadata.to_comments()))
     }
     /// Converts part of an ND-tree into a serializable format. If `rect` is
     /// `None`, the entire grid is exported.
@@ -312,7 +710,7 @@ pub trait CaFormatTrait: FromStr + ToString {
         s: &str,
         resolve_rule: R,
     ) -> Result<Result<Automaton, R::Err>, Self::Err> {
-        s.parse::<Self>()?.to_automaton(resolve_rule)
+        Self::from_reader_to_automaton(s.as_bytes(), resolve_rule)
     }
     /// Deserializes an automaton, given a rule (ignoring the serialized rule
     /// name).
@@ -320,24 +718,39 @@ pub trait CaFormatTrait: FromStr + ToString {
         s: &str,
         rule: Arc<dyn NdRule<D>>,
     ) -> Result<NdAutomaton<D>, Self::Err> {
-        s.parse::<Self>()?
-            .to_ndautomaton(rule, SharedNodePool::new())
+        Self::from_reader_to_ndautomaton(s.as_bytes(), rule)
     }
     /// Deserializes an ND-tree.
     fn from_string_to_ndtree<D: Dim>(s: &str) -> Result<NdTree<D>, Self::Err> {
-        s.parse::<Self>()?.to_ndtree(SharedNodePool::new())
+        Self::from_reader_to_ndtree(s.as_bytes())
+    }
+
+    /// Deserializes an automaton of unknown dimensionality from a reader, given
+    /// a closure to resolve the name of a rule.
+    fn from_reader_to_automaton<R: ResolveRule>(
+        reader: impl io::BufRead,
+        resolve_rule: R,
+    ) -> Result<Result<Automaton, R::Err>, Self::Err> {
+        Self::read_from(reader)?.to_automaton(resolve_rule)
+    }
+    /// Deserializes an automaton from a reader, given a rule (ignoring the
+    /// serialized rule name).
+    fn from_reader_to_ndautomaton<D: Dim>(
+        reader: impl io::BufRead,
+        rule: Arc<dyn NdRule<D>>,
+    ) -> Result<NdAutomaton<D>, Self::Err> {
+        Self::read_from(reader)?.to_ndautomaton(rule, SharedNodePool::new())
+    }
+    /// Deserializes an ND-tree from a reader.
+    fn from_reader_to_ndtree<D: Dim>(reader: impl io::BufRead) -> Result<NdTree<D>, Self::Err> {
+        Self::read_from(reader)?.to_ndtree(SharedNodePool::new())
     }
 
     /// Serializes an automaton of any dimensionality.
     fn from_automaton_to_string(automaton: &Automaton) -> Result<String, Self::Err> {
-        match automaton {
-            Automaton::Automaton1D(a) => Self::from_ndautomaton_to_string(a, None),
-            Automaton::Automaton2D(a) => Self::from_ndautomaton_to_string(a, None),
-            Automaton::Automaton3D(a) => Self::from_ndautomaton_to_string(a, None),
-            Automaton::Automaton4D(a) => Self::from_ndautomaton_to_string(a, None),
-            Automaton::Automaton5D(a) => Self::from_ndautomaton_to_string(a, None),
-            Automaton::Automaton6D(a) => Self::from_ndautomaton_to_string(a, None),
-        }
+        let mut buffer = vec![];
+        Self::from_automaton_to_writer(automaton, &mut buffer)?;
+        Ok(utf8_buffer_to_string(buffer))
     }
     /// Serializes part of an automaton. If `rect` is `None`, the entire grid is
     /// exported.
@@ -345,12 +758,9 @@ pub trait CaFormatTrait: FromStr + ToString {
         automaton: &NdAutomaton<D>,
         rect: Option<BigRect<D>>,
     ) -> Result<String, Self::Err> {
-        let this = Self::from_ndautomaton(automaton, rect)?;
-        let two_states = TwoState::from_rule(&*automaton.rule);
-        match two_states {
-            TwoState::TwoStates => Ok(this.to_string_2_state()),
-            TwoState::MoreStates => Ok(this.to_string()),
-        }
+        let mut buffer = vec![];
+        Self::from_ndautomaton_to_writer(automaton, rect, &mut buffer)?;
+        Ok(utf8_buffer_to_string(buffer))
     }
     /// Serializes part of an ND-tree. If `rect` is `None`, the entire grid is
     /// exported.
@@ -359,10 +769,48 @@ pub trait CaFormatTrait: FromStr + ToString {
         rect: Option<BigRect<D>>,
         two_states: TwoState,
     ) -> Result<String, Self::Err> {
-        let this = Self::from_ndtree(ndtree, rect)?;
-        match two_states {
-            TwoState::TwoStates => Ok(this.to_string_2_state()),
-            TwoState::MoreStates => Ok(this.to_string()),
+        let mut buffer = vec![];
+        Self::from_ndtree_to_writer(ndtree, rect, two_states, &mut buffer)?;
+        Ok(utf8_buffer_to_string(buffer))
+    }
+
+    /// Serializes an automaton of any dimensionality to a writer.
+    fn from_automaton_to_writer(
+        automaton: &Automaton,
+        writer: impl io::Write,
+    ) -> Result<(), Self::Err> {
+        match automaton {
+            Automaton::Automaton1D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
+            Automaton::Automaton2D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
+            Automaton::Automaton3D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
+            Automaton::Automaton4D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
+            Automaton::Automaton5D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
+            Automaton::Automaton6D(a) => Self::from_ndautomaton_to_writer(a, None, writer),
         }
     }
+    /// Serializes part of an automaton to a writer. If `rect` is `None`, the
+    /// entire grid is exported.
+    fn from_ndautomaton_to_writer<D: Dim>(
+        automaton: &NdAutomaton<D>,
+        rect: Option<BigRect<D>>,
+        writer: impl io::Write,
+    ) -> Result<(), Self::Err> {
+        let two_states = TwoState::from_rule(&*automaton.rule);
+        Self::from_ndautomaton(automaton, rect)?.write_to(writer, two_states)
+    }
+    /// Serializes part of an ND-tree to a writer. If `rect` is `None`, the
+    /// entire grid is exported.
+    fn from_ndtree_to_writer<D: Dim>(
+        ndtree: &NdTree<D>,
+        rect: Option<BigRect<D>>,
+        two_states: TwoState,
+        writer: impl io::Write,
+    ) -> Result<(), Self::Err> {
+        Self::from_ndtree(ndtree, rect)?.write_to(writer, two_states)
+    }
+}
+
+/// Converts the output of a serializer to a string.
+fn utf8_buffer_to_string(buffer: Vec<u8>) -> String {
+    String::from_utf8(buffer).expect("Serialized pattern is not valid UTF-8")
 }
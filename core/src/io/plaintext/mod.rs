@@ -0,0 +1,305 @@
+//! Golly/LifeWiki ["plaintext"
+//! format](https://www.conwaylife.com/wiki/Plaintext) (`.cells`)
+//!
+//! Each line beginning with `!` is a comment, and `!Name:` gives the name of
+//! the pattern. Every other line is a row of cells, where `.` is a dead cell
+//! and `O` (or `*`) is a live cell. Trailing dead cells may be omitted, and an
+//! empty line is a row of dead cells. This format only supports 2D patterns
+//! with two states.
+//!
+//! Comments are stored using the same conventions as RLE: the name becomes a
+//! `#N` line, and other comments become `#C` lines.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use super::utils::ndtree_rows_2d;
+use super::{CaFormatTrait, TextSpan, TwoState};
+use crate::axis::{X, Y};
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndtree::{NdTree, Region, SharedNodePool};
+use crate::ndvec::BigVec;
+use crate::num::{BigInt, Zero};
+
+/// Result type returned by fallible plaintext routines.
+pub type PlaintextResult<T> = Result<T, PlaintextError>;
+
+/// Error encountered during plaintext import/export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaintextError {
+    /// The type of error.
+    pub kind: PlaintextErrorKind,
+    /// The location of the error in the plaintext (if any).
+    pub span: Option<TextSpan>,
+}
+impl PlaintextError {
+    /// Attaches a span to this error, if it does not already have one.
+    pub fn with_span(mut self, span: TextSpan) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+impl From<PlaintextErrorKind> for PlaintextError {
+    fn from(kind: PlaintextErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+}
+impl From<io::Error> for PlaintextError {
+    fn from(e: io::Error) -> Self {
+        PlaintextErrorKind::IoError(e.to_string()).into()
+    }
+}
+impl fmt::Display for PlaintextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+/// Type of error encountered during plaintext import/export.
+#[allow(missing_docs)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlaintextErrorKind {
+    UnknownSymbol(char),
+    MissingPattern,
+    StateOutOfRange(u8),
+    Non2D,
+    TooBig,
+    IoError(String),
+}
+impl fmt::Display for PlaintextErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownSymbol(ch) => write!(f, "Unknown symbol: '{}'", ch),
+            Self::MissingPattern => write!(f, "Missing pattern rows or '!Name:' line"),
+            Self::StateOutOfRange(state) => write!(
+                f,
+                "State out of range: #{}; plaintext only supports two states",
+                state,
+            ),
+            Self::Non2D => write!(f, "Plaintext only supports 2D patterns"),
+            Self::TooBig => write!(f, "Pattern is too big; try another format like Macrocell"),
+            Self::IoError(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+/// Plaintext contents.
+#[derive(Debug, Clone)]
+pub struct Plaintext {
+    /// Pattern comments, including the name.
+    comments: String,
+    /// Rows of cells from top to bottom, each omitting trailing dead cells.
+    rows: Vec<Vec<bool>>,
+}
+impl Plaintext {
+    /// Returns the name of the pattern, from the `!Name:` line.
+    pub fn name(&self) -> Option<&str> {
+        self.comments
+            .lines()
+            .find_map(|line| line.strip_prefix("#N"))
+            .map(str::trim)
+    }
+    /// Returns the width of the pattern.
+    fn width(&self) -> usize {
+        self.rows.iter().map(Vec::len).max().unwrap_or(0)
+    }
+}
+impl fmt::Display for Plaintext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in self.comments.trim_end().lines() {
+            if let Some(name) = line.strip_prefix("#N") {
+                writeln!(f, "!Name: {}", name.trim())?;
+            } else if let Some(comment) =
+                line.strip_prefix("#C").or_else(|| line.strip_prefix("#c"))
+            {
+                writeln!(f, "!{}", comment.trim())?;
+            } else {
+                writeln!(f, "!{}", line)?;
+            }
+        }
+        if self.rows.is_empty() {
+            // Input without any rows is rejected, so use a dead cell instead.
+            writeln!(f, ".")?;
+        }
+        for row in &self.rows {
+            if row.is_empty() {
+                // Leading blank lines are ignored, so use a dead cell instead.
+                write!(f, ".")?;
+            }
+            for &cell in row {
+                write!(f, "{}", if cell { 'O' } else { '.' })?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+impl FromStr for Plaintext {
+    type Err = PlaintextError;
+
+    fn from_str(s: &str) -> PlaintextResult<Self> {
+        Self::read_from(s.as_bytes())
+    }
+}
+
+impl CaFormatTrait for Plaintext {
+    fn write_to(&self, mut writer: impl io::Write, _two_states: TwoState) -> PlaintextResult<()> {
+        write!(writer, "{}", self)?;
+        Ok(())
+    }
+    fn read_from(mut reader: impl io::BufRead) -> PlaintextResult<Self> {
+        let mut comments = String::new();
+        let mut rows: Vec<Vec<bool>> = vec![];
+        // Whether there was a `!Name:` line, which marks otherwise empty input
+        // as a plaintext pattern.
+        let mut has_name = false;
+
+        let mut line_buffer = String::new();
+        let mut line_number = 0;
+        while reader.read_line(&mut line_buffer)? != 0 {
+            line_number += 1;
+            let raw_line = line_buffer.trim_end_matches(&['\n', '\r'][..]);
+            let line = raw_line.trim();
+
+            if let Some(comment) = line.strip_prefix('!') {
+                match comment.strip_prefix("Name:") {
+                    Some(name) => {
+                        has_name = true;
+                        comments.push_str(&format!("#N {}", name.trim()));
+                    }
+                    None => comments.push_str(&format!("#C {}", comment.trim())),
+                }
+                comments.push('\n');
+            } else if line.is_empty() && rows.is_empty() {
+                // Ignore blank lines before the first row.
+            } else {
+                let line_start = raw_line.len() - raw_line.trim_start().len();
+                let mut row = Vec::with_capacity(line.len());
+                for (i, ch) in line.char_indices() {
+                    match ch {
+                        '.' => row.push(false),
+                        'O' | 'o' | '*' => row.push(true),
+                        _ => {
+                            let start = line_start + i;
+                            let span =
+                                TextSpan::new(line_number, raw_line, start..start + ch.len_utf8());
+                            return Err(PlaintextError::from(PlaintextErrorKind::UnknownSymbol(
+                                ch,
+                            ))
+                            .with_span(span));
+                        }
+                    }
+                }
+                // Omit trailing dead cells.
+                while row.last() == Some(&false) {
+                    row.pop();
+                }
+                rows.push(row);
+            }
+            line_buffer.clear();
+        }
+
+        if rows.is_empty() && !has_name {
+            return Err(PlaintextErrorKind::MissingPattern.into());
+        }
+
+        // Omit trailing empty rows.
+        while matches!(rows.last(), Some(row) if row.is_empty()) {
+            rows.pop();
+        }
+
+        Ok(Self { comments, rows })
+    }
+
+    fn rule(&self) -> Option<&str> {
+        None
+    }
+    fn with_rule(self, _rule: Option<impl ToString>) -> Self {
+        self
+    }
+
+    fn generation(&self) -> BigInt {
+        BigInt::zero()
+    }
+    fn with_generation(self, _generation: BigInt) -> Self {
+        self
+    }
+
+    fn comments(&self) -> &str {
+        &self.comments
+    }
+    fn comments_mut(&mut self) -> &mut String {
+        &mut self.comments
+    }
+
+    fn region<D: Dim>(&self) -> Region<D> {
+        let width = self.width();
+        if width == 0 {
+            return Region::Empty;
+        }
+        // The top left corner is at the origin.
+        let corner = BigVec::from_fn(|ax| match ax {
+            X => BigInt::from(width - 1),
+            Y => -BigInt::from(self.rows.len() - 1),
+            _ => BigInt::zero(),
+        });
+        Region::Rect(BigRect::span(BigVec::origin(), corner))
+    }
+
+    fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> PlaintextResult<NdTree<D>> {
+        if D::NDIM != 2 {
+            return Err(PlaintextErrorKind::Non2D.into());
+        }
+        let mut ret = NdTree::with_node_pool(node_pool);
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, &cell) in row.iter().enumerate() {
+                if cell {
+                    // Y increases downwards in plaintext but upwards in NDCell.
+                    let pos = BigVec::from_fn(|ax| match ax {
+                        X => BigInt::from(x),
+                        _ => -BigInt::from(y),
+                    });
+                    ret.set_cell(&pos, 1_u8);
+                }
+            }
+        }
+        Ok(ret)
+    }
+
+    fn from_ndtree<D: Dim>(ndtree: &NdTree<D>, rect: Option<BigRect<D>>) -> PlaintextResult<Self> {
+        if D::NDIM != 2 {
+            return Err(PlaintextErrorKind::Non2D.into());
+        }
+        let rows = match rect.or_else(|| ndtree.bounding_rect()) {
+            None => vec![],
+            Some(rect) => ndtree_rows_2d(ndtree, &rect)
+                .ok_or(PlaintextErrorKind::TooBig)?
+                .into_iter()
+                .map(|row| {
+                    row.into_iter()
+                        .map(|state| match state {
+                            0 => Ok(false),
+                            1 => Ok(true),
+                            _ => Err(PlaintextErrorKind::StateOutOfRange(state)),
+                        })
+                        .collect()
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        Ok(Self {
+            comments: String::new(),
+            rows,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
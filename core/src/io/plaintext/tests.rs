@@ -0,0 +1,99 @@
+use super::*;
+use crate::prelude::*;
+
+const GLIDER: &str = "\
+!Name: Glider
+!The smallest spaceship.
+.O
+..O
+OOO
+";
+
+#[test]
+fn test_plaintext_2d() {
+    let ndtree: NdTree2D = Plaintext::from_string_to_ndtree(GLIDER).unwrap();
+    assert_eq!(5, ndtree.root_ref().population().to_usize().unwrap());
+    for &pos in &[[1, 0], [2, -1], [0, -2], [1, -2], [2, -2]] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+
+    let plaintext: Plaintext = GLIDER.parse().unwrap();
+    assert_eq!(Some("Glider"), plaintext.name());
+    assert_eq!(
+        "#N Glider\n#C The smallest spaceship.\n",
+        plaintext.comments()
+    );
+    assert_eq!(GLIDER, plaintext.to_string());
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([0, 0]), NdVec::big([2, -2]))),
+        plaintext.region::<Dim2D>().bounding_rect(),
+    );
+
+    // Comments survive a round trip through RLE.
+    let rle_string = Rle::from_ndtree(&ndtree, None)
+        .unwrap()
+        .with_comments(plaintext.comments())
+        .to_string_2_state();
+    let rle: Rle = rle_string.parse().unwrap();
+    assert_eq!(plaintext.comments(), rle.comments());
+
+    // Empty rows at the top are preserved on export.
+    let rect = NdRect::span(NdVec::big([0, 2]), NdVec::big([2, -2]));
+    assert_eq!(
+        ".\n.\n.O\n..O\nOOO\n",
+        Plaintext::from_ndtree_to_string(&ndtree, Some(rect), TwoState::TwoStates).unwrap(),
+    );
+
+    // Blank lines are empty rows, except at the beginning and end.
+    let ndtree2: NdTree2D = Plaintext::from_string_to_ndtree("\n\nO\n\n*O\n\n").unwrap();
+    assert_eq!(
+        "O\n.\nOO\n",
+        Plaintext::from_ndtree_to_string(&ndtree2, None, TwoState::TwoStates).unwrap(),
+    );
+
+    // The format is detected from the first non-blank line, even if the reader
+    // returns only one byte at a time.
+    let reader = std::io::BufReader::with_capacity(1, &b"\n\n.O\n..O\nOOO\n"[..]);
+    let ndtree3: NdTree2D = crate::io::import_ndtree_from_reader(reader).unwrap();
+    assert_eq!(5, ndtree3.root_ref().population().to_usize().unwrap());
+}
+
+#[test]
+fn test_plaintext_errors() {
+    let e = Plaintext::from_string_to_ndtree::<Dim2D>("!Name: x\n.O\n.OX\n").unwrap_err();
+    assert_eq!(PlaintextErrorKind::UnknownSymbol('X'), e.kind);
+    assert_eq!(Some((3, 3)), e.span.map(|span| (span.line, span.column)));
+
+    assert_eq!(
+        PlaintextErrorKind::Non2D,
+        Plaintext::from_string_to_ndtree::<Dim3D>(GLIDER)
+            .unwrap_err()
+            .kind,
+    );
+
+    // Blank input is not a pattern, but a named empty pattern or a single
+    // dead cell is.
+    for &s in &["", "\n  \n", "!Just a comment\n"] {
+        assert_eq!(
+            PlaintextErrorKind::MissingPattern,
+            Plaintext::from_string_to_ndtree::<Dim2D>(s)
+                .unwrap_err()
+                .kind,
+        );
+    }
+    assert!(crate::io::import_ndtree_from_string::<Dim2D>("").is_err());
+    for &s in &["!Name: Nothing\n", ".\n"] {
+        let ndtree: NdTree2D = Plaintext::from_string_to_ndtree(s).unwrap();
+        assert!(ndtree.root_ref().is_empty());
+    }
+    let empty =
+        Plaintext::from_ndtree_to_string(&NdTree2D::new(), None, TwoState::TwoStates).unwrap();
+    assert_eq!(".\n", empty);
+
+    let mut ndtree = NdTree2D::new();
+    ndtree.set_cell(&NdVec::big([3, 4]), 2_u8);
+    assert_eq!(
+        PlaintextErrorKind::StateOutOfRange(2),
+        Plaintext::from_ndtree(&ndtree, None).unwrap_err().kind,
+    );
+}
@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use std::fmt;
+use std::io;
 use std::str::FromStr;
 
 use super::*;
@@ -7,7 +8,7 @@ use crate::axis::{Axis, AXES, U, V, W, X, Y, Z};
 use crate::dim::Dim;
 use crate::ndrect::BigRect;
 use crate::ndvec::{BigVec, BigVec6D, UVec, UVec6D};
-use crate::num::{BigInt, One, Zero};
+use crate::num::{BigInt, One, Signed, ToPrimitive, Zero};
 
 /// RLE contents.
 #[derive(Debug, Clone)]
@@ -16,8 +17,14 @@ pub struct Rle {
     pub(super) header: RleHeader,
     /// CXRLE (extended RLE) header.
     pub(super) cxrle_header: Option<CxrleHeader>,
+    /// Golly 3D RLE header, present if the RLE uses Golly's 3D dialect.
+    pub(super) golly_3d_header: Option<Golly3DHeader>,
     /// RLE runs.
     pub(super) runs: Vec<RleRun>,
+    /// Location of the first run that advances along each axis in the text
+    /// that the RLE was read from, used to report an error when converting to
+    /// an ND-tree without that axis.
+    pub(super) axis_spans: [Option<TextSpan>; 6],
     /// Pattern comments, not including CXRLE header.
     pub(super) comments: String,
 }
@@ -28,6 +35,64 @@ impl Rle {
         self.cxrle_header = None;
         self
     }
+    /// Converts the RLE to the dialect used by Golly's 3D script (`.rle3`).
+    ///
+    /// The grid size is chosen so that the pattern keeps its position relative
+    /// to the center of the grid if possible; if that would make the grid too
+    /// large, the pattern is centered instead.
+    ///
+    /// Returns an error if the pattern has more than three dimensions.
+    pub fn with_golly_3d(mut self) -> RleResult<Self> {
+        if [W, U, V].iter().any(|&ax| self.header.size[ax] > 1) {
+            return Err(RleErrorKind::Golly3DTooManyAxes.into());
+        }
+        let header_size = self.header.size;
+        let extent = |ax: Axis| BigInt::from(header_size[ax]);
+        let cxrle = self.cxrle_header.get_or_insert_with(CxrleHeader::default);
+
+        // Check whether the pattern already fits in the existing grid.
+        if let Some(golly_3d_header) = &self.golly_3d_header {
+            if golly_3d_header.contains(&cxrle.pos, extent) {
+                return Ok(self);
+            }
+        }
+
+        // Make the grid big enough that the pattern does not need to move.
+        let half_size = [X, Y, Z]
+            .iter()
+            .map(|&ax| std::cmp::max(-&cxrle.pos[ax], &cxrle.pos[ax] + extent(ax)))
+            .max()
+            .unwrap()
+            .to_usize()
+            .unwrap_or(usize::MAX / 2);
+        let size = std::cmp::max(GOLLY_3D_DEFAULT_SIZE, half_size.saturating_mul(2));
+        let size = if size <= GOLLY_3D_MAX_SIZE {
+            size
+        } else {
+            // Center the pattern in a grid that fits it.
+            let extent = [X, Y, Z].iter().map(|&ax| header_size[ax]).max().unwrap();
+            let size = std::cmp::max(GOLLY_3D_DEFAULT_SIZE, extent);
+            let golly_3d_header = Golly3DHeader { size };
+            for &ax in &[X, Y, Z] {
+                cxrle.pos[ax] = golly_3d_header.centered_pos(header_size[ax]);
+            }
+            size
+        };
+        self.golly_3d_header = Some(Golly3DHeader { size });
+        Ok(self)
+    }
+    /// Converts the RLE from the dialect used by Golly's 3D script (`.rle3`)
+    /// to ordinary N-dimensional RLE.
+    #[must_use = "This method returns a new value instead of mutating its input"]
+    pub fn without_golly_3d(mut self) -> Self {
+        self.golly_3d_header = None;
+        self
+    }
+    /// Returns whether the RLE uses the dialect of Golly's 3D script
+    /// (`.rle3`).
+    pub fn is_golly_3d(&self) -> bool {
+        self.golly_3d_header.is_some()
+    }
 
     /// Returns the starting position of the first RLE run, in NDCell
     /// coordinates (meaning Y increases upward).
@@ -62,24 +127,25 @@ impl Rle {
         BigRect::span(corner1.clone(), corner1 + size)
     }
 
-    fn _fmt(&self, f: &mut fmt::Formatter<'_>, is_2_state: TwoState) -> fmt::Result {
-        if let Some(cxrle_header) = &self.cxrle_header {
-            writeln!(f, "{}", cxrle_header)?;
-        }
-        for comment_line in self.comments.trim_end().lines() {
-            if !comment_line.starts_with('#') {
-                write!(f, "#C ")?;
+    /// Returns a value that displays only the headers and comments of the
+    /// RLE, without any runs.
+    pub(super) fn header_display(&self) -> impl '_ + fmt::Display {
+        struct RleHeaderDisplay<'a>(&'a Rle);
+        impl fmt::Display for RleHeaderDisplay<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0._fmt_header(f)
             }
-            writeln!(f, "{}", comment_line)?;
         }
-        writeln!(f, "{}", self.header)?;
+        RleHeaderDisplay(self)
+    }
+
+    fn _fmt(&self, f: &mut fmt::Formatter<'_>, is_2_state: TwoState) -> fmt::Result {
+        self._fmt_header(f)?;
 
         let mut line_len = 0;
-        for run in &self.runs {
-            line_len += run.str_len();
-            if line_len > MAX_LINE_LEN {
+        for &run in &self.runs {
+            if wrap_line(&mut line_len, run) {
                 writeln!(f)?;
-                line_len = run.str_len();
             }
             match is_2_state {
                 TwoState::TwoStates => write!(f, "{:b}", run)?,
@@ -90,6 +156,48 @@ impl Rle {
 
         Ok(())
     }
+    fn _fmt_header(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(golly_3d_header) = &self.golly_3d_header {
+            let cxrle_header = self.cxrle_header.clone().unwrap_or_default();
+            write!(f, "3D version=1 size={} pos=", golly_3d_header.size)?;
+            for &ax in &[X, Y, Z] {
+                if ax != X {
+                    write!(f, ",")?;
+                }
+                write!(f, "{}", golly_3d_header.grid_pos(&cxrle_header.pos[ax]))?;
+            }
+            if !cxrle_header.gen.is_zero() {
+                write!(f, " gen={}", cxrle_header.gen)?;
+            }
+            writeln!(f)?;
+            self._fmt_comments(f)?;
+            write!(
+                f,
+                "x={} y={} z={}",
+                self.header.size[X], self.header.size[Y], self.header.size[Z],
+            )?;
+            if let Some(rule) = &self.header.rule {
+                write!(f, " rule={}", rule)?;
+            }
+            writeln!(f)?;
+        } else {
+            if let Some(cxrle_header) = &self.cxrle_header {
+                writeln!(f, "{}", cxrle_header)?;
+            }
+            self._fmt_comments(f)?;
+            writeln!(f, "{}", self.header)?;
+        }
+        Ok(())
+    }
+    fn _fmt_comments(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for comment_line in self.comments.trim_end().lines() {
+            if !comment_line.starts_with('#') {
+                write!(f, "#C ")?;
+            }
+            writeln!(f, "{}", comment_line)?;
+        }
+        Ok(())
+    }
 }
 impl fmt::Display for Rle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -105,41 +213,7 @@ impl FromStr for Rle {
     type Err = RleError;
 
     fn from_str(s: &str) -> RleResult<Self> {
-        let mut header = None;
-        let mut cxrle_header = None;
-        let mut runs = vec![];
-        let mut comments = String::new();
-
-        for line in s.lines() {
-            let line = line.trim();
-            if line.starts_with("#CXRLE") {
-                if cxrle_header.is_some() {
-                    return Err(RleError::DuplicateCxrleHeader);
-                }
-                cxrle_header = Some(line.parse()?);
-            } else if line.starts_with("#") {
-                comments.push_str(line);
-                comments.push('\n');
-            } else if line.starts_with("x") {
-                if header.is_some() {
-                    return Err(RleError::DuplicateRleHeader);
-                }
-                header = Some(line.parse()?);
-            } else {
-                for run in RLE_RUN_REGEX.find_iter(line) {
-                    runs.push(run.as_str().parse()?);
-                }
-            }
-        }
-
-        let header = header.ok_or(RleError::MissingHeader)?;
-
-        Ok(Self {
-            header,
-            cxrle_header,
-            runs,
-            comments,
-        })
+        Self::read_from(s.as_bytes())
     }
 }
 
@@ -187,26 +261,42 @@ impl FromStr for RleHeader {
 
         // Parse comma-separated parameters.
         for param in s.split(',') {
-            // Each parameter consists of `name = value`.
-            match param.split('=').map(str::trim).collect_vec().as_slice() {
-                ["x", x] => ret.size[X] = parse_rle_size(x)?,
-                ["y", y] => ret.size[Y] = parse_rle_size(y)?,
-                ["z", z] => ret.size[Z] = parse_rle_size(z)?,
-                ["w", w] => ret.size[W] = parse_rle_size(w)?,
-                ["u", u] => ret.size[U] = parse_rle_size(u)?,
-                ["v", v] => ret.size[V] = parse_rle_size(v)?,
-                ["rule", rule_name] => ret.rule = Some((*rule_name).to_owned()),
-                _ => (), // Ignore unknown parameters.
-            }
+            ret.parse_param(param)?;
         }
 
         Ok(ret)
     }
 }
+impl RleHeader {
+    /// Parses the size line of Golly's 3D RLE dialect, which has the form
+    /// `x=3 y=3 z=3 rule=3D5..7/6`. Parameters are separated by spaces
+    /// instead of commas, because the rule may contain commas.
+    pub fn from_golly_3d_line(s: &str) -> RleResult<Self> {
+        let mut ret = Self::default();
+        for param in s.split_whitespace() {
+            ret.parse_param(param)?;
+        }
+        Ok(ret)
+    }
+    /// Parses a single parameter of the form `name = value`.
+    fn parse_param(&mut self, param: &str) -> RleResult<()> {
+        match param.splitn(2, '=').map(str::trim).collect_vec().as_slice() {
+            ["x", x] => self.size[X] = parse_rle_size(x)?,
+            ["y", y] => self.size[Y] = parse_rle_size(y)?,
+            ["z", z] => self.size[Z] = parse_rle_size(z)?,
+            ["w", w] => self.size[W] = parse_rle_size(w)?,
+            ["u", u] => self.size[U] = parse_rle_size(u)?,
+            ["v", v] => self.size[V] = parse_rle_size(v)?,
+            ["rule", rule_name] => self.rule = Some((*rule_name).to_owned()),
+            _ => (), // Ignore unknown parameters.
+        }
+        Ok(())
+    }
+}
 
 fn parse_rle_size(s: &str) -> RleResult<usize> {
     Ok(s.parse::<usize>()
-        .map_err(|_| RleError::InvalidSize)?
+        .map_err(|_| RleErrorKind::InvalidSize)?
         .max(1))
 }
 
@@ -254,7 +344,7 @@ impl FromStr for CxrleHeader {
     type Err = RleError;
 
     fn from_str(s: &str) -> RleResult<Self> {
-        use RleError::BadCxrleHeader;
+        use RleErrorKind::BadCxrleHeader;
 
         let s = s.strip_prefix("#CXRLE").ok_or(BadCxrleHeader)?.trim();
 
@@ -290,21 +380,97 @@ impl FromStr for CxrleHeader {
     }
 }
 
+/// Header of the RLE dialect used by Golly's 3D script (`.rle3`), which has
+/// the form `3D version=1 size=N pos=x,y,z gen=G`.
+///
+/// Golly simulates 3D patterns on a cubic grid of size `N` with coordinates
+/// from `0` to `N - 1`, where `pos` is the position of the first cell of the
+/// RLE. The center of the grid corresponds to the origin in NDCell. The
+/// pattern position and generation count are stored in the CXRLE header, so
+/// only the grid size is stored here.
+#[derive(Debug, Clone)]
+pub struct Golly3DHeader {
+    /// Size of the grid along each axis.
+    pub size: usize,
+}
+impl Golly3DHeader {
+    /// Parses the header, returning it along with the `pos` parameter (if
+    /// any), converted to a CXRLE position, and the `gen` parameter (if any).
+    pub fn parse_line(s: &str) -> RleResult<(Self, Option<BigVec6D>, Option<BigInt>)> {
+        use RleErrorKind::BadGolly3DHeader;
+
+        let s = s.strip_prefix("3D").ok_or(BadGolly3DHeader)?;
+
+        let mut size = None;
+        let mut pos = None;
+        let mut gen = None;
+        for kv_pair in s.split_whitespace() {
+            match kv_pair.split('=').collect_vec().as_slice() {
+                ["version", version] if *version != "1" => return Err(BadGolly3DHeader.into()),
+                ["size", n] => {
+                    size = Some(n.parse::<usize>().map_err(|_| BadGolly3DHeader)?);
+                }
+                ["pos", coords] => {
+                    let coords: Vec<BigInt> = coords
+                        .split(',')
+                        .map(|coord| coord.trim().parse().map_err(|_| BadGolly3DHeader))
+                        .try_collect()?;
+                    if coords.len() != 3 {
+                        return Err(BadGolly3DHeader.into());
+                    }
+                    pos = Some(coords);
+                }
+                ["gen", g] => {
+                    // Ignore invalid generation count, like in the CXRLE
+                    // header.
+                    gen = g.parse().ok();
+                }
+                _ => (), // Ignore unknown keys.
+            }
+        }
+
+        let ret = Self {
+            size: size.ok_or(BadGolly3DHeader)?,
+        };
+        let pos = pos.map(|coords| {
+            let mut cxrle_pos = BigVec6D::origin();
+            for (&ax, coord) in AXES.iter().zip(coords) {
+                cxrle_pos[ax] = ret.cxrle_pos(coord);
+            }
+            cxrle_pos
+        });
+        Ok((ret, pos, gen))
+    }
+
+    /// Converts a coordinate in the grid to a CXRLE coordinate.
+    pub fn cxrle_pos(&self, grid_pos: BigInt) -> BigInt {
+        grid_pos - self.size / 2
+    }
+    /// Converts a CXRLE coordinate to a coordinate in the grid.
+    pub fn grid_pos(&self, cxrle_pos: &BigInt) -> BigInt {
+        cxrle_pos + self.size / 2
+    }
+    /// Returns the CXRLE coordinate that centers a pattern with length `len`
+    /// in the grid, which is what Golly does when `pos` is absent.
+    pub fn centered_pos(&self, len: usize) -> BigInt {
+        self.cxrle_pos(BigInt::from(self.size.saturating_sub(len) / 2))
+    }
+    /// Returns whether a pattern at the CXRLE position `pos` with the given
+    /// extent along each axis lies entirely within the grid.
+    fn contains(&self, pos: &BigVec6D, extent: impl Fn(Axis) -> BigInt) -> bool {
+        [X, Y, Z].iter().all(|&ax| {
+            let start = self.grid_pos(&pos[ax]);
+            !start.is_negative() && start + extent(ax) <= BigInt::from(self.size)
+        })
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct RleRunVec(pub Vec<RleRun>);
 impl RleRunVec {
     pub fn into_vec(self) -> Vec<RleRun> {
         self.0
     }
-    pub fn try_extend(
-        &mut self,
-        run_iter: impl IntoIterator<Item = RleResult<RleRun>>,
-    ) -> RleResult<()> {
-        for run in run_iter {
-            self.append(run?);
-        }
-        Ok(())
-    }
     pub fn append(&mut self, run: impl Into<RleRun>) {
         let mut run = run.into();
         while let Some(last) = self.0.last() {
@@ -320,6 +486,69 @@ impl RleRunVec {
     }
 }
 
+/// Writer that writes RLE runs one at a time, merging them and wrapping lines
+/// the same way as `Rle`'s `Display` implementation.
+pub struct RleRunWriter<W> {
+    writer: W,
+    two_states: TwoState,
+    line_len: usize,
+    /// Runs that may still be merged with later runs.
+    pending: RleRunVec,
+}
+impl<W: io::Write> RleRunWriter<W> {
+    pub fn new(writer: W, two_states: TwoState) -> Self {
+        Self {
+            writer,
+            two_states,
+            line_len: 0,
+            pending: RleRunVec::default(),
+        }
+    }
+    pub fn append(&mut self, run: impl Into<RleRun>) -> io::Result<()> {
+        self.pending.append(run);
+        // Merging never removes a run of nonzero cells, so everything before
+        // the last one is final.
+        let final_runs = self
+            .pending
+            .0
+            .iter()
+            .rposition(|run| matches!(run.item, RleItem::Cell(state) if state != 0))
+            .unwrap_or(0);
+        for run in self.pending.0.drain(..final_runs).collect_vec() {
+            self.write_run(run)?;
+        }
+        Ok(())
+    }
+    /// Writes all remaining runs, followed by a newline.
+    pub fn finish(mut self) -> io::Result<()> {
+        for run in std::mem::take(&mut self.pending.0) {
+            self.write_run(run)?;
+        }
+        writeln!(self.writer)
+    }
+    fn write_run(&mut self, run: RleRun) -> io::Result<()> {
+        if wrap_line(&mut self.line_len, run) {
+            writeln!(self.writer)?;
+        }
+        match self.two_states {
+            TwoState::TwoStates => write!(self.writer, "{:b}", run),
+            TwoState::MoreStates => write!(self.writer, "{}", run),
+        }
+    }
+}
+
+/// Adds the length of a run to the length of the current line, returning
+/// `true` if the run should start a new line instead.
+fn wrap_line(line_len: &mut usize, run: RleRun) -> bool {
+    *line_len += run.str_len();
+    if *line_len > MAX_LINE_LEN {
+        *line_len = run.str_len();
+        true
+    } else {
+        false
+    }
+}
+
 /// Run of repeated items in an RLE.
 #[derive(Debug, Copy, Clone)]
 pub struct RleRun {
@@ -374,12 +603,12 @@ impl FromStr for RleRun {
     fn from_str(s: &str) -> RleResult<Self> {
         let end_of_count = s
             .find(|ch: char| !ch.is_ascii_digit())
-            .ok_or(RleError::InvalidItem)?;
+            .ok_or(RleErrorKind::InvalidItem)?;
 
         let count: usize = if end_of_count != 0 {
             s[..end_of_count]
                 .parse()
-                .map_err(|_| RleError::InvalidCount)?
+                .map_err(|_| RleErrorKind::InvalidCount)?
         } else {
             1
         };
@@ -443,7 +672,7 @@ impl FromStr for RleItem {
     fn from_str(s: &str) -> RleResult<Self> {
         // Make sure the string is ASCII so that we can use byte indexing.
         if !s.is_ascii() {
-            return Err(RleError::NonAscii);
+            return Err(RleErrorKind::NonAscii.into());
         }
 
         if let Some((ch,)) = s.chars().collect_tuple() {
@@ -462,7 +691,7 @@ impl FromStr for RleItem {
 
                 '!' => Ok(Self::End),
 
-                _ => Err(RleError::UnknownSymbol(ch)),
+                _ => Err(RleErrorKind::UnknownSymbol(ch).into()),
             }
         } else if let Some((ch1, ch2)) = s.chars().collect_tuple() {
             // Two characters
@@ -481,12 +710,12 @@ impl FromStr for RleItem {
                 ('%', 'W') => Ok(Self::Next(W)), // Advance along W axis
                 ('%', 'U') => Ok(Self::Next(U)), // Advance along U axis
                 ('%', 'V') => Ok(Self::Next(V)), // Advance along V axis
-                ('%', _) => Err(RleError::ExpectedWUV),
+                ('%', _) => Err(RleErrorKind::ExpectedWUV.into()),
 
-                _ => Err(RleError::UnknownSymbolPair(ch1, ch2)),
+                _ => Err(RleErrorKind::UnknownSymbolPair(ch1, ch2).into()),
             }
         } else {
-            Err(RleError::InvalidItem)
+            Err(RleErrorKind::InvalidItem.into())
         }
     }
 }
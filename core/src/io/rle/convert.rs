@@ -1,19 +1,46 @@
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
+
 use super::*;
-use crate::axis::Axis::X;
+use crate::automaton::{Automaton, NdAutomaton};
+use crate::axis::Axis::{X, Y, Z};
 use crate::dim::Dim;
 use crate::io::utils::{SemiReverseRectIter, SemiReverseRectIterItem};
+use crate::io::{PatternMetadata, ResolveRule};
 use crate::ndrect::{BigRect, NdRect};
 use crate::ndtree::{
     LeafNodeRef, NdTree, NodeRef, NodeRefEnum, NodeRefTrait, NonLeafNodeRef, Region, SharedNodePool,
 };
-use crate::ndvec::{BigVec, NdVec, UVec};
+use crate::ndvec::{BigVec, BigVec6D, NdVec, UVec};
 use crate::num::{BigInt, Integer, ToPrimitive, Zero};
+use crate::sim::rule::{NdRule, Rule};
 
 impl CaFormatTrait for Rle {
     fn to_string_2_state(&self) -> String {
         format!("{:b}", self)
     }
 
+    fn write_to(&self, mut writer: impl io::Write, two_states: TwoState) -> RleResult<()> {
+        match two_states {
+            TwoState::TwoStates => write!(writer, "{:b}", self)?,
+            TwoState::MoreStates => write!(writer, "{}", self)?,
+        }
+        Ok(())
+    }
+    fn read_from(reader: impl io::BufRead) -> RleResult<Self> {
+        let mut parser = RleParser::new(reader);
+        let mut runs = vec![];
+        parser.read_runs(|run| {
+            runs.push(run);
+            Ok(())
+        })?;
+        Ok(Self {
+            runs,
+            ..parser.to_rle()?
+        })
+    }
+
     fn rule(&self) -> Option<&str> {
         self.header.rule.as_ref().map(|r| r.as_str())
     }
@@ -47,35 +74,15 @@ impl CaFormatTrait for Rle {
     }
 
     fn to_ndtree<D: Dim>(&self, node_pool: SharedNodePool<D>) -> Result<NdTree<D>, Self::Err> {
-        let mut ret = NdTree::with_node_pool(node_pool);
-
-        let start: BigVec<D> = self.first_run_start();
-
-        let mut pos = start.clone();
-        for run in &self.runs {
-            match run.item {
-                RleItem::Cell(0) => pos[X] += run.count,
-                RleItem::Cell(state) => {
-                    for _ in 0..run.count {
-                        ret.set_cell(&pos, state);
-                        pos[X] += 1;
-                    }
-                }
-                RleItem::Next(axis) => {
-                    // Reset all axes before this one.
-                    for &ax in D::axes() {
-                        if ax < axis {
-                            pos[ax] = start[ax].clone();
-                        }
-                    }
-                    // Advance along this axis, but negative because all axes
-                    // except X are reversed.
-                    pos[axis] -= run.count;
-                }
-                RleItem::End => break,
-            }
+        if let Some(span) = self.axis_spans[D::NDIM..].iter().flatten().next() {
+            return Err(RleError::from(RleErrorKind::TooManyDimensions).with_span(span.clone()));
         }
 
+        let mut ret = NdTree::with_node_pool(node_pool);
+        let mut cursor = RleCursor::new(self.first_run_start());
+        for &run in &self.runs {
+            cursor.apply(&mut ret, run)?;
+        }
         Ok(ret)
     }
 
@@ -83,63 +90,440 @@ impl CaFormatTrait for Rle {
         ndtree: &NdTree<D>,
         rect: Option<BigRect<D>>,
     ) -> Result<Self, Self::Err> {
-        let mut header = RleHeader {
-            size: NdVec::origin(),
-            rule: None,
-        };
-        let mut cxrle_header = None;
+        let (mut ret, rect) = rle_header_of_ndtree(ndtree, rect)?;
         let mut runs = RleRunVec::default();
+        rle_runs_of_ndtree(ndtree, rect, |run| {
+            runs.append(run);
+            Ok(())
+        })?;
+        ret.runs = runs.into_vec();
+        Ok(ret)
+    }
 
-        if let Some(bounding_rect) = rect.or_else(|| ndtree.bounding_rect()) {
-            let size = bounding_rect.size();
-            let pos = {
-                // Negate all axes except X.
-                let mut cxrle_bounding_rect = bounding_rect.clone();
-                for &ax in &D::axes()[1..] {
-                    cxrle_bounding_rect.negate_axis(ax);
-                }
-                cxrle_bounding_rect.min()
-            };
+    fn from_reader_to_automaton<R: ResolveRule>(
+        reader: impl io::BufRead,
+        resolve_rule: R,
+    ) -> RleResult<Result<Automaton, R::Err>> {
+        // The rule is in the RLE header, which comes before any runs.
+        let mut parser = RleParser::new(reader);
+        parser.read_header()?;
+        let rule_name = parser.header.as_ref().and_then(|h| h.rule.clone());
+        Ok(match resolve_rule.resolve_rule(rule_name.as_deref()) {
+            Ok(Rule::Rule1D(rule)) => Ok(Automaton::Automaton1D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule2D(rule)) => Ok(Automaton::Automaton2D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule3D(rule)) => Ok(Automaton::Automaton3D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule4D(rule)) => Ok(Automaton::Automaton4D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule5D(rule)) => Ok(Automaton::Automaton5D(parser.read_ndautomaton(rule)?)),
+            Ok(Rule::Rule6D(rule)) => Ok(Automaton::Automaton6D(parser.read_ndautomaton(rule)?)),
+            Err(rule_resolution_error) => Err(rule_resolution_error),
+        })
+    }
+    fn from_reader_to_ndautomaton<D: Dim>(
+        reader: impl io::BufRead,
+        rule: Arc<dyn NdRule<D>>,
+    ) -> RleResult<NdAutomaton<D>> {
+        RleParser::new(reader).read_ndautomaton(rule)
+    }
+    fn from_reader_to_ndtree<D: Dim>(reader: impl io::BufRead) -> RleResult<NdTree<D>> {
+        let (_, ndtree) = RleParser::new(reader).read_ndtree(SharedNodePool::new())?;
+        Ok(ndtree)
+    }
 
-            header.size = NdVec::repeat(1);
-            let mut cxrle_pos = NdVec::origin();
-            for &ax in D::axes() {
-                header.size[ax] = size[ax].to_usize().ok_or(RleError::TooBig)?;
-                cxrle_pos[ax] = pos[ax].clone();
+    fn from_ndautomaton_to_writer<D: Dim>(
+        automaton: &NdAutomaton<D>,
+        rect: Option<BigRect<D>>,
+        writer: impl io::Write,
+    ) -> RleResult<()> {
+        let (header, rect) = rle_header_of_ndtree(&automaton.ndtree, rect)?;
+        let header = header
+            .with_rule(Some(&automaton.rule))
+            .with_generation(automaton.generations.clone())
+            .with_comments(automaton.metadata.to_comments());
+        let two_states = TwoState::from_rule(&*automaton.rule);
+        write_rle(&header, &automaton.ndtree, rect, two_states, writer)
+    }
+    fn from_ndtree_to_writer<D: Dim>(
+        ndtree: &NdTree<D>,
+        rect: Option<BigRect<D>>,
+        two_states: TwoState,
+        writer: impl io::Write,
+    ) -> RleResult<()> {
+        let (header, rect) = rle_header_of_ndtree(ndtree, rect)?;
+        write_rle(&header, ndtree, rect, two_states, writer)
+    }
+}
+
+/// Parser that reads an RLE one line at a time, passing each run to a closure
+/// instead of storing them.
+struct RleParser<R> {
+    reader: R,
+    /// Buffer for the current line, reused for every line.
+    line_buffer: String,
+    line_number: usize,
+    /// Whether `line_buffer` contains runs that have not been parsed yet.
+    has_pending_runs: bool,
+
+    header: Option<RleHeader>,
+    cxrle_header: Option<CxrleHeader>,
+    golly_3d_header: Option<Golly3DHeader>,
+    golly_3d_pos: Option<BigVec6D>,
+    golly_3d_gen: Option<BigInt>,
+    comments: String,
+    axis_spans: [Option<TextSpan>; 6],
+
+    /// Whether any runs have been parsed.
+    has_runs: bool,
+    /// Whether the end of the pattern (`!`) has been reached.
+    ended: bool,
+}
+impl<R: io::BufRead> RleParser<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line_buffer: String::new(),
+            line_number: 0,
+            has_pending_runs: false,
+
+            header: None,
+            cxrle_header: None,
+            golly_3d_header: None,
+            golly_3d_pos: None,
+            golly_3d_gen: None,
+            comments: String::new(),
+            axis_spans: Default::default(),
+
+            has_runs: false,
+            ended: false,
+        }
+    }
+
+    /// Reads lines up to the first line of runs, which is left to be parsed by
+    /// `read_runs()`.
+    fn read_header(&mut self) -> RleResult<()> {
+        while !self.has_pending_runs && self.read_line()? {}
+        match self.header {
+            Some(_) => Ok(()),
+            None => Err(RleErrorKind::MissingHeader.into()),
+        }
+    }
+    /// Reads the rest of the input, calling `on_run` for each run.
+    fn read_runs(&mut self, mut on_run: impl FnMut(RleRun) -> RleResult<()>) -> RleResult<()> {
+        loop {
+            if self.has_pending_runs {
+                self.parse_runs(&mut on_run)?;
             }
-            cxrle_header = Some(CxrleHeader {
-                pos: cxrle_pos,
-                gen: BigInt::zero(),
-            });
-
-            let bounding_rect = bounding_rect - ndtree.base_pos();
-            let row_rect_min = bounding_rect.min();
-            let mut row_rect_max = bounding_rect.max();
-            row_rect_max[X] = row_rect_min[X].clone();
-            let row_rect = NdRect::span(row_rect_min, row_rect_max);
-            let row_length = bounding_rect.size()[X].to_usize().ok_or(RleError::TooBig)?;
-            for rect_iter_item in SemiReverseRectIter::new(row_rect) {
-                match rect_iter_item {
-                    SemiReverseRectIterItem::Pos(row_start) => {
-                        let root_node = ndtree.root_ref();
-                        let runs_iter = rle_row_of_node(root_node.as_ref(), row_start, row_length);
-                        runs.try_extend(runs_iter)?;
+            if !self.read_line()? {
+                return Ok(());
+            }
+        }
+    }
+    /// Reads the rest of the input into an ND-tree, returning it along with the
+    /// RLE without any runs.
+    fn read_ndtree<D: Dim>(mut self, node_pool: SharedNodePool<D>) -> RleResult<(Rle, NdTree<D>)> {
+        self.read_header()?;
+        let start: BigVec<D> = self.to_rle()?.first_run_start();
+
+        let mut ndtree = NdTree::with_node_pool(node_pool);
+        let mut cursor = RleCursor::new(start.clone());
+        self.read_runs(|run| cursor.apply(&mut ndtree, run))?;
+
+        // A CXRLE header after the runs may have moved the pattern.
+        let rle = self.to_rle()?;
+        let new_start: BigVec<D> = rle.first_run_start();
+        if new_start != start {
+            ndtree.set_base_pos(new_start - &start + ndtree.base_pos());
+        }
+        Ok((rle, ndtree))
+    }
+    /// Reads the rest of the input into an automaton with the given rule.
+    fn read_ndautomaton<D: Dim>(self, rule: Arc<dyn NdRule<D>>) -> RleResult<NdAutomaton<D>> {
+        let (rle, ndtree) = self.read_ndtree(SharedNodePool::new())?;
+        Ok(NdAutomaton {
+            ndtree,
+            rule,
+            generations: rle.generation(),
+            metadata: PatternMetadata::from_comments(rle.comments()),
+        })
+    }
+    /// Returns the RLE that has been read so far, without any runs.
+    fn to_rle(&self) -> RleResult<Rle> {
+        let header: RleHeader = self.header.clone().ok_or(RleErrorKind::MissingHeader)?;
+        let mut cxrle_header = self.cxrle_header.clone();
+
+        // Golly's 3D dialect stores the position and generation count in its
+        // own header.
+        if let Some(golly_3d_header) = &self.golly_3d_header {
+            let cxrle = cxrle_header.get_or_insert_with(CxrleHeader::default);
+            match &self.golly_3d_pos {
+                Some(pos) => cxrle.pos = pos.clone(),
+                None => {
+                    for &ax in &[X, Y, Z] {
+                        cxrle.pos[ax] = golly_3d_header.centered_pos(header.size[ax]);
                     }
-                    SemiReverseRectIterItem::Next(ax) => runs.append(RleItem::Next(ax)),
                 }
             }
+            if let Some(gen) = &self.golly_3d_gen {
+                cxrle.gen = gen.clone();
+            }
         }
 
-        runs.append(RleItem::End);
-        let runs = runs.into_vec();
-
         Ok(Rle {
             header,
             cxrle_header,
-            runs,
-            comments: String::new(),
+            golly_3d_header: self.golly_3d_header.clone(),
+            runs: vec![],
+            comments: self.comments.clone(),
+            axis_spans: self.axis_spans.clone(),
         })
     }
+
+    /// Reads the next line, returning `false` if there are no more lines. If
+    /// the line contains runs, they are left in `line_buffer` to be parsed by
+    /// `parse_runs()`.
+    fn read_line(&mut self) -> RleResult<bool> {
+        self.line_buffer.clear();
+        if self.reader.read_line(&mut self.line_buffer)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        let line_number = self.line_number;
+        let raw_line = self.line_buffer.trim_end_matches(&['\n', '\r'][..]);
+        let line = raw_line.trim();
+        let line_span = || TextSpan::whole_line(line_number, raw_line);
+
+        if line.starts_with("#CXRLE") {
+            if self.cxrle_header.is_some() {
+                return Err(
+                    RleError::from(RleErrorKind::DuplicateCxrleHeader).with_span(line_span())
+                );
+            }
+            self.cxrle_header = Some(
+                line.parse()
+                    .map_err(|e: RleError| e.with_span(line_span()))?,
+            );
+        } else if line.starts_with('#') {
+            self.comments.push_str(line);
+            self.comments.push('\n');
+        } else if line.starts_with("3D") && self.header.is_none() && !self.has_runs {
+            if self.golly_3d_header.is_some() {
+                return Err(
+                    RleError::from(RleErrorKind::DuplicateGolly3DHeader).with_span(line_span())
+                );
+            }
+            let (h, pos, gen) =
+                Golly3DHeader::parse_line(line).map_err(|e: RleError| e.with_span(line_span()))?;
+            self.golly_3d_header = Some(h);
+            self.golly_3d_pos = pos;
+            self.golly_3d_gen = gen;
+        } else if self.ended {
+            // Ignore everything after the end of the pattern.
+        } else if line.is_empty() {
+            // Skip blank lines.
+        } else if line.starts_with('x') {
+            if self.header.is_some() {
+                return Err(RleError::from(RleErrorKind::DuplicateRleHeader).with_span(line_span()));
+            }
+            let parsed = if self.golly_3d_header.is_some() {
+                RleHeader::from_golly_3d_line(line)
+            } else {
+                line.parse()
+            };
+            self.header = Some(parsed.map_err(|e: RleError| e.with_span(line_span()))?);
+        } else if self.header.is_none() {
+            // Runs are interpreted as they are read, so the header must come
+            // first.
+            return Err(RleErrorKind::MissingHeader.into());
+        } else {
+            self.has_pending_runs = true;
+        }
+        Ok(true)
+    }
+    /// Parses the runs in `line_buffer`, calling `on_run` for each one.
+    fn parse_runs(&mut self, on_run: &mut impl FnMut(RleRun) -> RleResult<()>) -> RleResult<()> {
+        self.has_pending_runs = false;
+        self.has_runs = true;
+
+        let line_number = self.line_number;
+        let raw_line = self.line_buffer.trim_end_matches(&['\n', '\r'][..]);
+        let mut unparsed_start = 0;
+        for run_match in RLE_RUN_REGEX.find_iter(raw_line) {
+            check_unparsed_rle(line_number, raw_line, unparsed_start..run_match.start())?;
+            unparsed_start = run_match.end();
+
+            let run_span = || TextSpan::new(line_number, raw_line, run_match.range());
+            let run: RleRun = run_match
+                .as_str()
+                .parse()
+                .map_err(|e: RleError| e.with_span(run_span()))?;
+            if let RleItem::Next(axis) = run.item {
+                self.axis_spans[axis as usize].get_or_insert_with(run_span);
+            }
+            on_run(run).map_err(|e| e.with_span(run_span()))?;
+            if run.item == RleItem::End {
+                self.ended = true;
+                return Ok(());
+            }
+        }
+        check_unparsed_rle(line_number, raw_line, unparsed_start..raw_line.len())
+    }
+}
+
+/// Position at which the next RLE run is placed in an ND-tree.
+struct RleCursor<D: Dim> {
+    /// Starting position of the first run.
+    start: BigVec<D>,
+    /// Starting position of the next run.
+    pos: BigVec<D>,
+}
+impl<D: Dim> RleCursor<D> {
+    fn new(start: BigVec<D>) -> Self {
+        Self {
+            pos: start.clone(),
+            start,
+        }
+    }
+    /// Places a run in an ND-tree and advances past it.
+    fn apply(&mut self, ndtree: &mut NdTree<D>, run: RleRun) -> RleResult<()> {
+        match run.item {
+            RleItem::Cell(0) => self.pos[X] += run.count,
+            RleItem::Cell(state) => {
+                for _ in 0..run.count {
+                    ndtree.set_cell(&self.pos, state);
+                    self.pos[X] += 1;
+                }
+            }
+            RleItem::Next(axis) if axis as usize >= D::NDIM => {
+                return Err(RleErrorKind::TooManyDimensions.into());
+            }
+            RleItem::Next(axis) => {
+                // Reset all axes before this one.
+                for &ax in D::axes() {
+                    if ax < axis {
+                        self.pos[ax] = self.start[ax].clone();
+                    }
+                }
+                // Advance along this axis, but negative because all axes
+                // except X are reversed.
+                self.pos[axis] -= run.count;
+            }
+            RleItem::End => (),
+        }
+        Ok(())
+    }
+}
+
+/// Returns an RLE with the headers for part of an ND-tree, but no runs, along
+/// with the rectangle to export. If `rect` is `None`, the rectangle is the
+/// bounding rectangle of the ND-tree.
+fn rle_header_of_ndtree<D: Dim>(
+    ndtree: &NdTree<D>,
+    rect: Option<BigRect<D>>,
+) -> RleResult<(Rle, Option<BigRect<D>>)> {
+    let mut header = RleHeader {
+        size: NdVec::origin(),
+        rule: None,
+    };
+    let mut cxrle_header = None;
+
+    let rect = rect.or_else(|| ndtree.bounding_rect());
+    if let Some(bounding_rect) = &rect {
+        let size = bounding_rect.size();
+        let pos = {
+            // Negate all axes except X.
+            let mut cxrle_bounding_rect = bounding_rect.clone();
+            for &ax in &D::axes()[1..] {
+                cxrle_bounding_rect.negate_axis(ax);
+            }
+            cxrle_bounding_rect.min()
+        };
+
+        header.size = NdVec::repeat(1);
+        let mut cxrle_pos = NdVec::origin();
+        for &ax in D::axes() {
+            header.size[ax] = size[ax].to_usize().ok_or(RleErrorKind::TooBig)?;
+            cxrle_pos[ax] = pos[ax].clone();
+        }
+        cxrle_header = Some(CxrleHeader {
+            pos: cxrle_pos,
+            gen: BigInt::zero(),
+        });
+    }
+
+    let ret = Rle {
+        header,
+        cxrle_header,
+        golly_3d_header: None,
+        runs: vec![],
+        comments: String::new(),
+        axis_spans: Default::default(),
+    };
+    Ok((ret, rect))
+}
+
+/// Calls `on_run` for each run of cells in `rect` of an ND-tree, followed by
+/// the end of the pattern. Consecutive runs are not merged.
+fn rle_runs_of_ndtree<D: Dim>(
+    ndtree: &NdTree<D>,
+    rect: Option<BigRect<D>>,
+    mut on_run: impl FnMut(RleRun) -> RleResult<()>,
+) -> RleResult<()> {
+    if let Some(bounding_rect) = rect {
+        let bounding_rect = bounding_rect - ndtree.base_pos();
+        let row_rect_min = bounding_rect.min();
+        let mut row_rect_max = bounding_rect.max();
+        row_rect_max[X] = row_rect_min[X].clone();
+        let row_rect = NdRect::span(row_rect_min, row_rect_max);
+        let row_length = bounding_rect.size()[X]
+            .to_usize()
+            .ok_or(RleErrorKind::TooBig)?;
+        let root_node = ndtree.root_ref();
+        for rect_iter_item in SemiReverseRectIter::new(row_rect) {
+            match rect_iter_item {
+                SemiReverseRectIterItem::Pos(row_start) => {
+                    for run in rle_row_of_node(root_node.as_ref(), row_start, row_length) {
+                        on_run(run?)?;
+                    }
+                }
+                SemiReverseRectIterItem::Next(ax) => on_run(RleItem::Next(ax).into())?,
+            }
+        }
+    }
+    on_run(RleItem::End.into())
+}
+
+/// Writes an RLE with the given headers containing part of an ND-tree.
+fn write_rle<D: Dim>(
+    header: &Rle,
+    ndtree: &NdTree<D>,
+    rect: Option<BigRect<D>>,
+    two_states: TwoState,
+    mut writer: impl io::Write,
+) -> RleResult<()> {
+    write!(writer, "{}", header.header_display())?;
+    let mut run_writer = RleRunWriter::new(writer, two_states);
+    rle_runs_of_ndtree(ndtree, rect, |run| Ok(run_writer.append(run)?))?;
+    run_writer.finish()?;
+    Ok(())
+}
+
+/// Returns an error if a part of a line of RLE that does not match any RLE
+/// runs contains anything other than whitespace.
+fn check_unparsed_rle(line_number: usize, line: &str, range: Range<usize>) -> RleResult<()> {
+    let unparsed = &line[range.clone()];
+    match unparsed.char_indices().find(|(_, ch)| !ch.is_whitespace()) {
+        None => Ok(()),
+        Some((i, ch)) => {
+            let start = range.start + i;
+            let kind = if ch.is_ascii_digit() {
+                // A count with no item after it
+                RleErrorKind::InvalidItem
+            } else {
+                RleErrorKind::UnknownSymbol(ch)
+            };
+            let span = TextSpan::new(line_number, line, start..start + ch.len_utf8());
+            Err(RleError::from(kind).with_span(span))
+        }
+    }
 }
 
 fn rle_row_of_node<'pool, D: Dim>(
@@ -160,7 +544,7 @@ fn rle_row_of_node<'pool, D: Dim>(
                     max_length,
                     (node.layer().big_len() - &node.layer().modulo_pos(&start_pos)[X])
                         .to_usize()
-                        .ok_or(RleError::TooBig)?,
+                        .ok_or(RleErrorKind::TooBig)?,
                 ),
                 item: RleItem::Cell(single_state),
             })
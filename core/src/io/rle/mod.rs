@@ -6,15 +6,24 @@
 //! and offset, so Y coordinates of 2D RLEs must be negated and offset.
 //!
 //! See NDCell documentation for a description of this format.
+//!
+//! The dialect used by Golly's 3D script (`.rle3` files) is also supported. It
+//! begins with a header of the form `3D version=1 size=N pos=x,y,z gen=G`,
+//! followed by comments and a size line such as `x=3 y=3 z=3 rule=3D5..7/6`.
+//! Use `Rle::with_golly_3d()` to export to this dialect.
 
 mod components;
 mod convert;
 
-use super::{CaFormatTrait, TwoState};
+use super::{CaFormatTrait, TextSpan, TwoState};
 pub use components::Rle;
 use components::*;
 
 const MAX_LINE_LEN: usize = 70;
+/// Default grid size used by Golly's 3D script.
+const GOLLY_3D_DEFAULT_SIZE: usize = 30;
+/// Maximum grid size supported by Golly's 3D script.
+const GOLLY_3D_MAX_SIZE: usize = 100;
 
 /// Result type returned by fallible RLE routines.
 pub type RleResult<T> = Result<T, RleError>;
@@ -27,9 +36,46 @@ lazy_static::lazy_static! {
 }
 
 /// Error encountered during RLE import/export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RleError {
+    /// The type of error.
+    pub kind: RleErrorKind,
+    /// The location of the error in the RLE (if any).
+    pub span: Option<TextSpan>,
+}
+impl RleError {
+    /// Attaches a span to this error, if it does not already have one.
+    pub fn with_span(mut self, span: TextSpan) -> Self {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+}
+impl From<RleErrorKind> for RleError {
+    fn from(kind: RleErrorKind) -> Self {
+        Self { kind, span: None }
+    }
+}
+impl From<std::io::Error> for RleError {
+    fn from(e: std::io::Error) -> Self {
+        RleErrorKind::IoError(e.to_string()).into()
+    }
+}
+impl std::fmt::Display for RleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(span) = &self.span {
+            write!(f, " at {}", span)?;
+        }
+        Ok(())
+    }
+}
+
+/// Type of error encountered during RLE import/export.
 #[allow(missing_docs)]
 #[derive(Debug, Clone, Eq, PartialEq)]
-pub enum RleError {
+pub enum RleErrorKind {
     UnknownSymbol(char),
     UnknownSymbolPair(char, char),
     ExpectedABC(char),
@@ -40,6 +86,10 @@ pub enum RleError {
     BadCxrleHeader,
     DuplicateRleHeader,
     DuplicateCxrleHeader,
+    BadGolly3DHeader,
+    DuplicateGolly3DHeader,
+    Golly3DTooManyAxes,
+    TooManyDimensions,
     MissingHeader,
     InvalidItem,
     TooBig,
@@ -47,33 +97,43 @@ pub enum RleError {
     UnknownRule(String),
     ErrorLoadingRule(String),
     BadRuleDimensionality { expected: usize, got: usize },
+    IoError(String),
 }
-impl std::fmt::Display for RleError {
+impl std::fmt::Display for RleErrorKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            RleError::UnknownSymbol(ch) => write!(f, "Unknown symbol: '{}'", ch),
-            RleError::UnknownSymbolPair(ch1, ch2) => write!(f, "Unknown symbols: '{}{}'", ch1, ch2),
-            RleError::ExpectedABC(ch) => {
+            RleErrorKind::UnknownSymbol(ch) => write!(f, "Unknown symbol: '{}'", ch),
+            RleErrorKind::UnknownSymbolPair(ch1, ch2) => write!(f, "Unknown symbols: '{}{}'", ch1, ch2),
+            RleErrorKind::ExpectedABC(ch) => {
                 write!(f, "Expected letter from 'A' to 'X' to follow '{}'", ch)
             }
-            RleError::ExpectedWUV => write!(f, "Expected 'W', 'U', or 'V' to follow '%'"),
-            RleError::StateOutOfRange(state) => write!(f, "State out of range: #{}", state),
-            RleError::InvalidSize => write!(f, "Invalid size"),
-            RleError::InvalidCount => write!(f, "Invalid count"),
-            RleError::BadCxrleHeader => write!(f, "Bad CXRLE header"),
-            RleError::DuplicateRleHeader => write!(f, "Duplicate RLE header"),
-            RleError::DuplicateCxrleHeader => write!(f, "Duplicate CXRLE header"),
-            RleError::MissingHeader => write!(f, "Missing RLE header"),
-            RleError::InvalidItem => write!(f, "Invalid RLE item"),
-            RleError::TooBig => write!(f, "Pattern is too big; try another format like Macrocell"),
-            RleError::NonAscii => write!(f, "RLE contains non-ASCII characters"),
-            RleError::UnknownRule(name) => write!(f, "Unknown rule {:?}", name),
-            RleError::ErrorLoadingRule(name) => write!(
+            RleErrorKind::ExpectedWUV => write!(f, "Expected 'W', 'U', or 'V' to follow '%'"),
+            RleErrorKind::StateOutOfRange(state) => write!(f, "State out of range: #{}", state),
+            RleErrorKind::InvalidSize => write!(f, "Invalid size"),
+            RleErrorKind::InvalidCount => write!(f, "Invalid count"),
+            RleErrorKind::BadCxrleHeader => write!(f, "Bad CXRLE header"),
+            RleErrorKind::DuplicateRleHeader => write!(f, "Duplicate RLE header"),
+            RleErrorKind::DuplicateCxrleHeader => write!(f, "Duplicate CXRLE header"),
+            RleErrorKind::BadGolly3DHeader => write!(f, "Bad Golly 3D RLE header"),
+            RleErrorKind::DuplicateGolly3DHeader => write!(f, "Duplicate Golly 3D RLE header"),
+            RleErrorKind::Golly3DTooManyAxes => {
+                write!(f, "Golly 3D RLE only supports patterns with up to 3 dimensions")
+            }
+            RleErrorKind::TooManyDimensions => {
+                write!(f, "Pattern has more dimensions than the automaton")
+            }
+            RleErrorKind::MissingHeader => write!(f, "Missing RLE header"),
+            RleErrorKind::InvalidItem => write!(f, "Invalid RLE item"),
+            RleErrorKind::TooBig => write!(f, "Pattern is too big; try another format like Macrocell"),
+            RleErrorKind::NonAscii => write!(f, "RLE contains non-ASCII characters"),
+            RleErrorKind::UnknownRule(name) => write!(f, "Unknown rule {:?}", name),
+            RleErrorKind::ErrorLoadingRule(name) => write!(
                 f,
                 "Something is wrong with the rule {:?}\nTry creating a new pattern with that rule to troubleshoot",
                 name
             ),
-            RleError::BadRuleDimensionality{expected, got} => write!(f, "Expected {}D rule; got {}D rule", expected, got),
+            RleErrorKind::BadRuleDimensionality{expected, got} => write!(f, "Expected {}D rule; got {}D rule", expected, got),
+            RleErrorKind::IoError(e) => write!(f, "I/O error: {}", e),
         }
     }
 }
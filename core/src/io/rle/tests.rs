@@ -1,8 +1,11 @@
-use proptest::*;
+use itertools::Itertools;
+use proptest::prelude::*;
+use std::sync::Arc;
 
 use super::*;
 use crate::axis::Axis::{U, V, W, Y, Z};
 use crate::prelude::*;
+use crate::sim::rule::DummyRule;
 
 #[test]
 fn test_rle_item() {
@@ -159,3 +162,333 @@ fn test_rle_rect() {
         }
     }
 }
+
+#[test]
+fn test_rle_streaming() {
+    let mut ndtree = NdTree2D::new();
+    for i in -100..=100 {
+        ndtree.set_cell(&NdVec::big([i, i * 3 % 17]), (i + 101) as u8);
+    }
+
+    // Writing to a writer produces the same output as writing to a string.
+    let mut buffer = vec![];
+    Rle::from_ndtree_to_writer(&ndtree, None, TwoState::MoreStates, &mut buffer).unwrap();
+    let rle_string = Rle::from_ndtree_to_string(&ndtree, None, TwoState::MoreStates).unwrap();
+    assert_eq!(rle_string.as_bytes(), &buffer[..]);
+    // Runs are written as they are generated, but the output is the same as
+    // for a whole `Rle`.
+    assert_eq!(
+        Rle::from_ndtree(&ndtree, None).unwrap().to_string(),
+        rle_string
+    );
+
+    // Reading from a reader produces the same output as reading from a
+    // string, even if lines are split across many reads.
+    let reader = std::io::BufReader::with_capacity(3, &buffer[..]);
+    let reimported: NdTree2D = Rle::from_reader_to_ndtree(reader).unwrap();
+    assert_eq!(
+        rle_string,
+        Rle::from_ndtree_to_string(&reimported, None, TwoState::MoreStates).unwrap(),
+    );
+
+    // I/O errors are reported as such.
+    let reader = std::io::BufReader::new(&[0xFF_u8, 0xFF, b'\n'][..]);
+    assert!(matches!(
+        Rle::from_reader_to_ndtree::<Dim2D>(reader),
+        Err(RleError {
+            kind: RleErrorKind::IoError(_),
+            ..
+        }),
+    ));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    /// Tests that writing runs directly to a writer produces the same output
+    /// as building an `Rle` first, in every dimensionality.
+    #[test]
+    fn test_rle_streaming_nd(
+        cells in prop::collection::vec(
+            (prop::array::uniform6(-2_isize..2), 0..=3_u8),
+            0..30,
+        ),
+    ) {
+        _test_rle_streaming_nd::<Dim1D>(&cells);
+        _test_rle_streaming_nd::<Dim2D>(&cells);
+        _test_rle_streaming_nd::<Dim3D>(&cells);
+        _test_rle_streaming_nd::<Dim4D>(&cells);
+        _test_rle_streaming_nd::<Dim5D>(&cells);
+        _test_rle_streaming_nd::<Dim6D>(&cells);
+    }
+}
+
+fn _test_rle_streaming_nd<D: Dim>(cells: &[([isize; 6], u8)]) {
+    let mut ndtree = NdTree::<D>::new();
+    for &(pos, state) in cells {
+        ndtree.set_cell(&BigVec::from_fn(|ax| BigInt::from(pos[ax as usize])), state);
+    }
+
+    let rle = Rle::from_ndtree(&ndtree, None).unwrap();
+    let s = Rle::from_ndtree_to_string(&ndtree, None, TwoState::MoreStates).unwrap();
+    assert_eq!(rle.to_string(), s);
+    let s = Rle::from_ndtree_to_string(&ndtree, None, TwoState::TwoStates).unwrap();
+    assert_eq!(rle.to_string_2_state(), s);
+
+    let reimported: NdTree<D> = Rle::from_string_to_ndtree(&rle.to_string()).unwrap();
+    assert_eq!(ndtree.bounding_rect(), reimported.bounding_rect());
+    assert_eq!(
+        ndtree.nonzero_cells().collect_vec(),
+        reimported.nonzero_cells().collect_vec(),
+    );
+}
+
+#[test]
+fn test_rle_late_cxrle_header() {
+    // The CXRLE header may come after the runs, in which case the pattern is
+    // moved after it has been read.
+    let ndtree: NdTree2D =
+        Rle::from_string_to_ndtree("x = 3, y = 1\n3o!\n#CXRLE Pos=10,-20\n").unwrap();
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([10, 20]), NdVec::big([12, 20]))),
+        ndtree.bounding_rect(),
+    );
+
+    // The header line must come before the runs.
+    let e = Rle::from_string_to_ndtree::<Dim2D>("3o!\nx = 3, y = 1\n").unwrap_err();
+    assert_eq!(RleErrorKind::MissingHeader, e.kind);
+}
+
+#[test]
+fn test_rle_error_spans() {
+    let import = |s: &str| Rle::from_string_to_ndtree::<Dim2D>(s).unwrap_err();
+
+    let e = import("#C glider\nx = 3, y = 3\nbo$2bo$\n  3o$q!\n");
+    assert_eq!(RleErrorKind::UnknownSymbol('q'), e.kind);
+    let span = e.span.as_ref().unwrap();
+    assert_eq!((4, 6, 1), (span.line, span.column, span.len));
+    assert_eq!(
+        "Unknown symbol: 'q' at line 4, column 6:\n3o$q!\n   ^",
+        e.to_string(),
+    );
+
+    let e = import("x = 3, y = 3\nbo$2bo$3o12\n!\n");
+    assert_eq!(RleErrorKind::InvalidItem, e.kind);
+    assert_eq!(Some((2, 10)), e.span.map(|span| (span.line, span.column)));
+
+    let e = import("x = 3, y = 3\nx = 4, y = 4\n");
+    assert_eq!(RleErrorKind::DuplicateRleHeader, e.kind);
+    assert_eq!(Some((2, 1, 12)), e.span.map(|s| (s.line, s.column, s.len)));
+
+    let e = import("x = 3, y = z\n");
+    assert_eq!(RleErrorKind::InvalidSize, e.kind);
+    assert_eq!(Some(1), e.span.map(|span| span.line));
+
+    let e = import("#CXRLE Pos=1,a\nx = 3, y = 3\n");
+    assert_eq!(RleErrorKind::BadCxrleHeader, e.kind);
+    assert_eq!(Some(1), e.span.map(|span| span.line));
+
+    // Conversion errors are located at the run that caused them, whether or
+    // not the RLE is converted while it is being read.
+    let s = "x = 1, y = 1, z = 2\no/o!\n";
+    let e = import(s);
+    assert_eq!(RleErrorKind::TooManyDimensions, e.kind);
+    assert_eq!(Some((2, 2, 1)), e.span.map(|s| (s.line, s.column, s.len)));
+    let rle: Rle = s.parse().unwrap();
+    let e = rle.to_ndtree::<Dim2D>(SharedNodePool::new()).unwrap_err();
+    assert_eq!(RleErrorKind::TooManyDimensions, e.kind);
+    assert_eq!(Some((2, 2, 1)), e.span.map(|s| (s.line, s.column, s.len)));
+    assert!(rle.to_ndtree::<Dim3D>(SharedNodePool::new()).is_ok());
+
+    // Missing headers have no location.
+    let e = import("bo$2bo$3o!\n");
+    assert_eq!(RleError::from(RleErrorKind::MissingHeader), e);
+
+    // Anything after the end of the pattern is ignored.
+    let ndtree: NdTree2D = Rle::from_string_to_ndtree("x = 1, y = 1\no! trailing text\nmore\n")
+        .expect("Failed to import RLE");
+    assert_eq!(1, ndtree.root_ref().population().to_usize().unwrap());
+}
+
+#[test]
+fn test_import_guessed_format() {
+    let e = crate::io::import_ndtree_from_string::<Dim2D>("x = 3, y = 3\nbo$2bo$3o?!").unwrap_err();
+    assert_eq!(CaFormat::Rle, e.guessed_format);
+    assert_eq!(CaFormat::ALL.len(), e.errors.len());
+    assert!(e
+        .to_string()
+        .starts_with("Unable to import pattern (looks like RLE)"));
+    assert!(matches!(
+        e.guessed_format_error(),
+        Some(CaFormatError::RleError(RleError {
+            kind: RleErrorKind::UnknownSymbol('?'),
+            ..
+        })),
+    ));
+
+    let e = crate::io::import_ndtree_from_string::<Dim2D>("\n[M2]\n1 0 1 x 0\n").unwrap_err();
+    assert_eq!(CaFormat::Macrocell, e.guessed_format);
+    assert!(matches!(
+        e.guessed_format_error(),
+        Some(CaFormatError::MacrocellError(_)),
+    ));
+
+    for &(prefix, format) in &[
+        ("#Life 1.05\n", CaFormat::Life105),
+        ("#Life 1.06\n", CaFormat::Life106),
+        ("!Name: glider\n", CaFormat::Plaintext),
+        (".O\n..O\nOOO\n", CaFormat::Plaintext),
+        (".o\n..o\nooo\n", CaFormat::Plaintext),
+        ("bo$2bo$3o!\n", CaFormat::Rle),
+        ("", CaFormat::Rle),
+    ] {
+        assert_eq!(format, CaFormat::guess_from_prefix(prefix.as_bytes()));
+    }
+    let ndtree = crate::io::import_ndtree_from_string::<Dim2D>("#Life 1.06\n0 0\n").unwrap();
+    assert_eq!(1, ndtree.get_cell(&NdVec::origin()));
+}
+
+#[test]
+fn test_golly_3d_rle() {
+    // Written by hand following the save routine in Golly's `3D.lua` (`gen`
+    // on the first line, then comments, then the size and rule), not
+    // exported from Golly itself. Replace this with a verbatim Golly export
+    // when one is available.
+    const GOLLY_3D: &str = "\
+3D version=1 size=30 pos=14,13,14 gen=12
+#C A small pattern
+x=3 y=3 z=2 rule=3D5..7/6
+bo$obo$bo/3o$bo!
+";
+
+    let rle: Rle = GOLLY_3D.parse().expect("Failed to import Golly 3D RLE");
+    assert!(rle.is_golly_3d());
+    assert_eq!(Some("3D5..7/6"), rle.rule());
+    assert_eq!(BigInt::from(12), rle.generation());
+    assert_eq!("#C A small pattern\n", rle.comments());
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([-1, 0, 0]), NdVec::big([1, 2, 1]))),
+        rle.region::<Dim3D>().bounding_rect(),
+    );
+    // Golly's 3D RLE round-trips exactly.
+    assert_eq!(GOLLY_3D, rle.to_string_2_state());
+
+    let ndtree: NdTree3D = rle.to_ndtree(SharedNodePool::new()).unwrap();
+    assert_eq!(8, ndtree.root_ref().population().to_usize().unwrap());
+    for &pos in &[
+        [0, 2, 1],
+        [-1, 1, 1],
+        [1, 1, 1],
+        [0, 0, 1],
+        [-1, 2, 0],
+        [0, 2, 0],
+        [1, 2, 0],
+        [0, 1, 0],
+    ] {
+        assert_eq!(1, ndtree.get_cell(&NdVec::big(pos)));
+    }
+
+    // Export from an ND-tree.
+    let exported = Rle::from_ndtree(&ndtree, None)
+        .unwrap()
+        .with_rule(Some("3D5..7/6"))
+        .with_generation(BigInt::from(12))
+        .with_comments("#C A small pattern")
+        .with_golly_3d()
+        .unwrap()
+        .to_string_2_state();
+    assert_eq!(GOLLY_3D, exported);
+    // It can be converted back to NDCell's own RLE dialect.
+    let rle: Rle = exported.parse().unwrap();
+    assert_eq!(
+        "\
+#CXRLE Pos=-1,-2,-1 Gen=12
+#C A small pattern
+x = 3, y = 3, z = 2, rule = 3D5..7/6
+bo$obo$bo/3o$bo!
+",
+        rle.without_golly_3d().to_string_2_state(),
+    );
+
+    // Rules may contain commas, and `gen` is omitted at generation 0.
+    let rle: Rle = "3D version=1 size=40 pos=19,19,19\nx=2 y=1 z=1 rule=3D4,7/5,8\n2o!\n"
+        .parse()
+        .unwrap();
+    assert_eq!(Some("3D4,7/5,8"), rle.rule());
+    assert_eq!(BigInt::zero(), rle.generation());
+    assert_eq!(
+        "3D version=1 size=40 pos=19,19,19\nx=2 y=1 z=1 rule=3D4,7/5,8\n2o!\n",
+        rle.to_string_2_state(),
+    );
+
+    // Without `pos`, the pattern is centered in the grid.
+    let rle: Rle = "3D version=1 size=10\nx=2 y=1 z=1\n2o!\n".parse().unwrap();
+    assert_eq!(
+        Some(NdRect::span(NdVec::big([-1, 1, 1]), NdVec::big([0, 1, 1]))),
+        rle.region::<Dim3D>().bounding_rect(),
+    );
+
+    // Patterns far from the origin are centered in the grid.
+    let mut ndtree = NdTree3D::default();
+    ndtree.set_cell(&NdVec::big([1000, 0, 0]), 1);
+    assert_eq!(
+        "3D version=1 size=30 pos=14,14,14\nx=1 y=1 z=1\no!\n",
+        Rle::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_golly_3d()
+            .unwrap()
+            .to_string_2_state(),
+    );
+
+    // Golly's 3D script does not support 4D patterns.
+    let mut ndtree = NdTree4D::default();
+    ndtree.set_cell(&NdVec::big([0, 0, 0, 0]), 1);
+    ndtree.set_cell(&NdVec::big([0, 0, 0, 1]), 1);
+    assert_eq!(
+        RleErrorKind::Golly3DTooManyAxes,
+        Rle::from_ndtree(&ndtree, None)
+            .unwrap()
+            .with_golly_3d()
+            .unwrap_err()
+            .kind,
+    );
+
+    let e =
+        Rle::from_string_to_ndtree::<Dim3D>("3D version=1 size=x\nx=1 y=1 z=1\no!").unwrap_err();
+    assert_eq!(RleErrorKind::BadGolly3DHeader, e.kind);
+    assert_eq!(Some(1), e.span.map(|span| span.line));
+}
+
+#[test]
+fn test_rle_metadata() {
+    const RLE: &str = "\
+#CXRLE Pos=-1,-1 Gen=42
+#N Glider
+#O Richard K. Guy, 1969
+#C The smallest spaceship.
+#C [[ ZOOM 16
+#C    AUTOSTART ]]
+x = 3, y = 3, rule = B3/S23
+bo$2bo$3o!
+";
+    let rule: Arc<dyn NdRule<Dim2D>> = Arc::new(DummyRule);
+    let automaton = Rle::from_string_to_ndautomaton(RLE, Arc::clone(&rule)).unwrap();
+    let metadata = &automaton.metadata;
+    assert_eq!(Some("Glider"), metadata.name.as_deref());
+    assert_eq!(Some("Richard K. Guy, 1969"), metadata.author.as_deref());
+    assert_eq!(vec!["The smallest spaceship."], metadata.comments);
+    assert_eq!(
+        vec!["#C [[ ZOOM 16\n#C    AUTOSTART ]]"],
+        metadata.viewer_scripts,
+    );
+    assert_eq!(BigInt::from(42), automaton.generations);
+    assert_eq!(1, automaton.ndtree.get_cell(&NdVec::big([0, 1])));
+
+    let exported = Rle::from_ndautomaton(&automaton, None)
+        .unwrap()
+        .with_rule(Some("B3/S23"))
+        .to_string_2_state();
+    assert_eq!(RLE, exported);
+    let reimported = Rle::from_string_to_ndautomaton(&exported, rule).unwrap();
+    assert_eq!(automaton.metadata, reimported.metadata);
+}
@@ -0,0 +1,123 @@
+//! Locations of errors within serialized patterns.
+
+use std::fmt;
+use std::ops::Range;
+
+/// Maximum number of characters to include in a snippet on either side of the
+/// span.
+const SNIPPET_CONTEXT: usize = 30;
+
+/// Location of an error within a serialized pattern, along with the
+/// surrounding text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextSpan {
+    /// The 1-indexed line number.
+    pub line: usize,
+    /// The 1-indexed column number of the first character of the span.
+    pub column: usize,
+    /// The number of characters in the span.
+    pub len: usize,
+    /// Part of the line containing the span, with leading and trailing
+    /// whitespace removed.
+    pub snippet: String,
+    /// The number of characters in `snippet` before the span.
+    pub snippet_offset: usize,
+}
+impl TextSpan {
+    /// Constructs a span covering the given byte range of a line.
+    ///
+    /// Long lines are truncated to a snippet of the text around the span.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `range` is out of bounds or does not lie on
+    /// character boundaries.
+    pub fn new(line_number: usize, line: &str, range: Range<usize>) -> Self {
+        let before = &line[..range.start];
+        let after = &line[range.end..];
+
+        let snippet_start = before
+            .char_indices()
+            .rev()
+            .take(SNIPPET_CONTEXT)
+            .last()
+            .map(|(i, _)| i)
+            .unwrap_or(range.start);
+        let snippet_end = after
+            .char_indices()
+            .nth(SNIPPET_CONTEXT)
+            .map(|(i, _)| range.end + i)
+            .unwrap_or(line.len());
+        let context_before = line[snippet_start..range.start].trim_start();
+        let context_after = line[range.end..snippet_end].trim_end();
+
+        let mut snippet = String::new();
+        if snippet_start > 0 && !before[..snippet_start].trim().is_empty() {
+            snippet.push_str("...");
+        }
+        snippet.push_str(context_before);
+        let snippet_offset = snippet.chars().count();
+        snippet.push_str(&line[range.clone()]);
+        snippet.push_str(context_after);
+        if snippet_end < line.len() && !line[snippet_end..].trim().is_empty() {
+            snippet.push_str("...");
+        }
+
+        Self {
+            line: line_number,
+            column: before.chars().count() + 1,
+            len: line[range].chars().count(),
+            snippet,
+            snippet_offset,
+        }
+    }
+    /// Constructs a span covering a whole line, excluding leading and trailing
+    /// whitespace.
+    pub fn whole_line(line_number: usize, line: &str) -> Self {
+        let start = line.len() - line.trim_start().len();
+        let end = line.trim_end().len().max(start);
+        Self::new(line_number, line, start..end)
+    }
+}
+impl fmt::Display for TextSpan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "line {}, column {}:", self.line, self.column)?;
+        writeln!(f, "{}", self.snippet)?;
+        // Write arrows pointing to the span.
+        write!(
+            f,
+            "{}{}",
+            " ".repeat(self.snippet_offset),
+            "^".repeat(std::cmp::max(1, self.len)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_span() {
+        let span = TextSpan::new(3, "  bo$2bq!  ", 7..8);
+        assert_eq!(3, span.line);
+        assert_eq!(8, span.column);
+        assert_eq!(1, span.len);
+        assert_eq!("bo$2bq!", span.snippet);
+        assert_eq!("line 3, column 8:\nbo$2bq!\n     ^", span.to_string());
+
+        let long_line = format!("{}?{}", "o".repeat(100), "b".repeat(100));
+        let span = TextSpan::new(1, &long_line, 100..101);
+        assert_eq!(101, span.column);
+        assert_eq!(
+            format!("...{}?{}...", "o".repeat(30), "b".repeat(30)),
+            span.snippet,
+        );
+        assert_eq!(33, span.snippet_offset);
+
+        let span = TextSpan::whole_line(2, "\tx = 3, y = q\r\n");
+        assert_eq!(2, span.column);
+        assert_eq!("x = 3, y = q", span.snippet);
+        assert_eq!("^^^^^^^^^^^^", span.to_string().lines().last().unwrap());
+    }
+}
@@ -1,8 +1,11 @@
-use crate::axis::Axis;
+use std::io;
+
+use crate::axis::{Axis, X, Y};
 use crate::dim::Dim;
 use crate::ndrect::{self, BigRect};
+use crate::ndtree::NdTree;
 use crate::ndvec::BigVec;
-use crate::num::BigInt;
+use crate::num::{BigInt, ToPrimitive};
 
 /// Iterator over a rectangle that iterates in normal order for the X axis but
 /// reverse order for the Y, Z, W, U, and V axes. It also returns sentinels
@@ -78,6 +81,63 @@ pub enum SemiReverseRectIterItem<D: Dim> {
     Next(Axis),
 }
 
+/// Maximum number of rows returned by `ndtree_rows_2d()`.
+pub const MAX_ROWS_2D: usize = 1 << 24;
+/// Maximum number of cells in the rectangle passed to `ndtree_rows_2d()`,
+/// which is roughly the size of a text format containing those cells.
+pub const MAX_CELLS_2D: usize = 1 << 28;
+
+/// Returns the cell states in a rectangle of a 2D ND-tree as a list of rows,
+/// from top to bottom (decreasing Y), with cells in each row from left to right
+/// (increasing X). Trailing zeros are omitted from each row.
+///
+/// Returns `None` if the rectangle has more than `MAX_ROWS_2D` rows or more
+/// than `MAX_CELLS_2D` cells.
+pub fn ndtree_rows_2d<D: Dim>(ndtree: &NdTree<D>, rect: &BigRect<D>) -> Option<Vec<Vec<u8>>> {
+    if rect.len(Y) > BigInt::from(MAX_ROWS_2D) || rect.count() > BigInt::from(MAX_CELLS_2D) {
+        return None;
+    }
+    let mut rows = vec![vec![]; rect.len(Y).to_usize()?];
+    let slice = ndtree.slice_containing(rect);
+    for (pos, state) in slice.nonzero_cells_in_rect(rect.clone()) {
+        let row = &mut rows[(&rect.max()[Y] - &pos[Y]).to_usize()?];
+        let column = (&pos[X] - &rect.min()[X]).to_usize()?;
+        if row.len() <= column {
+            row.resize(column + 1, 0_u8);
+        }
+        row[column] = state;
+    }
+    Some(rows)
+}
+
+/// Reader returned by `read_prefix()`.
+pub type PrefixedReader<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+/// Reads from the beginning of the input until `is_enough` returns `true` for
+/// the bytes read so far, `max_len` bytes have been read, or the input ends.
+/// Returns a reader that yields the whole input again, starting with those
+/// bytes, which can be accessed using `.get_ref().0.get_ref()`.
+///
+/// Unlike a single call to `BufRead::fill_buf()`, this does not stop early if
+/// the underlying reader returns fewer bytes than are needed.
+pub fn read_prefix<R: io::BufRead>(
+    mut reader: R,
+    max_len: usize,
+    is_enough: impl Fn(&[u8]) -> bool,
+) -> io::Result<PrefixedReader<R>> {
+    let mut prefix = vec![];
+    while prefix.len() < max_len && !is_enough(&prefix) {
+        let buffer = reader.fill_buf()?;
+        if buffer.is_empty() {
+            break;
+        }
+        let len = std::cmp::min(buffer.len(), max_len - prefix.len());
+        prefix.extend_from_slice(&buffer[..len]);
+        reader.consume(len);
+    }
+    Ok(io::Read::chain(io::Cursor::new(prefix), reader))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -47,12 +47,16 @@ pub mod ndrect;
 pub mod ndtree;
 pub mod ndvec;
 pub mod num;
+#[cfg(feature = "serde")]
+mod serde_impls;
 pub mod sim;
 
 /// Fast non-cryptographic hasher.
 type FastHashBuilder = std::hash::BuildHasherDefault<seahash::SeaHasher>;
 /// `HashMap` using a fast non-cryptographic hasher.
 type HashMap<K, V> = std::collections::HashMap<K, V, FastHashBuilder>;
+/// `HashSet` using a fast non-cryptographic hasher.
+type HashSet<K> = std::collections::HashSet<K, FastHashBuilder>;
 
 /// Header used for Macrocell format.
 const MACROCELL_HEADER: &str = concat!("[M2] (ndcell ", env!("CARGO_PKG_VERSION"), ")");
@@ -79,15 +83,19 @@ pub mod prelude {
     pub use crate::axis::{Axis, AxisSet};
     pub use crate::dim::{Dim1D, Dim2D, Dim3D, Dim4D, Dim5D, Dim6D};
     pub use crate::io::{
-        CaFormat, CaFormatError, Macrocell, MacrocellError, MacrocellResult, Rle, RleError,
-        RleResult, TwoState,
+        Binary, BinaryError, BinaryResult, CaFormat, CaFormatError, CaFormatImportError,
+        Compression, Life105, Life105Error, Life105ErrorKind, Life105Result, Life106, Life106Error,
+        Life106ErrorKind, Life106Result, Macrocell, MacrocellError, MacrocellErrorKind,
+        MacrocellResult, PatternMetadata, Plaintext, PlaintextError, PlaintextErrorKind,
+        PlaintextResult, Rle, RleError, RleErrorKind, RleResult, TextSpan, TwoState,
     };
     pub use crate::ndrect::aliases::*;
     pub use crate::ndrect::NdRect;
     pub use crate::ndtree::aliases::*;
     pub use crate::ndtree::{
-        ArcNode, FlatNdTree, FlatNdTreeNode, Layer, LeafNodeRef, NdTree, NdTreeSlice, NodePool,
-        NodeRef, NodeRefEnum, NonLeafNodeRef, Region, SharedNodePool,
+        ArcNode, Connectivity, FlatNdTree, FlatNdTreeNode, Layer, LeafNodeRef, NdTree, NdTreeDiff,
+        NdTreeDiffSummary, NdTreeSlice, NodePool, NodeRef, NodeRefEnum, NonLeafNodeRef,
+        NonzeroCells, Norm, OwnedNonzeroCells, Region, SharedNodePool, SoupParams, SoupSymmetry,
     };
     pub use crate::ndvec::aliases::*;
     pub use crate::ndvec::{AnyDimVec, NdVec};
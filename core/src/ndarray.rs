@@ -96,7 +96,7 @@ impl<T, D: Dim> NdArray<T, D> {
     }
 
     /// Returns the index into `data` corresponding to a `UVec` position.
-    fn flatten_idx(&self, pos: UVec<D>) -> usize {
+    pub(crate) fn flatten_idx(&self, pos: UVec<D>) -> usize {
         flatten_idx(self.size.clone(), pos)
     }
 }
@@ -0,0 +1,250 @@
+//! Cell-by-cell comparison of two ND-trees.
+
+use itertools::{izip, Itertools};
+use std::fmt;
+use std::ops::AddAssign;
+
+use super::{NdTree, NodeRef, NodeRefEnum, NodeRefTrait, Region};
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndvec::BigVec;
+use crate::num::{BigUint, Zero};
+use crate::HashMap;
+
+/// Set of cells that differ between two ND-trees.
+#[derive(Debug, Clone)]
+pub struct NdTreeDiff<D: Dim> {
+    /// Original ND-tree.
+    old: NdTree<D>,
+    /// New ND-tree.
+    new: NdTree<D>,
+    /// ND-tree with state #1 for each cell that differs between `old` and
+    /// `new` and state #0 for each cell that does not.
+    mask: NdTree<D>,
+    /// Summary of the differences.
+    summary: NdTreeDiffSummary<D>,
+}
+
+/// Summary of the differences between two ND-trees.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NdTreeDiffSummary<D: Dim> {
+    /// Number of cells that are state #0 in the old ND-tree and nonzero in the
+    /// new one.
+    pub added: BigUint,
+    /// Number of cells that are nonzero in the old ND-tree and state #0 in the
+    /// new one.
+    pub removed: BigUint,
+    /// Number of cells that are nonzero in both ND-trees, but with different
+    /// states.
+    pub changed: BigUint,
+    /// Smallest rectangle containing all differing cells, or `None` if the
+    /// ND-trees are identical.
+    pub bounding_rect: Option<BigRect<D>>,
+}
+impl<D: Dim> Default for NdTreeDiffSummary<D> {
+    fn default() -> Self {
+        Self {
+            added: BigUint::zero(),
+            removed: BigUint::zero(),
+            changed: BigUint::zero(),
+            bounding_rect: None,
+        }
+    }
+}
+impl<D: Dim> fmt::Display for NdTreeDiffSummary<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.bounding_rect {
+            None => write!(f, "no differences"),
+            Some(rect) => write!(
+                f,
+                "{} cells differ ({} added, {} removed, {} changed) within {}",
+                self.total(),
+                self.added,
+                self.removed,
+                self.changed,
+                rect,
+            ),
+        }
+    }
+}
+impl<D: Dim> NdTreeDiffSummary<D> {
+    /// Returns the total number of cells that differ.
+    pub fn total(&self) -> BigUint {
+        &self.added + &self.removed + &self.changed
+    }
+}
+
+/// Counts of differing cells, used while computing a diff.
+#[derive(Debug, Default, Clone)]
+struct DiffCounts {
+    added: BigUint,
+    removed: BigUint,
+    changed: BigUint,
+}
+impl AddAssign<&DiffCounts> for DiffCounts {
+    fn add_assign(&mut self, rhs: &DiffCounts) {
+        self.added += &rhs.added;
+        self.removed += &rhs.removed;
+        self.changed += &rhs.changed;
+    }
+}
+
+/// Cache of diff results for pairs of nodes.
+type DiffMemo<'pool, D> =
+    HashMap<(NodeRef<'pool, D>, NodeRef<'pool, D>), (NodeRef<'pool, D>, DiffCounts)>;
+
+impl<D: Dim> NdTreeDiff<D> {
+    /// Compares two ND-trees cell-by-cell.
+    ///
+    /// Identical nodes are skipped without examining their contents, so this is
+    /// fast when the ND-trees share most of their structure. The ND-trees do
+    /// not need to use the same node pool, but comparison is faster when they
+    /// do.
+    pub fn new(old: &NdTree<D>, new: &NdTree<D>) -> Self {
+        let old = old.clone();
+        let new = new.clone();
+
+        // Ensure same center and layer.
+        let center = old.center_pos();
+        let mut a = old.clone();
+        let mut b = new.clone();
+        b.recenter(&center);
+        let common_layer = std::cmp::max(a.layer(), b.layer());
+        a.expand_while(|ndtree| ndtree.layer() < common_layer);
+        b.expand_while(|ndtree| ndtree.layer() < common_layer);
+
+        let a_root = a.root_ref();
+        let b_root = b.root_ref();
+        let node_pool = a_root.pool();
+        let (mask_root, counts) = Self::_diff(
+            a_root.as_ref(),
+            node_pool.copy_from_other_pool(&b_root),
+            &mut HashMap::default(),
+        );
+
+        let mut mask = NdTree::from_node_centered_on(mask_root, center);
+        drop((a_root, b_root));
+        mask.shrink();
+
+        let summary = NdTreeDiffSummary {
+            added: counts.added,
+            removed: counts.removed,
+            changed: counts.changed,
+            bounding_rect: mask.bounding_rect(),
+        };
+        Self {
+            old,
+            new,
+            mask,
+            summary,
+        }
+    }
+    fn _diff<'pool>(
+        a: NodeRef<'pool, D>,
+        b: NodeRef<'pool, D>,
+        memo: &mut DiffMemo<'pool, D>,
+    ) -> (NodeRef<'pool, D>, DiffCounts) {
+        let node_pool = a.pool();
+        if a == b {
+            return (node_pool.get_empty(a.layer()), DiffCounts::default());
+        }
+        if let Some(already_computed) = memo.get(&(a, b)) {
+            return already_computed.clone();
+        }
+
+        use NodeRefEnum::{Leaf, NonLeaf};
+        let ret = match (a.as_enum(), b.as_enum()) {
+            (Leaf(a_leaf), Leaf(b_leaf)) => {
+                let mut added = 0_usize;
+                let mut removed = 0_usize;
+                let mut changed = 0_usize;
+                let mask_cells = izip!(a_leaf.cells(), b_leaf.cells())
+                    .map(|(&a_cell, &b_cell)| {
+                        match (a_cell, b_cell) {
+                            _ if a_cell == b_cell => return 0_u8,
+                            (0, _) => added += 1,
+                            (_, 0) => removed += 1,
+                            _ => changed += 1,
+                        }
+                        1_u8
+                    })
+                    .collect_vec();
+                let counts = DiffCounts {
+                    added: added.into(),
+                    removed: removed.into(),
+                    changed: changed.into(),
+                };
+                (node_pool.get_from_cells(mask_cells), counts)
+            }
+            (NonLeaf(a_non_leaf), NonLeaf(b_non_leaf)) => {
+                let mut counts = DiffCounts::default();
+                let mask_children = izip!(a_non_leaf.children(), b_non_leaf.children())
+                    .map(|(a_child, b_child)| {
+                        let (mask_child, child_counts) = Self::_diff(a_child, b_child, memo);
+                        counts += &child_counts;
+                        mask_child
+                    })
+                    .collect_vec();
+                (node_pool.join_nodes(mask_children), counts)
+            }
+            _ => panic!("Layer mismatch"),
+        };
+        memo.insert((a, b), ret.clone());
+        ret
+    }
+
+    /// Returns the original ND-tree.
+    #[inline]
+    pub fn old_ndtree(&self) -> &NdTree<D> {
+        &self.old
+    }
+    /// Returns the new ND-tree.
+    #[inline]
+    pub fn new_ndtree(&self) -> &NdTree<D> {
+        &self.new
+    }
+    /// Returns a summary of the differences.
+    #[inline]
+    pub fn summary(&self) -> &NdTreeDiffSummary<D> {
+        &self.summary
+    }
+    /// Returns `true` if the ND-trees have the same contents.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.summary.bounding_rect.is_none()
+    }
+    /// Returns the set of cells that differ.
+    pub fn region(&self) -> Region<D> {
+        if self.is_empty() {
+            Region::Empty
+        } else {
+            Region::NdTree(self.mask.clone())
+        }
+    }
+    /// Returns a list of differing cells in the order yielded by
+    /// `NonzeroCells`, each with its position, state in the old ND-tree, and
+    /// state in the new ND-tree.
+    pub fn cells(&self) -> Vec<(BigVec<D>, u8, u8)> {
+        self.mask
+            .nonzero_cells()
+            .map(|(pos, _)| {
+                let old_state = self.old.get_cell(&pos);
+                let new_state = self.new.get_cell(&pos);
+                (pos, old_state, new_state)
+            })
+            .collect()
+    }
+}
+impl<D: Dim> fmt::Display for NdTreeDiff<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.summary, f)
+    }
+}
+
+impl<D: Dim> NdTree<D> {
+    /// Compares this ND-tree to another one cell-by-cell. See `NdTreeDiff` for
+    /// details.
+    pub fn diff(&self, new: &NdTree<D>) -> NdTreeDiff<D> {
+        NdTreeDiff::new(self, new)
+    }
+}
@@ -0,0 +1,102 @@
+//! Flood fill for ND-trees.
+
+use std::collections::VecDeque;
+
+use super::{NdTree, Region};
+use crate::dim::Dim;
+use crate::ndrect::{BigRect, CanContain, IRect};
+use crate::ndvec::BigVec;
+use crate::HashSet;
+
+/// Which cells are considered adjacent when flood filling.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Connectivity {
+    /// Cells are adjacent if they differ by one along a single axis (von
+    /// Neumann neighborhood). In 2D, each cell has 4 neighbors; in 3D, 6.
+    Orthogonal,
+    /// Cells are adjacent if they differ by at most one along every axis
+    /// (Moore neighborhood). In 2D, each cell has 8 neighbors; in 3D, 26.
+    Full,
+}
+impl Default for Connectivity {
+    fn default() -> Self {
+        Self::Orthogonal
+    }
+}
+impl Connectivity {
+    /// Returns the offsets from a cell to each of its neighbors.
+    fn neighbor_offsets<D: Dim>(self) -> Vec<BigVec<D>> {
+        IRect::<D>::moore(1)
+            .iter()
+            .filter(|offset| !offset.is_zero())
+            .filter(|offset| match self {
+                Self::Orthogonal => offset.abs().sum() == 1,
+                Self::Full => true,
+            })
+            .map(|offset| offset.to_bigvec())
+            .collect()
+    }
+}
+
+impl<D: Dim> NdTree<D> {
+    /// Returns the set of cells with the same state as the cell at `seed`
+    /// that are connected to it through other cells with that state, without
+    /// leaving `limit`. Returns `Region::Empty` if `seed` is outside `limit`.
+    ///
+    /// This takes time proportional to the number of cells in the result, so
+    /// `limit` should not be much larger than needed.
+    pub fn flood_region(
+        &self,
+        seed: &BigVec<D>,
+        connectivity: Connectivity,
+        limit: &BigRect<D>,
+    ) -> Region<D> {
+        if !limit.contains(seed) {
+            return Region::Empty;
+        }
+
+        let target_state = self.get_cell(seed);
+        let neighbor_offsets = connectivity.neighbor_offsets::<D>();
+
+        let mut mask = NdTree::with_node_pool(self.pool().new_ref());
+        let mut visited: HashSet<BigVec<D>> = HashSet::default();
+        let mut queue: VecDeque<BigVec<D>> = VecDeque::new();
+        visited.insert(seed.clone());
+        queue.push_back(seed.clone());
+        while let Some(pos) = queue.pop_front() {
+            mask.set_cell(&pos, 1_u8);
+            for offset in &neighbor_offsets {
+                let neighbor = &pos + offset;
+                if limit.contains(&neighbor)
+                    && !visited.contains(&neighbor)
+                    && self.get_cell(&neighbor) == target_state
+                {
+                    visited.insert(neighbor.clone());
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+        Region::NdTree(mask)
+    }
+
+    /// Sets every cell in `flood_region()` to `new_state` and returns the
+    /// region that was filled.
+    pub fn flood_fill(
+        &mut self,
+        seed: &BigVec<D>,
+        connectivity: Connectivity,
+        limit: &BigRect<D>,
+        new_state: u8,
+    ) -> Region<D> {
+        let region = self.flood_region(seed, connectivity, limit);
+        if !matches!(region, Region::Empty) {
+            self.paste_custom(
+                NdTree::with_node_pool(self.pool().new_ref()),
+                region.clone(),
+                |_, _| None,
+                |_, _| new_state,
+            );
+        }
+        region
+    }
+}
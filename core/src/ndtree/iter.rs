@@ -0,0 +1,174 @@
+//! Sparse iteration over the nonzero cells of an ND-tree.
+
+use super::region::RectRelation;
+use super::{ArcNode, NodeRef, NodeRefEnum, NodeRefTrait, Region, SharedNodePool};
+use crate::dim::Dim;
+use crate::ndrect::CanContain;
+use crate::ndvec::BigVec;
+
+/// Iterator over the positions and states of nonzero cells in an ND-tree,
+/// optionally restricted to a region.
+///
+/// Empty nodes are skipped in O(1), so the running time is proportional to the
+/// number of nonzero cells (plus the depth of the tree) rather than the volume
+/// of the bounding rectangle.
+///
+/// Cells are yielded in a deterministic order: children of each non-leaf node
+/// are visited in order of child index, and cells within each leaf node are
+/// visited in row-major order (see the crate root documentation). For a given
+/// ND-tree, this order does not depend on the node pool or on the order in
+/// which cells were set.
+#[derive(Debug, Clone)]
+pub struct NonzeroCells<'pool, D: Dim> {
+    traversal: Traversal<NodeRef<'pool, D>, D>,
+}
+impl<'pool, D: Dim> NonzeroCells<'pool, D> {
+    /// Returns an iterator over the nonzero cells in a node whose lowest corner
+    /// is at `base_pos`, optionally restricted to a region.
+    pub fn new(
+        node: impl NodeRefTrait<'pool, D = D>,
+        base_pos: BigVec<D>,
+        region: Option<Region<D>>,
+    ) -> Self {
+        let mut traversal = Traversal::new(region);
+        traversal.push(node.as_ref(), base_pos, false, |n| n);
+        Self { traversal }
+    }
+}
+impl<'pool, D: Dim> Iterator for NonzeroCells<'pool, D> {
+    type Item = (BigVec<D>, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.traversal.next(|&n| n, |n| n)
+    }
+}
+
+/// Iterator over the positions and states of nonzero cells in an ND-tree,
+/// optionally restricted to a region, that holds an `Arc` reference to the
+/// node pool instead of borrowing it.
+///
+/// This yields cells in the same order as `NonzeroCells`. The node pool is
+/// only locked during each call to `next()`, so unlike `NonzeroCells` this
+/// iterator does not block garbage collection while it exists. Nodes that
+/// have not been visited yet are kept alive using `ArcNode`s, which makes
+/// this iterator somewhat slower than `NonzeroCells`.
+#[derive(Debug, Clone)]
+pub struct OwnedNonzeroCells<D: Dim> {
+    pool: SharedNodePool<D>,
+    traversal: Traversal<ArcNode<D>, D>,
+}
+impl<D: Dim> OwnedNonzeroCells<D> {
+    /// Returns an iterator over the nonzero cells in a node whose lowest corner
+    /// is at `base_pos`, optionally restricted to a region.
+    pub fn new(node: &ArcNode<D>, base_pos: BigVec<D>, region: Option<Region<D>>) -> Self {
+        let pool = node.pool().new_ref();
+        let mut traversal = Traversal::new(region);
+        traversal.push(node.as_ref(&pool.access()), base_pos, false, ArcNode::from);
+        Self { pool, traversal }
+    }
+}
+impl<D: Dim> Iterator for OwnedNonzeroCells<D> {
+    type Item = (BigVec<D>, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pool = self.pool.access();
+        self.traversal.next(|n| n.as_ref(&pool), ArcNode::from)
+    }
+}
+
+/// Depth-first traversal of the nonzero cells in an ND-tree, generic over the
+/// type `N` used to hold onto nodes between calls to `next()`.
+#[derive(Debug, Clone)]
+struct Traversal<N, D: Dim> {
+    /// Region to restrict iteration to, or `None` to include all cells.
+    region: Option<Region<D>>,
+    /// Nodes remaining to be visited, along with the position of the lowest
+    /// corner of each one and whether the node is known to be completely
+    /// contained within `region`. The top of the stack is visited first.
+    stack: Vec<(N, BigVec<D>, bool)>,
+    /// Leaf node currently being visited, along with its position, whether it
+    /// is completely contained within `region`, and the index of the next cell
+    /// to check.
+    leaf: Option<(N, BigVec<D>, bool, usize)>,
+}
+impl<N, D: Dim> Traversal<N, D> {
+    fn new(region: Option<Region<D>>) -> Self {
+        Self {
+            region,
+            stack: vec![],
+            leaf: None,
+        }
+    }
+
+    /// Pushes a node onto the stack, unless it contains no nonzero cells that
+    /// lie within the region.
+    fn push<'n>(
+        &mut self,
+        node: NodeRef<'n, D>,
+        base_pos: BigVec<D>,
+        mut inside: bool,
+        hold: impl FnOnce(NodeRef<'n, D>) -> N,
+    ) {
+        if node.is_empty() {
+            return;
+        }
+        if !inside {
+            match &self.region {
+                Some(region) => {
+                    let node_rect = node.big_rect() + &base_pos;
+                    match region.classify_rect(&node_rect) {
+                        RectRelation::Inside => inside = true,
+                        RectRelation::Outside => return,
+                        RectRelation::Partial => (),
+                    }
+                }
+                None => inside = true,
+            }
+        }
+        self.stack.push((hold(node), base_pos, inside));
+    }
+
+    /// Returns the next nonzero cell, using `get` to access held nodes and
+    /// `hold` to hold onto new ones.
+    fn next<'n>(
+        &mut self,
+        get: impl Fn(&N) -> NodeRef<'n, D>,
+        hold: impl Fn(NodeRef<'n, D>) -> N,
+    ) -> Option<(BigVec<D>, u8)> {
+        loop {
+            // Scan the current leaf node for another nonzero cell.
+            if let Some((node, base_pos, inside, index)) = &mut self.leaf {
+                let node = get(&*node);
+                let leaf = node.as_leaf().unwrap();
+                let cells = leaf.cells();
+                while *index < cells.len() {
+                    let i = *index;
+                    *index += 1;
+                    if cells[i] == 0_u8 {
+                        continue;
+                    }
+                    let pos = leaf.cell_index_to_pos(i).to_bigvec() + &*base_pos;
+                    // If there is no region, then `inside` is always `true`.
+                    if *inside || matches!(&self.region, Some(r) if r.contains(&pos)) {
+                        return Some((pos, cells[i]));
+                    }
+                }
+                self.leaf = None;
+            }
+
+            // Move on to the next node.
+            let (held, base_pos, inside) = self.stack.pop()?;
+            match get(&held).as_enum() {
+                NodeRefEnum::Leaf(_) => self.leaf = Some((held, base_pos, inside, 0)),
+                NodeRefEnum::NonLeaf(n) => {
+                    // Push in reverse order so that the child at index 0 is
+                    // visited first.
+                    for index in (0..D::BRANCHING_FACTOR).rev() {
+                        let child_pos = n.layer().big_child_offset(index) + &base_pos;
+                        self.push(n.child_at_index(index), child_pos, inside, &hold);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -13,20 +13,29 @@ use itertools::{izip, Itertools};
 use std::fmt;
 
 pub mod aliases;
+mod diff;
 mod flat;
+mod flood;
+mod iter;
 mod node;
 mod region;
 mod slice;
+mod soup;
 
 use crate::dim::*;
 use crate::ndrect::{BigRect, CanContain, URect};
 use crate::ndvec::BigVec;
-use crate::num::BigInt;
+use crate::num::{BigInt, BigUint};
+use crate::HashMap;
 pub use aliases::*;
+pub use diff::{NdTreeDiff, NdTreeDiffSummary};
 pub use flat::{FlatNdTree, FlatNdTreeNode};
+pub use flood::Connectivity;
+pub use iter::{NonzeroCells, OwnedNonzeroCells};
 pub use node::*;
-pub use region::Region;
+pub use region::{Norm, Region};
 pub use slice::NdTreeSlice;
+pub use soup::{SoupParams, SoupSymmetry};
 
 /// An N-dimensional generalization of a quadtree.
 ///
@@ -400,6 +409,22 @@ impl<D: Dim> NdTree<D> {
         }
     }
 
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the ND-tree. See `NonzeroCells` for details about iteration order.
+    pub fn nonzero_cells(&self) -> OwnedNonzeroCells<D> {
+        OwnedNonzeroCells::new(self.root(), self.base_pos().clone(), None)
+    }
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the ND-tree that are within `rect`.
+    pub fn nonzero_cells_in_rect(&self, rect: BigRect<D>) -> OwnedNonzeroCells<D> {
+        self.nonzero_cells_in_region(Region::Rect(rect))
+    }
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the ND-tree that are within `region`.
+    pub fn nonzero_cells_in_region(&self, region: Region<D>) -> OwnedNonzeroCells<D> {
+        OwnedNonzeroCells::new(self.root(), self.base_pos().clone(), Some(region))
+    }
+
     /// Returns `true` if all cells within the rectangle are state #0.
     pub fn rect_is_empty(&self, rect: BigRect<D>) -> bool {
         self.root_ref().rect_is_empty(&(rect - self.base_pos()))
@@ -411,6 +436,49 @@ impl<D: Dim> NdTree<D> {
             .min_nonzero_rect()
             .map(|r| r + self.base_pos())
     }
+    /// Returns the number of cells in each state, indexed by state, up to the
+    /// highest state present. The count for state #0 is always zero, and the
+    /// result is empty if there are no nonzero cells.
+    pub fn population_by_state(&self) -> Vec<BigUint> {
+        fn count_states<'pool, D: Dim>(
+            node: NodeRef<'pool, D>,
+            memo: &mut HashMap<NodeRef<'pool, D>, Vec<BigUint>>,
+        ) -> Vec<BigUint> {
+            if let Some(counts) = memo.get(&node) {
+                return counts.clone();
+            }
+            let mut counts: Vec<BigUint> = vec![];
+            let mut add = |state: usize, count: BigUint| {
+                if counts.len() <= state {
+                    counts.resize(state + 1, BigUint::from(0_u8));
+                }
+                counts[state] += count;
+            };
+            match (node.single_state(), node.as_enum()) {
+                (Some(0), _) => (),
+                (Some(state), _) => add(state as usize, node.layer().big_num_cells::<D>()),
+                (None, NodeRefEnum::Leaf(n)) => {
+                    for &cell in n.cells() {
+                        if cell != 0 {
+                            add(cell as usize, BigUint::from(1_u8));
+                        }
+                    }
+                }
+                (None, NodeRefEnum::NonLeaf(n)) => {
+                    for child in n.children() {
+                        for (state, count) in count_states(child, memo).into_iter().enumerate() {
+                            add(state, count);
+                        }
+                    }
+                }
+            }
+            memo.insert(node, counts.clone());
+            counts
+        }
+
+        let root = self.root_ref();
+        count_states(root.as_ref(), &mut HashMap::default())
+    }
     /// Shrinks a rectangle as much as possible while still containing the same
     /// nonzero cells. Returns `None` if all cells in the rectangle are zero.
     pub fn shrink_nonzero_rect(&self, rect: BigRect<D>) -> Option<BigRect<D>> {
@@ -509,6 +577,9 @@ impl<D: Dim> NdTree<D> {
     /// provided closure do **not** need to use the same node pool; the returned
     /// node will be from the same pool as `self`.
     ///
+    /// If `mask` is unbounded, only the part of it that overlaps `self` or
+    /// `other` is used.
+    ///
     /// # Panics
     ///
     /// This method panics if `self`, `other`, and `mask` are not all at the
@@ -526,7 +597,13 @@ impl<D: Dim> NdTree<D> {
     ) {
         // Ensure same center.
         other.recenter(&self.center_pos());
-        let mut mask = mask.into_ndtree(self.center_pos());
+        let mut mask = if mask.is_bounded() {
+            mask.into_ndtree(self.center_pos())
+        } else {
+            // Only cells within `self` or `other` can be affected.
+            let rect = BigRect::span_rects(self.rect(), other.rect());
+            mask.into_ndtree_within(self.center_pos(), &rect)
+        };
         assert_eq!(self.center_pos(), other.center_pos());
         assert_eq!(self.center_pos(), mask.center_pos());
 
@@ -12,13 +12,18 @@ mod raw;
 mod refs;
 mod results;
 mod set;
+mod snapshot;
+mod stats;
 
 pub use layer::{Layer, LayerTooSmall};
 pub use pool::{ArcNode, NodePool, SharedNodePool, SimCacheGuard};
-use raw::RawNode;
+use raw::{RawNode, RAW_NODE_MEMORY_OVERHEAD};
 pub use refs::{LeafNodeRef, NodeRef, NodeRefEnum, NodeRefTrait, NodeRefWithGuard, NonLeafNodeRef};
 pub use results::HashLifeResultParams;
+use results::{StashedResults, DEFAULT_STASHED_RESULTS_CAPACITY, STASHED_RESULT_MEMORY_USAGE};
 use set::ShardedBoxedSet;
+use stats::StatsRecorder;
+pub use stats::{GcKind, GcStats, MemoryStats, NodePoolStats, ResultCacheStats};
 
 #[cfg(test)]
 mod tests;
@@ -9,10 +9,13 @@ use std::fmt;
 use std::hash::{Hash, Hasher};
 use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
 use std::sync::{Arc, Weak};
+use std::time::Instant;
 
 use super::{
-    HashLifeResultParams, Layer, LayerTooSmall, NodeRef, NodeRefEnum, NodeRefTrait,
-    NodeRefWithGuard, RawNode, ShardedBoxedSet,
+    GcKind, GcStats, HashLifeResultParams, Layer, LayerTooSmall, MemoryStats, NodePoolStats,
+    NodeRef, NodeRefEnum, NodeRefTrait, NodeRefWithGuard, RawNode, ShardedBoxedSet, StashedResults,
+    StatsRecorder, DEFAULT_STASHED_RESULTS_CAPACITY, RAW_NODE_MEMORY_OVERHEAD,
+    STASHED_RESULT_MEMORY_USAGE,
 };
 use crate::dim::Dim;
 use crate::ndvec::{BigVec, UVec};
@@ -38,6 +41,9 @@ impl<D: Dim> SharedNodePool<D> {
             node_heap_size: AtomicUsize::new(0),
 
             sim_lock: RwLock::new(HashLifeResultParams::default()),
+            stashed_results: Mutex::new(StashedResults::new(DEFAULT_STASHED_RESULTS_CAPACITY)),
+
+            stats: StatsRecorder::default(),
         }));
 
         {
@@ -97,7 +103,7 @@ pub struct NodePool<D: Dim> {
     /// https://docs.rs/crate/sharded/0.0.5). We use `Mutex` instead of `RwLock`
     /// because any reader could turn into a writer if the node it's looking for
     /// is not present.
-    nodes: ShardedBoxedSet<RawNode<D>>,
+    pub(super) nodes: ShardedBoxedSet<RawNode<D>>,
     /// Map of pointers to nodes held by an `ArcNode` to number of references.
     ///
     /// Nodes with a refcount of zero are removed during GC. Nodes with a
@@ -123,6 +129,12 @@ pub struct NodePool<D: Dim> {
     /// (such as simulation step size) requires a write handle to this lock, and
     /// modifying this value may invalidate some or all results.
     sim_lock: RwLock<HashLifeResultParams>,
+    /// HashLife results for recently used simulation parameters other than
+    /// the current ones.
+    stashed_results: Mutex<StashedResults<D>>,
+
+    /// Counters for statistics returned by `stats()`.
+    pub(super) stats: StatsRecorder,
 }
 impl<D: Dim> fmt::Debug for NodePool<D> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -171,26 +183,91 @@ impl<D: Dim> NodePool<D> {
         self.assert_same_as(node.pool());
     }
 
-    /// Returns the amount of space used by nodes on the heap.
+    /// Returns the amount of space used by nodes and stashed HashLife results on
+    /// the heap.
     pub fn memory_usage(&self) -> usize {
         self.node_heap_size.load(Relaxed)
     }
+    /// Returns statistics about the contents and performance of the pool.
+    ///
+    /// This iterates over every node in the pool, so it may be slow for large
+    /// pools.
+    pub fn stats(&self) -> NodePoolStats {
+        let mut nodes_per_layer = vec![];
+        let mut nodes_with_results = 0;
+        let mut memory = MemoryStats::default();
+        self.nodes.for_each(|node| {
+            let layer = node.layer().to_usize();
+            if nodes_per_layer.len() <= layer {
+                nodes_per_layer.resize(layer + 1, 0);
+            }
+            nodes_per_layer[layer] += 1;
+            if node.result().is_some() {
+                nodes_with_results += 1;
+            }
+
+            memory.nodes += std::mem::size_of::<RawNode<D>>();
+            memory.cells += node.cell_slice().map(std::mem::size_of_val).unwrap_or(0);
+            memory.children += node
+                .children_slice()
+                .map(std::mem::size_of_val)
+                .unwrap_or(0);
+            memory.populations += node.population_heap_size();
+            memory.overhead += RAW_NODE_MEMORY_OVERHEAD;
+        });
+        memory.stashed_results = self.stashed_results.lock().len() * STASHED_RESULT_MEMORY_USAGE;
+
+        let current_params = *self.sim_lock.read_recursive();
+        NodePoolStats {
+            nodes_per_layer,
+            nodes_with_results,
+            shard_lens: self.nodes.shard_lens(),
+            shard_capacities: self.nodes.shard_capacities(),
+            memory,
+            results: self.stats.result_stats(current_params),
+            gc_history: self.stats.gc_history(),
+        }
+    }
+
     /// Does a "strong" garbage collection. This invalidates all HashLife
-    /// results, deletes all unused empty nodes, and then removes all nodes not
-    /// reachable from an `ArcNode`.
+    /// results (including those stashed for other step sizes), deletes all
+    /// unused empty nodes, and then removes all nodes not reachable from an
+    /// `ArcNode`.
     ///
     /// Returns the tuple `(nodes_dropped, nodes_kept)`.
     pub fn strong_gc(&mut self) -> (usize, usize) {
-        self.invalidate_results();
-        self.clear_empty_node_cache();
-        self.remove_unreachable()
+        self.record_gc(GcKind::Strong, |this| {
+            this.invalidate_results();
+            this.clear_empty_node_cache();
+            this.remove_unreachable()
+        })
     }
     /// Does a "weak" garbage collection. This removes all nodes that are not
-    /// reachable from an `ArcNode`.
+    /// reachable from an `ArcNode` or the empty node cache, along with
+    /// HashLife results stashed for those nodes.
     ///
     /// Returns the tuple `(nodes_dropped, nodes_kept)`.
     pub fn weak_gc(&mut self) -> (usize, usize) {
-        self.remove_unreachable()
+        self.record_gc(GcKind::Weak, Self::remove_unreachable)
+    }
+    /// Runs a garbage collection function and records statistics about it.
+    fn record_gc(
+        &mut self,
+        kind: GcKind,
+        gc: impl FnOnce(&mut Self) -> (usize, usize),
+    ) -> (usize, usize) {
+        let start = Instant::now();
+        let memory_before = self.memory_usage();
+        let (nodes_dropped, nodes_kept) = gc(self);
+        self.stats.record_gc(GcStats {
+            kind,
+            nodes_dropped,
+            nodes_kept,
+            memory_before,
+            memory_after: self.memory_usage(),
+            duration: start.elapsed(),
+        });
+        (nodes_dropped, nodes_kept)
     }
     /// Removes all nodes that are not recursively reachable from `ArcNode`s.
     ///
@@ -199,8 +276,12 @@ impl<D: Dim> NodePool<D> {
         // Mark all nodes as unreachable ("innocent until proven guilty").
         self.nodes.for_each(RawNode::mark_gc_unreachable);
 
-        // Mark `ArcNode`s and their children/results as reachable. This
-        // includes `self.empty_nodes` because they are all `ArcNodes`.
+        // Mark cached empty nodes as reachable.
+        for &node_ptr in self.empty_nodes.get_mut().iter() {
+            unsafe { &*node_ptr }.mark_gc_reachable();
+        }
+
+        // Mark `ArcNode`s and their children/results as reachable.
         self.arc_nodes.get_mut().retain(|&node_ptr, &mut refcount| {
             let node = unsafe { &*node_ptr };
             if refcount > 0 {
@@ -213,7 +294,26 @@ impl<D: Dim> NodePool<D> {
             }
         });
 
-        let mut bytes_dropped = 0;
+        // Stashed results are reachable if the node they belong to is
+        // reachable. Marking a result may make more nodes reachable, so repeat
+        // until nothing changes.
+        let stashed_results = self.stashed_results.get_mut();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            stashed_results.for_each(|node, result| {
+                if node.is_gc_reachable() && !result.is_gc_reachable() {
+                    result.mark_gc_reachable();
+                    changed = true;
+                }
+            });
+        }
+        // Discard stashed results for nodes that are about to be deleted.
+        let old_stashed_len = stashed_results.len();
+        stashed_results.retain(|node, _| node.is_gc_reachable());
+        let mut bytes_dropped =
+            (old_stashed_len - stashed_results.len()) * STASHED_RESULT_MEMORY_USAGE;
+
         let mut nodes_dropped = 0;
         let mut nodes_kept = 0;
 
@@ -235,24 +335,71 @@ impl<D: Dim> NodePool<D> {
         self.node_heap_size.fetch_sub(bytes_dropped, Relaxed);
         (nodes_dropped, nodes_kept)
     }
-    /// Clears the HashLife results cache from every node.
+    /// Clears the HashLife results cache from every node, along with all
+    /// stashed results.
     ///
     /// Note that another thread may add HashLife results to a node while this
     /// function is running.
-    fn invalidate_results(&self) {
+    fn invalidate_results(&mut self) {
         self.nodes.for_each(|node| unsafe { node.set_result(None) });
+        let stashed_results = self.stashed_results.get_mut();
+        let bytes_dropped = stashed_results.len() * STASHED_RESULT_MEMORY_USAGE;
+        stashed_results.clear();
+        self.node_heap_size.fetch_sub(bytes_dropped, Relaxed);
     }
-    /// Clears the HashLife results cache from every node above a particular
-    /// layer.
+    /// Moves the HashLife results of every node above a particular layer into
+    /// the stashed results for `old_params`, and then restores any stashed
+    /// results for `new_params`.
+    ///
+    /// This walks every node in the pool twice (once to collect results and
+    /// once to clear them) and builds a `HashMap` with an entry for every
+    /// result above `layer`, while holding the lock on the stashed results.
+    /// Each change of step size therefore takes time and temporary memory
+    /// proportional to the size of the pool, no matter how many results are
+    /// restored; a large pool whose step size changes often would be better
+    /// off with a result cache capacity of zero, which skips the first walk
+    /// and the `HashMap`.
     ///
     /// Note that another thread may add HashLife results to a node while this
     /// function is running.
-    fn invalidate_results_above(&self, layer: Layer) {
+    fn swap_results_above(
+        &self,
+        layer: Layer,
+        old_params: HashLifeResultParams,
+        new_params: HashLifeResultParams,
+    ) {
+        let mut stashed_results = self.stashed_results.lock();
+        let old_stashed_len = stashed_results.len();
+
+        let mut old_results = HashMap::default();
+        if stashed_results.capacity() > 0 {
+            self.nodes.for_each(|node| {
+                if node.layer() > layer {
+                    if let Some(result) = node.result() {
+                        old_results.insert(node as *const _, result as *const _);
+                    }
+                }
+            });
+        }
         self.nodes.for_each(|node| {
             if node.layer() > layer {
                 unsafe { node.set_result(None) }
             }
         });
+        let new_results = stashed_results.take(new_params);
+        stashed_results.stash(old_params, old_results);
+        for (node, result) in new_results.into_iter().flatten() {
+            unsafe { (*node).set_result(Some(&*result)) };
+        }
+
+        let new_stashed_len = stashed_results.len();
+        if new_stashed_len > old_stashed_len {
+            let bytes_added = (new_stashed_len - old_stashed_len) * STASHED_RESULT_MEMORY_USAGE;
+            self.node_heap_size.fetch_add(bytes_added, Relaxed);
+        } else {
+            let bytes_dropped = (old_stashed_len - new_stashed_len) * STASHED_RESULT_MEMORY_USAGE;
+            self.node_heap_size.fetch_sub(bytes_dropped, Relaxed);
+        }
     }
     /// Clears empty nodes that have no other references.
     pub fn clear_empty_node_cache(&self) {
@@ -611,6 +758,27 @@ impl<D: Dim> NodePool<D> {
         }
     }
 
+    /// Returns the maximum number of step sizes for which HashLife results are
+    /// kept in addition to the current one.
+    pub fn result_cache_capacity(&self) -> usize {
+        self.stashed_results.lock().capacity()
+    }
+    /// Sets the maximum number of step sizes for which HashLife results are
+    /// kept in addition to the current one, discarding results for the least
+    /// recently used step sizes if necessary. If the capacity is zero, results
+    /// are discarded whenever the step size changes.
+    ///
+    /// Stashing results when the step size changes takes time proportional to
+    /// the number of nodes in the pool, so a capacity of zero may be faster
+    /// for very large pools whose step size changes often.
+    pub fn set_result_cache_capacity(&self, capacity: usize) {
+        let mut stashed_results = self.stashed_results.lock();
+        let old_stashed_len = stashed_results.len();
+        stashed_results.set_capacity(capacity);
+        let bytes_dropped = (old_stashed_len - stashed_results.len()) * STASHED_RESULT_MEMORY_USAGE;
+        self.node_heap_size.fetch_sub(bytes_dropped, Relaxed);
+    }
+
     /// Returns a simulation guard, invalidating the results cache partially or
     /// fully if necessary.
     ///
@@ -663,6 +831,7 @@ impl<D: Dim> NodePool<D> {
     ) {
         let old_params = **sim_lock;
         **sim_lock = params;
+        self.stats.finish_params(old_params);
         // If the radius changes, that means the rule has changed, and the cache
         // should have been invalidated by whoever changed the rule. The only
         // thing we need to worry about here is the `log2_node_step_size` for
@@ -673,8 +842,13 @@ impl<D: Dim> NodePool<D> {
         if old_base_layer != new_base_layer {
             // Nodes at or below the base layer are simulated for the full
             // amount possible, and the rule radius (presumably) hasn't changed
-            // so that's still the same.
-            self.invalidate_results_above(std::cmp::min(old_base_layer, new_base_layer))
+            // so that's still the same. Results for nodes above the base layer
+            // are kept around in case we switch back to the old parameters.
+            self.swap_results_above(
+                std::cmp::min(old_base_layer, new_base_layer),
+                old_params,
+                params,
+            )
         }
     }
 }
@@ -15,7 +15,7 @@ use crate::num::{BigUint, MaybeBigUint};
 
 /// Estimated overhead from the set data structure that stores nodes and from
 /// slop in the allocator.
-const RAW_NODE_MEMORY_OVERHEAD: usize = 3 * std::mem::size_of::<usize>();
+pub(super) const RAW_NODE_MEMORY_OVERHEAD: usize = 3 * std::mem::size_of::<usize>();
 
 /// Owned ND-tree node.
 ///
@@ -491,16 +491,21 @@ impl<D: Dim> RawNode<D> {
                 .map(std::mem::size_of_val)
                 .unwrap_or(0)
             // Heap size of population.
-            + if let Some(MaybeBigUint::Big(i)) = self.population() {
-                std::mem::size_of::<BigUint>()
+            + self.population_heap_size()
+            // Estimate the overhead of storing this node in the node pool.
+            + RAW_NODE_MEMORY_OVERHEAD
+    }
+    /// Returns an estimate for the amount of space on the heap used by this
+    /// node's population, if it is too large to store inline.
+    pub(super) fn population_heap_size(&self) -> usize {
+        if let Some(MaybeBigUint::Big(i)) = self.population() {
+            std::mem::size_of::<BigUint>()
                 // Ceiling division; assume that `BigUint` allocates 64 bits at
                 // a time.
                 + ((i.bits() - 1) / 64 + 1 * 8) as usize
-            } else {
-                0
-            }
-            // Estimate the overhead of storing this node in the node pool.
-            + RAW_NODE_MEMORY_OVERHEAD
+        } else {
+            0
+        }
     }
 
     /// Marks the node as unreachable during garbage collection.
@@ -129,10 +129,13 @@ pub trait NodeRefTrait<'pool>: Copy + private::Sealed {
     #[inline]
     fn result(self, guard: &SimCacheGuard<'_, Self::D>) -> Option<NodeRef<'pool, Self::D>> {
         guard.pool().assert_owns_node(self);
-        self.as_ref()
+        let ret = self
+            .as_ref()
             .as_raw()
             .result()
-            .map(|res| unsafe { NodeRef::new(self.pool(), res) })
+            .map(|res| unsafe { NodeRef::new(self.pool(), res) });
+        guard.pool().stats.record_result_lookup(ret.is_some());
+        ret
     }
     /// Atomically sets the result of simulating this node for some fixed number
     /// of generations.
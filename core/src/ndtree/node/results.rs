@@ -1,7 +1,17 @@
 use std::convert::TryInto;
 
-use super::Layer;
+use super::{Layer, RawNode};
+use crate::dim::Dim;
 use crate::num::{BigInt, BigUint, One, ToPrimitive, Zero};
+use crate::HashMap;
+
+/// Default number of step sizes whose HashLife results are kept in addition to
+/// the current one.
+pub(super) const DEFAULT_STASHED_RESULTS_CAPACITY: usize = 4;
+
+/// Estimated memory used by each stashed HashLife result, including overhead
+/// from the hash map.
+pub(super) const STASHED_RESULT_MEMORY_USAGE: usize = 3 * std::mem::size_of::<usize>();
 
 /// Parameters that determine the meaning of the "result" of a node.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -34,6 +44,17 @@ impl HashLifeResultParams {
         }
     }
 
+    /// Creates a `HashLifeResultParams` from the values returned by
+    /// `log2_rule_radius()`, `log2_step_size()`, and `num_steps()`.
+    pub(super) fn from_parts(log2_rule_radius: u32, log2_step_size: u32, num_steps: u32) -> Self {
+        HashLifeResultParamsBuilder {
+            log2_rule_radius,
+            log2_step_size,
+            num_steps,
+        }
+        .build()
+    }
+
     /// Returns the base-2 log of the rule radius (rounded up).
     #[inline]
     pub fn log2_rule_radius(&self) -> u32 {
@@ -62,6 +83,15 @@ impl HashLifeResultParams {
         self.min_layer
     }
 
+    /// Returns `true` if results computed using `self` are valid for `other`
+    /// and vice versa, which is the case when they have the same rule radius
+    /// and step size (but possibly different numbers of steps).
+    #[inline]
+    pub fn has_same_results_as(&self, other: Self) -> bool {
+        self.log2_rule_radius == other.log2_rule_radius
+            && self.log2_step_size == other.log2_step_size
+    }
+
     /// Returns the simulation step size.
     #[inline]
     pub fn big_step_size(&self) -> BigUint {
@@ -166,6 +196,97 @@ impl HashLifeResultParamsBuilder {
     }
 }
 
+/// Map from a node to the result of simulating it.
+type ResultMap<D> = HashMap<*const RawNode<D>, *const RawNode<D>>;
+
+/// HashLife results for simulation parameters other than the current ones.
+///
+/// Each node only has room for a single result, so when the simulation
+/// parameters change, results that are not valid for the new parameters are
+/// moved here instead of being discarded. Switching back to those parameters
+/// moves them back into the nodes. Only the most recently used
+/// `capacity` sets of results are kept.
+#[derive(Debug)]
+pub(super) struct StashedResults<D: Dim> {
+    /// Maximum number of sets of results to keep.
+    capacity: usize,
+    /// Sets of results, from least recently used to most recently used.
+    entries: Vec<(HashLifeResultParams, ResultMap<D>)>,
+}
+impl<D: Dim> StashedResults<D> {
+    /// Creates an empty set of stashed results.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: vec![],
+        }
+    }
+
+    /// Returns the maximum number of sets of results to keep.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Sets the maximum number of sets of results to keep, evicting the least
+    /// recently used ones if necessary.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+    /// Returns the total number of stashed results.
+    pub fn len(&self) -> usize {
+        self.entries.iter().map(|(_, results)| results.len()).sum()
+    }
+
+    /// Stashes results that are valid for `params`, replacing any existing
+    /// ones for the same parameters and evicting the least recently used ones
+    /// if necessary.
+    pub fn stash(&mut self, params: HashLifeResultParams, results: ResultMap<D>) {
+        self.entries.retain(|(p, _)| !p.has_same_results_as(params));
+        if !results.is_empty() {
+            self.entries.push((params, results));
+        }
+        self.evict();
+    }
+    /// Removes and returns results that are valid for `params`, if there are
+    /// any.
+    pub fn take(&mut self, params: HashLifeResultParams) -> Option<ResultMap<D>> {
+        let i = self
+            .entries
+            .iter()
+            .position(|(p, _)| p.has_same_results_as(params))?;
+        Some(self.entries.remove(i).1)
+    }
+    /// Removes all stashed results.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Calls a function for each stashed result with the node and its result.
+    pub fn for_each(&self, mut f: impl FnMut(&RawNode<D>, &RawNode<D>)) {
+        for (_, results) in &self.entries {
+            for (&node, &result) in results {
+                f(unsafe { &*node }, unsafe { &*result });
+            }
+        }
+    }
+    /// Keeps only the stashed results for which the predicate returns `true`.
+    pub fn retain(&mut self, mut f: impl FnMut(&RawNode<D>, &RawNode<D>) -> bool) {
+        for (_, results) in &mut self.entries {
+            results.retain(|&node, &mut result| f(unsafe { &*node }, unsafe { &*result }));
+        }
+        self.entries.retain(|(_, results)| !results.is_empty());
+    }
+
+    /// Evicts the least recently used sets of results until there are no more
+    /// than `capacity`.
+    fn evict(&mut self) {
+        if self.entries.len() > self.capacity {
+            let excess = self.entries.len() - self.capacity;
+            self.entries.drain(..excess);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -108,4 +108,17 @@ impl<T: Eq + Hash> ShardedBoxedSet<T> {
             }
         }
     }
+
+    /// Returns the number of elements in each shard.
+    pub fn shard_lens(&self) -> Vec<usize> {
+        self.shards.iter().map(|shard| shard.lock().len()).collect()
+    }
+    /// Returns the number of elements each shard can hold without
+    /// reallocating.
+    pub fn shard_capacities(&self) -> Vec<usize> {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().capacity())
+            .collect()
+    }
 }
@@ -0,0 +1,224 @@
+//! Snapshots of a node pool, including cached HashLife results.
+//!
+//! Long simulations build up a valuable cache of HashLife results, which can
+//! be saved to disk and reloaded so that resuming a simulation doesn't start
+//! cold.
+//!
+//! Like NDCell's binary pattern format (see `crate::io::binary`), all integers
+//! are stored as LEB128 varints. A snapshot consists of the magic bytes
+//! `NDCP`, a version byte, and then, in order:
+//!
+//! - number of dimensions
+//! - base-2 log of the rule radius, base-2 log of the step size, and number of
+//!   steps (see `HashLifeResultParams`)
+//! - number of nodes, followed by each node
+//! - number of HashLife results, followed by each result
+//!
+//! Each node begins with its layer. Leaf nodes contain their cell states, one
+//! byte each; non-leaf nodes contain the indices of their `2^NDIM` children.
+//! Nodes are indexed starting from 0 in the order they appear and are sorted
+//! by layer, so each node's children appear before it. Each result consists
+//! of the index of a node followed by the index of its result.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Write};
+
+use super::{
+    HashLifeResultParams, Layer, NodePool, NodeRef, NodeRefTrait, RawNode, SharedNodePool,
+};
+use crate::dim::Dim;
+use crate::io::binary::{read_varint, write_varint};
+use crate::io::{BinaryError, BinaryResult};
+use crate::HashMap;
+
+/// Magic bytes at the beginning of every snapshot.
+const MAGIC: &[u8] = b"NDCP";
+/// Current version of the snapshot format.
+const VERSION: u8 = 1;
+
+impl<D: Dim> NodePool<D> {
+    /// Writes every node in the pool, along with HashLife results computed
+    /// using `params`, so that they can be reloaded using
+    /// `SharedNodePool::read_snapshot()`.
+    ///
+    /// If `params` is not the most recent set of parameters used for
+    /// simulation, then results that are not valid for `params` are discarded
+    /// first, as with `sim_with()`. Results depend on the rule as well, so a
+    /// snapshot should only be reloaded for use with the same rule.
+    ///
+    /// Nodes and results added by other threads while the snapshot is being
+    /// written may be left out.
+    ///
+    /// Snapshots are not compressed; use `Compression::compress_writer()` to
+    /// compress one.
+    pub fn write_snapshot(
+        &self,
+        params: HashLifeResultParams,
+        mut w: impl Write,
+    ) -> io::Result<()> {
+        // Hold the simulation lock so that the meaning of results can't change
+        // while we write them.
+        let _sim_guard = self.sim_with(params);
+
+        // Nodes can't be removed without a `&mut NodePool`, so these pointers
+        // are valid as long as `self` is borrowed.
+        let mut nodes: Vec<*const RawNode<D>> = vec![];
+        self.nodes.for_each(|node| nodes.push(node));
+        let mut nodes: Vec<&RawNode<D>> = nodes.into_iter().map(|ptr| unsafe { &*ptr }).collect();
+        nodes.sort_by_key(|node| node.layer());
+
+        // Another thread may have added a node after we listed the shard
+        // containing one of its children, so skip any node whose children we
+        // can't refer to. Nodes are sorted by layer, so each node's children
+        // have already been considered by the time we reach it.
+        let mut indices: HashMap<*const RawNode<D>, usize> = HashMap::default();
+        let mut complete_nodes: Vec<(&RawNode<D>, Vec<usize>)> = vec![];
+        for node in nodes {
+            let child_indices = match node.children_slice() {
+                Some(children) => match children
+                    .iter()
+                    .map(|&child| indices.get(&(child as *const _)).copied())
+                    .collect::<Option<Vec<usize>>>()
+                {
+                    Some(child_indices) => child_indices,
+                    None => continue,
+                },
+                None => vec![],
+            };
+            indices.insert(node as *const _, complete_nodes.len());
+            complete_nodes.push((node, child_indices));
+        }
+
+        w.write_all(MAGIC)?;
+        w.write_all(&[VERSION])?;
+        write_varint(&mut w, D::NDIM)?;
+        write_varint(&mut w, params.log2_rule_radius() as usize)?;
+        write_varint(&mut w, params.log2_step_size() as usize)?;
+        write_varint(&mut w, params.num_steps() as usize)?;
+
+        write_varint(&mut w, complete_nodes.len())?;
+        for (node, child_indices) in &complete_nodes {
+            write_varint(&mut w, node.layer().to_usize())?;
+            match node.cell_slice() {
+                Some(cells) => w.write_all(cells)?,
+                None => {
+                    for &child_index in child_indices {
+                        write_varint(&mut w, child_index)?;
+                    }
+                }
+            }
+        }
+
+        // For the same reason, skip any results we can't refer to.
+        let results: Vec<(usize, usize)> = complete_nodes
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (node, _))| Some((i, *indices.get(&(node.result()? as *const _))?)))
+            .collect();
+        write_varint(&mut w, results.len())?;
+        for (node_index, result_index) in results {
+            write_varint(&mut w, node_index)?;
+            write_varint(&mut w, result_index)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D: Dim> SharedNodePool<D> {
+    /// Reads a snapshot written by `NodePool::write_snapshot()` into a new
+    /// node pool, returning the pool and the parameters for which its HashLife
+    /// results are valid.
+    ///
+    /// Nodes that are not reachable from an `ArcNode` are removed by the next
+    /// garbage collection, so load the pattern into the new pool (such as by
+    /// passing it to `CaFormatTrait::to_ndtree()`) before collecting garbage.
+    pub fn read_snapshot(mut r: impl Read) -> BinaryResult<(Self, HashLifeResultParams)> {
+        let mut header = [0_u8; 5];
+        r.read_exact(&mut header)?;
+        if !header.starts_with(MAGIC) {
+            return Err(BinaryError::MissingHeader);
+        }
+        let version = header[4];
+        if version != VERSION {
+            return Err(BinaryError::UnsupportedVersion(version));
+        }
+
+        let ndim = read_varint(&mut r)?;
+        if ndim != D::NDIM {
+            return Err(BinaryError::BadDimensionality {
+                expected: D::NDIM,
+                got: ndim,
+            });
+        }
+        let log2_rule_radius = read_u32(&mut r)?;
+        let log2_step_size = read_u32(&mut r)?;
+        let num_steps = read_u32(&mut r)?;
+        // Avoid overflow when computing the size of simulated nodes.
+        if log2_rule_radius
+            .checked_add(log2_step_size)
+            .and_then(|n| n.checked_add(2))
+            .is_none()
+        {
+            return Err(BinaryError::InvalidContent);
+        }
+        let params = HashLifeResultParams::from_parts(log2_rule_radius, log2_step_size, num_steps);
+
+        let node_pool = Self::new();
+        {
+            let node_pool_access = node_pool.access();
+            let _sim_guard = node_pool_access.sim_with(params);
+
+            let mut nodes: Vec<NodeRef<'_, D>> = vec![];
+            let node_count = read_varint(&mut r)?;
+            for _ in 0..node_count {
+                let layer = Layer(read_u32(&mut r)?);
+                let node = if layer.is_leaf::<D>() {
+                    let mut cells = vec![0_u8; layer.num_cells::<D>().unwrap()];
+                    r.read_exact(&mut cells)?;
+                    node_pool_access.get_from_cells(cells)
+                } else {
+                    let mut children = Vec::with_capacity(D::BRANCHING_FACTOR);
+                    for _ in 0..D::BRANCHING_FACTOR {
+                        let child = get_node(&nodes, read_varint(&mut r)?)?;
+                        if child.layer() != layer.child_layer() {
+                            return Err(BinaryError::InvalidContent);
+                        }
+                        children.push(child);
+                    }
+                    node_pool_access.join_nodes(children)
+                };
+                nodes.push(node);
+            }
+
+            let result_count = read_varint(&mut r)?;
+            for _ in 0..result_count {
+                let node = get_node(&nodes, read_varint(&mut r)?)?;
+                let result = get_node(&nodes, read_varint(&mut r)?)?;
+                if node.layer() < params.min_layer() || result.layer() != node.layer().child_layer()
+                {
+                    return Err(BinaryError::InvalidContent);
+                }
+                node.set_result(Some(result));
+            }
+        }
+
+        Ok((node_pool, params))
+    }
+}
+
+/// Returns the node at index `i`, or an error if it has not been defined yet.
+fn get_node<'pool, D: Dim>(
+    nodes: &[NodeRef<'pool, D>],
+    i: usize,
+) -> BinaryResult<NodeRef<'pool, D>> {
+    nodes
+        .get(i)
+        .copied()
+        .ok_or(BinaryError::InvalidNodeIndex(i))
+}
+
+/// Reads an unsigned LEB128 varint that must fit in a `u32`.
+fn read_u32(r: &mut impl Read) -> BinaryResult<u32> {
+    u32::try_from(read_varint(r)?).map_err(|_| BinaryError::InvalidVarint)
+}
@@ -0,0 +1,273 @@
+//! Statistics about the contents and performance of a node pool.
+
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::time::Duration;
+
+use super::HashLifeResultParams;
+
+/// Number of garbage collections to remember.
+const GC_HISTORY_LEN: usize = 32;
+
+/// Statistics about the contents and performance of a node pool, returned by
+/// `NodePool::stats()`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NodePoolStats {
+    /// Number of nodes at each layer, indexed by layer.
+    pub nodes_per_layer: Vec<usize>,
+    /// Number of nodes with a cached HashLife result for the current
+    /// simulation parameters.
+    pub nodes_with_results: usize,
+    /// Number of nodes in each shard of the pool's hash set.
+    pub shard_lens: Vec<usize>,
+    /// Number of nodes each shard of the pool's hash set can hold without
+    /// reallocating.
+    pub shard_capacities: Vec<usize>,
+    /// Estimated memory usage, by category.
+    pub memory: MemoryStats,
+    /// HashLife result cache hits and misses for each set of parameters that
+    /// has been used for simulation, with the current parameters last.
+    pub results: Vec<ResultCacheStats>,
+    /// Recent garbage collections, from oldest to newest.
+    pub gc_history: Vec<GcStats>,
+}
+impl fmt::Display for NodePoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Nodes: {} ({} with cached results)",
+            self.node_count(),
+            self.nodes_with_results,
+        )?;
+        for (layer, &count) in self.nodes_per_layer.iter().enumerate() {
+            if count != 0 {
+                writeln!(f, "  Layer {}: {}", layer, count)?;
+            }
+        }
+
+        let shard_count = self.shard_lens.len();
+        let min_shard_len = self.shard_lens.iter().min().copied().unwrap_or(0);
+        let max_shard_len = self.shard_lens.iter().max().copied().unwrap_or(0);
+        let total_capacity: usize = self.shard_capacities.iter().sum();
+        writeln!(
+            f,
+            "Shards: {} with {}-{} nodes each ({:.0}% full)",
+            shard_count,
+            min_shard_len,
+            max_shard_len,
+            percent(self.node_count(), total_capacity).unwrap_or(0.0),
+        )?;
+
+        writeln!(f, "Memory: {} bytes", self.memory.total())?;
+        writeln!(f, "  Nodes: {}", self.memory.nodes)?;
+        writeln!(f, "  Cells: {}", self.memory.cells)?;
+        writeln!(f, "  Children: {}", self.memory.children)?;
+        writeln!(f, "  Populations: {}", self.memory.populations)?;
+        writeln!(f, "  Overhead: {}", self.memory.overhead)?;
+        writeln!(f, "  Stashed results: {}", self.memory.stashed_results)?;
+
+        writeln!(f, "Result cache:")?;
+        for r in &self.results {
+            write!(
+                f,
+                "  Step 2^{}, radius 2^{}: {} hits, {} misses",
+                r.params.log2_step_size(),
+                r.params.log2_rule_radius(),
+                r.hits,
+                r.misses,
+            )?;
+            if let Some(hit_rate) = r.hit_rate() {
+                write!(f, " ({:.1}% hit rate)", hit_rate * 100.0)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "Garbage collections:")?;
+        for gc in &self.gc_history {
+            writeln!(
+                f,
+                "  {:?}: {} dropped, {} kept, {} -> {} bytes in {:.2?}",
+                gc.kind,
+                gc.nodes_dropped,
+                gc.nodes_kept,
+                gc.memory_before,
+                gc.memory_after,
+                gc.duration,
+            )?;
+        }
+        Ok(())
+    }
+}
+impl NodePoolStats {
+    /// Returns the total number of nodes in the pool.
+    pub fn node_count(&self) -> usize {
+        self.nodes_per_layer.iter().sum()
+    }
+}
+
+/// Estimated memory usage of a node pool, by category.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// Memory used by the fixed-size part of each node.
+    pub nodes: usize,
+    /// Memory used by the cells of leaf nodes.
+    pub cells: usize,
+    /// Memory used by pointers to the children of non-leaf nodes.
+    pub children: usize,
+    /// Memory used by populations too large to store inline.
+    pub populations: usize,
+    /// Estimated overhead from the hash set and from slop in the allocator.
+    pub overhead: usize,
+    /// Memory used by HashLife results stashed for other step sizes.
+    pub stashed_results: usize,
+}
+impl MemoryStats {
+    /// Returns the total estimated memory usage, which is the same as
+    /// `NodePool::memory_usage()`.
+    pub fn total(&self) -> usize {
+        self.nodes
+            + self.cells
+            + self.children
+            + self.populations
+            + self.overhead
+            + self.stashed_results
+    }
+}
+
+/// HashLife result cache hits and misses for a single set of simulation
+/// parameters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ResultCacheStats {
+    /// Simulation parameters.
+    pub params: HashLifeResultParams,
+    /// Number of times a result was already computed.
+    pub hits: usize,
+    /// Number of times a result had to be computed.
+    pub misses: usize,
+}
+impl ResultCacheStats {
+    /// Returns the total number of times a result was requested.
+    pub fn lookups(&self) -> usize {
+        self.hits + self.misses
+    }
+    /// Returns the fraction of lookups that were hits, or `None` if there have
+    /// been no lookups.
+    pub fn hit_rate(&self) -> Option<f64> {
+        percent(self.hits, self.lookups()).map(|p| p / 100.0)
+    }
+}
+
+/// Kind of garbage collection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GcKind {
+    /// Garbage collection that also invalidated all HashLife results.
+    Strong,
+    /// Garbage collection that kept HashLife results for reachable nodes.
+    Weak,
+}
+
+/// Record of a single garbage collection.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct GcStats {
+    /// Kind of garbage collection.
+    pub kind: GcKind,
+    /// Number of nodes removed from the pool.
+    pub nodes_dropped: usize,
+    /// Number of nodes kept in the pool.
+    pub nodes_kept: usize,
+    /// Estimated memory usage before garbage collection.
+    pub memory_before: usize,
+    /// Estimated memory usage after garbage collection.
+    pub memory_after: usize,
+    /// Time taken by garbage collection.
+    pub duration: Duration,
+}
+
+/// Counters that a node pool updates as it is used.
+#[derive(Debug, Default)]
+pub(super) struct StatsRecorder {
+    /// Number of result cache hits for the current simulation parameters.
+    result_hits: AtomicUsize,
+    /// Number of result cache misses for the current simulation parameters.
+    result_misses: AtomicUsize,
+    /// Result cache hits and misses for previous simulation parameters.
+    past_results: Mutex<Vec<ResultCacheStats>>,
+    /// Recent garbage collections, from oldest to newest.
+    gc_history: Mutex<VecDeque<GcStats>>,
+}
+impl StatsRecorder {
+    /// Records a HashLife result lookup for the current simulation parameters.
+    #[inline]
+    pub fn record_result_lookup(&self, hit: bool) {
+        if hit {
+            self.result_hits.fetch_add(1, Relaxed);
+        } else {
+            self.result_misses.fetch_add(1, Relaxed);
+        }
+    }
+    /// Moves the counts for the current simulation parameters, which are about
+    /// to change, into the record for `old_params`.
+    pub fn finish_params(&self, old_params: HashLifeResultParams) {
+        let hits = self.result_hits.swap(0, Relaxed);
+        let misses = self.result_misses.swap(0, Relaxed);
+        if hits + misses == 0 {
+            return;
+        }
+        let mut past_results = self.past_results.lock();
+        match past_results.iter_mut().find(|r| r.params == old_params) {
+            Some(r) => {
+                r.hits += hits;
+                r.misses += misses;
+            }
+            None => past_results.push(ResultCacheStats {
+                params: old_params,
+                hits,
+                misses,
+            }),
+        }
+    }
+    /// Returns result cache statistics for every set of parameters, including
+    /// `current_params`.
+    pub fn result_stats(&self, current_params: HashLifeResultParams) -> Vec<ResultCacheStats> {
+        let mut ret = self.past_results.lock().clone();
+        let mut current = ResultCacheStats {
+            params: current_params,
+            hits: self.result_hits.load(Relaxed),
+            misses: self.result_misses.load(Relaxed),
+        };
+        if let Some(i) = ret.iter().position(|r| r.params == current_params) {
+            let past = ret.remove(i);
+            current.hits += past.hits;
+            current.misses += past.misses;
+        }
+        if current.lookups() > 0 {
+            ret.push(current);
+        }
+        ret
+    }
+
+    /// Records a garbage collection, forgetting the oldest one if necessary.
+    pub fn record_gc(&self, gc: GcStats) {
+        let mut gc_history = self.gc_history.lock();
+        if gc_history.len() >= GC_HISTORY_LEN {
+            gc_history.pop_front();
+        }
+        gc_history.push_back(gc);
+    }
+    /// Returns recent garbage collections, from oldest to newest.
+    pub fn gc_history(&self) -> Vec<GcStats> {
+        self.gc_history.lock().iter().copied().collect()
+    }
+}
+
+/// Returns `numerator` as a percentage of `denominator`, or `None` if
+/// `denominator` is zero.
+fn percent(numerator: usize, denominator: usize) -> Option<f64> {
+    if denominator == 0 {
+        None
+    } else {
+        Some(numerator as f64 * 100.0 / denominator as f64)
+    }
+}
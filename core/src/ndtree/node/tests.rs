@@ -1,9 +1,11 @@
 use proptest::prelude::*;
+use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::hash::{BuildHasher, Hash, Hasher};
 
 use super::*;
 use crate::dim::{Dim2D, Dim3D};
+use crate::io::BinaryError;
 use crate::ndrect::{BigRect3D, CanContain, NdRect};
 use crate::ndvec::{proptest_bigvec, BigVec3D, IVec3D, NdVec};
 
@@ -209,6 +211,234 @@ proptest! {
     }
 }
 
+#[test]
+fn test_ndtree_node_pool_snapshot() {
+    use crate::ndtree::NdTree;
+    use crate::num::BigInt;
+    use crate::sim::{hashlife, rule::LIFE};
+
+    let gens = BigInt::from(64);
+    let params = HashLifeResultParams::new()
+        .with_rule_radius(1)
+        .with_step_size(&gens)
+        .build();
+    let mut ndtree = NdTree::<Dim2D>::new();
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        ndtree.set_cell(&NdVec::big(pos), 1_u8);
+    }
+    let initial = ndtree.clone();
+    hashlife::step(&mut ndtree, &LIFE, &gens);
+
+    let mut snapshot = vec![];
+    ndtree
+        .pool()
+        .access()
+        .write_snapshot(params, &mut snapshot)
+        .unwrap();
+    let (new_node_pool, new_params) = SharedNodePool::read_snapshot(&snapshot[..]).unwrap();
+    assert_eq!(params, new_params);
+
+    // Simulating the same pattern in the new pool only uses cached results,
+    // so no new nodes are created.
+    let mut new_ndtree = NdTree::with_node_pool(new_node_pool.new_ref());
+    new_ndtree.set_root_centered(
+        new_node_pool
+            .access()
+            .copy_from_other_pool(&initial.root_ref()),
+    );
+    let memory_usage = new_node_pool.access().memory_usage();
+    hashlife::step(&mut new_ndtree, &LIFE, &gens);
+    assert_eq!(memory_usage, new_node_pool.access().memory_usage());
+    assert_eq!(
+        ndtree.nonzero_cells().collect::<HashSet<_>>(),
+        new_ndtree.nonzero_cells().collect::<HashSet<_>>(),
+    );
+
+    // Snapshots must match the dimensionality of the pool.
+    assert_eq!(
+        BinaryError::BadDimensionality {
+            expected: 3,
+            got: 2
+        },
+        SharedNodePool::<Dim3D>::read_snapshot(&snapshot[..]).unwrap_err(),
+    );
+    assert_eq!(
+        BinaryError::UnexpectedEnd,
+        SharedNodePool::<Dim2D>::read_snapshot(&snapshot[..snapshot.len() - 1]).unwrap_err(),
+    );
+    assert_eq!(
+        BinaryError::MissingHeader,
+        SharedNodePool::<Dim2D>::read_snapshot(&b"NDCB\x01"[..]).unwrap_err(),
+    );
+}
+
+#[test]
+fn test_ndtree_node_pool_snapshot_concurrent() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+
+    let node_pool = SharedNodePool::<Dim2D>::new();
+    let params = HashLifeResultParams::new()
+        .with_rule_radius(1)
+        .with_step_size(&crate::num::BigInt::from(4))
+        .build();
+
+    // Snapshots written while another thread adds nodes must still be valid.
+    let cells_set = AtomicUsize::new(0);
+    let done = AtomicBool::new(false);
+    let result = std::thread::scope(|s| {
+        s.spawn(|| {
+            let node_pool_access = node_pool.access();
+            let mut node = node_pool_access.get_empty(Layer(6));
+            for i in 0..20_000 {
+                if done.load(Relaxed) {
+                    break;
+                }
+                let pos = NdVec::big([i % 64, i / 64 % 64]);
+                node = node.set_cell(&pos, (i / 4096 % 255 + 1) as u8);
+                cells_set.store(i as usize, Relaxed);
+            }
+        });
+        while cells_set.load(Relaxed) < 1000 {
+            std::thread::yield_now();
+        }
+        // Catch panics so that the other thread is always told to stop.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            for _ in 0..20 {
+                let mut snapshot = vec![];
+                node_pool
+                    .access()
+                    .write_snapshot(params, &mut snapshot)
+                    .unwrap();
+                SharedNodePool::<Dim2D>::read_snapshot(&snapshot[..]).unwrap();
+            }
+        }));
+        done.store(true, Relaxed);
+        result
+    });
+    result.unwrap();
+}
+
+#[test]
+fn test_ndtree_node_pool_weak_gc_keeps_empty_nodes() {
+    let node_pool = SharedNodePool::<Dim2D>::new();
+    assert!(node_pool.access().get_empty(TEST_LAYER).is_empty());
+    let memory_usage = node_pool.access().memory_usage();
+
+    // Cached empty nodes are not referenced by any `ArcNode`, but they must
+    // survive a weak garbage collection.
+    let (nodes_dropped, _) = node_pool.total_access().weak_gc();
+    assert_eq!(0, nodes_dropped);
+    assert_eq!(memory_usage, node_pool.access().memory_usage());
+    assert!(node_pool.access().get_empty(TEST_LAYER).is_empty());
+    assert_eq!(memory_usage, node_pool.access().memory_usage());
+
+    // A strong garbage collection clears the cache and drops them.
+    let (nodes_dropped, nodes_kept) = node_pool.total_access().strong_gc();
+    assert!(nodes_dropped > 0);
+    assert_eq!(0, nodes_kept);
+}
+
+#[test]
+fn test_ndtree_node_pool_result_cache() {
+    use crate::ndtree::NdTree;
+    use crate::num::BigInt;
+    use crate::sim::{hashlife, rule::LIFE};
+
+    let mut initial = NdTree::<Dim2D>::new();
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        initial.set_cell(&NdVec::big(pos), 1_u8);
+    }
+    // Make the tree big enough to simulate 1024 generations, and keep the
+    // node that will actually be simulated so that we can check its result.
+    initial.expand_while(|ndtree| ndtree.layer() < Layer(12));
+    let mut simulated = initial.clone();
+    simulated.expand();
+    simulated.expand();
+
+    let node_pool = initial.pool().new_ref();
+    let big_gens = BigInt::from(1024);
+    let small_gens = BigInt::from(1);
+    let has_big_result = || {
+        let node_pool_access = node_pool.access();
+        let sim_guard = node_pool_access.sim_with(
+            HashLifeResultParams::new()
+                .with_rule_radius(1)
+                .with_step_size(&big_gens)
+                .build(),
+        );
+        let root = simulated.root().as_ref(&node_pool_access);
+        root.result(&sim_guard).is_some()
+    };
+    let cells = |ndtree: &NdTree<Dim2D>| ndtree.nonzero_cells().collect::<HashSet<_>>();
+
+    let mut big_step = initial.clone();
+    hashlife::step(&mut big_step, &LIFE, &big_gens);
+    assert!(has_big_result());
+    let mut small_step = initial.clone();
+    hashlife::step(&mut small_step, &LIFE, &small_gens);
+
+    // Results for the big step size are kept while simulating using the small
+    // step size, even across garbage collection.
+    node_pool.total_access().weak_gc();
+    assert!(has_big_result());
+    let mut big_step_again = initial.clone();
+    hashlife::step(&mut big_step_again, &LIFE, &big_gens);
+    assert_eq!(cells(&big_step), cells(&big_step_again));
+
+    // With no room for other step sizes, switching step size discards results.
+    node_pool.access().set_result_cache_capacity(0);
+    let mut small_step_again = initial.clone();
+    hashlife::step(&mut small_step_again, &LIFE, &small_gens);
+    assert_eq!(cells(&small_step), cells(&small_step_again));
+    assert!(!has_big_result());
+}
+
+#[test]
+fn test_ndtree_node_pool_stats() {
+    use crate::ndtree::NdTree;
+    use crate::sim::{hashlife, rule::LIFE};
+
+    let mut ndtree = NdTree::<Dim2D>::new();
+    for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+        ndtree.set_cell(&NdVec::big(pos), 1_u8);
+    }
+    let node_pool = ndtree.pool().new_ref();
+    for _ in 0..4 {
+        hashlife::step(&mut ndtree, &LIFE, &64.into());
+    }
+
+    let stats = node_pool.access().stats();
+    assert_eq!(stats.node_count(), stats.shard_lens.iter().sum::<usize>());
+    assert!(stats.nodes_with_results > 0);
+    assert_eq!(node_pool.access().memory_usage(), stats.memory.total());
+    // A glider repeats itself, so later steps reuse earlier results.
+    assert_eq!(1, stats.results.len());
+    assert_eq!(6, stats.results[0].params.log2_step_size());
+    assert!(stats.results[0].hits > 0);
+    assert!(stats.results[0].misses > 0);
+    assert!(stats.gc_history.is_empty());
+
+    let (nodes_dropped, nodes_kept) = node_pool.total_access().strong_gc();
+    let stats = node_pool.access().stats();
+    assert_eq!(nodes_kept, stats.node_count());
+    assert_eq!(0, stats.nodes_with_results);
+    assert_eq!(1, stats.gc_history.len());
+    assert_eq!(GcKind::Strong, stats.gc_history[0].kind);
+    assert_eq!(nodes_dropped, stats.gc_history[0].nodes_dropped);
+    assert_eq!(stats.memory.total(), stats.gc_history[0].memory_after);
+
+    let s = stats.to_string();
+    assert!(s.starts_with(&format!(
+        "Nodes: {} (0 with cached results)\n",
+        stats.node_count(),
+    )));
+    assert!(s.contains(&format!("\nMemory: {} bytes\n", stats.memory.total())));
+    assert!(s.contains(&format!(
+        "\nGarbage collections:\n  Strong: {} dropped, {} kept, ",
+        nodes_dropped, nodes_kept,
+    )));
+}
+
 fn pos_in_node(layer: Layer) -> impl Strategy<Value = BigVec3D> {
     let len = layer
         .len()
@@ -1,12 +1,18 @@
 //! Arbitrary regions of an N-dimensional grid.
 
-use super::{ArcNode, NdTree, NodeRef, NodeRefTrait};
+use itertools::Itertools;
+
+use super::{ArcNode, NdTree, NodeRef, NodeRefTrait, SharedNodePool};
 use crate::dim::Dim;
 use crate::lazyvec::LazyVec;
 use crate::ndrect::{BigRect, CanContain};
 use crate::ndvec::BigVec;
+use crate::num::{BigInt, Signed, Zero};
 
-/// Finite set of cell positions, with several possible representations.
+/// Set of cell positions, with several possible representations.
+///
+/// Shapes and combinations of shapes are only converted to an ND-tree when
+/// necessary (e.g., when used as a mask for `NdTree::paste_custom()`).
 #[derive(Debug, Clone)]
 pub enum Region<D: Dim> {
     /// Region containing no cells.
@@ -15,6 +21,38 @@ pub enum Region<D: Dim> {
     Rect(BigRect<D>),
     /// Arbitrarily-shaped region, represented using cell states #0 and #1.
     NdTree(NdTree<D>),
+    /// Cells within a given distance of a center cell. If `radius` is
+    /// negative, the region is empty.
+    Ball {
+        /// Center cell.
+        center: BigVec<D>,
+        /// Maximum distance from the center (inclusive).
+        radius: BigInt,
+        /// Norm used to measure distance.
+        norm: Norm,
+    },
+    /// Axis-aligned ellipsoid. If any radius is negative, the region is empty.
+    Ellipsoid {
+        /// Center cell.
+        center: BigVec<D>,
+        /// Radius along each axis (inclusive).
+        radii: BigVec<D>,
+    },
+    /// Unbounded region containing all cells `pos` for which the dot product
+    /// of `normal` and `pos` is less than or equal to `offset`.
+    HalfSpace {
+        /// Vector pointing out of the region.
+        normal: BigVec<D>,
+        /// Maximum dot product of `normal` and a position in the region.
+        offset: BigInt,
+    },
+    /// Cells that are in any of the regions.
+    Union(Vec<Region<D>>),
+    /// Cells that are in all of the regions. If the list is empty, this
+    /// contains every cell (and is unbounded).
+    Intersection(Vec<Region<D>>),
+    /// Cells that are in the first region but not the second.
+    Difference(Box<Region<D>>, Box<Region<D>>),
 }
 impl<D: Dim> Default for Region<D> {
     fn default() -> Self {
@@ -26,67 +64,454 @@ impl<D: Dim> From<BigRect<D>> for Region<D> {
         Region::Rect(rect)
     }
 }
+
+/// Norm used to measure distance for `Region::Ball`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Norm {
+    /// Sum of the absolute values of components (taxicab distance). Balls in
+    /// this norm are diamonds/octahedra.
+    L1,
+    /// Euclidean distance. Balls in this norm are circles/spheres.
+    L2,
+    /// Maximum of the absolute values of components (Chebyshev distance).
+    /// Balls in this norm are squares/cubes.
+    LInfinity,
+}
+
+/// Relationship between a region and a rectangle.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum RectRelation {
+    /// The rectangle is completely inside the region.
+    Inside,
+    /// The rectangle is completely outside the region.
+    Outside,
+    /// The rectangle may be partially inside the region.
+    Partial,
+}
+
+/// Rough bounds of a region.
+#[derive(Debug, Clone)]
+enum Bounds<D: Dim> {
+    /// The region is empty.
+    Empty,
+    /// The region is contained within the rectangle.
+    Bounded(BigRect<D>),
+    /// The region may be infinite.
+    Unbounded,
+}
+impl<D: Dim> Bounds<D> {
+    fn intersect_rect(self, rect: &BigRect<D>) -> Option<BigRect<D>> {
+        match self {
+            Bounds::Empty => None,
+            Bounds::Bounded(r) => r.intersection(rect),
+            Bounds::Unbounded => Some(rect.clone()),
+        }
+    }
+}
+
 impl<D: Dim> Region<D> {
+    /// Returns the union of two regions.
+    pub fn union(self, other: Self) -> Self {
+        match (self, other) {
+            (Region::Empty, r) | (r, Region::Empty) => r,
+            (Region::Union(mut a), Region::Union(b)) => {
+                a.extend(b);
+                Region::Union(a)
+            }
+            (Region::Union(mut a), r) | (r, Region::Union(mut a)) => {
+                a.push(r);
+                Region::Union(a)
+            }
+            (a, b) => Region::Union(vec![a, b]),
+        }
+    }
+    /// Returns the intersection of two regions.
+    pub fn intersection(self, other: Self) -> Self {
+        match (self, other) {
+            (Region::Empty, _) | (_, Region::Empty) => Region::Empty,
+            (Region::Rect(a), Region::Rect(b)) => match a.intersection(&b) {
+                Some(r) => Region::Rect(r),
+                None => Region::Empty,
+            },
+            (Region::Intersection(mut a), Region::Intersection(b)) => {
+                a.extend(b);
+                Region::Intersection(a)
+            }
+            (Region::Intersection(mut a), r) | (r, Region::Intersection(mut a)) => {
+                a.push(r);
+                Region::Intersection(a)
+            }
+            (a, b) => Region::Intersection(vec![a, b]),
+        }
+    }
+    /// Returns the set of cells that are in `self` but not in `other`.
+    pub fn difference(self, other: Self) -> Self {
+        match (self, other) {
+            (Region::Empty, _) => Region::Empty,
+            (r, Region::Empty) => r,
+            (a, b) => Region::Difference(Box::new(a), Box::new(b)),
+        }
+    }
+
+    /// Returns `true` if the region contains finitely many cells.
+    ///
+    /// This may return `false` for some regions that are actually finite, such
+    /// as the intersection of two opposing half-spaces.
+    pub fn is_bounded(&self) -> bool {
+        !matches!(self.bounds(), Bounds::Unbounded)
+    }
+
     /// Returns the smallest rectangle containing the region, or `None` if the
     /// region is empty.
+    ///
+    /// For intersections and differences, this requires converting the region
+    /// to an ND-tree.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the region is unbounded.
     pub fn bounding_rect(&self) -> Option<BigRect<D>> {
         match self {
             Region::Empty => None,
             Region::Rect(rect) => Some(rect.clone()),
             Region::NdTree(ndtree) => ndtree.bounding_rect(),
+            Region::Intersection(_) | Region::Difference(_, _) => match self.bounds() {
+                Bounds::Empty => None,
+                Bounds::Bounded(rect) => self
+                    .to_ndtree_within(rect.center(), &rect, &SharedNodePool::new())
+                    .bounding_rect(),
+                Bounds::Unbounded => panic!("Cannot get bounding rectangle of unbounded region"),
+            },
+            Region::Union(regions) => {
+                regions
+                    .iter()
+                    .filter_map(|r| r.bounding_rect())
+                    .fold(None, |acc, r| match acc {
+                        Some(acc) => Some(BigRect::span_rects(acc, r)),
+                        None => Some(r),
+                    })
+            }
+            _ => match self.bounds() {
+                Bounds::Empty => None,
+                Bounds::Bounded(rect) => Some(rect),
+                Bounds::Unbounded => panic!("Cannot get bounding rectangle of unbounded region"),
+            },
         }
     }
-
-    /// Converts the region into an ND-tree using state #1 for cells that are
-    /// inside the region and state #0 for cells that are not.
-    pub fn into_ndtree(self, center: BigVec<D>) -> NdTree<D> {
+    /// Returns a rectangle containing the region, which may be larger than
+    /// necessary.
+    fn bounds(&self) -> Bounds<D> {
         match self {
-            Region::Empty => NdTree::with_center(center),
-            Region::Rect(rect) => {
-                let mut ret = NdTree::with_center(center);
-                ret.expand_to(&rect);
-
-                let node_pool = ret.pool().access();
-
-                let mut full_nodes: LazyVec<NodeRef<'_, D>, _> =
-                    LazyVec::new(|previous| match previous.last() {
-                        Some(smaller_full_node) => {
-                            node_pool.join_nodes(std::iter::repeat(*smaller_full_node))
+            Region::Empty => Bounds::Empty,
+            Region::Rect(rect) => Bounds::Bounded(rect.clone()),
+            Region::NdTree(ndtree) => match ndtree.bounding_rect() {
+                Some(rect) => Bounds::Bounded(rect),
+                None => Bounds::Empty,
+            },
+            Region::Ball { center, radius, .. } => {
+                if radius.is_negative() {
+                    Bounds::Empty
+                } else {
+                    Bounds::Bounded(BigRect::centered(center.clone(), radius))
+                }
+            }
+            Region::Ellipsoid { center, radii } => {
+                if D::axes().iter().any(|&ax| radii[ax].is_negative()) {
+                    Bounds::Empty
+                } else {
+                    Bounds::Bounded(BigRect::span(center - radii, center + radii))
+                }
+            }
+            Region::HalfSpace { normal, offset } => {
+                if normal.is_zero() && offset.is_negative() {
+                    Bounds::Empty
+                } else {
+                    Bounds::Unbounded
+                }
+            }
+            Region::Union(regions) => {
+                let mut ret = Bounds::Empty;
+                for r in regions {
+                    ret = match (ret, r.bounds()) {
+                        (Bounds::Unbounded, _) | (_, Bounds::Unbounded) => {
+                            return Bounds::Unbounded
                         }
-                        None => node_pool.get_from_cells(vec![1_u8]),
-                    });
+                        (Bounds::Empty, b) | (b, Bounds::Empty) => b,
+                        (Bounds::Bounded(a), Bounds::Bounded(b)) => {
+                            Bounds::Bounded(BigRect::span_rects(a, b))
+                        }
+                    };
+                }
+                ret
+            }
+            Region::Intersection(regions) => {
+                let mut ret = Bounds::Unbounded;
+                for r in regions {
+                    ret = match (ret, r.bounds()) {
+                        (Bounds::Empty, _) | (_, Bounds::Empty) => return Bounds::Empty,
+                        (Bounds::Unbounded, b) | (b, Bounds::Unbounded) => b,
+                        (Bounds::Bounded(a), Bounds::Bounded(b)) => match a.intersection(&b) {
+                            Some(r) => Bounds::Bounded(r),
+                            None => return Bounds::Empty,
+                        },
+                    };
+                }
+                ret
+            }
+            Region::Difference(a, _) => a.bounds(),
+        }
+    }
 
-                // It should be possible to do this in O(d log n) time, but this
-                // O(d n^(d-1)) algorithm is simpler. If converting a rectangle
-                // to an ND-tree is a performance bottleneck, this can be
-                // rewritten.
-                let root = ret.root_ref();
-                let new_root = root.recursive_modify_with_offset(
-                    ret.base_pos(),
-                    &mut |node_pos, node| {
-                        let node_rect = node.big_rect() + node_pos;
-                        if rect.contains(&node_rect) {
-                            Some(*full_nodes.get(node.layer().to_usize()))
-                        } else if rect.intersects(&node_rect) {
-                            None
+    /// Returns whether a rectangle is inside, outside, or partially inside the
+    /// region. This check is conservative; it may return
+    /// `RectRelation::Partial` even if the rectangle is actually completely
+    /// inside or outside the region.
+    pub(crate) fn classify_rect(&self, rect: &BigRect<D>) -> RectRelation {
+        match self {
+            Region::Empty => RectRelation::Outside,
+            Region::Rect(r) => {
+                if r.contains(rect) {
+                    RectRelation::Inside
+                } else if r.intersects(rect) {
+                    RectRelation::Partial
+                } else {
+                    RectRelation::Outside
+                }
+            }
+            Region::NdTree(ndtree) => {
+                if ndtree.rect_is_empty(rect.clone()) {
+                    RectRelation::Outside
+                } else {
+                    RectRelation::Partial
+                }
+            }
+            Region::Ball { center, .. } | Region::Ellipsoid { center, .. } => {
+                // These shapes are convex, so the rectangle is inside if all of
+                // its corners are inside. They are also symmetric along each
+                // axis, so the closest point in the rectangle to the center is
+                // found by clamping the center to the rectangle.
+                let closest = BigVec::from_fn(|ax| {
+                    center[ax]
+                        .clone()
+                        .max(rect.min()[ax].clone())
+                        .min(rect.max()[ax].clone())
+                });
+                if !self.contains(&closest) {
+                    RectRelation::Outside
+                } else if rect_corners(rect).all(|corner| self.contains(&corner)) {
+                    RectRelation::Inside
+                } else {
+                    RectRelation::Partial
+                }
+            }
+            Region::HalfSpace { normal, offset } => {
+                let min_dot = dot(
+                    normal,
+                    &BigVec::from_fn(|ax| {
+                        if normal[ax].is_negative() {
+                            rect.max()[ax].clone()
                         } else {
-                            Some(node) // already empty
+                            rect.min()[ax].clone()
                         }
-                    },
-                    &mut |pos, _| rect.contains(pos) as u8,
+                    }),
                 );
-
-                let new_root = ArcNode::from(new_root);
-                drop((node_pool, root));
-                ret.set_root_centered(new_root);
+                let max_dot = dot(
+                    normal,
+                    &BigVec::from_fn(|ax| {
+                        if normal[ax].is_negative() {
+                            rect.min()[ax].clone()
+                        } else {
+                            rect.max()[ax].clone()
+                        }
+                    }),
+                );
+                if max_dot <= *offset {
+                    RectRelation::Inside
+                } else if min_dot > *offset {
+                    RectRelation::Outside
+                } else {
+                    RectRelation::Partial
+                }
+            }
+            Region::Union(regions) => {
+                let mut ret = RectRelation::Outside;
+                for r in regions {
+                    match r.classify_rect(rect) {
+                        RectRelation::Inside => return RectRelation::Inside,
+                        RectRelation::Outside => (),
+                        RectRelation::Partial => ret = RectRelation::Partial,
+                    }
+                }
+                ret
+            }
+            Region::Intersection(regions) => {
+                let mut ret = RectRelation::Inside;
+                for r in regions {
+                    match r.classify_rect(rect) {
+                        RectRelation::Inside => (),
+                        RectRelation::Outside => return RectRelation::Outside,
+                        RectRelation::Partial => ret = RectRelation::Partial,
+                    }
+                }
                 ret
             }
+            Region::Difference(a, b) => match (a.classify_rect(rect), b.classify_rect(rect)) {
+                (RectRelation::Outside, _) | (_, RectRelation::Inside) => RectRelation::Outside,
+                (RectRelation::Inside, RectRelation::Outside) => RectRelation::Inside,
+                _ => RectRelation::Partial,
+            },
+        }
+    }
+
+    /// Converts the region into an ND-tree using state #1 for cells that are
+    /// inside the region and state #0 for cells that are not.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the region is unbounded.
+    pub fn into_ndtree(self, center: BigVec<D>) -> NdTree<D> {
+        match self {
             Region::NdTree(mut ndtree) => {
                 ndtree.recenter(&center);
                 ndtree
             }
+            _ => match self.bounds() {
+                Bounds::Empty => NdTree::with_center(center),
+                Bounds::Bounded(rect) => {
+                    self.to_ndtree_within(center, &rect, &SharedNodePool::new())
+                }
+                Bounds::Unbounded => panic!("Cannot convert unbounded region to ND-tree"),
+            },
+        }
+    }
+    /// Converts the part of the region within a rectangle into an ND-tree
+    /// using state #1 for cells that are inside the region and state #0 for
+    /// cells that are not.
+    pub fn into_ndtree_within(self, center: BigVec<D>, rect: &BigRect<D>) -> NdTree<D> {
+        self.to_ndtree_within(center, rect, &SharedNodePool::new())
+    }
+    fn to_ndtree_within(
+        &self,
+        center: BigVec<D>,
+        within: &BigRect<D>,
+        node_pool: &SharedNodePool<D>,
+    ) -> NdTree<D> {
+        let mut ret = NdTree::with_node_pool(node_pool.new_ref());
+        ret.set_center_pos(center.clone());
+
+        let within = match self.bounds().intersect_rect(within) {
+            Some(r) => r,
+            None => return ret,
+        };
+
+        match self {
+            Region::Empty => ret,
+            Region::NdTree(ndtree) => {
+                let mut ret = ndtree.get_region(Region::Rect(within));
+                ret.recenter(&center);
+                ret
+            }
+            Region::Union(regions) => {
+                for r in regions {
+                    let other = r.to_ndtree_within(center.clone(), &within, node_pool);
+                    combine_masks(
+                        &mut ret,
+                        other,
+                        |a, b| match () {
+                            _ if a == b || b.is_empty() => Some(a),
+                            _ if a.is_empty() => Some(b),
+                            _ => None,
+                        },
+                        |a, b| a | b,
+                    );
+                }
+                ret
+            }
+            Region::Intersection(regions) => {
+                let mut regions = regions.iter();
+                ret = match regions.next() {
+                    Some(r) => r.to_ndtree_within(center.clone(), &within, node_pool),
+                    None => self.shape_to_ndtree(ret, &within),
+                };
+                for r in regions {
+                    let other = r.to_ndtree_within(center.clone(), &within, node_pool);
+                    combine_masks(
+                        &mut ret,
+                        other,
+                        |a, b| match () {
+                            _ if a == b || a.is_empty() => Some(a),
+                            _ if b.is_empty() => Some(b),
+                            _ => None,
+                        },
+                        |a, b| a & b,
+                    );
+                }
+                ret
+            }
+            Region::Difference(a, b) => {
+                let mut ret = a.to_ndtree_within(center.clone(), &within, node_pool);
+                let other = b.to_ndtree_within(center, &within, node_pool);
+                combine_masks(
+                    &mut ret,
+                    other,
+                    |a, b| match () {
+                        _ if a.is_empty() || b.is_empty() => Some(a),
+                        _ if a == b => Some(a.pool().get_empty(a.layer())),
+                        _ => None,
+                    },
+                    |a, b| a & !b & 1,
+                );
+                ret
+            }
+            Region::Rect(_)
+            | Region::Ball { .. }
+            | Region::Ellipsoid { .. }
+            | Region::HalfSpace { .. } => self.shape_to_ndtree(ret, &within),
         }
     }
+    /// Sets every cell of `ndtree` within `within` that is in the region to
+    /// state #1, using `classify_rect()` to avoid checking cells individually
+    /// where possible.
+    fn shape_to_ndtree(&self, mut ndtree: NdTree<D>, within: &BigRect<D>) -> NdTree<D> {
+        ndtree.expand_to(within);
+
+        let node_pool = ndtree.pool().access();
+
+        let mut full_nodes: LazyVec<NodeRef<'_, D>, _> =
+            LazyVec::new(|previous| match previous.last() {
+                Some(smaller_full_node) => {
+                    node_pool.join_nodes(std::iter::repeat(*smaller_full_node))
+                }
+                None => node_pool.get_from_cells(vec![1_u8]),
+            });
+
+        // It should be possible to do this in O(d log n) time for rectangles,
+        // but this O(d n^(d-1)) algorithm is simpler. If converting a shape to
+        // an ND-tree is a performance bottleneck, this can be rewritten.
+        let root = ndtree.root_ref();
+        let new_root = root.recursive_modify_with_offset(
+            ndtree.base_pos(),
+            &mut |node_pos, node| {
+                let node_rect = node.big_rect() + node_pos;
+                if !within.intersects(&node_rect) {
+                    return Some(node); // already empty
+                }
+                match self.classify_rect(&node_rect) {
+                    RectRelation::Inside if within.contains(&node_rect) => {
+                        Some(*full_nodes.get(node.layer().to_usize()))
+                    }
+                    RectRelation::Outside => Some(node), // already empty
+                    _ => None,
+                }
+            },
+            &mut |pos, _| (within.contains(pos) && self.contains(pos)) as u8,
+        );
+
+        let new_root = ArcNode::from(new_root);
+        drop((node_pool, root));
+        ndtree.set_root_centered(new_root);
+        ndtree
+    }
 }
 impl<D: Dim> CanContain<BigVec<D>> for Region<D> {
     fn contains(&self, pos: &BigVec<D>) -> bool {
@@ -94,17 +519,104 @@ impl<D: Dim> CanContain<BigVec<D>> for Region<D> {
             Region::Empty => false,
             Region::Rect(rect) => rect.contains(pos),
             Region::NdTree(ndtree) => ndtree.get_cell(pos) != 0_u8,
+            Region::Ball {
+                center,
+                radius,
+                norm,
+            } => {
+                if radius.is_negative() {
+                    return false;
+                }
+                let delta = (pos - center).abs();
+                match norm {
+                    Norm::L1 => delta.sum() <= *radius,
+                    Norm::L2 => dot(&delta, &delta) <= radius * radius,
+                    Norm::LInfinity => delta.max_component() <= radius,
+                }
+            }
+            Region::Ellipsoid { center, radii } => {
+                if D::axes().iter().any(|&ax| radii[ax].is_negative()) {
+                    return false;
+                }
+                let delta = pos - center;
+                // Along axes with a radius of zero, the position must match
+                // the center exactly.
+                let axes = D::axes()
+                    .iter()
+                    .copied()
+                    .filter(|&ax| !radii[ax].is_zero())
+                    .collect_vec();
+                if D::axes()
+                    .iter()
+                    .any(|&ax| radii[ax].is_zero() && !delta[ax].is_zero())
+                {
+                    return false;
+                }
+                // Multiply both sides of `sum((delta / radii)^2) <= 1` by the
+                // product of all squared radii to avoid division.
+                let squared_radii = BigVec::<D>::from_fn(|ax| &radii[ax] * &radii[ax]);
+                let product: BigInt = axes.iter().map(|&ax| squared_radii[ax].clone()).product();
+                let lhs: BigInt = axes
+                    .iter()
+                    .map(|&ax| {
+                        let others: BigInt = axes
+                            .iter()
+                            .filter(|&&other_ax| other_ax != ax)
+                            .map(|&other_ax| squared_radii[other_ax].clone())
+                            .product();
+                        &delta[ax] * &delta[ax] * others
+                    })
+                    .sum();
+                lhs <= product
+            }
+            Region::HalfSpace { normal, offset } => dot(normal, pos) <= *offset,
+            Region::Union(regions) => regions.iter().any(|r| r.contains(pos)),
+            Region::Intersection(regions) => regions.iter().all(|r| r.contains(pos)),
+            Region::Difference(a, b) => a.contains(pos) && !b.contains(pos),
         }
     }
 }
 
+/// Returns the dot product of two vectors.
+fn dot<D: Dim>(a: &BigVec<D>, b: &BigVec<D>) -> BigInt {
+    D::axes().iter().map(|&ax| &a[ax] * &b[ax]).sum()
+}
+
+/// Returns an iterator over the corners of a rectangle.
+fn rect_corners<D: Dim>(rect: &BigRect<D>) -> impl '_ + Iterator<Item = BigVec<D>> {
+    let min = rect.min();
+    let max = rect.max();
+    (0..D::BRANCHING_FACTOR).map(move |i| {
+        BigVec::from_fn(|ax| {
+            if i & (1 << ax as usize) == 0 {
+                min[ax].clone()
+            } else {
+                max[ax].clone()
+            }
+        })
+    })
+}
+
+/// Combines two ND-trees that use states #0 and #1, storing the result in
+/// `mask`.
+fn combine_masks<D: Dim>(
+    mask: &mut NdTree<D>,
+    other: NdTree<D>,
+    combine_nodes: impl for<'node> Fn(NodeRef<'node, D>, NodeRef<'node, D>) -> Option<NodeRef<'node, D>>,
+    combine_cells: impl Fn(u8, u8) -> u8,
+) {
+    let rect = BigRect::span_rects(mask.rect(), other.rect());
+    mask.paste_custom(other, Region::Rect(rect), combine_nodes, combine_cells);
+}
+
 #[cfg(test)]
 mod tests {
     use proptest::prelude::*;
 
     use super::*;
-    use crate::ndrect::proptest_irect2d;
-    use crate::ndvec::proptest_ivec2d;
+    use crate::dim::Dim2D;
+    use crate::ndrect::{proptest_irect2d, BigRect2D, IRect2D};
+    use crate::ndvec::{proptest_ivec2d, NdVec};
 
     proptest! {
         #[test]
@@ -126,5 +638,100 @@ mod tests {
                 assert_eq!(expected, ndtree.get_cell(&big_pos) == 1_u8);
             }
         }
+
+        #[test]
+        fn test_shape_region_into_ndtree(
+            region in proptest_region2d(),
+            center in proptest_ivec2d(-30..=30),
+        ) {
+            let center = center.to_bigvec();
+            let ndtree = region.clone().into_ndtree(center.clone());
+            assert_eq!(center, ndtree.center_pos());
+
+            let mut expected_bounding_rect: Option<BigRect2D> = None;
+            for pos in IRect2D::centered(NdVec::origin(), 24).iter() {
+                let big_pos = pos.to_bigvec();
+                let expected = region.contains(&big_pos);
+                assert_eq!(expected, ndtree.get_cell(&big_pos) == 1_u8);
+                if expected {
+                    let r = BigRect::single_cell(big_pos);
+                    expected_bounding_rect = Some(match expected_bounding_rect {
+                        Some(old) => BigRect::span_rects(old, r),
+                        None => r,
+                    });
+                }
+            }
+            assert_eq!(expected_bounding_rect, region.bounding_rect());
+            assert_eq!(expected_bounding_rect, ndtree.bounding_rect());
+
+            // Test `classify_rect()` on a few rectangles.
+            for rect in IRect2D::centered(NdVec::origin(), 3).iter() {
+                let rect = IRect2D::centered(rect * 8, 3).to_bigrect();
+                let cells = rect.iter().map(|pos| region.contains(&pos)).collect_vec();
+                match region.classify_rect(&rect) {
+                    RectRelation::Inside => assert!(cells.iter().all(|&x| x)),
+                    RectRelation::Outside => assert!(cells.iter().all(|&x| !x)),
+                    RectRelation::Partial => (),
+                }
+            }
+        }
+
+        #[test]
+        fn test_half_space_clear_region(
+            normal in proptest_ivec2d(-3..=3),
+            offset in -20..=20_isize,
+            rect in proptest_irect2d(-16..=16),
+        ) {
+            let region = Region::HalfSpace {
+                normal: normal.to_bigvec(),
+                offset: offset.into(),
+            };
+            assert_eq!(normal.is_zero() && offset < 0, region.is_bounded());
+
+            let mut ndtree = Region::Rect(rect.to_bigrect()).into_ndtree(BigVec::origin());
+            ndtree.clear_region(region.clone());
+            for pos in IRect2D::centered(NdVec::origin(), 18).iter() {
+                let big_pos = pos.to_bigvec();
+                let expected = rect.contains(&pos) && !region.contains(&big_pos);
+                assert_eq!(expected, ndtree.get_cell(&big_pos) == 1_u8);
+            }
+        }
+    }
+
+    fn proptest_shape2d() -> impl Strategy<Value = Region<Dim2D>> {
+        prop_oneof![
+            proptest_irect2d(-12..=12).prop_map(|r| Region::Rect(r.to_bigrect())),
+            (
+                proptest_ivec2d(-12..=12),
+                -1..=10_isize,
+                prop_oneof![Just(Norm::L1), Just(Norm::L2), Just(Norm::LInfinity)],
+            )
+                .prop_map(|(center, radius, norm)| Region::Ball {
+                    center: center.to_bigvec(),
+                    radius: radius.into(),
+                    norm,
+                }),
+            (proptest_ivec2d(-12..=12), proptest_ivec2d(-1..=10)).prop_map(|(center, radii)| {
+                Region::Ellipsoid {
+                    center: center.to_bigvec(),
+                    radii: radii.to_bigvec(),
+                }
+            }),
+        ]
+    }
+
+    fn proptest_region2d() -> impl Strategy<Value = Region<Dim2D>> {
+        prop_oneof![
+            proptest_shape2d(),
+            (proptest_shape2d(), proptest_shape2d()).prop_map(|(a, b)| a.union(b)),
+            (proptest_shape2d(), proptest_shape2d()).prop_map(|(a, b)| a.intersection(b)),
+            (proptest_shape2d(), proptest_shape2d()).prop_map(|(a, b)| a.difference(b)),
+            (proptest_shape2d(), proptest_ivec2d(-3..=3), -12..=12_isize).prop_map(
+                |(a, normal, offset)| a.intersection(Region::HalfSpace {
+                    normal: normal.to_bigvec(),
+                    offset: offset.into(),
+                })
+            ),
+        ]
     }
 }
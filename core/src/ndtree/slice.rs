@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use std::fmt;
 
-use super::{Layer, NodeRefTrait, NodeRefWithGuard};
+use super::{Layer, NodeRefTrait, NodeRefWithGuard, NonzeroCells, Region};
 use crate::axis::{X, Y};
 use crate::dim::*;
 use crate::ndrect::{BigRect, CanContain};
@@ -89,4 +89,20 @@ impl<'pool, D: Dim> NdTreeSlice<'pool, D> {
             Err(cell_state) => Err((cell_state, &self.base_pos)),
         }
     }
+
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the slice. See `NonzeroCells` for details about iteration order.
+    pub fn nonzero_cells(&'pool self) -> NonzeroCells<'pool, D> {
+        NonzeroCells::new(&self.root, self.base_pos.clone(), None)
+    }
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the slice that are within `rect`.
+    pub fn nonzero_cells_in_rect(&'pool self, rect: BigRect<D>) -> NonzeroCells<'pool, D> {
+        self.nonzero_cells_in_region(Region::Rect(rect))
+    }
+    /// Returns an iterator over the positions and states of all nonzero cells
+    /// in the slice that are within `region`.
+    pub fn nonzero_cells_in_region(&'pool self, region: Region<D>) -> NonzeroCells<'pool, D> {
+        NonzeroCells::new(&self.root, self.base_pos.clone(), Some(region))
+    }
 }
@@ -0,0 +1,226 @@
+//! Reproducible random fill ("soup") for regions of an ND-tree.
+//!
+//! Filling a region requires the `random` feature (enabled by default), but
+//! `SoupParams` and `SoupSymmetry` are always available.
+
+#[cfg(feature = "random")]
+use rand::{Rng, SeedableRng};
+#[cfg(feature = "random")]
+use rand_chacha::ChaCha8Rng;
+
+#[cfg(feature = "random")]
+use super::{NdTree, Region};
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+#[cfg(feature = "random")]
+use crate::ndrect::CanContain;
+use crate::ndvec::BigVec;
+#[cfg(feature = "random")]
+use crate::HashMap;
+
+/// Symmetry imposed on a random soup.
+///
+/// Each symmetry is defined relative to the bounding rectangle of the region
+/// being filled. The names come from the 2D symmetry groups, but each has a
+/// natural analogue in any number of dimensions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum SoupSymmetry {
+    /// No symmetry.
+    Asymmetric,
+    /// Symmetric under 180-degree rotation about the center of the rectangle.
+    ///
+    /// In N dimensions, this is point reflection through the center (negating
+    /// every axis at once).
+    C2,
+    /// Symmetric under reflection across the horizontal and vertical center
+    /// lines of the rectangle.
+    ///
+    /// In N dimensions, this is reflection along each axis independently.
+    D4,
+    /// Symmetric under all the rotations and reflections of a square.
+    ///
+    /// In N dimensions, this is reflection along each axis independently plus
+    /// any permutation of the axes. The bounding rectangle of the region must
+    /// be a hypercube.
+    D8,
+}
+impl Default for SoupSymmetry {
+    fn default() -> Self {
+        Self::Asymmetric
+    }
+}
+impl SoupSymmetry {
+    /// Returns the canonical representative of the set of positions that must
+    /// have the same state as `pos`, relative to the minimum corner of `rect`.
+    pub(crate) fn canonicalize<D: Dim>(self, rect: &BigRect<D>, pos: &BigVec<D>) -> BigVec<D> {
+        let rel = pos - rect.min();
+        let reflected = rect.max() - pos;
+        match self {
+            Self::Asymmetric => rel,
+            Self::C2 => {
+                let reflected_components = D::axes().iter().map(|&ax| &reflected[ax]);
+                let rel_components = D::axes().iter().map(|&ax| &rel[ax]);
+                if reflected_components.lt(rel_components) {
+                    reflected
+                } else {
+                    rel
+                }
+            }
+            Self::D4 => BigVec::from_fn(|ax| std::cmp::min(&rel[ax], &reflected[ax]).clone()),
+            Self::D8 => {
+                let mut folded = D::axes()
+                    .iter()
+                    .map(|&ax| std::cmp::min(&rel[ax], &reflected[ax]).clone())
+                    .collect::<Vec<_>>();
+                folded.sort();
+                BigVec::from_fn(|ax| folded[ax as usize].clone())
+            }
+        }
+    }
+}
+
+/// Parameters for filling a region with a random soup.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoupParams {
+    /// Seed for the random number generator.
+    seed: u64,
+    /// Probability of each nonzero state, indexed by state minus one.
+    probabilities: Vec<f64>,
+    /// Symmetry of the soup.
+    symmetry: SoupSymmetry,
+}
+impl SoupParams {
+    /// Returns parameters for an asymmetric soup with 50% density of state #1,
+    /// using the given random seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            probabilities: vec![0.5],
+            symmetry: SoupSymmetry::Asymmetric,
+        }
+    }
+
+    /// Sets the probability that each cell will be state #1. All other cells
+    /// will be state #0.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `density` is not between 0 and 1 (inclusive).
+    pub fn with_density(self, density: f64) -> Self {
+        self.with_state_probabilities(&[density])
+    }
+    /// Sets the probability of each nonzero state; `probabilities[0]` is the
+    /// probability of state #1, `probabilities[1]` is the probability of state
+    /// #2, etc. The remaining probability is assigned to state #0.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if any probability is negative, if the probabilities
+    /// sum to more than 1 (allowing for rounding error), or if there are more
+    /// than 255 of them.
+    pub fn with_state_probabilities(mut self, probabilities: &[f64]) -> Self {
+        assert!(
+            probabilities.len() <= u8::MAX as usize,
+            "Too many states for soup",
+        );
+        assert!(
+            probabilities.iter().all(|&p| p >= 0.0),
+            "Soup state probabilities must be non-negative",
+        );
+        // Allow for rounding error, so that probabilities such as `[0.2, 0.4,
+        // 0.3, 0.1]` are accepted.
+        let tolerance = f64::EPSILON * probabilities.len() as f64;
+        assert!(
+            probabilities.iter().sum::<f64>() <= 1.0 + tolerance,
+            "Soup state probabilities must sum to at most 1",
+        );
+        self.probabilities = probabilities.to_vec();
+        self
+    }
+    /// Sets the symmetry of the soup.
+    pub fn with_symmetry(mut self, symmetry: SoupSymmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    /// Returns the random seed.
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// Returns the probability of each nonzero state, starting with state #1.
+    #[inline]
+    pub fn state_probabilities(&self) -> &[f64] {
+        &self.probabilities
+    }
+    /// Returns the symmetry of the soup.
+    #[inline]
+    pub fn symmetry(&self) -> SoupSymmetry {
+        self.symmetry
+    }
+
+    /// Picks a random cell state.
+    #[cfg(feature = "random")]
+    fn random_state(&self, rng: &mut impl Rng) -> u8 {
+        let mut x: f64 = rng.gen();
+        for (i, &p) in self.probabilities.iter().enumerate() {
+            if x < p {
+                return i as u8 + 1;
+            }
+            x -= p;
+        }
+        0_u8
+    }
+}
+
+#[cfg(feature = "random")]
+impl<D: Dim> NdTree<D> {
+    /// Fills a region with random cells.
+    ///
+    /// The result depends only on `region` and `params`, so the same seed
+    /// always produces the same soup. Cells outside the region are not
+    /// modified.
+    ///
+    /// This takes time proportional to the volume of the region's bounding
+    /// rectangle, so it should only be used for reasonably small regions.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the region is unbounded (see
+    /// `Region::is_bounded()`), or if `params` uses `SoupSymmetry::D8` and the
+    /// bounding rectangle of the region is not a hypercube.
+    pub fn fill_random(&mut self, region: Region<D>, params: &SoupParams) {
+        let rect = match region.bounding_rect() {
+            Some(r) => r,
+            None => return,
+        };
+        if params.symmetry == SoupSymmetry::D8 {
+            let size = rect.size();
+            assert!(
+                D::axes().iter().all(|&ax| size[ax] == size[D::axes()[0]]),
+                "D8 soup symmetry requires a hypercubic region",
+            );
+        }
+
+        let mut rng = ChaCha8Rng::seed_from_u64(params.seed);
+        let mut soup = NdTree::with_node_pool(self.pool().new_ref());
+        // Map from canonical position to the state of every cell that shares
+        // that canonical position. Positions are visited in a fixed order, so
+        // random states are always generated in the same order.
+        let mut states: HashMap<BigVec<D>, u8> = HashMap::default();
+        for pos in rect.iter() {
+            if !region.contains(&pos) {
+                continue;
+            }
+            let canonical_pos = params.symmetry.canonicalize(&rect, &pos);
+            let state = *states
+                .entry(canonical_pos)
+                .or_insert_with(|| params.random_state(&mut rng));
+            if state != 0_u8 {
+                soup.set_cell(&pos, state);
+            }
+        }
+
+        self.paste_custom(soup, region, |_, soup| Some(soup), |_, soup| soup);
+    }
+}
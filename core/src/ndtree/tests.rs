@@ -5,7 +5,7 @@ use super::*;
 use crate::axis::{X, Y};
 use crate::ndrect::{proptest_irect2d, IRect2D};
 use crate::ndvec::{proptest_ivec2d, IVec2D, NdVec};
-use crate::num::BigUint;
+use crate::num::{BigUint, ToPrimitive};
 use crate::HashMap;
 
 fn assert_ndtree_valid(
@@ -258,6 +258,274 @@ proptest! {
             assert_eq!(expected, ndtree.get_cell(&pos.to_bigvec()));
         }
     }
+
+    /// Tests `NdTree::nonzero_cells()`, `NdTreeSlice::nonzero_cells()`, and
+    /// related methods.
+    #[test]
+    fn test_ndtree_nonzero_cells(
+        cells_to_set in proptest_cells_to_set(),
+        ndtree_center in proptest_ivec2d(-100..=100),
+        filter_rect in proptest_irect2d(-100..=100),
+    ) {
+        let mut ndtree = NdTree::with_center(ndtree_center.to_bigvec());
+        let mut hashmap = HashMap::default();
+        for (pos, state) in &cells_to_set {
+            ndtree.set_cell(&pos.to_bigvec(), *state);
+            hashmap.insert(*pos, *state);
+        }
+        hashmap.retain(|_, state| *state != 0);
+        let expected_in_rect: HashMap<IVec2D, u8> = hashmap
+            .iter()
+            .filter(|(pos, _)| filter_rect.contains(*pos))
+            .map(|(&pos, &state)| (pos, state))
+            .collect();
+
+        let to_hashmap = |cells: NonzeroCells<'_, Dim2D>| {
+            let mut ret = HashMap::default();
+            for (pos, state) in cells {
+                let pos = IVec2D::try_from_fn(|ax| pos[ax].to_isize()).unwrap();
+                assert!(ret.insert(pos, state).is_none(), "cell yielded twice");
+            }
+            ret
+        };
+
+        let slice = ndtree.as_slice();
+        assert_eq!(hashmap, to_hashmap(slice.nonzero_cells()));
+        assert_eq!(
+            expected_in_rect,
+            to_hashmap(slice.nonzero_cells_in_rect(filter_rect.to_bigrect())),
+        );
+        let mask = Region::Rect(filter_rect.to_bigrect()).into_ndtree(BigVec::origin());
+        assert_eq!(
+            expected_in_rect,
+            to_hashmap(slice.nonzero_cells_in_region(Region::NdTree(mask))),
+        );
+        assert!(slice.nonzero_cells_in_region(Region::Empty).next().is_none());
+
+        assert_eq!(
+            slice.nonzero_cells().collect_vec(),
+            ndtree.nonzero_cells().collect_vec(),
+        );
+        assert_eq!(
+            slice.nonzero_cells_in_rect(filter_rect.to_bigrect()).collect_vec(),
+            ndtree.nonzero_cells_in_rect(filter_rect.to_bigrect()).collect_vec(),
+        );
+        assert!(ndtree.nonzero_cells_in_region(Region::Empty).next().is_none());
+
+        // Test that iteration order does not depend on the order in which
+        // cells were set.
+        let mut ndtree2 = NdTree::with_center(ndtree_center.to_bigvec());
+        ndtree2.expand_to(&ndtree.rect());
+        for (pos, state) in cells_to_set.iter().rev() {
+            if hashmap.get(pos) == Some(state) {
+                ndtree2.set_cell(&pos.to_bigvec(), *state);
+            }
+        }
+        assert_eq!(
+            slice.nonzero_cells().collect_vec(),
+            ndtree2.nonzero_cells().collect_vec(),
+        );
+    }
+
+    /// Tests `NdTree::diff()` by comparing against a HashMap.
+    #[test]
+    fn test_ndtree_diff(
+        cells_to_set in proptest_cells_to_set(),
+        cells_to_change in proptest_cells_to_set(),
+        new_center in proptest_ivec2d(-100..=100),
+        same_pool: bool,
+    ) {
+        let mut old = NdTree::default();
+        let mut hashmap = HashMap::default();
+        for (pos, state) in cells_to_set {
+            old.set_cell(&pos.to_bigvec(), state);
+            hashmap.insert(pos, state);
+        }
+
+        let mut new = if same_pool {
+            old.clone()
+        } else {
+            let mut ret = NdTree::with_center(new_center.to_bigvec());
+            for (&pos, &state) in &hashmap {
+                ret.set_cell(&pos.to_bigvec(), state);
+            }
+            ret
+        };
+        let mut expected = HashMap::default();
+        for (pos, state) in cells_to_change {
+            new.set_cell(&pos.to_bigvec(), state);
+            let old_state = *hashmap.get(&pos).unwrap_or(&0);
+            if old_state == state {
+                expected.remove(&pos);
+            } else {
+                expected.insert(pos, (old_state, state));
+            }
+        }
+
+        let diff = old.diff(&new);
+        let actual: HashMap<IVec2D, (u8, u8)> = diff
+            .cells()
+            .into_iter()
+            .map(|(pos, old_state, new_state)| {
+                let pos = IVec2D::try_from_fn(|ax| pos[ax].to_isize()).unwrap();
+                (pos, (old_state, new_state))
+            })
+            .collect();
+        assert_eq!(expected, actual);
+
+        let summary = diff.summary();
+        let count = |f: fn(u8, u8) -> bool| {
+            BigUint::from(expected.values().filter(|&&(a, b)| f(a, b)).count())
+        };
+        assert_eq!(count(|a, _| a == 0), summary.added);
+        assert_eq!(count(|_, b| b == 0), summary.removed);
+        assert_eq!(count(|a, b| a != 0 && b != 0), summary.changed);
+        assert_eq!(BigUint::from(expected.len()), summary.total());
+        assert_eq!(expected.is_empty(), diff.is_empty());
+        for pos in expected.keys() {
+            assert!(diff.region().contains(&pos.to_bigvec()));
+        }
+    }
+
+    /// Tests `NdTree::fill_random()`.
+    #[cfg(feature = "random")]
+    #[test]
+    fn test_ndtree_fill_random(
+        cells_to_set in proptest_cells_to_set(),
+        seed: u64,
+        rect in proptest_irect2d(-20..=20),
+        symmetry in prop_oneof![
+            Just(SoupSymmetry::Asymmetric),
+            Just(SoupSymmetry::C2),
+            Just(SoupSymmetry::D4),
+            Just(SoupSymmetry::D8),
+        ],
+    ) {
+        let rect = if symmetry == SoupSymmetry::D8 {
+            IRect2D::with_size(rect.min(), NdVec::repeat(rect.size().max_component().clone()))
+        } else {
+            rect
+        };
+        let params = SoupParams::new(seed)
+            .with_state_probabilities(&[0.3, 0.2, 0.1])
+            .with_symmetry(symmetry);
+
+        let mut ndtree = NdTree::default();
+        let mut hashmap = HashMap::default();
+        for (pos, state) in cells_to_set {
+            ndtree.set_cell(&pos.to_bigvec(), state);
+            hashmap.insert(pos, state);
+        }
+        let original = ndtree.clone();
+        ndtree.fill_random(Region::Rect(rect.to_bigrect()), &params);
+
+        // Cells outside the region are unchanged.
+        for (pos, state) in hashmap {
+            if !rect.contains(&pos) {
+                assert_eq!(state, ndtree.get_cell(&pos.to_bigvec()));
+            }
+        }
+        // Cells inside the region are random, but deterministic.
+        let mut other = NdTree::with_center(NdVec::big([7, -3]));
+        other.fill_random(Region::Rect(rect.to_bigrect()), &params);
+        for pos in rect.iter() {
+            let state = ndtree.get_cell(&pos.to_bigvec());
+            assert!(state <= 3);
+            assert_eq!(state, other.get_cell(&pos.to_bigvec()));
+
+            // Check symmetry.
+            let reflect_x = NdVec([rect.max()[X] + rect.min()[X] - pos[X], pos[Y]]);
+            let reflect_y = NdVec([pos[X], rect.max()[Y] + rect.min()[Y] - pos[Y]]);
+            let rel = pos - rect.min();
+            let transpose = NdVec([rel[Y], rel[X]]) + rect.min();
+            let images = match symmetry {
+                SoupSymmetry::Asymmetric => vec![],
+                SoupSymmetry::C2 => vec![reflect_x + reflect_y - pos],
+                SoupSymmetry::D4 => vec![reflect_x, reflect_y],
+                SoupSymmetry::D8 => vec![reflect_x, reflect_y, transpose],
+            };
+            for image in images {
+                assert_eq!(state, ndtree.get_cell(&image.to_bigvec()));
+            }
+        }
+        // Filling an empty region does nothing.
+        let mut unchanged = original.clone();
+        unchanged.fill_random(Region::Empty, &params);
+        assert_eq!(original, unchanged);
+    }
+
+    /// Tests `NdTree::flood_region()` and `NdTree::flood_fill()` by comparing
+    /// against a naive flood fill on a HashMap.
+    #[test]
+    fn test_ndtree_flood_fill(
+        cells_to_set in prop::collection::vec((proptest_ivec2d(-10..10), 0..=2_u8), 0..200),
+        seed in proptest_ivec2d(-12..12),
+        limit in proptest_irect2d(-12..=12),
+        full_connectivity: bool,
+        new_state in 0..=3_u8,
+    ) {
+        let connectivity = if full_connectivity {
+            Connectivity::Full
+        } else {
+            Connectivity::Orthogonal
+        };
+
+        let mut ndtree = NdTree::default();
+        let mut hashmap = HashMap::default();
+        for (pos, state) in cells_to_set {
+            ndtree.set_cell(&pos.to_bigvec(), state);
+            hashmap.insert(pos, state);
+        }
+        let get = |pos: &IVec2D| *hashmap.get(pos).unwrap_or(&0);
+
+        let mut expected = HashSet::new();
+        if limit.contains(&seed) {
+            let mut stack = vec![seed];
+            expected.insert(seed);
+            while let Some(pos) = stack.pop() {
+                for neighbor in IRect2D::centered(pos, 1).iter() {
+                    let offset = neighbor - pos;
+                    if !full_connectivity && offset.abs().sum() != 1 {
+                        continue;
+                    }
+                    if limit.contains(&neighbor)
+                        && get(&neighbor) == get(&seed)
+                        && expected.insert(neighbor)
+                    {
+                        stack.push(neighbor);
+                    }
+                }
+            }
+        }
+
+        let big_limit = limit.to_bigrect();
+        let region = ndtree.flood_region(&seed.to_bigvec(), connectivity, &big_limit);
+        let filled = ndtree.flood_fill(&seed.to_bigvec(), connectivity, &big_limit, new_state);
+        for pos in IRect2D::centered(NdVec::origin(), 13).iter() {
+            let big_pos = pos.to_bigvec();
+            let is_expected = expected.contains(&pos);
+            assert_eq!(is_expected, region.contains(&big_pos));
+            assert_eq!(is_expected, filled.contains(&big_pos));
+            let expected_state = if is_expected { new_state } else { get(&pos) };
+            assert_eq!(expected_state, ndtree.get_cell(&big_pos));
+        }
+    }
+}
+
+/// Tests that `SoupParams` accepts probabilities whose sum rounds above 1.
+#[test]
+fn test_soup_params_rounding() {
+    let probabilities = [0.2, 0.4, 0.3, 0.1];
+    assert!(probabilities.iter().sum::<f64>() > 1.0);
+    let params = SoupParams::new(0).with_state_probabilities(&probabilities);
+    assert_eq!(&probabilities, params.state_probabilities());
+}
+
+/// Tests that `SoupParams` rejects probabilities that sum to more than 1.
+#[test]
+#[should_panic(expected = "Soup state probabilities must sum to at most 1")]
+fn test_soup_params_too_likely() {
+    SoupParams::new(0).with_state_probabilities(&[0.5, 0.50001]);
 }
 
 fn proptest_cells_to_set() -> impl Strategy<Value = Vec<(IVec2D, u8)>> {
@@ -25,7 +25,11 @@ const FRACTIONAL_MASK: u64 = INTEGRAL_ONE - 1;
 
 /// Fixed-point number with an arbitrarily large integer component and a 32-bit
 /// fractional component.
+///
+/// With the `serde` feature, this is serialized as the underlying `BigInt`,
+/// which is the number multiplied by 2^32.
 #[derive(Default, Clone, Ord, PartialOrd, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FixedPoint(BigInt);
 
 impl FixedPoint {
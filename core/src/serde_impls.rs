@@ -0,0 +1,511 @@
+//! `serde` implementations, enabled by the `serde` feature.
+//!
+//! Simple types such as `Axis` and `FixedPoint` derive their implementations.
+//! The rest are implemented here:
+//!
+//! - `NdVec` is serialized as a tuple with one element per axis.
+//! - `NdRect` is serialized as its minimum corner (`start`) and its `size`.
+//! - `NdTree` is serialized as a flat list of deduplicated nodes (see
+//!   `FlatNdTree`), so that repetitive patterns stay small.
+//! - `NdAutomaton` and `Automaton` store the rule by name. Most rules can only
+//!   be resolved from their name by a higher-level crate, so deserializing
+//!   these directly only recognizes a few built-in rules; use `AutomatonSeed`
+//!   to resolve any other rule.
+
+use serde::de::{self, DeserializeSeed, Deserializer, SeqAccess, Visitor};
+use serde::ser::{SerializeTuple, Serializer};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use crate::automaton::{Automaton, NdAutomaton};
+use crate::dim::*;
+use crate::io::{PatternMetadata, ResolveRule};
+use crate::ndrect::{NdRect, NdRectVec};
+use crate::ndtree::{FlatNdTree, FlatNdTreeNode, Layer, NdTree, NodeRefTrait, SharedNodePool};
+use crate::ndvec::{BigVec, NdVec};
+use crate::num::{BigInt, NdVecNum};
+use crate::sim::rule::{DummyRule, MooreTotalistic2D, NdRule, Rule};
+
+impl<D: DimFor<N>, N: NdVecNum + Serialize> Serialize for NdVec<D, N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut tuple = serializer.serialize_tuple(D::Dim::NDIM)?;
+        for &ax in D::Dim::axes() {
+            tuple.serialize_element(&self[ax])?;
+        }
+        tuple.end()
+    }
+}
+impl<'de, D: DimFor<N>, N: NdVecNum + Deserialize<'de>> Deserialize<'de> for NdVec<D, N> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        struct NdVecVisitor<D, N>(PhantomData<(D, N)>);
+        impl<'de, D: DimFor<N>, N: NdVecNum + Deserialize<'de>> Visitor<'de> for NdVecVisitor<D, N> {
+            type Value = NdVec<D, N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "a {}-dimensional vector", D::Dim::NDIM)
+            }
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut ret = NdVec::origin();
+                for &ax in D::Dim::axes() {
+                    ret[ax] = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(ax as usize, &self))?;
+                }
+                Ok(ret)
+            }
+        }
+
+        deserializer.deserialize_tuple(D::Dim::NDIM, NdVecVisitor(PhantomData))
+    }
+}
+
+/// Serialized form of an `NdRect`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "NdRect")]
+struct NdRectRepr<V> {
+    start: V,
+    size: V,
+}
+impl<D: DimFor<N>, N: NdVecNum + Serialize> Serialize for NdRect<D, N>
+where
+    NdVec<D, N>: NdRectVec,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NdRectRepr {
+            start: self.min(),
+            size: self.size(),
+        }
+        .serialize(serializer)
+    }
+}
+impl<'de, D: DimFor<N>, N: NdVecNum + Deserialize<'de>> Deserialize<'de> for NdRect<D, N>
+where
+    NdVec<D, N>: NdRectVec,
+{
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let NdRectRepr { start, size } = NdRectRepr::<NdVec<D, N>>::deserialize(deserializer)?;
+        if size > NdVec::origin() {
+            Ok(NdRect::with_size(start, size))
+        } else {
+            Err(de::Error::custom("NdRect must have positive volume"))
+        }
+    }
+}
+
+/// Serialized form of an `NdTree`.
+///
+/// `nodes` lists every distinct non-empty node, with children before their
+/// parents. Children are referred to by their index in `nodes` plus one, and
+/// `0` refers to an empty node. The root is the last node, or an empty node at
+/// `layer` if there are no nodes.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "NdTree")]
+struct NdTreeRepr<V> {
+    center: V,
+    layer: u32,
+    nodes: Vec<NodeRepr>,
+}
+/// Maximum number of layers that the root of a serialized `NdTree` may have
+/// above the number of nodes, to avoid creating absurd numbers of empty nodes
+/// from invalid input. This only matters for empty trees; the root of a
+/// non-empty tree is never above the number of nodes, since every layer below
+/// it has at least one node.
+const MAX_EXTRA_LAYERS: usize = 64;
+
+/// Serialized form of a node in an `NdTree`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "Node")]
+enum NodeRepr {
+    /// Layer-1 node, containing 2^NDIM cells.
+    Leaf(Vec<u8>),
+    /// Node containing 2^NDIM children.
+    NonLeaf(Vec<usize>),
+}
+impl<D: Dim> Serialize for NdTree<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let root = self.root_ref();
+        let flat = FlatNdTree::from_node(&root, Layer(1), |node| {
+            node.as_leaf()
+                .expect("Layer-1 node is not a leaf node")
+                .cells()
+                .to_vec()
+        });
+        // Index 0 is always the empty node, so leave it out.
+        let nodes = flat.nodes()[1..]
+            .iter()
+            .map(|node| match node {
+                FlatNdTreeNode::Leaf(cells, _) => NodeRepr::Leaf(cells.clone()),
+                FlatNdTreeNode::NonLeaf(children, _) => NodeRepr::NonLeaf(children.to_vec()),
+            })
+            .collect();
+        NdTreeRepr {
+            center: self.center_pos(),
+            layer: root.layer().0,
+            nodes,
+        }
+        .serialize(serializer)
+    }
+}
+impl<'de, D: Dim> Deserialize<'de> for NdTree<D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let NdTreeRepr {
+            center,
+            layer,
+            nodes,
+        } = NdTreeRepr::<BigVec<D>>::deserialize(deserializer)?;
+        let root_layer = Layer(layer);
+        if root_layer < Layer(1) {
+            return Err(de::Error::custom("root of NdTree must be at least layer 1"));
+        }
+        if layer as usize > nodes.len() + MAX_EXTRA_LAYERS {
+            return Err(de::Error::custom(format_args!(
+                "root of NdTree is layer {} but there are only {} nodes",
+                layer,
+                nodes.len(),
+            )));
+        }
+
+        let node_pool = SharedNodePool::new();
+        let node_pool_access = node_pool.access();
+        // Layer of each node, or `None` for the empty node at index 0.
+        let mut layers = vec![None];
+        let mut new_nodes = vec![node_pool_access.get_empty_base()];
+        for node in nodes {
+            let (layer, new_node) = match node {
+                NodeRepr::Leaf(cells) => {
+                    let num_cells = Layer(1).num_cells::<D>().unwrap();
+                    if cells.len() != num_cells {
+                        return Err(de::Error::invalid_length(
+                            cells.len(),
+                            &format!("{} cells", num_cells).as_str(),
+                        ));
+                    }
+                    (Layer(1), node_pool_access.get_from_cells(cells))
+                }
+                NodeRepr::NonLeaf(children) => {
+                    if children.len() != D::BRANCHING_FACTOR {
+                        return Err(de::Error::invalid_length(
+                            children.len(),
+                            &format!("{} children", D::BRANCHING_FACTOR).as_str(),
+                        ));
+                    }
+                    if let Some(&i) = children.iter().find(|&&i| i >= layers.len()) {
+                        return Err(de::Error::custom(format_args!("invalid node index {}", i)));
+                    }
+                    let child_layer =
+                        children.iter().find_map(|&i| layers[i]).ok_or_else(|| {
+                            de::Error::custom("non-leaf node has only empty children")
+                        })?;
+                    if children
+                        .iter()
+                        .any(|&i| matches!(layers[i], Some(l) if l != child_layer))
+                    {
+                        return Err(de::Error::custom("children of node have different layers"));
+                    }
+                    let new_node = node_pool_access.join_nodes(children.iter().map(|&i| match i {
+                        0 => node_pool_access.get_empty(child_layer),
+                        i => new_nodes[i],
+                    }));
+                    (child_layer.parent_layer(), new_node)
+                }
+            };
+            layers.push(Some(layer));
+            new_nodes.push(new_node);
+        }
+
+        let root = match new_nodes.len() {
+            1 => node_pool_access.get_empty(root_layer),
+            _ => *new_nodes.last().unwrap(),
+        };
+        if root.layer() != root_layer {
+            return Err(de::Error::custom(format_args!(
+                "root node is layer {} but should be layer {}",
+                root.layer().0,
+                layer,
+            )));
+        }
+        Ok(NdTree::from_node_centered_on(root, center))
+    }
+}
+
+/// Serialized form of an `NdAutomaton`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename = "NdAutomaton")]
+struct NdAutomatonRepr<T> {
+    rule: String,
+    generations: BigInt,
+    #[serde(default)]
+    metadata: PatternMetadata,
+    ndtree: T,
+}
+impl<D: Dim> NdAutomatonRepr<NdTree<D>> {
+    /// Converts the serialized automaton into an `NdAutomaton` using one of
+    /// the rules that can be resolved without a higher-level crate.
+    fn resolve_builtin_rule<E: de::Error>(self) -> Result<NdAutomaton<D>, E> {
+        let rule: Option<Arc<dyn NdRule<D>>> = if self.rule == DummyRule.to_string() {
+            Some(DummyRule.into_arc())
+        } else if D::NDIM == 2 {
+            MooreTotalistic2D::try_from(self.rule.as_str())
+                .ok()
+                .map(|r| Rule::transmute(r.into_arc()))
+        } else {
+            None
+        };
+        match rule {
+            Some(rule) => Ok(self.with_rule(rule)),
+            None => Err(E::custom(format_args!(
+                "unknown rule {:?}; use AutomatonSeed to resolve it",
+                self.rule,
+            ))),
+        }
+    }
+    /// Converts the serialized automaton into an `NdAutomaton`, resolving its
+    /// rule name using `resolve_rule`.
+    fn resolve_rule<E: de::Error>(
+        self,
+        resolve_rule: impl ResolveRule,
+    ) -> Result<NdAutomaton<D>, E> {
+        let rule = resolve_rule
+            .resolve_rule(Some(&self.rule))
+            .map_err(|_| E::custom(format_args!("unable to resolve rule {:?}", self.rule)))?;
+        match Arc::<dyn NdRule<D>>::try_from(rule) {
+            Ok(rule) => Ok(self.with_rule(rule)),
+            Err(_) => Err(E::custom(format_args!(
+                "rule {:?} is not {}-dimensional",
+                self.rule,
+                D::NDIM,
+            ))),
+        }
+    }
+    fn with_rule(self, rule: Arc<dyn NdRule<D>>) -> NdAutomaton<D> {
+        NdAutomaton {
+            ndtree: self.ndtree,
+            rule,
+            generations: self.generations,
+            metadata: self.metadata,
+        }
+    }
+}
+impl<D: Dim> Serialize for NdAutomaton<D> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        NdAutomatonRepr {
+            rule: self.rule.to_string(),
+            generations: self.generations.clone(),
+            metadata: self.metadata.clone(),
+            ndtree: &self.ndtree,
+        }
+        .serialize(serializer)
+    }
+}
+impl<'de, D: Dim> Deserialize<'de> for NdAutomaton<D> {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        NdAutomatonRepr::<NdTree<D>>::deserialize(deserializer)?.resolve_builtin_rule()
+    }
+}
+
+/// Serialized form of an `Automaton`.
+#[derive(Deserialize)]
+#[serde(rename = "Automaton")]
+enum AutomatonRepr {
+    Automaton1D(NdAutomatonRepr<NdTree<Dim1D>>),
+    Automaton2D(NdAutomatonRepr<NdTree<Dim2D>>),
+    Automaton3D(NdAutomatonRepr<NdTree<Dim3D>>),
+    Automaton4D(NdAutomatonRepr<NdTree<Dim4D>>),
+    Automaton5D(NdAutomatonRepr<NdTree<Dim5D>>),
+    Automaton6D(NdAutomatonRepr<NdTree<Dim6D>>),
+}
+impl Serialize for Automaton {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Automaton1D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 0, "Automaton1D", a)
+            }
+            Self::Automaton2D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 1, "Automaton2D", a)
+            }
+            Self::Automaton3D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 2, "Automaton3D", a)
+            }
+            Self::Automaton4D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 3, "Automaton4D", a)
+            }
+            Self::Automaton5D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 4, "Automaton5D", a)
+            }
+            Self::Automaton6D(a) => {
+                serializer.serialize_newtype_variant("Automaton", 5, "Automaton6D", a)
+            }
+        }
+    }
+}
+impl<'de> Deserialize<'de> for Automaton {
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        Ok(match AutomatonRepr::deserialize(deserializer)? {
+            AutomatonRepr::Automaton1D(a) => Self::Automaton1D(a.resolve_builtin_rule()?),
+            AutomatonRepr::Automaton2D(a) => Self::Automaton2D(a.resolve_builtin_rule()?),
+            AutomatonRepr::Automaton3D(a) => Self::Automaton3D(a.resolve_builtin_rule()?),
+            AutomatonRepr::Automaton4D(a) => Self::Automaton4D(a.resolve_builtin_rule()?),
+            AutomatonRepr::Automaton5D(a) => Self::Automaton5D(a.resolve_builtin_rule()?),
+            AutomatonRepr::Automaton6D(a) => Self::Automaton6D(a.resolve_builtin_rule()?),
+        })
+    }
+}
+
+/// Deserializer for an `Automaton` that resolves its rule name using a
+/// `ResolveRule`, like `import_automaton_from_string()`.
+///
+/// `Automaton` also implements `Deserialize` directly, but that only
+/// recognizes the few rules that are built into this crate.
+#[derive(Debug, Copy, Clone)]
+pub struct AutomatonSeed<R>(pub R);
+impl<'de, R: ResolveRule> DeserializeSeed<'de> for AutomatonSeed<R> {
+    type Value = Automaton;
+
+    fn deserialize<De: Deserializer<'de>>(self, deserializer: De) -> Result<Automaton, De::Error> {
+        let resolve_rule = self.0;
+        Ok(match AutomatonRepr::deserialize(deserializer)? {
+            AutomatonRepr::Automaton1D(a) => Automaton::Automaton1D(a.resolve_rule(resolve_rule)?),
+            AutomatonRepr::Automaton2D(a) => Automaton::Automaton2D(a.resolve_rule(resolve_rule)?),
+            AutomatonRepr::Automaton3D(a) => Automaton::Automaton3D(a.resolve_rule(resolve_rule)?),
+            AutomatonRepr::Automaton4D(a) => Automaton::Automaton4D(a.resolve_rule(resolve_rule)?),
+            AutomatonRepr::Automaton5D(a) => Automaton::Automaton5D(a.resolve_rule(resolve_rule)?),
+            AutomatonRepr::Automaton6D(a) => Automaton::Automaton6D(a.resolve_rule(resolve_rule)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use std::collections::HashSet;
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(value: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_serde_geometry() {
+        assert_eq!("\"W\"", serde_json::to_string(&Axis::W).unwrap());
+        assert_eq!(Axis::U, round_trip(&Axis::U));
+
+        let huge = BigInt::from(u64::MAX) * BigInt::from(u64::MAX);
+        let v: BigVec2D = NdVec([BigInt::from(-3), huge]);
+        assert_eq!(v, round_trip(&v));
+        let v: IVec3D = NdVec([1, -2, 3]);
+        assert_eq!("[1,-2,3]", serde_json::to_string(&v).unwrap());
+        assert_eq!(v, round_trip(&v));
+        assert!(serde_json::from_str::<IVec3D>("[1,2]").is_err());
+        assert!(serde_json::from_str::<IVec3D>("[1,2,3,4]").is_err());
+
+        let rect = BigRect2D::span(NdVec::big([5, -2]), NdVec::big([-1, 3]));
+        assert_eq!(rect, round_trip(&rect));
+        let rect = IRect3D::span(NdVec([0, 0, 0]), NdVec([1, 2, 3]));
+        assert_eq!(
+            r#"{"start":[0,0,0],"size":[2,3,4]}"#,
+            serde_json::to_string(&rect).unwrap(),
+        );
+        assert!(serde_json::from_str::<IRect2D>(r#"{"start":[0,0],"size":[0,4]}"#).is_err());
+
+        let x = FixedPoint::from(r64(-2.75));
+        assert_eq!(x, round_trip(&x));
+    }
+
+    #[test]
+    fn test_serde_ndtree() {
+        let mut ndtree = NdTree3D::new();
+        for (i, &pos) in [[0, 0, 0], [-5, 3, 9], [100, -100, 7], [1, 2, 3]]
+            .iter()
+            .enumerate()
+        {
+            ndtree.set_cell(&NdVec::big(pos), i as u8 + 1);
+        }
+        let new_ndtree: NdTree3D = round_trip(&ndtree);
+        assert_eq!(ndtree.center_pos(), new_ndtree.center_pos());
+        assert_eq!(
+            ndtree.nonzero_cells().collect::<HashSet<_>>(),
+            new_ndtree.nonzero_cells().collect::<HashSet<_>>(),
+        );
+
+        // Repetitive patterns are deduplicated.
+        let mut ndtree = NdTree2D::new();
+        for x in (0..256).step_by(4) {
+            for y in (0..256).step_by(4) {
+                ndtree.set_cell(&NdVec::big([x, y]), 1_u8);
+            }
+        }
+        assert!(serde_json::to_string(&ndtree).unwrap().len() < 300);
+        let new_ndtree: NdTree2D = round_trip(&ndtree);
+        assert_eq!(
+            ndtree.root_ref().population(),
+            new_ndtree.root_ref().population()
+        );
+
+        let empty = NdTree2D::new();
+        assert!(round_trip(&empty).root_ref().is_empty());
+        let empty: NdTree2D =
+            serde_json::from_str(r#"{"center":[[0,[]],[0,[]]],"layer":64,"nodes":[]}"#).unwrap();
+        assert_eq!(Layer(64), empty.root_ref().layer());
+
+        let single_cell: NdTree2D = serde_json::from_str(
+            r#"{"center":[[0,[]],[0,[]]],"layer":1,"nodes":[{"Leaf":[1,0,0,0]}]}"#,
+        )
+        .unwrap();
+        assert_eq!(1, single_cell.root_ref().population().to_usize().unwrap());
+
+        // Invalid trees are rejected.
+        for s in &[
+            r#"{"center":[[0,[]],[0,[]]],"layer":0,"nodes":[]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":4294967295,"nodes":[]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":100,"nodes":[{"Leaf":[1,0,0,0]}]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":1,"nodes":[{"Leaf":[1,0,0]}]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":2,"nodes":[{"NonLeaf":[0,0,0,1]}]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":2,"nodes":[{"Leaf":[1,0,0,0]},{"NonLeaf":[0,0,0,0]}]}"#,
+            r#"{"center":[[0,[]],[0,[]]],"layer":3,"nodes":[{"Leaf":[1,0,0,0]},{"NonLeaf":[0,0,0,1]}]}"#,
+        ] {
+            assert!(serde_json::from_str::<NdTree2D>(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn test_serde_automaton() {
+        let mut automaton = Automaton2D::default();
+        automaton.set_rule(crate::sim::rule::LIFE);
+        automaton.generations = BigInt::from(-7);
+        automaton.metadata.name = Some("Glider".to_owned());
+        for &pos in &[[0, 1], [1, 0], [-1, -1], [0, -1], [1, -1]] {
+            automaton.ndtree.set_cell(&NdVec::big(pos), 1_u8);
+        }
+
+        let s = serde_json::to_string(&Automaton::from(automaton.clone())).unwrap();
+        let new_automaton = match serde_json::from_str(&s).unwrap() {
+            Automaton::Automaton2D(a) => a,
+            _ => panic!("wrong dimensionality"),
+        };
+        assert_eq!("B3/S23", new_automaton.rule.to_string());
+        assert_eq!(automaton.generations, new_automaton.generations);
+        assert_eq!(automaton.metadata, new_automaton.metadata);
+        assert_eq!(
+            automaton.ndtree.nonzero_cells().collect::<HashSet<_>>(),
+            new_automaton.ndtree.nonzero_cells().collect::<HashSet<_>>(),
+        );
+
+        // Unknown rules need a resolver.
+        let s = s.replace("B3/S23", "B36/S23 custom");
+        assert!(serde_json::from_str::<Automaton>(&s).is_err());
+        let resolved = AutomatonSeed(Rule::from(crate::sim::rule::LIFE.into_arc()))
+            .deserialize(&mut serde_json::Deserializer::from_str(&s))
+            .unwrap();
+        assert_eq!(2, resolved.ndim());
+        let wrong_ndim = AutomatonSeed(Rule::from(DummyRule.into_arc() as Rule3D))
+            .deserialize(&mut serde_json::Deserializer::from_str(&s));
+        assert!(wrong_ndim.is_err());
+
+        let automaton = Automaton3D::default();
+        let new_automaton: Automaton3D = round_trip(&automaton);
+        assert_eq!("Dummy", new_automaton.rule.to_string());
+    }
+}
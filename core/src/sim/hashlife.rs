@@ -10,62 +10,138 @@ use crate::ndtree::{
     HashLifeResultParams, Layer, NdTree, NodeRef, NodeRefEnum, NodeRefTrait, SimCacheGuard,
 };
 use crate::ndvec::UVec;
-use crate::num::{BigInt, Signed, Zero};
+use crate::num::{BigInt, One, Signed, Zero};
 
 // TODO: parallelize using threadpool and crossbeam_channel (call execute threadpool.max_count times with closures that just loop)
 
 /// Advances the given ND-tree by the given number of generations.
 pub fn step<D: Dim>(tree: &mut NdTree<D>, rule: &dyn NdRule<D>, gens: &BigInt) {
+    step_with_memory_limit(tree, rule, gens, usize::MAX);
+}
+
+/// Advances the given ND-tree by the given number of generations, collecting
+/// garbage whenever the node pool uses more than `max_memory` bytes.
+///
+/// When the limit is exceeded partway through a power-of-2-sized step, that
+/// step is abandoned and a strong garbage collection evicts all cached
+/// HashLife results and unreachable nodes. Completed steps are kept, since the
+/// tree holds its root node. If the same step exceeds the limit again, or if
+/// the garbage collection leaves more than half of `max_memory` in use, the
+/// step size is halved. Once it cannot be halved any further, the step is
+/// finished regardless of memory usage, so that the simulation always makes
+/// progress; this is the only way the limit can be exceeded.
+///
+/// If the node pool uses more than `max_memory` bytes at the end, a final
+/// strong garbage collection is performed, so the pool stays within the limit
+/// afterward unless the pattern itself does not fit.
+///
+/// Returns the number of garbage collections performed.
+pub fn step_with_memory_limit<D: Dim>(
+    tree: &mut NdTree<D>,
+    rule: &dyn NdRule<D>,
+    gens: &BigInt,
+    max_memory: usize,
+) -> usize {
     if gens.is_negative() {
         panic!("Cannot simulate negative timestep");
     }
     if gens.is_zero() {
         // No need to simulate anything!
-        return;
+        return 0;
     }
 
     // TODO: consider being nicer to GC threads
     let node_pool = tree.pool().new_ref();
-    let node_pool_access = node_pool.access();
-    let sim_guard = node_pool_access.sim_with(
-        HashLifeResultParams::new()
-            .with_rule_radius(rule.radius())
-            .with_step_size(gens)
-            .build(),
-    );
+    let mut sim_params = HashLifeResultParams::new()
+        .with_rule_radius(rule.radius())
+        .with_step_size(gens)
+        .build();
 
     // Prepare the transition function.
     let mut transition_function = rule.transition_function();
 
     // If the number of generations is not a power of 2, we may have to
     // break this into multiple power-of-2-sized steps.
-    for _ in 0..sim_guard.params().num_steps() {
-        // Expand the existing pattern to `sim_base_layer`.
-        tree.expand_while(|ndtree| ndtree.layer() < sim_guard.params().sim_base_layer());
-
-        // Expand it by another layer to give room for new cells to be born.
-        tree.expand();
-
-        // Now expand one more layer to guarantee that the edges of the current
-        // ND-tree, which could be altered by cells in the middle, will be
-        // included in the final result, because `advance_inner_node()` returns
-        // a node one layer lower than its input.
-        tree.expand();
-
-        assert!(tree.layer() > Layer(2));
-
-        // Now do the actual simulation.
-        let new_root = advance_inner_node(
-            tree.root().as_ref(&node_pool_access),
-            &sim_guard,
-            &mut transition_function,
-        );
-        tree.set_root_centered(new_root);
+    let mut remaining_steps = sim_params.num_steps();
+    let mut gc_count = 0;
+    let mut is_retry = false;
+    while remaining_steps > 0 {
+        let can_halve_step_size =
+            sim_params.log2_step_size() > 0 && remaining_steps.checked_mul(2).is_some();
+        let step_max_memory = if is_retry && !can_halve_step_size {
+            usize::MAX
+        } else {
+            max_memory
+        };
+
+        let success = {
+            let node_pool_access = node_pool.access();
+            let sim_guard = node_pool_access.sim_with(sim_params);
+
+            // Expand the existing pattern to `sim_base_layer`.
+            tree.expand_while(|ndtree| ndtree.layer() < sim_guard.params().sim_base_layer());
+
+            // Expand it by another layer to give room for new cells to be born.
+            tree.expand();
+
+            // Now expand one more layer to guarantee that the edges of the
+            // current ND-tree, which could be altered by cells in the middle,
+            // will be included in the final result, because
+            // `advance_inner_node()` returns a node one layer lower than its
+            // input.
+            tree.expand();
+
+            assert!(tree.layer() > Layer(2));
+
+            // Now do the actual simulation.
+            let new_root = advance_inner_node(
+                tree.root().as_ref(&node_pool_access),
+                &sim_guard,
+                &mut transition_function,
+                step_max_memory,
+            );
+            match new_root {
+                Some(new_root) => {
+                    tree.set_root_centered(new_root);
+                    true
+                }
+                None => false,
+            }
+        };
 
         // Shrink the tree as much as possible to avoid wasted space. TODO:
         // is it better to have this inside the loop or outside the loop?
         tree.shrink();
+
+        if success {
+            remaining_steps -= 1;
+            is_retry = false;
+        } else {
+            let memory_after_gc = {
+                let mut node_pool_access = node_pool.total_access();
+                node_pool_access.strong_gc();
+                node_pool_access.memory_usage()
+            };
+            gc_count += 1;
+            // Smaller steps need fewer nodes at once. If the pattern itself
+            // uses most of the budget, retrying the same step would probably
+            // run out of memory again, so halve the step size right away.
+            if can_halve_step_size && (is_retry || memory_after_gc > max_memory / 2) {
+                remaining_steps *= 2;
+                sim_params = HashLifeResultParams::new()
+                    .with_rule_radius(rule.radius())
+                    .with_step_size(&(BigInt::one() << (sim_params.log2_step_size() - 1)))
+                    .build();
+            }
+            is_retry = true;
+        }
+    }
+
+    if node_pool.access().memory_usage() > max_memory {
+        node_pool.total_access().strong_gc();
+        gc_count += 1;
     }
+    gc_count
 }
 
 /// Computes the inner node for a given node after some predetermined number
@@ -86,12 +162,16 @@ pub fn step<D: Dim>(tree: &mut NdTree<D>, rule: &dyn NdRule<D>, gens: &BigInt) {
 /// inner node to the edge of the outer node.) In practice, however, each
 /// layer must be computed separately, so the `r` and `t` must each be
 /// replaced with their next lowest power of two.
+///
+/// Returns `None` if the node pool uses more than `max_memory` bytes before
+/// the result is computed.
 #[must_use = "This method returns a new value instead of mutating its input"]
 fn advance_inner_node<'pool, D: Dim>(
     node: NodeRef<'pool, D>,
     sim_guard: &SimCacheGuard<'_, D>,
     transition_function: &mut TransitionFunction<'_, D>,
-) -> NodeRef<'pool, D> {
+    max_memory: usize,
+) -> Option<NodeRef<'pool, D>> {
     let sim_params = sim_guard.params();
 
     // Make sure we're above the minimum layer.
@@ -102,7 +182,11 @@ fn advance_inner_node<'pool, D: Dim>(
 
     if let Some(result) = node.result(sim_guard) {
         // If the result is already computed, just return that.
-        return result;
+        return Some(result);
+    }
+
+    if node.pool().memory_usage() > max_memory {
+        return None;
     }
 
     let ret: NodeRef<'pool, D> = if node.is_empty() {
@@ -206,8 +290,14 @@ fn advance_inner_node<'pool, D: Dim>(
         // 3. Simulate each of those nodes to get a new node at layer `L-2`
         //    and time `t/2` (red squares).
         let half_simmed_quarter_size_nodes: NdArray<NodeRef<'pool, D>, D> =
-            unsimmed_half_size_nodes
-                .map(|&n| advance_inner_node(n, sim_guard, transition_function));
+            NdArray::from_flat_slice(
+                UVec::repeat(3_usize),
+                unsimmed_half_size_nodes
+                    .as_flat_slice()
+                    .iter()
+                    .map(|&n| advance_inner_node(n, sim_guard, transition_function, max_memory))
+                    .collect::<Option<Vec<_>>>()?,
+            );
 
         // 4. Combine adjacent nodes from step #3 to make a 2^D array of
         //    nodes at layer `L-1` and time `t/2`.
@@ -226,18 +316,20 @@ fn advance_inner_node<'pool, D: Dim>(
 
         // 5. Simulate each of those nodes to get a new node at layer `L-2`
         //    and time `t` (green squares).
-        let fully_simmed_quarter_size_nodes = half_simmed_half_size_nodes.map(|node| {
-            if this_nodes_log2_step_size != childrens_log2_step_size {
-                advance_inner_node(node, sim_guard, transition_function)
-            } else {
-                // ... unless that `t/2` was actually the total number of
-                // generations we needed to simulate, in which case don't
-                // simulate any more. Just grab the inner node of each of
-                // those nodes (which is like simulating them for zero
-                // generations).
-                node.centered_inner().unwrap()
-            }
-        });
+        let fully_simmed_quarter_size_nodes = half_simmed_half_size_nodes
+            .map(|node| {
+                if this_nodes_log2_step_size != childrens_log2_step_size {
+                    advance_inner_node(node, sim_guard, transition_function, max_memory)
+                } else {
+                    // ... unless that `t/2` was actually the total number of
+                    // generations we needed to simulate, in which case don't
+                    // simulate any more. Just grab the inner node of each of
+                    // those nodes (which is like simulating them for zero
+                    // generations).
+                    Some(node.centered_inner().unwrap())
+                }
+            })
+            .collect::<Option<Vec<_>>>()?;
 
         // 6. Combine the nodes from step #5 to make a new node at layer
         //    `L-1` and time `t` (blue square). This is the final result.
@@ -246,5 +338,5 @@ fn advance_inner_node<'pool, D: Dim>(
 
     // Cache that result so we don't have to do all that work next time.
     node.set_result(Some(ret));
-    ret
+    Some(ret)
 }
@@ -1,7 +1,19 @@
 //! Rules and simulation algorithms.
 
 pub mod hashlife;
+mod periodic_search;
+mod predecessor;
+mod recorder;
 pub mod rule;
+mod run_until;
 mod simulate;
+#[cfg(feature = "random")]
+mod soup_search;
 
+pub use periodic_search::{PeriodicSearch, PeriodicSearchParams, SearchStatus};
+pub use predecessor::PredecessorSearch;
+pub use recorder::{Recorder, Sample};
+pub use run_until::{run_until, StopCondition};
 pub use simulate::{AsSimulate, Simulate};
+#[cfg(feature = "random")]
+pub use soup_search::{Census, SoupSearch, SoupSearchParams, PATHOLOGICAL, UNSTABLE};
@@ -0,0 +1,617 @@
+//! Backtracking search for still lifes, oscillators, and spaceships.
+
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::rule::{NdRule, TransitionFunction};
+use crate::dim::Dim;
+use crate::io::{CaFormatTrait, Rle, RleResult, TwoState};
+use crate::ndarray::NdArray;
+use crate::ndrect::{BigRect, CanContain, URect};
+use crate::ndtree::{NdTree, SoupSymmetry};
+use crate::ndvec::{IVec, UVec};
+use crate::num::{BigInt, ToPrimitive};
+use crate::{HashMap, HashSet};
+
+/// First line of a saved search state.
+const STATE_HEADER: &str = "NDCell periodic search v1";
+
+/// Position of a cell in the last generation and the corresponding position in
+/// generation 0, or `None` if it is outside the search rectangle.
+type Check<D> = (UVec<D>, Option<UVec<D>>);
+
+/// Parameters for a `PeriodicSearch`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeriodicSearchParams<D: Dim> {
+    /// Rectangle that contains the pattern at generation 0.
+    rect: BigRect<D>,
+    /// Number of generations after which the pattern repeats.
+    period: usize,
+    /// Displacement of the pattern after each period.
+    velocity: IVec<D>,
+    /// Symmetry of the pattern at generation 0.
+    symmetry: SoupSymmetry,
+}
+impl<D: Dim> fmt::Display for PeriodicSearchParams<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "rect {}, period {}, velocity {}, symmetry {:?}",
+            self.rect, self.period, self.velocity, self.symmetry,
+        )
+    }
+}
+impl<D: Dim> PeriodicSearchParams<D> {
+    /// Returns parameters for an asymmetric pattern within `rect` that
+    /// reappears displaced by `velocity` after `period` generations. A zero
+    /// velocity searches for still lifes (with a period of 1) or oscillators.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `period` is zero.
+    pub fn new(rect: BigRect<D>, period: usize, velocity: IVec<D>) -> Self {
+        assert!(period > 0, "Period must be positive");
+        Self {
+            rect,
+            period,
+            velocity,
+            symmetry: SoupSymmetry::Asymmetric,
+        }
+    }
+    /// Sets the symmetry of the pattern at generation 0, relative to the
+    /// search rectangle.
+    pub fn with_symmetry(mut self, symmetry: SoupSymmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+
+    /// Returns the rectangle that contains the pattern at generation 0.
+    pub fn rect(&self) -> &BigRect<D> {
+        &self.rect
+    }
+    /// Returns the number of generations after which the pattern repeats.
+    pub fn period(&self) -> usize {
+        self.period
+    }
+    /// Returns the displacement of the pattern after each period.
+    pub fn velocity(&self) -> &IVec<D> {
+        &self.velocity
+    }
+    /// Returns the symmetry of the pattern at generation 0.
+    pub fn symmetry(&self) -> SoupSymmetry {
+        self.symmetry
+    }
+}
+
+/// Result of running a `PeriodicSearch` for a while.
+#[derive(Debug, Clone)]
+pub enum SearchStatus<D: Dim> {
+    /// A pattern was found. Continuing the search will find the next one.
+    Found(NdTree<D>),
+    /// The step limit was reached. Continuing the search will pick up where
+    /// it left off.
+    Paused,
+    /// Every possibility has been tried.
+    Done,
+}
+
+/// Search for patterns that repeat, possibly displaced, after a fixed number
+/// of generations, in the style of lifesrc.
+///
+/// Every cell of the search rectangle at generation 0 is assigned a state, one
+/// at a time, and each later generation is computed as soon as enough of
+/// generation 0 is known. The search backtracks as soon as the last
+/// generation differs from the displaced first one, so it is only practical
+/// for small rectangles and rules with few states and small neighborhoods.
+///
+/// Each pattern is reported once for every phase and position in which it
+/// fits inside the rectangle, except that translations of a pattern already
+/// found by the same `PeriodicSearch` are skipped. Patterns whose actual
+/// period is a proper divisor of the requested period (with a proportional
+/// displacement) and the empty pattern are skipped too.
+///
+/// The search can be paused after any number of steps and its state saved
+/// with `write_state()` and restored with `read_state()`.
+pub struct PeriodicSearch<'a, D: Dim> {
+    /// Rule to search in.
+    rule: &'a dyn NdRule<D>,
+    /// Transition function of the rule.
+    transition_function: TransitionFunction<'a, D>,
+    /// Search parameters.
+    params: PeriodicSearchParams<D>,
+
+    /// Position of the minimum corner of the search rectangle in `gens`.
+    offset: usize,
+    /// Cells at each generation, each covering every cell that can be
+    /// affected by the search rectangle by the last generation plus one rule
+    /// radius.
+    gens: Vec<NdArray<u8, D>>,
+    /// Positions in `gens[0]` of every cell that shares a state with each
+    /// variable.
+    variables: Vec<Vec<UVec<D>>>,
+    /// Cells (generation and position) to compute after assigning each
+    /// variable, in order.
+    schedule: Vec<Vec<(usize, UVec<D>)>>,
+    /// Pairs of cells (position in the last generation and corresponding
+    /// position in generation 0, if it is inside the search rectangle) that
+    /// must be equal, to check after assigning each variable.
+    checks: Vec<Vec<Check<D>>>,
+
+    /// States assigned to the first variables.
+    assignment: Vec<u8>,
+    /// Whether the current assignment is consistent and has not been reported
+    /// as a result yet.
+    consistent: bool,
+    /// Whether every possibility has been tried.
+    done: bool,
+    /// RLE of every pattern found so far, with newlines replaced by `|`,
+    /// used to skip translations.
+    found: HashSet<String>,
+}
+impl<D: Dim> fmt::Debug for PeriodicSearch<'_, D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeriodicSearch")
+            .field("rule", &self.rule)
+            .field("params", &self.params)
+            .field("assignment", &self.assignment)
+            .field("consistent", &self.consistent)
+            .field("done", &self.done)
+            .finish()
+    }
+}
+impl<'a, D: Dim> PeriodicSearch<'a, D> {
+    /// Sets up a search in `rule` using the given parameters.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if the rule turns empty neighborhoods into
+    /// nonzero cells, if the velocity is faster than the rule allows, if the
+    /// symmetry is `D8` and the search rectangle is not a hypercube, or if the
+    /// search rectangle is too large to fit in memory as an array.
+    pub fn new(rule: &'a dyn NdRule<D>, params: PeriodicSearchParams<D>) -> Self {
+        let radius = rule.radius();
+        let period = params.period;
+        let mut transition_function = rule.transition_function();
+
+        let empty = NdArray::from_fn(UVec::repeat(2 * radius + 1), |_| 0_u8);
+        let next = transition_function(&empty, URect::single_cell(UVec::repeat(radius)));
+        assert_eq!(
+            0,
+            next.as_flat_slice()[0],
+            "Periodic search does not support rules where empty neighborhoods become nonzero",
+        );
+        assert!(
+            D::axes()
+                .iter()
+                .all(|&ax| params.velocity[ax].unsigned_abs() <= period * radius),
+            "Velocity is faster than the speed of light",
+        );
+        let rect_size = params.rect.size().to_uvec();
+        if params.symmetry == SoupSymmetry::D8 {
+            assert!(
+                D::axes()
+                    .iter()
+                    .all(|&ax| rect_size[ax] == rect_size[D::axes()[0]]),
+                "D8 symmetry requires a hypercubic search rectangle",
+            );
+        }
+
+        let offset = (period + 1) * radius;
+        let size = rect_size.clone() + 2 * offset;
+        let gens = vec![NdArray::from_fn(size.clone(), |_| 0_u8); period + 1];
+        let all_positions: Vec<UVec<D>> = gens[0].rect().iter().collect();
+
+        // Assign variables to the cells in the search rectangle, in order of
+        // the first cell in each set of symmetric cells.
+        let rect_min = params.rect.min();
+        let mut variables: Vec<Vec<UVec<D>>> = vec![];
+        let mut variable_indices = HashMap::default();
+        // Variable that determines each cell at each generation.
+        let mut last_variable = vec![vec![None; all_positions.len()]; period + 1];
+        for rect_pos in URect::with_size(UVec::origin(), rect_size.clone()).iter() {
+            let canonical_pos = params
+                .symmetry
+                .canonicalize(&params.rect, &(&rect_min + &rect_pos.to_bigvec()));
+            let index = *variable_indices.entry(canonical_pos).or_insert_with(|| {
+                variables.push(vec![]);
+                variables.len() - 1
+            });
+            let pos = rect_pos + offset;
+            last_variable[0][gens[0].flatten_idx(pos.clone())] = Some(index);
+            variables[index].push(pos);
+        }
+
+        // Each cell after generation 0 is determined by the last variable in
+        // its neighborhood in the previous generation.
+        let mut schedule = vec![vec![]; variables.len()];
+        for t in 1..=period {
+            let reach = offset - t * radius;
+            let rect = URect::with_size(UVec::repeat(reach), size.clone() - 2 * reach);
+            for pos in rect.iter() {
+                let neighborhood = URect::span(pos.clone() - radius, pos.clone() + radius);
+                let last = neighborhood
+                    .iter()
+                    .filter_map(|neighbor| last_variable[t - 1][gens[0].flatten_idx(neighbor)])
+                    .max();
+                last_variable[t][gens[0].flatten_idx(pos.clone())] = last;
+                if let Some(index) = last {
+                    schedule[index].push((t, pos));
+                }
+            }
+        }
+
+        // Each cell in the last generation must match the cell displaced by
+        // the velocity in generation 0.
+        let mut checks = vec![vec![]; variables.len()];
+        let rect_in_gens = URect::with_size(UVec::repeat(offset), rect_size);
+        for (i, pos) in all_positions.iter().enumerate() {
+            let source = displace(pos, &-params.velocity.clone(), &size)
+                .filter(|source| rect_in_gens.contains(source));
+            let source_variable = source
+                .as_ref()
+                .and_then(|source| last_variable[0][gens[0].flatten_idx(source.clone())]);
+            if let Some(index) = std::cmp::max(last_variable[period][i], source_variable) {
+                checks[index].push((pos.clone(), source));
+            }
+        }
+
+        Self {
+            rule,
+            transition_function,
+            params,
+
+            offset,
+            gens,
+            variables,
+            schedule,
+            checks,
+
+            assignment: vec![],
+            consistent: true,
+            done: false,
+            found: HashSet::default(),
+        }
+    }
+
+    /// Returns the search parameters.
+    pub fn params(&self) -> &PeriodicSearchParams<D> {
+        &self.params
+    }
+    /// Returns the number of cells (or sets of symmetric cells) at generation
+    /// 0 whose states are searched.
+    pub fn variable_count(&self) -> usize {
+        self.variables.len()
+    }
+    /// Returns whether every possibility has been tried.
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Runs the search until it finds a pattern, tries `max_steps` more
+    /// assignments, or finishes.
+    ///
+    /// Returns an error if a pattern found cannot be converted to RLE, which
+    /// is how patterns are compared and saved. Continuing the search skips
+    /// that pattern.
+    pub fn search(&mut self, max_steps: usize) -> RleResult<SearchStatus<D>> {
+        let mut steps = 0;
+        loop {
+            if self.done {
+                return Ok(SearchStatus::Done);
+            }
+            if steps >= max_steps {
+                return Ok(SearchStatus::Paused);
+            }
+
+            if self.consistent && self.assignment.len() == self.variables.len() {
+                self.consistent = false;
+                if let Some(pattern) = self.new_result()? {
+                    return Ok(SearchStatus::Found(pattern));
+                }
+            } else if self.consistent {
+                self.assignment.push(0);
+                self.consistent = self.assign(self.assignment.len() - 1);
+                steps += 1;
+            } else {
+                // Try the next state of the last variable, or backtrack.
+                let state_count = self.rule.max_state() as usize + 1;
+                match self.assignment.last_mut() {
+                    None => self.done = true,
+                    Some(state) if (*state as usize) + 1 < state_count => {
+                        *state += 1;
+                        self.consistent = self.assign(self.assignment.len() - 1);
+                        steps += 1;
+                    }
+                    Some(_) => {
+                        self.assignment.pop();
+                    }
+                }
+            }
+        }
+    }
+    /// Runs the search to completion and returns every pattern found, or the
+    /// first error from `search()`.
+    pub fn search_all(&mut self) -> RleResult<Vec<NdTree<D>>> {
+        let mut ret = vec![];
+        loop {
+            match self.search(usize::MAX)? {
+                SearchStatus::Found(pattern) => ret.push(pattern),
+                SearchStatus::Paused => (),
+                SearchStatus::Done => return Ok(ret),
+            }
+        }
+    }
+
+    /// Returns a pattern as RLE, including the rule name.
+    pub fn to_rle(&self, pattern: &NdTree<D>) -> RleResult<String> {
+        let rle = Rle::from_ndtree(pattern, None)?
+            .without_cxrle()
+            .with_rule(Some(self.rule));
+        let mut buffer = vec![];
+        rle.write_to(&mut buffer, TwoState::from_rule(self.rule))?;
+        Ok(String::from_utf8(buffer).expect("RLE is not valid UTF-8"))
+    }
+
+    /// Writes the state of the search so that it can be resumed later with
+    /// `read_state()`, including which patterns have been found so that they
+    /// are not reported again.
+    pub fn write_state(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{}", STATE_HEADER)?;
+        writeln!(w, "{}, rule {}", self.params, self.rule)?;
+        writeln!(w, "{} {}", self.consistent, self.done)?;
+        let assignment: Vec<String> = self.assignment.iter().map(u8::to_string).collect();
+        writeln!(w, "{}", assignment.join(","))?;
+        let mut found: Vec<&String> = self.found.iter().collect();
+        found.sort();
+        for rle in found {
+            writeln!(w, "{}", rle)?;
+        }
+        Ok(())
+    }
+    /// Restores the state of the search from a reader, which must contain the
+    /// output of `write_state()` for a search with the same rule and
+    /// parameters.
+    pub fn read_state(&mut self, r: impl BufRead) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+        let lines: Vec<String> = r.lines().collect::<io::Result<_>>()?;
+        if lines.len() < 4 || lines[0] != STATE_HEADER {
+            return Err(invalid("Not a periodic search state"));
+        }
+        if lines[1] != format!("{}, rule {}", self.params, self.rule) {
+            return Err(invalid("Search state has different parameters"));
+        }
+        let flags: Vec<bool> = lines[2]
+            .split(' ')
+            .map(|s| s.parse().map_err(|_| invalid("Invalid search flags")))
+            .collect::<io::Result<_>>()?;
+        let assignment: Vec<u8> = lines[3]
+            .split(',')
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse().map_err(|_| invalid("Invalid search assignment")))
+            .collect::<io::Result<_>>()?;
+        if flags.len() != 2
+            || assignment.len() > self.variables.len()
+            || assignment
+                .iter()
+                .any(|&state| state > self.rule.max_state())
+        {
+            return Err(invalid("Invalid search state"));
+        }
+
+        self.assignment = assignment;
+        for i in 0..self.assignment.len() {
+            self.assign(i);
+        }
+        self.consistent = flags[0];
+        self.done = flags[1];
+        self.found = lines[4..].iter().cloned().collect();
+        Ok(())
+    }
+
+    /// Assigns the variable with index `i` its state from `self.assignment`,
+    /// computes every cell that depends on it, and returns whether the last
+    /// generation still matches generation 0 everywhere that is known.
+    fn assign(&mut self, i: usize) -> bool {
+        let state = self.assignment[i];
+        for pos in &self.variables[i] {
+            self.gens[0][pos.clone()] = state;
+        }
+        for (t, pos) in &self.schedule[i] {
+            let next =
+                (self.transition_function)(&self.gens[t - 1], URect::single_cell(pos.clone()));
+            self.gens[*t][pos.clone()] = next.as_flat_slice()[0];
+        }
+        let last = &self.gens[self.params.period];
+        let first = &self.gens[0];
+        self.checks[i].iter().all(|(pos, source)| {
+            let expected = source.as_ref().map_or(0, |source| first[source.clone()]);
+            last[pos.clone()] == expected
+        })
+    }
+
+    /// Returns the current assignment as a pattern, or `None` if it is empty,
+    /// has a smaller period, or is a translation of a pattern already found.
+    fn new_result(&mut self) -> RleResult<Option<NdTree<D>>> {
+        let first = &self.gens[0];
+        if first.as_flat_slice().iter().all(|&cell| cell == 0) {
+            return Ok(None);
+        }
+        let period = self.params.period;
+        for smaller_period in (1..period).filter(|p| period % p == 0) {
+            let scale = (period / smaller_period) as isize;
+            if D::axes()
+                .iter()
+                .any(|&ax| self.params.velocity[ax] % scale != 0)
+            {
+                continue;
+            }
+            let velocity = IVec::from_fn(|ax| self.params.velocity[ax] / scale);
+            let gen = &self.gens[smaller_period];
+            let repeats = gen.iter_enumerated().all(|(pos, &cell)| {
+                let expected = displace(&pos, &-velocity.clone(), first.size())
+                    .map_or(0, |source| first[source]);
+                cell == expected
+            });
+            if repeats {
+                return Ok(None);
+            }
+        }
+
+        let mut pattern = NdTree::new();
+        let base = self.params.rect.min() - BigInt::from(self.offset);
+        for (pos, &cell) in first.iter_enumerated() {
+            if cell != 0 {
+                pattern.set_cell(&(&base + &pos.to_bigvec()), cell);
+            }
+        }
+        let rle = self.to_rle(&pattern)?.trim_end().replace('\n', "|");
+        if !self.found.insert(rle) {
+            return Ok(None);
+        }
+        Ok(Some(pattern))
+    }
+}
+
+/// Returns `pos + delta`, or `None` if that is outside an array of the given
+/// size.
+fn displace<D: Dim>(pos: &UVec<D>, delta: &IVec<D>, size: &UVec<D>) -> Option<UVec<D>> {
+    let mut ret = UVec::origin();
+    for &ax in D::axes() {
+        let component = (pos[ax] as isize + delta[ax]).to_usize()?;
+        if component >= size[ax] {
+            return None;
+        }
+        ret[ax] = component;
+    }
+    Some(ret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dim::Dim2D;
+    use crate::ndrect::NdRect;
+    use crate::ndtree::NodeRefTrait;
+    use crate::ndvec::NdVec;
+    use crate::sim::hashlife;
+    use crate::sim::rule::LIFE;
+
+    fn rect(w: isize, h: isize) -> BigRect<Dim2D> {
+        NdRect::span(NdVec::big([0, 0]), NdVec::big([w - 1, h - 1]))
+    }
+
+    /// Returns the RLE bodies of the patterns found, sorted.
+    fn rle_bodies(search: &PeriodicSearch<'_, Dim2D>, patterns: &[NdTree<Dim2D>]) -> Vec<String> {
+        let mut ret: Vec<String> = patterns
+            .iter()
+            .map(|p| {
+                let rle = search.to_rle(p).unwrap();
+                rle.lines().skip(1).collect()
+            })
+            .collect();
+        ret.sort();
+        ret
+    }
+
+    /// Asserts that each pattern is displaced by `velocity` after `period`
+    /// generations.
+    fn assert_periodic(patterns: &[NdTree<Dim2D>], period: usize, velocity: [isize; 2]) {
+        for pattern in patterns {
+            let mut next = pattern.clone();
+            hashlife::step(&mut next, &LIFE, &BigInt::from(period));
+            let delta = NdVec::big(velocity);
+            let expected_rect = pattern.bounding_rect().unwrap() + delta;
+            assert_eq!(Some(expected_rect), next.bounding_rect());
+            assert_eq!(
+                pattern.root_ref().population(),
+                next.root_ref().population()
+            );
+        }
+    }
+
+    #[test]
+    fn test_periodic_search_still_lifes() {
+        let params = PeriodicSearchParams::new(rect(4, 4), 1, NdVec([0, 0]));
+        let mut search = PeriodicSearch::new(&LIFE, params);
+        let found = search.search_all().unwrap();
+        assert!(search.is_done());
+        assert_periodic(&found, 1, [0, 0]);
+        let bodies = rle_bodies(&search, &found);
+        for expected in &[
+            "2o$2o!",
+            "b2o$o2bo$bobo$2bo!",
+            "b2o$o2bo$o2bo$b2o!",
+            "2o$obo$bo!",
+        ] {
+            assert!(
+                bodies.contains(&expected.to_string()),
+                "missing {}",
+                expected
+            );
+        }
+        // Translations are skipped.
+        assert_eq!(1, bodies.iter().filter(|b| *b == "2o$2o!").count());
+
+        // The boat has no D4 symmetry, but the pond does.
+        let params =
+            PeriodicSearchParams::new(rect(4, 4), 1, NdVec([0, 0])).with_symmetry(SoupSymmetry::D4);
+        let mut search = PeriodicSearch::new(&LIFE, params);
+        let found = search.search_all().unwrap();
+        let bodies = rle_bodies(&search, &found);
+        assert!(bodies.contains(&"b2o$o2bo$o2bo$b2o!".to_string()));
+        assert!(!bodies.contains(&"2o$obo$bo!".to_string()));
+    }
+
+    #[test]
+    fn test_periodic_search_oscillators_and_spaceships() {
+        let params = PeriodicSearchParams::new(rect(3, 3), 2, NdVec([0, 0]));
+        let mut search = PeriodicSearch::new(&LIFE, params);
+        let found = search.search_all().unwrap();
+        // Both phases of the blinker, but no still lifes.
+        assert_eq!(vec!["3o!", "o$o$o!"], rle_bodies(&search, &found));
+
+        let params = PeriodicSearchParams::new(rect(3, 3), 4, NdVec([1, 1]));
+        let mut search = PeriodicSearch::new(&LIFE, params);
+        let found = search.search_all().unwrap();
+        // Each phase of the glider moving in the positive direction along
+        // both axes.
+        assert_eq!(4, found.len());
+        assert_periodic(&found, 4, [1, 1]);
+    }
+
+    #[test]
+    fn test_periodic_search_resume() {
+        let params = PeriodicSearchParams::new(rect(3, 3), 4, NdVec([1, 1]));
+        let mut search = PeriodicSearch::new(&LIFE, params.clone());
+        let found = search.search_all().unwrap();
+        let expected = rle_bodies(&search, &found);
+
+        // Save and restore the state after every few steps.
+        let mut found = vec![];
+        let mut state = vec![];
+        PeriodicSearch::new(&LIFE, params.clone())
+            .write_state(&mut state)
+            .unwrap();
+        loop {
+            let mut search = PeriodicSearch::new(&LIFE, params.clone());
+            search.read_state(&state[..]).unwrap();
+            let status = search.search(7).unwrap();
+            state.clear();
+            search.write_state(&mut state).unwrap();
+            match status {
+                SearchStatus::Found(pattern) => found.push(pattern),
+                SearchStatus::Paused => (),
+                SearchStatus::Done => break,
+            }
+        }
+        let search = PeriodicSearch::new(&LIFE, params);
+        assert_eq!(expected, rle_bodies(&search, &found));
+
+        let mut other = PeriodicSearch::new(
+            &LIFE,
+            PeriodicSearchParams::new(rect(3, 3), 4, NdVec([1, 0])),
+        );
+        assert!(other.read_state(&state[..]).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+//! Backtracking search for predecessors of a pattern.
+
+use super::rule::{NdRule, TransitionFunction};
+use crate::dim::Dim;
+use crate::ndarray::NdArray;
+use crate::ndrect::{BigRect, CanContain, URect};
+use crate::ndtree::NdTree;
+use crate::ndvec::UVec;
+use crate::num::BigInt;
+
+/// Search for patterns that become a given pattern after one generation.
+///
+/// Only the cells of the target pattern inside the target rectangle are
+/// constrained; cells outside it may become anything. Cells of the predecessor
+/// may be nonzero only inside the parent rectangle, which by default includes
+/// every cell that can affect the target rectangle. With the default parent
+/// rectangle, finding no predecessors proves that the target is a Garden of
+/// Eden.
+///
+/// The search tries every state for every cell in the parent rectangle, so it
+/// is only practical for small rectangles and rules with few states and small
+/// neighborhoods.
+#[derive(Debug, Clone)]
+pub struct PredecessorSearch<'a, D: Dim> {
+    /// Rule to search in.
+    rule: &'a dyn NdRule<D>,
+    /// Pattern that predecessors must become.
+    target: NdTree<D>,
+    /// Region of the target pattern that predecessors must match.
+    target_rect: BigRect<D>,
+    /// Region outside of which predecessors must be empty.
+    parent_rect: BigRect<D>,
+}
+impl<'a, D: Dim> PredecessorSearch<'a, D> {
+    /// Returns a search for patterns that become `target` within `target_rect`
+    /// after one generation of `rule`.
+    pub fn new(rule: &'a dyn NdRule<D>, target: &NdTree<D>, target_rect: BigRect<D>) -> Self {
+        let radius = BigInt::from(rule.radius());
+        let parent_rect = target_rect.clone().offset_min_max(-&radius, radius);
+        Self {
+            rule,
+            target: target.clone(),
+            target_rect,
+            parent_rect,
+        }
+    }
+    /// Restricts predecessors to be empty outside of `parent_rect`.
+    pub fn with_parent_rect(mut self, parent_rect: BigRect<D>) -> Self {
+        self.parent_rect = parent_rect;
+        self
+    }
+
+    /// Returns the region of the target pattern that predecessors must match.
+    pub fn target_rect(&self) -> &BigRect<D> {
+        &self.target_rect
+    }
+    /// Returns the region outside of which predecessors must be empty.
+    pub fn parent_rect(&self) -> &BigRect<D> {
+        &self.parent_rect
+    }
+
+    /// Returns one predecessor, or `None` if there is none.
+    pub fn find_one(&self) -> Option<NdTree<D>> {
+        self.find(1).pop()
+    }
+    /// Returns whether there is no predecessor.
+    pub fn is_garden_of_eden(&self) -> bool {
+        self.find_one().is_none()
+    }
+    /// Returns up to `max_results` distinct predecessors, sparsest-first in
+    /// the sense that cells earlier in the parent rectangle are tried as state
+    /// #0 before anything else.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if the target rectangle is too large to fit in
+    /// memory as an array.
+    pub fn find(&self, max_results: usize) -> Vec<NdTree<D>> {
+        if max_results == 0 {
+            return vec![];
+        }
+
+        let radius = self.rule.radius();
+        let big_radius = BigInt::from(radius);
+        // Window containing every cell that can affect the target rectangle.
+        let window = self
+            .target_rect
+            .clone()
+            .offset_min_max(-&big_radius, big_radius.clone());
+        let window_min = window.min();
+        let cells = NdArray::from_fn(window.size().to_uvec(), |_| 0_u8);
+        let positions: Vec<UVec<D>> = cells.rect().iter().collect();
+
+        // Cells that may be nonzero, in the order they are assigned.
+        let variables: Vec<usize> = (0..positions.len())
+            .filter(|&i| {
+                self.parent_rect
+                    .contains(&(&window_min + &positions[i].to_bigvec()))
+            })
+            .collect();
+        let mut variable_order = vec![None; positions.len()];
+        for (order, &i) in variables.iter().enumerate() {
+            variable_order[i] = Some(order);
+        }
+
+        // Check each target cell as soon as its whole neighborhood has been
+        // assigned. Because the array is flattened in row-major order, that
+        // is right after the last variable in the neighborhood.
+        let mut initial_checks = vec![];
+        let mut checks = vec![vec![]; variables.len()];
+        let target_min = self.target_rect.min();
+        for offset in URect::with_size(UVec::origin(), self.target_rect.size().to_uvec()).iter() {
+            let expected = self.target.get_cell(&(&target_min + &offset.to_bigvec()));
+            let pos = offset + radius;
+            let neighborhood = URect::span(pos.clone() - radius, pos.clone() + radius);
+            let last_variable = neighborhood
+                .iter()
+                .filter_map(|neighbor| variable_order[cells.flatten_idx(neighbor)])
+                .max();
+            match last_variable {
+                Some(order) => checks[order].push((pos, expected)),
+                None => initial_checks.push((pos, expected)),
+            }
+        }
+
+        let mut searcher = Searcher {
+            transition_function: self.rule.transition_function(),
+            state_count: self.rule.max_state() as usize + 1,
+            cells,
+            variables: variables.iter().map(|&i| positions[i].clone()).collect(),
+            checks,
+            max_results,
+            results: vec![],
+        };
+        if searcher.check(&initial_checks) {
+            searcher.search(0);
+        }
+
+        searcher
+            .results
+            .into_iter()
+            .map(|cells| {
+                let mut ndtree = NdTree::with_node_pool(self.target.pool().new_ref());
+                for (pos, &state) in cells.iter_enumerated() {
+                    if state != 0 {
+                        ndtree.set_cell(&(&window_min + &pos.to_bigvec()), state);
+                    }
+                }
+                ndtree
+            })
+            .collect()
+    }
+}
+
+/// State of a predecessor search in progress.
+struct Searcher<'a, D: Dim> {
+    /// Transition function of the rule.
+    transition_function: TransitionFunction<'a, D>,
+    /// Number of cell states in the rule.
+    state_count: usize,
+    /// Cells of the predecessor assigned so far, covering every cell that can
+    /// affect the target rectangle.
+    cells: NdArray<u8, D>,
+    /// Positions in `cells` of cells that may be nonzero, in the order they
+    /// are assigned.
+    variables: Vec<UVec<D>>,
+    /// Target cells (position in `cells` and required next state) to check
+    /// after assigning each variable.
+    checks: Vec<Vec<(UVec<D>, u8)>>,
+    /// Maximum number of predecessors to find.
+    max_results: usize,
+    /// Predecessors found so far.
+    results: Vec<NdArray<u8, D>>,
+}
+impl<D: Dim> Searcher<'_, D> {
+    /// Returns whether each cell will have the required state in the next
+    /// generation.
+    fn check(&mut self, checks: &[(UVec<D>, u8)]) -> bool {
+        let cells = &self.cells;
+        let transition_function = &mut self.transition_function;
+        checks.iter().all(|(pos, expected)| {
+            let next = transition_function(cells, URect::single_cell(pos.clone()));
+            next.as_flat_slice()[0] == *expected
+        })
+    }
+
+    /// Tries every state for the variable with index `i` and every one after
+    /// it, returning `true` once enough results have been found.
+    fn search(&mut self, i: usize) -> bool {
+        if i == self.variables.len() {
+            self.results.push(self.cells.clone());
+            return self.results.len() >= self.max_results;
+        }
+        let pos = self.variables[i].clone();
+        let checks = std::mem::take(&mut self.checks[i]);
+        let mut done = false;
+        for state in 0..self.state_count {
+            self.cells[pos.clone()] = state as u8;
+            if self.check(&checks) && self.search(i + 1) {
+                done = true;
+                break;
+            }
+        }
+        self.cells[pos] = 0;
+        self.checks[i] = checks;
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+    use crate::dim::{Dim1D, Dim2D, Dim3D};
+    use crate::ndrect::NdRect;
+    use crate::ndvec::NdVec;
+    use crate::num::One;
+    use crate::sim::hashlife;
+    use crate::sim::rule::{transition_cell_array, LIFE};
+
+    /// Rule where each cell becomes the parity of the number of live cells in
+    /// its Moore neighborhood, including itself.
+    #[derive(Debug)]
+    struct Parity;
+    impl fmt::Display for Parity {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "Parity")
+        }
+    }
+    impl<D: Dim> NdRule<D> for Parity {
+        fn radius(&self) -> usize {
+            1
+        }
+        fn transition_function<'a>(&'a self) -> TransitionFunction<'a, D> {
+            Box::new(|nbhd, rect| {
+                transition_cell_array(rect, |pos| {
+                    let count = URect::span(pos.clone() - 1, pos + 1)
+                        .iter()
+                        .filter(|neighbor| nbhd[neighbor.clone()] != 0)
+                        .count();
+                    (count % 2) as u8
+                })
+            })
+        }
+        fn max_state(&self) -> u8 {
+            1
+        }
+    }
+
+    /// Asserts that each predecessor becomes the target after one generation.
+    fn assert_predecessors<D: Dim>(search: &PredecessorSearch<'_, D>, predecessors: &[NdTree<D>]) {
+        let target_region = search.target.get_region(search.target_rect.clone().into());
+        for predecessor in predecessors {
+            let mut next = predecessor.clone();
+            hashlife::step(&mut next, search.rule, &BigInt::one());
+            let next_region = next.get_region(search.target_rect.clone().into());
+            assert!(target_region.diff(&next_region).is_empty());
+            if let Some(rect) = predecessor.bounding_rect() {
+                assert!(search.parent_rect.contains(&rect));
+            }
+        }
+    }
+
+    #[test]
+    fn test_predecessor_search_1d() {
+        let mut target = NdTree::<Dim1D>::new();
+        target.set_cell(&NdVec::big([0]), 1);
+        let search = PredecessorSearch::new(&Parity, &target, NdRect::single_cell(NdVec::big([0])));
+        // Each of the 4 patterns with an odd number of live cells among the 3
+        // that can affect the target.
+        let predecessors = search.find(usize::MAX);
+        assert_eq!(4, predecessors.len());
+        assert_predecessors(&search, &predecessors);
+    }
+
+    #[test]
+    fn test_predecessor_search_2d() {
+        let mut block = NdTree::<Dim2D>::new();
+        for &pos in &[[0, 0], [1, 0], [0, 1], [1, 1]] {
+            block.set_cell(&NdVec::big(pos), 1);
+        }
+        let rect = NdRect::span(NdVec::big([0, 0]), NdVec::big([1, 1]));
+        let search = PredecessorSearch::new(&LIFE, &block, rect.clone());
+        let predecessors = search.find(8);
+        assert_eq!(8, predecessors.len());
+        assert_predecessors(&search, &predecessors);
+        assert!(!search.is_garden_of_eden());
+
+        // Three cells are not enough to make a block with nothing around it.
+        let rect = NdRect::span(NdVec::big([-1, -1]), NdVec::big([2, 2]));
+        let search = PredecessorSearch::new(&LIFE, &block, rect)
+            .with_parent_rect(NdRect::span(NdVec::big([0, 0]), NdVec::big([2, 0])));
+        assert!(search.is_garden_of_eden());
+    }
+
+    #[test]
+    fn test_predecessor_search_3d() {
+        let mut target = NdTree::<Dim3D>::new();
+        target.set_cell(&NdVec::big([0, 0, 0]), 1);
+        let rect = NdRect::span(NdVec::big([0, 0, 0]), NdVec::big([1, 0, 0]));
+        let search = PredecessorSearch::new(&Parity, &target, rect);
+        let predecessor = search.find_one().unwrap();
+        assert_predecessors(&search, &[predecessor]);
+    }
+}
@@ -0,0 +1,287 @@
+//! Time series of population, bounding rectangle, and memory usage.
+
+use std::io::{self, Write};
+
+use super::Simulate;
+use crate::automaton::NdAutomaton;
+use crate::dim::Dim;
+use crate::ndrect::BigRect;
+use crate::ndvec::BigVec;
+use crate::num::{BigInt, BigUint, Integer, Signed};
+
+/// Measurements of an automaton at a single generation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample<D: Dim> {
+    /// Generation count.
+    pub generation: BigInt,
+    /// Number of nonzero cells.
+    pub population: BigUint,
+    /// Number of cells in each state, as returned by
+    /// `NdTree::population_by_state()`.
+    pub population_by_state: Vec<BigUint>,
+    /// Smallest rectangle containing all nonzero cells, or `None` if there are
+    /// none.
+    pub bounding_rect: Option<BigRect<D>>,
+    /// Estimated memory usage of the node pool, in bytes.
+    pub memory_usage: usize,
+}
+impl<D: Dim> Sample<D> {
+    /// Measures an automaton at its current generation.
+    pub fn of(automaton: &NdAutomaton<D>) -> Self {
+        let population_by_state = automaton.ndtree.population_by_state();
+        Self {
+            generation: automaton.generations.clone(),
+            population: population_by_state.iter().sum(),
+            population_by_state,
+            bounding_rect: automaton.ndtree.bounding_rect(),
+            memory_usage: automaton.memory_usage(),
+        }
+    }
+}
+
+/// Recorder that samples an automaton at regular generation intervals while
+/// stepping it, such as to plot the population of a methuselah over time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recorder<D: Dim> {
+    /// Number of generations between samples.
+    interval: BigInt,
+    /// Samples, in the order they were recorded.
+    samples: Vec<Sample<D>>,
+}
+impl<D: Dim> Recorder<D> {
+    /// Creates a recorder that samples every `interval` generations.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `interval` is not positive.
+    pub fn new(interval: BigInt) -> Self {
+        assert!(interval.is_positive(), "Sample interval must be positive");
+        Self {
+            interval,
+            samples: vec![],
+        }
+    }
+
+    /// Returns the number of generations between samples.
+    pub fn interval(&self) -> &BigInt {
+        &self.interval
+    }
+    /// Returns the samples recorded so far, in the order they were recorded.
+    pub fn samples(&self) -> &[Sample<D>] {
+        &self.samples
+    }
+    /// Removes all samples.
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Samples the automaton at its current generation.
+    pub fn record(&mut self, automaton: &NdAutomaton<D>) {
+        self.samples.push(Sample::of(automaton));
+    }
+    /// Steps the automaton forward by `gens` generations, sampling it at every
+    /// generation that is a multiple of the interval.
+    ///
+    /// If no samples have been recorded yet, the automaton is also sampled
+    /// before stepping.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `gens` is negative.
+    pub fn step(&mut self, automaton: &mut NdAutomaton<D>, gens: &BigInt) {
+        assert!(!gens.is_negative(), "Cannot simulate negative timestep");
+        if self.samples.is_empty() {
+            self.record(automaton);
+        }
+        let end = &automaton.generations + gens;
+        while automaton.generations < end {
+            // Step to the next multiple of the interval, or to the end.
+            let next_sample =
+                (automaton.generations.div_floor(&self.interval) + 1) * &self.interval;
+            let target = std::cmp::min(next_sample, end.clone());
+            let step_size = &target - &automaton.generations;
+            automaton.step(&step_size);
+            if automaton.generations.is_multiple_of(&self.interval) {
+                self.record(automaton);
+            }
+        }
+    }
+
+    /// Returns the number of states included in the output of `write_csv()`,
+    /// which is one more than the highest state present in any sample.
+    fn state_count(&self) -> usize {
+        self.samples
+            .iter()
+            .map(|s| s.population_by_state.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Writes the samples as CSV with a header row.
+    ///
+    /// Columns are `generation`, `population`, the population of each nonzero
+    /// state (`state_1`, `state_2`, etc.), the bounds of the bounding
+    /// rectangle along each axis (`min_x`, `max_x`, etc.; empty if there are
+    /// no nonzero cells), and `memory_usage`.
+    pub fn write_csv(&self, mut w: impl Write) -> io::Result<()> {
+        let state_count = self.state_count();
+
+        write!(w, "generation,population")?;
+        for state in 1..state_count {
+            write!(w, ",state_{}", state)?;
+        }
+        for &ax in D::axes() {
+            let name = ax.name().to_ascii_lowercase();
+            write!(w, ",min_{},max_{}", name, name)?;
+        }
+        writeln!(w, ",memory_usage")?;
+
+        for sample in &self.samples {
+            write!(w, "{},{}", sample.generation, sample.population)?;
+            for state in 1..state_count {
+                match sample.population_by_state.get(state) {
+                    Some(count) => write!(w, ",{}", count)?,
+                    None => write!(w, ",0")?,
+                }
+            }
+            for &ax in D::axes() {
+                match &sample.bounding_rect {
+                    Some(rect) => write!(w, ",{},{}", rect.min()[ax], rect.max()[ax])?,
+                    None => write!(w, ",,")?,
+                }
+            }
+            writeln!(w, ",{}", sample.memory_usage)?;
+        }
+        Ok(())
+    }
+    /// Writes the samples as a JSON array of objects.
+    ///
+    /// Each object has the fields `generation`, `population`,
+    /// `population_by_state` (an array indexed by state), `bounding_rect`
+    /// (an object with `min` and `max` arrays, or `null` if there are no
+    /// nonzero cells), and `memory_usage`. Large integers are written in full,
+    /// so some JSON parsers may lose precision.
+    pub fn write_json(&self, mut w: impl Write) -> io::Result<()> {
+        write!(w, "[")?;
+        for (i, sample) in self.samples.iter().enumerate() {
+            if i != 0 {
+                write!(w, ",")?;
+            }
+            write!(
+                w,
+                r#"{{"generation":{},"population":{},"population_by_state":["#,
+                sample.generation, sample.population,
+            )?;
+            for (state, count) in sample.population_by_state.iter().enumerate() {
+                if state != 0 {
+                    write!(w, ",")?;
+                }
+                write!(w, "{}", count)?;
+            }
+            write!(w, r#"],"bounding_rect":"#)?;
+            match &sample.bounding_rect {
+                Some(rect) => {
+                    let join = |v: BigVec<D>| {
+                        D::axes()
+                            .iter()
+                            .map(|&ax| v[ax].to_string())
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    };
+                    write!(
+                        w,
+                        r#"{{"min":[{}],"max":[{}]}}"#,
+                        join(rect.min()),
+                        join(rect.max()),
+                    )?;
+                }
+                None => write!(w, "null")?,
+            }
+            write!(w, r#","memory_usage":{}}}"#, sample.memory_usage)?;
+        }
+        writeln!(w, "]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dim::Dim2D;
+    use crate::ndvec::NdVec;
+    use crate::num::Zero;
+    use crate::sim::rule::LIFE;
+
+    fn glider() -> NdAutomaton<Dim2D> {
+        let mut automaton = NdAutomaton::default();
+        automaton.set_rule(LIFE);
+        for &pos in &[[1, 0], [2, 1], [0, 2], [1, 2], [2, 2]] {
+            automaton.ndtree.set_cell(&NdVec::big(pos), 1);
+        }
+        automaton
+    }
+
+    #[test]
+    fn test_recorder() {
+        let mut automaton = glider();
+        let mut recorder = Recorder::new(4.into());
+        recorder.step(&mut automaton, &10.into());
+        recorder.step(&mut automaton, &2.into());
+        assert_eq!(BigInt::from(12), automaton.generations);
+
+        let samples = recorder.samples();
+        let gens: Vec<BigInt> = samples.iter().map(|s| s.generation.clone()).collect();
+        assert_eq!(
+            vec![0, 4, 8, 12]
+                .into_iter()
+                .map(BigInt::from)
+                .collect::<Vec<_>>(),
+            gens,
+        );
+        for (i, sample) in samples.iter().enumerate() {
+            // The glider moves one cell diagonally every 4 generations.
+            assert_eq!(BigUint::from(5_u8), sample.population);
+            assert_eq!(
+                vec![BigUint::zero(), BigUint::from(5_u8)],
+                sample.population_by_state,
+            );
+            let rect = sample.bounding_rect.as_ref().unwrap();
+            assert_eq!(NdVec::big([i as isize, i as isize]), rect.min());
+            assert_eq!(NdVec::big([i as isize + 2, i as isize + 2]), rect.max());
+        }
+
+        let mut csv = vec![];
+        recorder.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            Some("generation,population,state_1,min_x,max_x,min_y,max_y,memory_usage"),
+            lines.next(),
+        );
+        assert!(lines.next().unwrap().starts_with("0,5,5,0,2,0,2,"));
+        assert_eq!(3, lines.count());
+
+        let mut json = vec![];
+        recorder.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.starts_with(
+            r#"[{"generation":0,"population":5,"population_by_state":[0,5],"bounding_rect":{"min":[0,0],"max":[2,2]},"memory_usage":"#
+        ));
+    }
+
+    #[test]
+    fn test_recorder_empty() {
+        let mut automaton = NdAutomaton::<Dim2D>::default();
+        let mut recorder = Recorder::new(1.into());
+        recorder.step(&mut automaton, &1.into());
+        let mut csv = vec![];
+        recorder.write_csv(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(
+            csv.starts_with("generation,population,min_x,max_x,min_y,max_y,memory_usage\n0,0,,,,,")
+        );
+        let mut json = vec![];
+        recorder.write_json(&mut json).unwrap();
+        let json = String::from_utf8(json).unwrap();
+        assert!(json.contains(r#""population_by_state":[],"bounding_rect":null"#));
+    }
+}
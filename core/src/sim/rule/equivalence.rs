@@ -0,0 +1,329 @@
+//! Differential testing of two rules that should be equivalent.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
+
+use super::NdRule;
+use crate::dim::Dim;
+use crate::ndarray::NdArray;
+use crate::ndrect::{BigRect, URect};
+use crate::ndtree::{NdTree, SoupParams};
+use crate::ndvec::{BigVec, UVec};
+use crate::num::BigInt;
+use crate::sim::hashlife;
+
+/// Parameters for `compare_rules()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleComparisonParams {
+    /// Seed for the random number generator.
+    seed: u64,
+    /// Maximum number of neighborhoods to check exhaustively.
+    max_exhaustive: usize,
+    /// Number of random neighborhoods to check when there are too many to
+    /// check exhaustively.
+    random_neighborhoods: usize,
+    /// Number of random soups to simulate.
+    soup_count: usize,
+    /// Length of each soup along each axis.
+    soup_size: usize,
+    /// Number of generations to simulate each soup.
+    soup_gens: usize,
+}
+impl Default for RuleComparisonParams {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+impl RuleComparisonParams {
+    /// Returns parameters that check up to 2^16 neighborhoods exhaustively (or
+    /// else 2^12 random ones) and simulate 8 soups of size 16 for 64
+    /// generations, using the given random seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            max_exhaustive: 1 << 16,
+            random_neighborhoods: 1 << 12,
+            soup_count: 8,
+            soup_size: 16,
+            soup_gens: 64,
+        }
+    }
+
+    /// Sets the maximum number of neighborhoods to check exhaustively. If
+    /// there are more possible neighborhoods than this, random ones are
+    /// checked instead.
+    pub fn with_max_exhaustive(mut self, max_exhaustive: usize) -> Self {
+        self.max_exhaustive = max_exhaustive;
+        self
+    }
+    /// Sets the number of random neighborhoods to check when there are too
+    /// many to check exhaustively.
+    pub fn with_random_neighborhoods(mut self, random_neighborhoods: usize) -> Self {
+        self.random_neighborhoods = random_neighborhoods;
+        self
+    }
+    /// Sets the number of random soups to simulate, the length of each soup
+    /// along each axis, and the number of generations to simulate each one.
+    pub fn with_soups(mut self, count: usize, size: usize, gens: usize) -> Self {
+        self.soup_count = count;
+        self.soup_size = size;
+        self.soup_gens = gens;
+        self
+    }
+
+    /// Returns the random seed.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    /// Returns the maximum number of neighborhoods to check exhaustively.
+    pub fn max_exhaustive(&self) -> usize {
+        self.max_exhaustive
+    }
+    /// Returns the number of random neighborhoods to check when there are too
+    /// many to check exhaustively.
+    pub fn random_neighborhoods(&self) -> usize {
+        self.random_neighborhoods
+    }
+    /// Returns the number of random soups to simulate.
+    pub fn soup_count(&self) -> usize {
+        self.soup_count
+    }
+    /// Returns the length of each soup along each axis.
+    pub fn soup_size(&self) -> usize {
+        self.soup_size
+    }
+    /// Returns the number of generations to simulate each soup.
+    pub fn soup_gens(&self) -> usize {
+        self.soup_gens
+    }
+}
+
+/// Counterexample showing that two rules are not equivalent, returned by
+/// `compare_rules()`.
+#[derive(Debug, Clone)]
+pub enum RuleMismatch<D: Dim> {
+    /// The rules have different numbers of states.
+    MaxState {
+        /// Maximum state of the first rule.
+        a: u8,
+        /// Maximum state of the second rule.
+        b: u8,
+    },
+    /// The transition functions give different results for the center cell
+    /// of a neighborhood.
+    Transition {
+        /// Neighborhood, with a length of twice the larger radius plus one
+        /// along each axis.
+        neighborhood: NdArray<u8, D>,
+        /// Next state of the center cell under the first rule.
+        a: u8,
+        /// Next state of the center cell under the second rule.
+        b: u8,
+    },
+    /// A soup evolves differently under each rule.
+    Evolution {
+        /// Initial pattern.
+        soup: NdTree<D>,
+        /// Earliest generation at which the patterns differ.
+        generation: usize,
+        /// Position of a cell that differs at that generation.
+        pos: BigVec<D>,
+        /// State of the cell under the first rule.
+        a: u8,
+        /// State of the cell under the second rule.
+        b: u8,
+    },
+}
+impl<D: Dim> fmt::Display for RuleMismatch<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MaxState { a, b } => write!(f, "max states differ ({} vs. {})", a, b),
+            Self::Transition { neighborhood, a, b } => write!(
+                f,
+                "neighborhood {:?} becomes {} vs. {}",
+                neighborhood.as_flat_slice(),
+                a,
+                b,
+            ),
+            Self::Evolution {
+                generation,
+                pos,
+                a,
+                b,
+                ..
+            } => write!(
+                f,
+                "soup differs at generation {}: cell {} is {} vs. {}",
+                generation, pos, a, b,
+            ),
+        }
+    }
+}
+
+/// Checks whether two rules behave identically, returning a counterexample
+/// if they do not.
+///
+/// First the transition functions are compared on single neighborhoods,
+/// exhaustively if there are few enough of them and otherwise randomly. Then
+/// random soups are simulated under both rules and compared generation by
+/// generation. Passing this check does not prove that the rules are
+/// equivalent unless the neighborhoods were checked exhaustively.
+pub fn compare_rules<D: Dim>(
+    a: &dyn NdRule<D>,
+    b: &dyn NdRule<D>,
+    params: &RuleComparisonParams,
+) -> Result<(), RuleMismatch<D>> {
+    if a.max_state() != b.max_state() {
+        return Err(RuleMismatch::MaxState {
+            a: a.max_state(),
+            b: b.max_state(),
+        });
+    }
+    compare_transitions(a, b, params)?;
+    compare_evolution(a, b, params)
+}
+
+/// Compares the transition functions of two rules on single neighborhoods.
+fn compare_transitions<D: Dim>(
+    a: &dyn NdRule<D>,
+    b: &dyn NdRule<D>,
+    params: &RuleComparisonParams,
+) -> Result<(), RuleMismatch<D>> {
+    let radius = std::cmp::max(a.radius(), b.radius());
+    let size = UVec::repeat(2 * radius + 1);
+    let center = URect::single_cell(UVec::repeat(radius));
+    let cell_count = (2 * radius + 1).pow(D::NDIM as u32);
+    let state_count = a.max_state() as usize + 1;
+
+    let mut tf_a = a.transition_function();
+    let mut tf_b = b.transition_function();
+    let mut check = |cells: Vec<u8>| {
+        let neighborhood = NdArray::from_flat_slice(size.clone(), cells);
+        let next_a = tf_a(&neighborhood, center.clone()).as_flat_slice()[0];
+        let next_b = tf_b(&neighborhood, center.clone()).as_flat_slice()[0];
+        if next_a == next_b {
+            Ok(())
+        } else {
+            Err(RuleMismatch::Transition {
+                neighborhood,
+                a: next_a,
+                b: next_b,
+            })
+        }
+    };
+
+    let neighborhood_count = (state_count as u32)
+        .checked_pow(cell_count as u32)
+        .map(|n| n as usize)
+        .filter(|&n| n <= params.max_exhaustive);
+    match neighborhood_count {
+        Some(n) => {
+            for index in 0..n {
+                // Interpret the index as a base-`state_count` number, with one
+                // digit per cell.
+                let mut rest = index;
+                let cells = (0..cell_count)
+                    .map(|_| {
+                        let digit = rest % state_count;
+                        rest /= state_count;
+                        digit as u8
+                    })
+                    .collect();
+                check(cells)?;
+            }
+        }
+        None => {
+            let mut rng = ChaCha8Rng::seed_from_u64(params.seed);
+            for _ in 0..params.random_neighborhoods {
+                let cells = (0..cell_count)
+                    .map(|_| rng.gen_range(0..state_count) as u8)
+                    .collect();
+                check(cells)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Simulates random soups under two rules and compares the results.
+fn compare_evolution<D: Dim>(
+    a: &dyn NdRule<D>,
+    b: &dyn NdRule<D>,
+    params: &RuleComparisonParams,
+) -> Result<(), RuleMismatch<D>> {
+    let state_count = a.max_state() as usize + 1;
+    let probabilities = vec![1.0 / state_count as f64; state_count - 1];
+    let rect = BigRect::with_size(BigVec::origin(), BigVec::repeat(params.soup_size.into()));
+
+    for i in 0..params.soup_count {
+        let soup_params = SoupParams::new(params.seed.wrapping_add(i as u64))
+            .with_state_probabilities(&probabilities);
+        // Use a separate node pool for each rule, because cached HashLife
+        // results do not depend on which rule computed them.
+        let mut soup = NdTree::new();
+        soup.fill_random(rect.clone().into(), &soup_params);
+        let mut tree_a = NdTree::new();
+        tree_a.fill_random(rect.clone().into(), &soup_params);
+        let mut tree_b = NdTree::new();
+        tree_b.fill_random(rect.clone().into(), &soup_params);
+
+        for generation in 1..=params.soup_gens {
+            hashlife::step(&mut tree_a, a, &BigInt::from(1));
+            hashlife::step(&mut tree_b, b, &BigInt::from(1));
+            let diff = tree_a.diff(&tree_b);
+            if let Some((pos, state_a, state_b)) = diff.cells().into_iter().next() {
+                return Err(RuleMismatch::Evolution {
+                    soup,
+                    generation,
+                    pos,
+                    a: state_a,
+                    b: state_b,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dim::Dim2D;
+    use crate::sim::rule::{MooreTotalistic2D, LIFE};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_compare_rules() {
+        let params = RuleComparisonParams::new(1).with_soups(2, 8, 16);
+        let life2 = MooreTotalistic2D::try_from("B3/S23").unwrap();
+        assert!(compare_rules::<Dim2D>(&LIFE, &life2, &params).is_ok());
+
+        let highlife = MooreTotalistic2D::try_from("B36/S23").unwrap();
+        match compare_rules::<Dim2D>(&LIFE, &highlife, &params) {
+            Err(RuleMismatch::Transition { neighborhood, a, b }) => {
+                assert_eq!((0, 1), (a, b));
+                let cells = neighborhood.as_flat_slice();
+                assert_eq!(0, cells[4]);
+                assert_eq!(6, cells.iter().filter(|&&c| c != 0).count());
+            }
+            other => panic!("expected transition mismatch; got {:?}", other),
+        }
+
+        // With no neighborhood checks, the soups must catch the difference.
+        let params = params
+            .with_max_exhaustive(0)
+            .with_random_neighborhoods(0)
+            .with_soups(8, 16, 64);
+        match compare_rules::<Dim2D>(&LIFE, &highlife, &params) {
+            Err(RuleMismatch::Evolution {
+                soup, pos, a, b, ..
+            }) => {
+                assert!(soup.bounding_rect().is_some());
+                assert_ne!(a, b);
+                assert!(pos.max_component() < &BigInt::from(1000));
+            }
+            other => panic!("expected evolution mismatch; got {:?}", other),
+        }
+    }
+}
@@ -3,9 +3,12 @@
 
 use core::fmt;
 use itertools::Itertools;
+use std::convert::TryFrom;
 use std::sync::Arc;
 
 pub mod aliases;
+#[cfg(feature = "random")]
+mod equivalence;
 mod totalistic;
 
 use crate::dim::*;
@@ -13,6 +16,8 @@ use crate::ndarray::NdArray;
 use crate::ndrect::URect;
 use crate::ndvec::UVec;
 pub use aliases::*;
+#[cfg(feature = "random")]
+pub use equivalence::{compare_rules, RuleComparisonParams, RuleMismatch};
 pub use totalistic::*;
 
 /// Type alias for a CA transition function that transitions all the cells in an
@@ -55,6 +60,21 @@ impl<D: Dim> From<Arc<dyn NdRule<D>>> for Rule {
         })
     }
 }
+impl<D: Dim> TryFrom<Rule> for Arc<dyn NdRule<D>> {
+    type Error = Rule;
+
+    fn try_from(rule: Rule) -> Result<Self, Self::Error> {
+        match rule {
+            Rule::Rule1D(r) if D::NDIM == 1 => Ok(Rule::transmute(r)),
+            Rule::Rule2D(r) if D::NDIM == 2 => Ok(Rule::transmute(r)),
+            Rule::Rule3D(r) if D::NDIM == 3 => Ok(Rule::transmute(r)),
+            Rule::Rule4D(r) if D::NDIM == 4 => Ok(Rule::transmute(r)),
+            Rule::Rule5D(r) if D::NDIM == 5 => Ok(Rule::transmute(r)),
+            Rule::Rule6D(r) if D::NDIM == 6 => Ok(Rule::transmute(r)),
+            _ => Err(rule),
+        }
+    }
+}
 impl Rule {
     /// Converts an `Arc<dyn NdRule>` between arbitrary dimensionalities, as
     /// long as those dimensionalities are the same. This function is only
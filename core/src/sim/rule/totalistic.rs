@@ -1,13 +1,18 @@
 use regex::Regex;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fmt;
 
 use super::{NdRule, TransitionFunction};
 use crate::dim::Dim2D;
 use crate::ndrect::Rect2D;
+use crate::ndtree::NdTree2D;
+use crate::ndvec::{BigVec2D, NdVec};
+use crate::num::{BigInt, One};
+use crate::sim::hashlife;
 
 /// A 2-state totalistic 2D range-1 Moore-neighborhood algorithm.
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct MooreTotalistic2D {
     birth: [u8; 9],
     survival: [u8; 9],
@@ -22,17 +27,17 @@ impl TryFrom<&str> for MooreTotalistic2D {
     type Error = ();
 
     fn try_from(s: &str) -> Result<Self, ()> {
-        let regex = Regex::new(r"^[Bb](\d*)/?[Ss](\d*)$").unwrap();
+        let regex = Regex::new(r"^[Bb]([0-8]*)/?[Ss]([0-8]*)$").unwrap();
         let captures = regex.captures(s).ok_or(())?;
         let mut conditions = [[0; 9]; 2];
         for i in 0..2 {
-            for ch in captures[i].chars() {
-                conditions[i][ch.to_string().parse::<usize>().unwrap()] = 1;
+            for ch in captures[i + 1].chars() {
+                conditions[i][ch.to_digit(10).unwrap() as usize] = 1;
             }
         }
         Ok(Self {
             birth: conditions[0],
-            survival: conditions[0],
+            survival: conditions[1],
         })
     }
 }
@@ -82,9 +87,146 @@ impl NdRule<Dim2D> for MooreTotalistic2D {
     }
 }
 
+impl MooreTotalistic2D {
+    /// Returns the range of rules in which a pattern evolves exactly as it
+    /// does in this rule for `gens` generations.
+    ///
+    /// Every transition (a live or dead cell with some number of live
+    /// neighbors) that occurs during those generations must be the same in the
+    /// other rule; all other transitions may be anything. A dead cell with no
+    /// live neighbors always counts as occurring, because there are infinitely
+    /// many of them.
+    ///
+    /// Only outer-totalistic rules (B/S notation) are supported, so the range
+    /// is in terms of neighbor counts. Isotropic non-totalistic rules, which
+    /// distinguish arrangements of neighbors with the same count, are not.
+    pub fn rule_range(&self, ndtree: &NdTree2D, gens: usize) -> MooreTotalistic2DRange {
+        // Transitions that occur, indexed by center cell state and then by
+        // number of live neighbors.
+        let mut occurring = [[false; 9]; 2];
+        occurring[0][0] = true;
+
+        let mut ndtree = ndtree.clone();
+        for _ in 0..gens {
+            let live: HashSet<BigVec2D> = ndtree.nonzero_cells().map(|(pos, _)| pos).collect();
+            if live.is_empty() {
+                break;
+            }
+            // Count the live neighbors of every cell that has any.
+            let mut live_neighbors: HashMap<BigVec2D, usize> = HashMap::new();
+            for pos in &live {
+                for &offset in &NEIGHBOR_OFFSETS {
+                    *live_neighbors.entry(pos + &NdVec::big(offset)).or_insert(0) += 1;
+                }
+            }
+            // Live cells with no live neighbors are not in the map.
+            for pos in &live {
+                occurring[1][live_neighbors.get(pos).copied().unwrap_or(0)] = true;
+            }
+            for (pos, &n) in &live_neighbors {
+                occurring[live.contains(pos) as usize][n] = true;
+            }
+            hashlife::step(&mut ndtree, self, &BigInt::one());
+        }
+
+        let mut min = *self;
+        let mut max = *self;
+        let [dead_occurring, live_occurring] = occurring;
+        for (i, (&dead, &live)) in dead_occurring.iter().zip(&live_occurring).enumerate() {
+            if !dead {
+                min.birth[i] = 0;
+                max.birth[i] = 1;
+            }
+            if !live {
+                min.survival[i] = 0;
+                max.survival[i] = 1;
+            }
+        }
+        MooreTotalistic2DRange { min, max }
+    }
+}
+
+/// Offsets of the neighbors in a range-1 Moore neighborhood.
+const NEIGHBOR_OFFSETS: [[isize; 2]; 8] = [
+    [-1, -1],
+    [0, -1],
+    [1, -1],
+    [-1, 0],
+    [1, 0],
+    [-1, 1],
+    [0, 1],
+    [1, 1],
+];
+
+/// Range of `MooreTotalistic2D` rules, from a minimum rule to a maximum rule
+/// with at least the same birth and survival conditions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MooreTotalistic2DRange {
+    /// Rule with only the required birth and survival conditions.
+    pub min: MooreTotalistic2D,
+    /// Rule with every birth and survival condition that is not forbidden.
+    pub max: MooreTotalistic2D,
+}
+impl fmt::Display for MooreTotalistic2DRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} - {}", self.min, self.max)
+    }
+}
+impl MooreTotalistic2DRange {
+    /// Returns whether `rule` is within the range.
+    pub fn contains(&self, rule: &MooreTotalistic2D) -> bool {
+        (0..=8).all(|i| {
+            self.min.birth[i] <= rule.birth[i]
+                && rule.birth[i] <= self.max.birth[i]
+                && self.min.survival[i] <= rule.survival[i]
+                && rule.survival[i] <= self.max.survival[i]
+        })
+    }
+}
+
 /// Conway's Game of Life, simulated using a general 2-state totalistic
 /// 2D range-1 Moore-neighborhood algorithm.
 pub const LIFE: MooreTotalistic2D = MooreTotalistic2D {
     birth: [0, 0, 0, 1, 0, 0, 0, 0, 0],
     survival: [0, 0, 1, 1, 0, 0, 0, 0, 0],
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::rule::NdRule;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_moore_totalistic_2d_rule_range() {
+        // Blinker
+        let mut ndtree = NdTree2D::new();
+        for &pos in &[[0, 1], [1, 1], [2, 1]] {
+            ndtree.set_cell(&NdVec::big(pos), 1);
+        }
+        let range = LIFE.rule_range(&ndtree, 2);
+        assert_eq!("B3/S2 - B345678/S02345678", range.to_string());
+        assert!(range.contains(&LIFE));
+        assert!(range.contains(&MooreTotalistic2D::try_from("B36/S23").unwrap()));
+        assert!(!range.contains(&MooreTotalistic2D::try_from("B2/S23").unwrap()));
+
+        // The blinker behaves the same in every rule in the range.
+        for rule in &[range.min, range.max] {
+            let mut a = ndtree.clone();
+            let mut b = NdTree2D::new();
+            for &pos in &[[0, 1], [1, 1], [2, 1]] {
+                b.set_cell(&NdVec::big(pos), 1);
+            }
+            hashlife::step(&mut a, &LIFE, &BigInt::from(7));
+            hashlife::step(&mut b, rule, &BigInt::from(7));
+            assert!(a.diff(&b).is_empty(), "blinker differs in {}", rule);
+            assert_eq!(1, NdRule::<Dim2D>::max_state(rule));
+        }
+
+        // A lone cell dies with no live neighbors.
+        let mut ndtree = NdTree2D::new();
+        ndtree.set_cell(&NdVec::big([0, 0]), 1);
+        let range = LIFE.rule_range(&ndtree, 3);
+        assert_eq!("B/S - B2345678/S12345678", range.to_string());
+    }
+}
@@ -0,0 +1,366 @@
+//! Running an automaton until a condition holds.
+
+use std::collections::HashMap;
+
+use super::Simulate;
+use crate::automaton::NdAutomaton;
+use crate::dim::Dim;
+use crate::ndtree::{ArcNode, NdTree, NodeRefTrait, Region};
+use crate::num::{BigInt, BigUint, One, Signed};
+
+/// Condition that stops `run_until()`.
+#[derive(Debug, Clone)]
+pub enum StopCondition<D: Dim> {
+    /// There are no nonzero cells.
+    Extinct,
+    /// There are at least this many nonzero cells.
+    PopulationAtLeast(BigUint),
+    /// There are at most this many nonzero cells.
+    PopulationAtMost(BigUint),
+    /// The bounding rectangle of the nonzero cells is longer than this along
+    /// any axis.
+    BoundsExceed(BigInt),
+    /// The pattern recurs, possibly translated, within `max_period`
+    /// generations.
+    ///
+    /// Checking this condition simulates up to `max_period` generations one
+    /// at a time, so it is slow for large periods.
+    Periodic {
+        /// Largest period to check.
+        max_period: usize,
+    },
+    /// Some cell in the region differs from the automaton's state when
+    /// `run_until()` was called.
+    RegionChanged(Region<D>),
+    /// Any of the conditions holds.
+    Any(Vec<StopCondition<D>>),
+}
+impl<D: Dim> StopCondition<D> {
+    /// Returns whether the condition holds for `automaton`, where `initial` is
+    /// the state of the automaton when the run started.
+    pub fn holds(&self, automaton: &NdAutomaton<D>, initial: &NdAutomaton<D>) -> bool {
+        Checker::new(initial).holds(self, automaton)
+    }
+
+    /// Returns whether the condition keeps holding in every later generation
+    /// once it holds.
+    ///
+    /// This is true for `Extinct` and `Periodic`, and for `Any` if it is true
+    /// for all of its conditions. The other conditions may hold only briefly,
+    /// such as a population peak or a glider passing through a region.
+    pub fn is_monotone(&self) -> bool {
+        match self {
+            Self::Extinct | Self::Periodic { .. } => true,
+            Self::PopulationAtLeast(_)
+            | Self::PopulationAtMost(_)
+            | Self::BoundsExceed(_)
+            | Self::RegionChanged(_) => false,
+            Self::Any(conditions) => conditions.iter().all(|c| c.is_monotone()),
+        }
+    }
+}
+
+/// Evaluates stop conditions, caching the states and canonical roots computed
+/// for `StopCondition::Periodic` so that nearby probes can share them.
+struct Checker<'a, D: Dim> {
+    initial: &'a NdAutomaton<D>,
+    /// States and their canonical roots, indexed by generation count.
+    states: HashMap<BigInt, (NdAutomaton<D>, Option<ArcNode<D>>)>,
+}
+impl<'a, D: Dim> Checker<'a, D> {
+    fn new(initial: &'a NdAutomaton<D>) -> Self {
+        Self {
+            initial,
+            states: HashMap::new(),
+        }
+    }
+
+    fn holds(&mut self, condition: &StopCondition<D>, automaton: &NdAutomaton<D>) -> bool {
+        let ndtree = &automaton.ndtree;
+        match condition {
+            StopCondition::Extinct => ndtree.root_ref().is_empty(),
+            StopCondition::PopulationAtLeast(n) => ndtree.root_ref().population() >= *n,
+            StopCondition::PopulationAtMost(n) => ndtree.root_ref().population() <= *n,
+            StopCondition::BoundsExceed(n) => match ndtree.bounding_rect() {
+                Some(rect) => rect.size().max_component() > n,
+                None => false,
+            },
+            StopCondition::Periodic { max_period } => {
+                let key = self.canonical_root(automaton);
+                let mut next = automaton.clone();
+                for _ in 0..*max_period {
+                    next = self.next_state(&next);
+                    if self.canonical_root(&next) == key {
+                        return true;
+                    }
+                }
+                false
+            }
+            StopCondition::RegionChanged(region) => {
+                let old = self.initial.ndtree.get_region(region.clone());
+                let new = ndtree.get_region(region.clone());
+                !old.diff(&new).is_empty()
+            }
+            StopCondition::Any(conditions) => conditions.iter().any(|c| self.holds(c, automaton)),
+        }
+    }
+
+    /// Returns the canonical root of `automaton`, computing and caching it if
+    /// necessary.
+    fn canonical_root(&mut self, automaton: &NdAutomaton<D>) -> Option<ArcNode<D>> {
+        self.states
+            .entry(automaton.generations.clone())
+            .or_insert_with(|| (automaton.clone(), canonical_root(&automaton.ndtree)))
+            .1
+            .clone()
+    }
+
+    /// Returns the state one generation after `automaton`, using the cache if
+    /// possible.
+    fn next_state(&mut self, automaton: &NdAutomaton<D>) -> NdAutomaton<D> {
+        let generation = &automaton.generations + 1;
+        if let Some((next, _)) = self.states.get(&generation) {
+            return next.clone();
+        }
+        let mut next = automaton.clone();
+        next.step(&BigInt::one());
+        next
+    }
+
+    /// Discards cached states before `generation`, which no later probe will
+    /// need.
+    fn forget_before(&mut self, generation: &BigInt) {
+        self.states.retain(|g, _| g >= generation);
+    }
+}
+
+/// Returns the root node of the ND-tree after translating the pattern so that
+/// its bounding rectangle starts at the center and shrinking the tree as much
+/// as possible, or `None` if the pattern is empty. Two patterns return the
+/// same node if and only if they are translations of each other.
+pub(super) fn canonical_root<D: Dim>(ndtree: &NdTree<D>) -> Option<ArcNode<D>> {
+    let rect = ndtree.bounding_rect()?;
+    let mut ndtree = ndtree.clone();
+    ndtree.recenter(&rect.min());
+    ndtree.shrink();
+    Some(ndtree.root().clone())
+}
+
+/// Steps the automaton until `condition` holds or `max_gens` generations
+/// have passed, whichever comes first.
+///
+/// If the condition is monotone (see `StopCondition::is_monotone()`), step
+/// sizes start at 1 and double after each step up to `max_step`, so HashLife
+/// can still make large jumps. Once the condition holds, the last interval is
+/// bisected to find the earliest generation at which it held. Otherwise a
+/// jump could skip over the generations at which the condition holds, so the
+/// automaton is stepped one generation at a time and `max_step` is ignored.
+/// If the condition holds at the start, the automaton is not stepped at all.
+///
+/// Returns the generation count at which the condition held, leaving the
+/// automaton at that generation, or `None` if it did not hold within
+/// `max_gens` generations, leaving the automaton `max_gens` generations
+/// ahead.
+///
+/// # Panics
+///
+/// This function panics if `max_gens` is negative or `max_step` is not
+/// positive.
+pub fn run_until<D: Dim>(
+    automaton: &mut NdAutomaton<D>,
+    condition: &StopCondition<D>,
+    max_gens: &BigInt,
+    max_step: &BigInt,
+) -> Option<BigInt> {
+    assert!(!max_gens.is_negative(), "Cannot simulate negative timestep");
+    assert!(max_step.is_positive(), "Maximum step size must be positive");
+
+    let initial = automaton.clone();
+    let mut checker = Checker::new(&initial);
+    if checker.holds(condition, automaton) {
+        return Some(automaton.generations.clone());
+    }
+
+    let max_step = if condition.is_monotone() {
+        max_step.clone()
+    } else {
+        BigInt::one()
+    };
+    let end = &automaton.generations + max_gens;
+    let mut step_size = BigInt::one();
+    while automaton.generations < end {
+        let this_step = std::cmp::min(&step_size, &max_step)
+            .min(&(&end - &automaton.generations))
+            .clone();
+        let before = automaton.clone();
+        automaton.step(&this_step);
+
+        if checker.holds(condition, automaton) {
+            // The condition did not hold at `before` but does hold at
+            // `automaton`; bisect to find the earliest generation between them
+            // at which it holds.
+            let mut lo = before;
+            let mut span = this_step;
+            while span > BigInt::one() {
+                let half = &span / 2;
+                let mut mid = lo.clone();
+                mid.step(&half);
+                if checker.holds(condition, &mid) {
+                    *automaton = mid;
+                    span = half;
+                } else {
+                    checker.forget_before(&mid.generations);
+                    lo = mid;
+                    span -= half;
+                }
+            }
+            return Some(automaton.generations.clone());
+        }
+        checker.forget_before(&automaton.generations);
+
+        if step_size < max_step {
+            step_size *= 2;
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dim::Dim2D;
+    use crate::ndrect::NdRect;
+    use crate::ndvec::NdVec;
+    use crate::sim::rule::LIFE;
+
+    fn automaton(cells: &[[isize; 2]]) -> NdAutomaton<Dim2D> {
+        let mut automaton = NdAutomaton::default();
+        automaton.set_rule(LIFE);
+        for &pos in cells {
+            automaton.ndtree.set_cell(&NdVec::big(pos), 1);
+        }
+        automaton
+    }
+
+    const GLIDER: &[[isize; 2]] = &[[1, 0], [2, 1], [0, 2], [1, 2], [2, 2]];
+    const PI_HEPTOMINO: &[[isize; 2]] = &[[0, 0], [1, 0], [2, 0], [0, 1], [2, 1], [0, 2], [2, 2]];
+
+    /// Returns the earliest generation at which the condition holds, found by
+    /// stepping one generation at a time.
+    fn brute_force(
+        mut automaton: NdAutomaton<Dim2D>,
+        condition: &StopCondition<Dim2D>,
+        max_gens: usize,
+    ) -> Option<BigInt> {
+        let initial = automaton.clone();
+        for _ in 0..=max_gens {
+            if condition.holds(&automaton, &initial) {
+                return Some(automaton.generations);
+            }
+            automaton.step(&BigInt::one());
+        }
+        None
+    }
+
+    fn assert_run_until(
+        cells: &[[isize; 2]],
+        condition: StopCondition<Dim2D>,
+        max_gens: usize,
+        max_step: usize,
+        expected: Option<isize>,
+    ) {
+        let expected = expected.map(BigInt::from);
+        assert_eq!(
+            expected,
+            brute_force(automaton(cells), &condition, max_gens)
+        );
+
+        let mut a = automaton(cells);
+        let result = run_until(&mut a, &condition, &max_gens.into(), &max_step.into());
+        assert_eq!(expected, result);
+        match expected {
+            Some(gens) => assert_eq!(gens, a.generations),
+            None => assert_eq!(BigInt::from(max_gens), a.generations),
+        }
+    }
+
+    #[test]
+    fn test_run_until_extinct() {
+        assert_run_until(&[[0, 0], [1, 0]], StopCondition::Extinct, 10, 8, Some(1));
+        assert_run_until(GLIDER, StopCondition::Extinct, 100, 64, None);
+    }
+
+    #[test]
+    fn test_run_until_region_changed() {
+        // The glider moves one cell diagonally every 4 generations.
+        let region = Region::from(NdRect::span(NdVec::big([10, 10]), NdVec::big([20, 20])));
+        let condition = StopCondition::RegionChanged(region);
+        assert_run_until(GLIDER, condition.clone(), 100, 1024, Some(32));
+        assert_run_until(GLIDER, condition.clone(), 100, 3, Some(32));
+        assert_run_until(GLIDER, condition, 20, 1024, None);
+
+        // The glider passes through this region without staying in it, so a
+        // large jump would skip over it.
+        let region = Region::from(NdRect::span(NdVec::big([40, 40]), NdVec::big([41, 41])));
+        let condition = StopCondition::RegionChanged(region);
+        assert!(!condition.is_monotone());
+        assert_run_until(GLIDER, condition, 300, 1024, Some(152));
+    }
+
+    #[test]
+    fn test_run_until_bounds_and_population() {
+        let condition = StopCondition::Any(vec![
+            StopCondition::BoundsExceed(8.into()),
+            StopCondition::PopulationAtLeast(20_u8.into()),
+        ]);
+        let expected = brute_force(automaton(PI_HEPTOMINO), &condition, 100);
+        assert!(expected.is_some());
+        for &max_step in &[1, 256] {
+            let mut a = automaton(PI_HEPTOMINO);
+            assert_eq!(
+                expected,
+                run_until(&mut a, &condition, &100.into(), &max_step.into())
+            );
+        }
+
+        // The pi-heptomino's population peaks and then falls.
+        let condition = StopCondition::PopulationAtLeast(40_u8.into());
+        let expected = brute_force(automaton(PI_HEPTOMINO), &condition, 200);
+        assert!(expected.is_some());
+        let mut a = automaton(PI_HEPTOMINO);
+        assert_eq!(
+            expected,
+            run_until(&mut a, &condition, &200.into(), &256.into())
+        );
+    }
+
+    #[test]
+    fn test_stop_condition_is_monotone() {
+        let periodic = StopCondition::<Dim2D>::Periodic { max_period: 2 };
+        assert!(StopCondition::<Dim2D>::Extinct.is_monotone());
+        assert!(periodic.is_monotone());
+        assert!(StopCondition::Any(vec![StopCondition::Extinct, periodic.clone()]).is_monotone());
+        assert!(
+            !StopCondition::Any(vec![periodic, StopCondition::PopulationAtMost(3_u8.into()),])
+                .is_monotone()
+        );
+    }
+
+    #[test]
+    fn test_run_until_periodic() {
+        let periodic = |max_period| StopCondition::Periodic { max_period };
+        // A glider is periodic up to translation with period 4.
+        assert_run_until(GLIDER, periodic(4), 0, 1, Some(0));
+        assert_run_until(GLIDER, periodic(3), 10, 1, None);
+        // An L-tromino becomes a block after one generation.
+        assert_run_until(&[[0, 0], [1, 0], [0, 1]], periodic(1), 10, 8, Some(1));
+        // The pi-heptomino settles into still lifes and blinkers.
+        let expected = brute_force(automaton(PI_HEPTOMINO), &periodic(2), 200);
+        assert!(expected.is_some());
+        let mut a = automaton(PI_HEPTOMINO);
+        assert_eq!(
+            expected,
+            run_until(&mut a, &periodic(2), &200.into(), &256.into())
+        );
+    }
+}
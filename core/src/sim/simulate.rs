@@ -1,3 +1,4 @@
+use crate::ndtree::NodePoolStats;
 use crate::num::{BigInt, BigUint};
 
 /// Dimension-independent automaton simulation methods.
@@ -12,9 +13,15 @@ pub trait Simulate {
     fn set_generation_count(&mut self, generations: BigInt);
     /// Steps forward in the simulation by the given number of generations.
     fn step(&mut self, gens: &BigInt);
+    /// Steps forward in the simulation by the given number of generations,
+    /// collecting garbage mid-step whenever memory usage exceeds `max_memory`
+    /// bytes. Returns the number of garbage collections performed.
+    fn step_with_memory_limit(&mut self, gens: &BigInt, max_memory: usize) -> usize;
 
     /// Returns memory usage.
     fn memory_usage(&self) -> usize;
+    /// Returns statistics about the node pool used by the simulation.
+    fn node_pool_stats(&self) -> NodePoolStats;
     /// Block if the GC thread is waiting and the `RwLock` has decided it's been
     /// waiting long enough.
     fn yield_to_gc(&self);
@@ -57,10 +64,16 @@ where
     fn step(&mut self, gens: &BigInt) {
         self.as_sim_mut().step(gens);
     }
+    fn step_with_memory_limit(&mut self, gens: &BigInt, max_memory: usize) -> usize {
+        self.as_sim_mut().step_with_memory_limit(gens, max_memory)
+    }
 
     fn memory_usage(&self) -> usize {
         self.as_sim().memory_usage()
     }
+    fn node_pool_stats(&self) -> NodePoolStats {
+        self.as_sim().node_pool_stats()
+    }
     fn yield_to_gc(&self) {
         self.as_sim().yield_to_gc();
     }
@@ -0,0 +1,676 @@
+//! Random soup search with a census of the resulting objects, in the style of
+//! apgsearch.
+
+use itertools::Itertools;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use super::hashlife;
+use super::rule::NdRule;
+use super::run_until::canonical_root;
+use crate::dim::Dim;
+use crate::io::{CaFormatTrait, Rle, RleResult, TwoState};
+use crate::ndrect::BigRect;
+use crate::ndtree::{Connectivity, NdTree, NodeRefTrait, SoupParams, SoupSymmetry};
+use crate::ndvec::{BigVec, NdVec};
+use crate::num::{BigInt, BigUint, One};
+
+/// First line of a saved census.
+const CENSUS_HEADER: &str = "NDCell soup census v1";
+
+/// Census name for soups that do not stabilize.
+pub const PATHOLOGICAL: &str = "PATHOLOGICAL";
+/// Census name for objects that are not periodic.
+pub const UNSTABLE: &str = "zz_UNSTABLE";
+
+/// Parameters for a `SoupSearch`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoupSearchParams {
+    /// Length of each soup along each axis.
+    soup_size: usize,
+    /// Probability that each cell of a soup is nonzero.
+    density: f64,
+    /// Symmetry of each soup.
+    symmetry: SoupSymmetry,
+    /// Largest period to detect.
+    max_period: usize,
+    /// Number of generations after which a soup that has not stabilized is
+    /// considered pathological.
+    max_gens: usize,
+    /// Number of generations between checks for stabilization.
+    check_interval: usize,
+    /// Seed of the first soup.
+    seed_base: u64,
+}
+impl Default for SoupSearchParams {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl fmt::Display for SoupSearchParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "size {}, density {}, symmetry {:?}, max period {}, max gens {}, check interval {}",
+            self.soup_size,
+            self.density,
+            self.symmetry,
+            self.max_period,
+            self.max_gens,
+            self.check_interval,
+        )
+    }
+}
+impl SoupSearchParams {
+    /// Returns parameters for asymmetric soups of size 16 with 50% density,
+    /// detecting periods up to 30 and giving up after 20000 generations.
+    pub fn new() -> Self {
+        Self {
+            soup_size: 16,
+            density: 0.5,
+            symmetry: SoupSymmetry::Asymmetric,
+            max_period: 30,
+            max_gens: 20_000,
+            check_interval: 100,
+            seed_base: 0,
+        }
+    }
+
+    /// Sets the length of each soup along each axis.
+    pub fn with_soup_size(mut self, soup_size: usize) -> Self {
+        self.soup_size = soup_size;
+        self
+    }
+    /// Sets the probability that each cell of a soup is nonzero. Nonzero
+    /// states are equally likely.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `density` is not between 0 and 1 (inclusive).
+    pub fn with_density(mut self, density: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&density),
+            "Soup density must be between 0 and 1",
+        );
+        self.density = density;
+        self
+    }
+    /// Sets the symmetry of each soup.
+    pub fn with_symmetry(mut self, symmetry: SoupSymmetry) -> Self {
+        self.symmetry = symmetry;
+        self
+    }
+    /// Sets the largest period to detect, both when checking whether a soup
+    /// has stabilized and when classifying objects.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `max_period` is zero.
+    pub fn with_max_period(mut self, max_period: usize) -> Self {
+        assert!(max_period > 0, "Maximum period must be positive");
+        self.max_period = max_period;
+        self
+    }
+    /// Sets the number of generations after which a soup that has not
+    /// stabilized is considered pathological, and the number of generations
+    /// between checks for stabilization.
+    ///
+    /// # Panics
+    ///
+    /// This method panics if `check_interval` is zero.
+    pub fn with_max_gens(mut self, max_gens: usize, check_interval: usize) -> Self {
+        assert!(check_interval > 0, "Check interval must be positive");
+        self.max_gens = max_gens;
+        self.check_interval = check_interval;
+        self
+    }
+    /// Sets the seed of the first soup. Searches running on different
+    /// machines should use seed bases far enough apart (such as a different
+    /// multiple of 2^32 for each machine) that they never search the same
+    /// soups, so that their censuses can be merged.
+    pub fn with_seed_base(mut self, seed_base: u64) -> Self {
+        self.seed_base = seed_base;
+        self
+    }
+
+    /// Returns the length of each soup along each axis.
+    pub fn soup_size(&self) -> usize {
+        self.soup_size
+    }
+    /// Returns the probability that each cell of a soup is nonzero.
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+    /// Returns the symmetry of each soup.
+    pub fn symmetry(&self) -> SoupSymmetry {
+        self.symmetry
+    }
+    /// Returns the largest period to detect.
+    pub fn max_period(&self) -> usize {
+        self.max_period
+    }
+    /// Returns the number of generations after which a soup that has not
+    /// stabilized is considered pathological.
+    pub fn max_gens(&self) -> usize {
+        self.max_gens
+    }
+    /// Returns the number of generations between checks for stabilization.
+    pub fn check_interval(&self) -> usize {
+        self.check_interval
+    }
+    /// Returns the seed of the first soup.
+    pub fn seed_base(&self) -> u64 {
+        self.seed_base
+    }
+}
+
+/// Number of times each kind of object has been found.
+///
+/// Objects are named like apgsearch: `xs` followed by the population for still
+/// lifes, `xp` followed by the period for oscillators, and `xq` followed by
+/// the period for spaceships. After an underscore is the RLE of the object in
+/// its canonical phase and orientation (rather than apgsearch's extended
+/// Wechsler format). Soups that do not stabilize count as `PATHOLOGICAL`, and
+/// objects that are not periodic count as `zz_UNSTABLE`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Census {
+    /// Number of soups searched.
+    soups: u64,
+    /// Number of times each object has been found.
+    counts: BTreeMap<String, u64>,
+}
+impl fmt::Display for Census {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} soups", self.soups)?;
+        for (name, count) in self.sorted() {
+            writeln!(f, "{} {}", count, name)?;
+        }
+        Ok(())
+    }
+}
+impl Census {
+    /// Returns an empty census.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of soups searched.
+    pub fn soups(&self) -> u64 {
+        self.soups
+    }
+    /// Returns the number of times an object has been found.
+    pub fn count(&self, name: &str) -> u64 {
+        self.counts.get(name).copied().unwrap_or(0)
+    }
+    /// Returns the total number of objects found.
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+    /// Returns each object and the number of times it has been found, from
+    /// most to least common.
+    pub fn sorted(&self) -> Vec<(&str, u64)> {
+        self.counts
+            .iter()
+            .map(|(name, &count)| (name.as_str(), count))
+            .sorted_by_key(|&(name, count)| (std::cmp::Reverse(count), name))
+            .collect()
+    }
+
+    /// Adds the objects from a single soup.
+    pub fn add_soup(&mut self, objects: impl IntoIterator<Item = String>) {
+        self.soups += 1;
+        for name in objects {
+            *self.counts.entry(name).or_insert(0) += 1;
+        }
+    }
+    /// Adds every soup and object from another census.
+    ///
+    /// The other census should come from a search with a different seed base,
+    /// or else soups searched by both are counted twice.
+    pub fn merge(&mut self, other: &Census) {
+        self.soups += other.soups;
+        for (name, &count) in &other.counts {
+            *self.counts.entry(name.clone()).or_insert(0) += count;
+        }
+    }
+}
+
+/// Search that simulates seeded random soups until they stabilize, separates
+/// the results into objects, and counts each kind of object in a `Census`.
+///
+/// Soups are numbered by the seed passed to `SoupParams`, starting at the seed
+/// base in `SoupSearchParams`, so a census can be reproduced and extended. The state of the search, including
+/// the census, can be saved with `write_census()` and resumed with
+/// `read_census()`.
+#[derive(Debug, Clone)]
+pub struct SoupSearch<'a, D: Dim> {
+    /// Rule to search in.
+    rule: &'a dyn NdRule<D>,
+    /// Search parameters.
+    params: SoupSearchParams,
+    /// Objects found so far.
+    census: Census,
+    /// Seed of the next soup to search.
+    next_seed: u64,
+}
+impl<'a, D: Dim> SoupSearch<'a, D> {
+    /// Sets up a search in `rule` using the given parameters.
+    pub fn new(rule: &'a dyn NdRule<D>, params: SoupSearchParams) -> Self {
+        Self {
+            rule,
+            next_seed: params.seed_base,
+            params,
+            census: Census::new(),
+        }
+    }
+
+    /// Returns the search parameters.
+    pub fn params(&self) -> &SoupSearchParams {
+        &self.params
+    }
+    /// Returns the objects found so far.
+    pub fn census(&self) -> &Census {
+        &self.census
+    }
+    /// Returns the seed of the next soup to search.
+    pub fn next_seed(&self) -> u64 {
+        self.next_seed
+    }
+
+    /// Searches `count` more soups.
+    ///
+    /// Returns an error if an object cannot be converted to RLE to name it,
+    /// leaving the soup that caused it as the next one to search.
+    pub fn run(&mut self, count: usize) -> RleResult<()> {
+        for _ in 0..count {
+            let objects = self.search_soup(self.next_seed)?;
+            self.census.add_soup(objects);
+            self.next_seed += 1;
+        }
+        Ok(())
+    }
+
+    /// Returns the soup with the given seed.
+    pub fn soup(&self, seed: u64) -> NdTree<D> {
+        let state_count = self.rule.max_state() as usize + 1;
+        let probabilities = vec![self.params.density / (state_count - 1) as f64; state_count - 1];
+        let soup_params = SoupParams::new(seed)
+            .with_state_probabilities(&probabilities)
+            .with_symmetry(self.params.symmetry);
+        let rect = BigRect::with_size(
+            BigVec::origin(),
+            BigVec::repeat(self.params.soup_size.into()),
+        );
+        let mut ret = NdTree::new();
+        ret.fill_random(rect.into(), &soup_params);
+        ret
+    }
+    /// Simulates the soup with the given seed until it stabilizes and returns
+    /// the census name of each object in the result, without adding them to
+    /// the census.
+    pub fn search_soup(&self, seed: u64) -> RleResult<Vec<String>> {
+        let mut ndtree = self.soup(seed);
+        match self.stabilize(&mut ndtree) {
+            Some(period) => self
+                .separate(&ndtree, period)
+                .iter()
+                .map(|object| self.classify(object))
+                .collect(),
+            None => Ok(vec![PATHOLOGICAL.to_owned()]),
+        }
+    }
+
+    /// Writes the parameters, progress, and census so that the search can be
+    /// resumed later with `read_census()`.
+    pub fn write_census(&self, mut w: impl Write) -> io::Result<()> {
+        writeln!(w, "{}", CENSUS_HEADER)?;
+        writeln!(w, "{}, rule {}", self.params, self.rule)?;
+        writeln!(w, "seed base {}", self.params.seed_base)?;
+        writeln!(w, "next seed {}", self.next_seed)?;
+        write!(w, "{}", self.census)
+    }
+    /// Restores the progress and census of the search from a reader, which
+    /// must contain the output of `write_census()` for a search with the same
+    /// rule and parameters, including the seed base.
+    pub fn read_census(&mut self, r: impl BufRead) -> io::Result<()> {
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_owned());
+
+        let mut lines = r.lines();
+        let mut next_line = || {
+            lines
+                .next()
+                .unwrap_or_else(|| Err(invalid("Census too short")))
+        };
+        if next_line()? != CENSUS_HEADER {
+            return Err(invalid("Not a soup census"));
+        }
+        if next_line()? != format!("{}, rule {}", self.params, self.rule) {
+            return Err(invalid("Census has different parameters"));
+        }
+        let seed_base: u64 = next_line()?
+            .strip_prefix("seed base ")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid("Invalid seed base"))?;
+        if seed_base != self.params.seed_base {
+            return Err(invalid("Census has a different seed base"));
+        }
+        let next_seed = next_line()?
+            .strip_prefix("next seed ")
+            .and_then(|s| s.parse().ok())
+            .filter(|&next_seed| next_seed >= seed_base)
+            .ok_or_else(|| invalid("Invalid next seed"))?;
+        let soups = next_line()?
+            .strip_suffix(" soups")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| invalid("Invalid soup count"))?;
+        let mut census = Census {
+            soups,
+            counts: BTreeMap::new(),
+        };
+        for line in lines {
+            let line = line?;
+            let (count, name) = line
+                .split_once(' ')
+                .and_then(|(count, name)| Some((count.parse().ok()?, name)))
+                .ok_or_else(|| invalid("Invalid census line"))?;
+            census.counts.insert(name.to_owned(), count);
+        }
+
+        self.census = census;
+        self.next_seed = next_seed;
+        Ok(())
+    }
+
+    /// Simulates a pattern until its population is periodic, returning the
+    /// period, or `None` if it does not stabilize within the generation limit.
+    ///
+    /// The pattern is stepped `check_interval` generations at a time, and the
+    /// population is only checked generation by generation once the sampled
+    /// populations repeat, since they must repeat with a lag of at most
+    /// `max_period` samples after the pattern stabilizes.
+    fn stabilize(&self, ndtree: &mut NdTree<D>) -> Option<usize> {
+        let max_period = self.params.max_period;
+        let check_interval = BigInt::from(self.params.check_interval);
+        // Populations every `check_interval` generations, most recent last.
+        let mut samples = VecDeque::with_capacity(max_period + 2);
+        let mut gens = 0;
+        loop {
+            if samples.len() == max_period + 2 {
+                samples.pop_front();
+            }
+            samples.push_back(ndtree.root_ref().population());
+
+            let at_limit = gens >= self.params.max_gens;
+            if at_limit || is_period_candidate(&samples, max_period) {
+                let period = self.population_period(ndtree);
+                if period.is_some() || at_limit {
+                    return period;
+                }
+            }
+            hashlife::step(ndtree, self.rule, &check_interval);
+            gens += self.params.check_interval;
+        }
+    }
+    /// Returns the smallest period of the population of a pattern over the
+    /// next `2 * max_period` generations, if it is at most `max_period`.
+    fn population_period(&self, ndtree: &NdTree<D>) -> Option<usize> {
+        let max_period = self.params.max_period;
+        let one = BigInt::one();
+        let mut ndtree = ndtree.clone();
+        let mut populations = vec![];
+        for _ in 0..2 * max_period {
+            populations.push(ndtree.root_ref().population());
+            hashlife::step(&mut ndtree, self.rule, &one);
+        }
+        (1..=max_period)
+            .find(|&p| (0..populations.len() - p).all(|i| populations[i] == populations[i + p]))
+    }
+
+    /// Splits a pattern into objects, where two cells are part of the same
+    /// object if they are adjacent in any generation over one period.
+    fn separate(&self, ndtree: &NdTree<D>, period: usize) -> Vec<NdTree<D>> {
+        // Mark every cell that is nonzero at any point during the period.
+        let mut mask = NdTree::with_node_pool(ndtree.pool().new_ref());
+        let mut phase = ndtree.clone();
+        for _ in 0..period {
+            for (pos, _) in phase.nonzero_cells() {
+                mask.set_cell(&pos, 1);
+            }
+            hashlife::step(&mut phase, self.rule, &BigInt::one());
+        }
+
+        let mut objects = vec![];
+        while let Some(limit) = mask.bounding_rect() {
+            let seed = mask.nonzero_cells().next().unwrap().0;
+            let limit = limit.offset_min_max(-BigInt::one(), BigInt::one());
+            let region = mask.flood_fill(&seed, Connectivity::Full, &limit, 0);
+            objects.push(ndtree.get_region(region));
+        }
+        objects
+    }
+
+    /// Returns the census name of an object.
+    fn classify(&self, object: &NdTree<D>) -> RleResult<String> {
+        let initial = canonical_root(object);
+        let initial_min = object.bounding_rect().map(|r| r.min());
+        let mut phases = vec![object.clone()];
+        let mut next = object.clone();
+        for period in 1..=self.params.max_period {
+            hashlife::step(&mut next, self.rule, &BigInt::one());
+            if canonical_root(&next) == initial {
+                let prefix = if period == 1 {
+                    format!("xs{}", object.root_ref().population())
+                } else if next.bounding_rect().map(|r| r.min()) == initial_min {
+                    format!("xp{}", period)
+                } else {
+                    format!("xq{}", period)
+                };
+                return Ok(format!("{}_{}", prefix, self.canonical_rle(&phases)?));
+            }
+            phases.push(next.clone());
+        }
+        Ok(UNSTABLE.to_owned())
+    }
+
+    /// Returns the RLE body of the smallest phase of an object, over every
+    /// rotation and reflection.
+    fn canonical_rle(&self, phases: &[NdTree<D>]) -> RleResult<String> {
+        let ndim = D::NDIM;
+        let orientations = (0..ndim)
+            .permutations(ndim)
+            .cartesian_product(0..1_usize << ndim)
+            .collect_vec();
+        let canonical_cells = phases
+            .iter()
+            .flat_map(|phase| {
+                let cells = phase
+                    .nonzero_cells()
+                    .map(|(pos, state)| (pos.to_ivec(), state))
+                    .collect_vec();
+                orientations.iter().map(move |(axes, flips)| {
+                    // Permute and reflect each cell, then move the minimum
+                    // corner to the origin.
+                    let mut transformed = cells
+                        .iter()
+                        .map(|(pos, state)| {
+                            let v = (0..ndim)
+                                .map(|i| {
+                                    let x = pos[D::axes()[axes[i]]];
+                                    if flips & (1 << i) == 0 {
+                                        x
+                                    } else {
+                                        -x
+                                    }
+                                })
+                                .collect_vec();
+                            (v, *state)
+                        })
+                        .collect_vec();
+                    for i in 0..ndim {
+                        let min = transformed.iter().map(|(v, _)| v[i]).min().unwrap_or(0);
+                        for (v, _) in &mut transformed {
+                            v[i] -= min;
+                        }
+                    }
+                    transformed.sort();
+                    transformed
+                })
+            })
+            .min()
+            .unwrap_or_default();
+
+        let mut ndtree = NdTree::<D>::new();
+        for (v, state) in canonical_cells {
+            let pos = NdVec::from_fn(|ax| BigInt::from(v[ax as usize]));
+            ndtree.set_cell(&pos, state);
+        }
+        let rle = Rle::from_ndtree(&ndtree, None)?.without_cxrle();
+        let mut buffer = vec![];
+        rle.write_to(&mut buffer, TwoState::from_rule(self.rule))?;
+        Ok(String::from_utf8(buffer)
+            .expect("RLE is not valid UTF-8")
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with("x "))
+            .collect())
+    }
+}
+
+/// Returns whether the last two samples both equal the samples some lag
+/// of at most `max_period` before them.
+fn is_period_candidate(samples: &VecDeque<BigUint>, max_period: usize) -> bool {
+    let last = samples.len() - 1;
+    (1..=max_period).take_while(|&lag| lag < last).any(|lag| {
+        samples[last] == samples[last - lag] && samples[last - 1] == samples[last - 1 - lag]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dim::Dim2D;
+    use crate::sim::rule::LIFE;
+
+    fn params() -> SoupSearchParams {
+        SoupSearchParams::new()
+            .with_soup_size(8)
+            .with_max_period(6)
+            .with_max_gens(2000, 50)
+    }
+
+    #[test]
+    fn test_soup_search_classify() {
+        let search = SoupSearch::<Dim2D>::new(&LIFE, params());
+        let mut pattern = NdTree::new();
+        // Block
+        for &pos in &[[0, 0], [1, 0], [0, 1], [1, 1]] {
+            pattern.set_cell(&NdVec::big(pos), 1);
+        }
+        // Blinker
+        for &pos in &[[10, 0], [11, 0], [12, 0]] {
+            pattern.set_cell(&NdVec::big(pos), 1);
+        }
+        // Glider, facing a different direction than the canonical one
+        for &pos in &[[20, 0], [21, 0], [22, 0], [20, 1], [21, 2]] {
+            pattern.set_cell(&NdVec::big(pos), 1);
+        }
+        let names = search
+            .separate(&pattern, 4)
+            .iter()
+            .map(|object| search.classify(object).unwrap())
+            .sorted()
+            .collect_vec();
+        assert_eq!(3, names.len());
+        assert_eq!("xp2_o$o$o!", names[0]);
+        assert!(names[1].starts_with("xq4_"));
+        assert_eq!("xs4_2o$2o!", names[2]);
+
+        // Every orientation of the glider has the same name.
+        let mut glider = NdTree::new();
+        for &pos in &[[1, 0], [2, 1], [0, 2], [1, 2], [2, 2]] {
+            glider.set_cell(&NdVec::big(pos), 1);
+        }
+        assert_eq!(names[1], search.classify(&glider).unwrap());
+    }
+
+    #[test]
+    fn test_soup_search_stabilize() {
+        // The pi-heptomino settles into still lifes and blinkers after 173
+        // generations, so its population is then constant.
+        let mut pi = NdTree::new();
+        for &pos in &[[0, 0], [1, 0], [2, 0], [0, 1], [2, 1], [0, 2], [2, 2]] {
+            pi.set_cell(&NdVec::big(pos), 1);
+        }
+        let search = SoupSearch::<Dim2D>::new(&LIFE, params());
+        let mut ndtree = pi.clone();
+        assert_eq!(Some(1), search.stabilize(&mut ndtree));
+        assert_eq!(Some(1), search.population_period(&ndtree));
+
+        let search = SoupSearch::<Dim2D>::new(&LIFE, params().with_max_gens(150, 50));
+        assert_eq!(None, search.stabilize(&mut pi.clone()));
+        let search = SoupSearch::<Dim2D>::new(&LIFE, params().with_max_gens(200, 200));
+        assert_eq!(Some(1), search.stabilize(&mut pi.clone()));
+
+        let samples = |pops: &[u32]| pops.iter().map(|&p| BigUint::from(p)).collect();
+        assert!(is_period_candidate(&samples(&[5, 7, 5, 7]), 6));
+        assert!(!is_period_candidate(&samples(&[5, 7, 5, 8]), 6));
+        assert!(!is_period_candidate(&samples(&[5, 7, 5, 7]), 1));
+        assert!(!is_period_candidate(&samples(&[5, 5]), 6));
+        assert!(is_period_candidate(&samples(&[5, 5, 5]), 6));
+    }
+
+    #[test]
+    fn test_soup_search_census() {
+        let mut search = SoupSearch::<Dim2D>::new(&LIFE, params());
+        search.run(3).unwrap();
+        assert_eq!(3, search.census().soups());
+        assert_eq!(3, search.next_seed());
+        assert!(search.census().total() > 0);
+        // Soups are reproducible.
+        assert_eq!(
+            search.search_soup(2).unwrap(),
+            search.search_soup(2).unwrap()
+        );
+
+        // Resume from a saved census.
+        let mut saved = vec![];
+        search.write_census(&mut saved).unwrap();
+        let mut resumed = SoupSearch::<Dim2D>::new(&LIFE, params());
+        resumed.read_census(&saved[..]).unwrap();
+        assert_eq!(search.census(), resumed.census());
+        search.run(1).unwrap();
+        resumed.run(1).unwrap();
+        assert_eq!(search.census(), resumed.census());
+
+        let mut other = SoupSearch::<Dim2D>::new(&LIFE, params().with_density(0.25));
+        assert!(other.read_census(&saved[..]).is_err());
+    }
+
+    #[test]
+    fn test_soup_search_seed_base() {
+        let base = 1 << 32;
+        let mut search = SoupSearch::<Dim2D>::new(&LIFE, params().with_seed_base(base));
+        assert_eq!(base, search.next_seed());
+        search.run(2).unwrap();
+        assert_eq!(base + 2, search.next_seed());
+        let other = SoupSearch::<Dim2D>::new(&LIFE, params());
+        assert_eq!(
+            other.soup(base).nonzero_cells().collect_vec(),
+            search.soup(base).nonzero_cells().collect_vec(),
+        );
+        assert_ne!(
+            other.soup(0).nonzero_cells().collect_vec(),
+            search.soup(base).nonzero_cells().collect_vec(),
+        );
+
+        // A census can only be resumed with the same seed base.
+        let mut saved = vec![];
+        search.write_census(&mut saved).unwrap();
+        let mut resumed = SoupSearch::<Dim2D>::new(&LIFE, params().with_seed_base(base));
+        resumed.read_census(&saved[..]).unwrap();
+        assert_eq!(base + 2, resumed.next_seed());
+        let mut other = SoupSearch::<Dim2D>::new(&LIFE, params());
+        let e = other.read_census(&saved[..]).unwrap_err();
+        assert_eq!("Census has a different seed base", e.to_string());
+    }
+}
@@ -136,3 +136,30 @@ fn test_cgol_ggg_non_power_of_2() {
     }
     assert_eq!(BigUint::from(118_usize), automaton.population());
 }
+
+#[test]
+fn test_cgol_ggg_memory_limit() {
+    let life = crate::sim::rule::LIFE.into_arc();
+    let mut automaton = Rle::from_string_to_ndautomaton(GGG, life).unwrap();
+    // A limit smaller than the pattern itself forces a garbage collection
+    // every generation, but the simulation still makes progress.
+    let gc_count = automaton.step_with_memory_limit(&64.into(), 1);
+    assert!(gc_count >= 64, "{} garbage collections", gc_count);
+    assert_eq!(&BigInt::from(64), automaton.generation_count());
+
+    // A limit that the pattern fits in is never exceeded.
+    let budget = 4 * automaton.ndtree.pool().access().memory_usage();
+    let mut total_gc_count = 0;
+    for _ in 0..8 {
+        total_gc_count += automaton.step_with_memory_limit(&56.into(), budget);
+        let memory_usage = automaton.ndtree.pool().access().memory_usage();
+        assert!(memory_usage <= budget, "{} > {}", memory_usage, budget);
+    }
+    assert!(total_gc_count > 0);
+    assert_eq!(BigUint::from(119_usize), automaton.population());
+    assert_eq!(&BigInt::from(512), automaton.generation_count());
+
+    // A generous limit never needs to collect garbage.
+    let gc_count = automaton.step_with_memory_limit(&512.into(), usize::MAX);
+    assert_eq!(0, gc_count);
+}
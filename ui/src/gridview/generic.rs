@@ -393,12 +393,17 @@ impl<G: GridViewDimension> GenericGridView<G> {
         self.reset_worker_thread();
 
         let mut automaton: Automaton = self.automaton.clone().into();
+        let max_memory = CONFIG.lock().sim.max_memory;
         self.do_on_worker_thread(
             WorkType::SimStep,
             Box::new(move |_hook| {
                 let start = Instant::now();
-                automaton.step(&step_size); // TODO: pass in `hook`
+                // TODO: pass in `hook`
+                let gc_count = automaton.step_with_memory_limit(&step_size, max_memory);
                 let end = Instant::now();
+                if gc_count > 0 {
+                    debug!("Collected garbage {} times during step", gc_count);
+                }
                 Ok(NewGridViewValues {
                     elapsed: end - start,
                     automaton: Some(automaton),
@@ -427,6 +432,7 @@ impl<G: GridViewDimension> GenericGridView<G> {
         self.reset_worker_thread();
         let mut automaton: Automaton = self.automaton.clone().into();
         let step_size = CONFIG.lock().sim.step_size.clone();
+        let max_memory = CONFIG.lock().sim.max_memory;
         self.do_on_worker_thread(
             WorkType::SimContinuous,
             Box::new(move |hook| loop {
@@ -435,8 +441,12 @@ impl<G: GridViewDimension> GenericGridView<G> {
                 }
 
                 let start = Instant::now();
-                automaton.step(&step_size); // TODO: pass in `hook`
+                // TODO: pass in `hook`
+                let gc_count = automaton.step_with_memory_limit(&step_size, max_memory);
                 let end = Instant::now();
+                if gc_count > 0 {
+                    debug!("Collected garbage {} times during step", gc_count);
+                }
 
                 hook.progress_report_blocking(WorkerProgressReport::NewValues(NewGridViewValues {
                     elapsed: end - start,
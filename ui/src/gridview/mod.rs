@@ -130,6 +130,12 @@ impl GridView {
             GridView::View3D(view3d) => view3d.is_running(),
         }
     }
+    pub fn has_selection(&self) -> bool {
+        match self {
+            GridView::View2D(view2d) => view2d.selection.is_some(),
+            GridView::View3D(view3d) => view3d.selection.is_some(),
+        }
+    }
 
     /// Enqueues a command to be executed on the next frame.
     pub fn enqueue(&self, command: impl Into<Command>) {
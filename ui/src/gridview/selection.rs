@@ -48,6 +48,22 @@ impl<D: Dim> Selection<D> {
             Ok(ok) => return Ok(ok),
             Err(e) => errors.push(e.into()),
         }
+        match Self::from_str_with_format::<Plaintext>(s, node_pool) {
+            Ok(ok) => return Ok(ok),
+            Err(e) => errors.push(e.into()),
+        }
+        match Self::from_str_with_format::<Life105>(s, node_pool) {
+            Ok(ok) => return Ok(ok),
+            Err(e) => errors.push(e.into()),
+        }
+        match Self::from_str_with_format::<Life106>(s, node_pool) {
+            Ok(ok) => return Ok(ok),
+            Err(e) => errors.push(e.into()),
+        }
+        match Self::from_str_with_format::<Binary>(s, node_pool) {
+            Ok(ok) => return Ok(ok),
+            Err(e) => errors.push(e.into()),
+        }
         Err(errors)
     }
     pub fn from_str_with_format<F: CaFormatTrait>(
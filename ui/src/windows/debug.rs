@@ -1,23 +1,40 @@
 use imgui::*;
 
+use ndcell_core::prelude::*;
+
 use crate::windows::BuildParams;
 
 #[derive(Debug, Default)]
 pub struct DebugWindow {
     pub is_visible: bool,
+    /// Most recently computed node pool statistics, which are expensive to
+    /// compute every frame.
+    node_pool_stats: Option<String>,
 }
 impl DebugWindow {
     /// Builds the window.
     pub fn build(&mut self, params: &mut BuildParams<'_>) {
-        let BuildParams { ui, .. } = params;
+        let BuildParams { ui, gridview, .. } = params;
 
         if self.is_visible {
+            let node_pool_stats = &mut self.node_pool_stats;
             Window::new(&ImString::new("Debug values"))
                 .size([400.0, 300.0], Condition::FirstUseEver)
                 .build(&ui, || {
                     let mut debug_info = crate::debug::FRAME_DEBUG_INFO.lock();
                     ui.text(&*debug_info);
                     *debug_info = String::new();
+
+                    ui.text("");
+                    if ui.button(
+                        im_str!("Refresh node pool statistics"),
+                        [ui.window_content_region_width(), 20.0],
+                    ) {
+                        *node_pool_stats = Some(gridview.node_pool_stats().to_string());
+                    }
+                    if let Some(stats) = node_pool_stats {
+                        ui.text(&*stats);
+                    }
                 })
         }
     }
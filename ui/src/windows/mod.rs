@@ -7,7 +7,7 @@ use ndcell_core::prelude::*;
 mod debug;
 mod simulation;
 
-use crate::commands::Command;
+use crate::commands::{Command, SelectCommand};
 use crate::gridview::*;
 use crate::mouse::MouseState;
 use crate::CONFIG;
@@ -157,6 +157,18 @@ impl MainWindow {
                 gridview.selected_cell_state(),
             ));
             ui.text("");
+            if gridview.has_selection() {
+                ui.text("Copy selection as:");
+                for &format in CaFormat::ALL {
+                    if ui.button(
+                        &ImString::new(format.to_string()),
+                        [ui.window_content_region_width(), 20.0],
+                    ) {
+                        gridview.enqueue(SelectCommand::Copy(format));
+                    }
+                }
+                ui.text("");
+            }
             ui.checkbox(im_str!("Simulation"), &mut self.simulation.is_visible);
             #[cfg(debug_assertions)]
             ui.checkbox(im_str!("Debug values"), &mut self.debug.is_visible);